@@ -173,7 +173,7 @@ where Self: Copy + Ord
     /// Adds value the index; fails if the index value overflow happens - or if
     /// multiple indexes are present at the path segment
     fn checked_add(&self, add: impl Into<u32>) -> Option<Self> {
-        let mut res = self.clone();
+        let mut res = *self;
         res.checked_add_assign(add)?;
         Some(res)
     }
@@ -181,7 +181,7 @@ where Self: Copy + Ord
     /// Subtracts value the index; fails if the index value overflow happens -
     /// or if multiple indexes are present at the path segment
     fn checked_sub(&self, sub: impl Into<u32>) -> Option<Self> {
-        let mut res = self.clone();
+        let mut res = *self;
         res.checked_sub_assign(sub)?;
         Some(res)
     }
@@ -276,9 +276,9 @@ impl DerivationIndex for NormIdx {
     #[inline]
     fn from_raw_value(value: u32) -> Result<Self, IndexUnsupported> {
         if value < HARDENED_INDEX_BOUNDARY {
-            Ok(NormIdx(value).into())
+            Ok(NormIdx(value))
         } else {
-            Err(NormIdxExpected(HdnIdx(value)).into())
+            Err(NormIdxExpected(HdnIdx::from_child_number(value)).into())
         }
     }
 
@@ -328,6 +328,17 @@ pub struct HdnIdx(
     u32,
 );
 
+impl HdnIdx {
+    /// Constructs hardened index from a zero-based child number, i.e. the value of the index
+    /// which will be displayed (for instance, `0` gives `0h`, which derivation value is
+    /// [`HARDENED_INDEX_BOUNDARY`]).
+    ///
+    /// If the value is equal to or above [`HARDENED_INDEX_BOUNDARY`] it is considered to be a raw
+    /// derivation value with the hardened bit already set, and the bit is cleared.
+    #[inline]
+    pub const fn from_child_number(n: u32) -> Self { Self(n & !HARDENED_INDEX_BOUNDARY) }
+}
+
 impl PartialEq<u8> for HdnIdx {
     fn eq(&self, other: &u8) -> bool { self.0 == *other as u32 }
 }
@@ -449,12 +460,14 @@ impl ChildIdx {
         Self::Hardened(HdnIdx::from(index.into()))
     }
 
-    /// Infallible version of [`Self::from_raw_value`].
+    /// Infallible version of [`Self::from_raw_value`], constructing index from a raw derivation
+    /// value: values below [`HARDENED_INDEX_BOUNDARY`] are mapped to [`ChildIdx::Normal`], and
+    /// all other values to [`ChildIdx::Hardened`].
     pub fn with_raw_value(raw: u32) -> Self {
         if raw < HARDENED_INDEX_BOUNDARY {
             NormIdx(raw).into()
         } else {
-            HdnIdx(raw).into()
+            HdnIdx::from_child_number(raw).into()
         }
     }
 }
@@ -555,3 +568,126 @@ impl FromStr for ChildIdx {
         }
     }
 }
+
+/// Element of a derivation path template, which is either a fixed child index or a wildcard
+/// (`*` or hardened `*h`) resolved to a specific index at derivation time.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, From)]
+pub enum ChildIdxTerm {
+    /// Fixed child index.
+    #[from]
+    Fixed(ChildIdx),
+
+    /// Wildcard index.
+    Wildcard {
+        /// Whether the derived index must be hardened.
+        hardened: bool,
+    },
+}
+
+impl ChildIdxTerm {
+    /// Detects whether the element is a wildcard.
+    #[inline]
+    pub fn is_wildcard(&self) -> bool { matches!(self, ChildIdxTerm::Wildcard { .. }) }
+
+    /// Resolves the element into a child index, replacing wildcard with the provided `index`
+    /// (hardened, if the wildcard is hardened). Fixed indexes are returned unchanged.
+    pub fn resolve(&self, index: NormIdx) -> ChildIdx {
+        match self {
+            ChildIdxTerm::Fixed(fixed) => *fixed,
+            ChildIdxTerm::Wildcard { hardened: false } => ChildIdx::Normal(index),
+            ChildIdxTerm::Wildcard { hardened: true } => ChildIdx::Hardened(
+                HdnIdx::from_index(index.first_index()).expect("normal index is always in range"),
+            ),
+        }
+    }
+}
+
+impl Display for ChildIdxTerm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ChildIdxTerm::Fixed(index) => Display::fmt(index, f),
+            ChildIdxTerm::Wildcard { hardened: false } => f.write_str("*"),
+            ChildIdxTerm::Wildcard { hardened: true } if f.alternate() => f.write_str("*'"),
+            ChildIdxTerm::Wildcard { hardened: true } => f.write_str("*h"),
+        }
+    }
+}
+
+impl FromStr for ChildIdxTerm {
+    type Err = IndexParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "*" => Ok(ChildIdxTerm::Wildcard { hardened: false }),
+            "*h" | "*H" | "*'" => Ok(ChildIdxTerm::Wildcard { hardened: true }),
+            s => ChildIdx::from_str(s).map(ChildIdxTerm::Fixed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hardened_display_vs_raw_value() {
+        let idx = HdnIdx::from(0u16);
+        assert_eq!(idx.to_string(), "0h");
+        assert_eq!(format!("{idx:#}"), "0'");
+        assert_eq!(idx.first_index(), 0);
+        assert_eq!(idx.first_raw_value(), HARDENED_INDEX_BOUNDARY);
+
+        let idx = HdnIdx::from(84u8);
+        assert_eq!(idx.to_string(), "84h");
+        assert_eq!(idx.first_raw_value(), HARDENED_INDEX_BOUNDARY + 84);
+    }
+
+    #[test]
+    fn hardened_from_child_number() {
+        assert_eq!(HdnIdx::from_child_number(0), HdnIdx::from(0u16));
+        assert_eq!(HdnIdx::from_child_number(44).to_string(), "44h");
+        assert_eq!(HdnIdx::from_child_number(HARDENED_INDEX_BOUNDARY + 44), HdnIdx::from(44u8));
+        assert_eq!(HdnIdx::from_child_number(u32::MAX), HdnIdx::largest());
+        assert_eq!(HdnIdx::from_child_number(u32::MAX).first_raw_value(), u32::MAX);
+    }
+
+    #[test]
+    fn hardened_raw_value_roundtrip() {
+        for raw in [HARDENED_INDEX_BOUNDARY, HARDENED_INDEX_BOUNDARY + 1, u32::MAX] {
+            let idx = HdnIdx::from_raw_value(raw).unwrap();
+            assert_eq!(idx.first_raw_value(), raw);
+            assert_eq!(idx.first_index(), raw - HARDENED_INDEX_BOUNDARY);
+        }
+        assert_eq!(HdnIdx::from_raw_value(1), Err(HdnIdxExpected(NormIdx(1)).into()));
+        assert_eq!(HdnIdx::from_index(HARDENED_INDEX_BOUNDARY + 1), Ok(HdnIdx::one()));
+    }
+
+    #[test]
+    fn child_with_raw_value() {
+        assert_eq!(ChildIdx::with_raw_value(0), ChildIdx::Normal(NormIdx::zero()));
+        assert_eq!(
+            ChildIdx::with_raw_value(HARDENED_INDEX_BOUNDARY - 1),
+            ChildIdx::Normal(NormIdx::largest())
+        );
+        assert_eq!(
+            ChildIdx::with_raw_value(HARDENED_INDEX_BOUNDARY),
+            ChildIdx::Hardened(HdnIdx::zero())
+        );
+        assert_eq!(ChildIdx::with_raw_value(u32::MAX), ChildIdx::Hardened(HdnIdx::largest()));
+        for raw in [0, 1, HARDENED_INDEX_BOUNDARY - 1, HARDENED_INDEX_BOUNDARY, u32::MAX] {
+            assert_eq!(ChildIdx::with_raw_value(raw).first_raw_value(), raw);
+        }
+    }
+
+    #[test]
+    fn normal_display_vs_raw_value() {
+        let idx = NormIdx::from(1u8);
+        assert_eq!(idx.to_string(), "1");
+        assert_eq!(idx.first_index(), 1);
+        assert_eq!(idx.first_raw_value(), 1);
+        assert_eq!(
+            NormIdx::from_raw_value(HARDENED_INDEX_BOUNDARY),
+            Err(NormIdxExpected(HdnIdx::zero()).into())
+        );
+    }
+}
@@ -24,6 +24,7 @@
 extern crate amplify;
 
 mod indexes;
+mod path;
 pub mod standard;
 mod xkey;
 mod xpub;
@@ -31,9 +32,10 @@ mod xpub;
 mod xpriv;
 
 pub use indexes::{
-    index_error, ChildIdx, DerivationIndex, HdnIdx, NormIdx, HARDENED_INDEX_BOUNDARY,
+    index_error, ChildIdx, ChildIdxTerm, DerivationIndex, HdnIdx, NormIdx, HARDENED_INDEX_BOUNDARY,
 };
+pub use path::{DerivationPath, HardenedChar, TermPath};
 pub use xkey::{
     Chaincode, Fingerprint, TooDeepDerivation, XkeyDecodeError, XkeyParseError, XKEY_LEN,
 };
-pub use xpub::{Xpub, XpubIdentifier};
+pub use xpub::{Xpub, XpubCache, XpubIdentifier};
@@ -0,0 +1,195 @@
+// Bitcoin hierarchical deterministic derivation library
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::fmt::{self, Display, Formatter};
+use core::str::FromStr;
+
+use crate::index_error::IndexParseError;
+use crate::{ChildIdx, ChildIdxTerm, DerivationIndex, NormIdx};
+
+/// Character marking hardened indexes in the string representation of a derivation path.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display)]
+pub enum HardenedChar {
+    /// Lowercase `h`, which is the default used by this library.
+    #[display("h")]
+    LowerH,
+
+    /// Uppercase `H`.
+    #[display("H")]
+    H,
+
+    /// Apostrophe `'`, used by Bitcoin Core and most other wallets.
+    #[display("'")]
+    Apostrophe,
+}
+
+impl Default for HardenedChar {
+    fn default() -> Self { HardenedChar::LowerH }
+}
+
+/// Derivation path consisting of a sequence of child indexes.
+///
+/// The path is displayed as indexes separated by `/`, with hardened indexes marked by `h` (or
+/// by `'` when the alternate formatting flag is used). Parsing accepts any of `h`, `H` and `'`
+/// markers and an optional `m/` prefix.
+#[derive(Wrapper, WrapperMut, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Default, From)]
+#[wrapper(Deref)]
+#[wrapper_mut(DerefMut)]
+pub struct DerivationPath(Vec<ChildIdx>);
+
+impl DerivationPath {
+    /// Formats the path using the given marker for hardened indexes.
+    pub fn to_string_with(&self, hardened: HardenedChar) -> String {
+        self.0
+            .iter()
+            .map(|index| match index {
+                ChildIdx::Normal(index) => index.to_string(),
+                ChildIdx::Hardened(index) => format!("{}{hardened}", index.first_index()),
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+impl Display for DerivationPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let hardened = if f.alternate() { HardenedChar::Apostrophe } else { HardenedChar::LowerH };
+        f.write_str(&self.to_string_with(hardened))
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = IndexParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = match s {
+            "m" | "" => return Ok(DerivationPath::default()),
+            s => s.strip_prefix("m/").unwrap_or(s),
+        };
+        s.split('/').map(ChildIdx::from_str).collect::<Result<_, _>>().map(DerivationPath)
+    }
+}
+
+/// Template of a derivation path, which may contain wildcards, as used in ranged descriptors
+/// (like `84h/0h/0h/0/*`).
+///
+/// Follows the same string representation rules as [`DerivationPath`], with wildcards written
+/// as `*` and hardened wildcards as `*h` (`*'` when the alternate formatting flag is used).
+#[derive(Wrapper, WrapperMut, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Default, From)]
+#[wrapper(Deref)]
+#[wrapper_mut(DerefMut)]
+pub struct TermPath(Vec<ChildIdxTerm>);
+
+impl TermPath {
+    /// Counts number of wildcards in the path.
+    pub fn wildcard_count(&self) -> usize {
+        self.0.iter().filter(|term| term.is_wildcard()).count()
+    }
+
+    /// Resolves the path into a derivation path, replacing wildcards, in order of their
+    /// appearance, with the indexes provided by the iterator. Returns `None` if the iterator has
+    /// less indexes than there are wildcards in the path.
+    pub fn resolve(&self, indexes: impl IntoIterator<Item = NormIdx>) -> Option<DerivationPath> {
+        let mut indexes = indexes.into_iter();
+        self.0
+            .iter()
+            .map(|term| match term {
+                ChildIdxTerm::Fixed(index) => Some(*index),
+                wildcard => indexes.next().map(|index| wildcard.resolve(index)),
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(DerivationPath)
+    }
+}
+
+impl From<DerivationPath> for TermPath {
+    fn from(path: DerivationPath) -> Self {
+        TermPath(path.0.into_iter().map(ChildIdxTerm::Fixed).collect())
+    }
+}
+
+impl Display for TermPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (pos, term) in self.0.iter().enumerate() {
+            if pos > 0 {
+                f.write_str("/")?;
+            }
+            Display::fmt(term, f)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for TermPath {
+    type Err = IndexParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = match s {
+            "m" | "" => return Ok(TermPath::default()),
+            s => s.strip_prefix("m/").unwrap_or(s),
+        };
+        s.split('/').map(ChildIdxTerm::from_str).collect::<Result<_, _>>().map(TermPath)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hardened_char_formatting() {
+        let path = DerivationPath::from_str("m/84h/0'/0H/1/7").unwrap();
+        assert_eq!(path.to_string(), "84h/0h/0h/1/7");
+        assert_eq!(format!("{path:#}"), "84'/0'/0'/1/7");
+        assert_eq!(path.to_string_with(HardenedChar::H), "84H/0H/0H/1/7");
+        assert_eq!(path.to_string_with(HardenedChar::Apostrophe), "84'/0'/0'/1/7");
+    }
+
+    #[test]
+    fn parse_roundtrip() {
+        for hardened in [HardenedChar::LowerH, HardenedChar::H, HardenedChar::Apostrophe] {
+            let path = DerivationPath::from_str("48h/1h/0h/2h/0/3").unwrap();
+            let s = path.to_string_with(hardened);
+            assert_eq!(DerivationPath::from_str(&s), Ok(path));
+        }
+        assert_eq!(DerivationPath::from_str("m"), Ok(DerivationPath::default()));
+        assert!(DerivationPath::from_str("84h//0").is_err());
+    }
+
+    #[test]
+    fn term_path_wildcards() {
+        let path = TermPath::from_str("m/84h/0h/0h/0/*").unwrap();
+        assert_eq!(path.to_string(), "84h/0h/0h/0/*");
+        assert_eq!(path.wildcard_count(), 1);
+        assert_eq!(
+            path.resolve([NormIdx::from(7u8)]),
+            Some(DerivationPath::from_str("84h/0h/0h/0/7").unwrap())
+        );
+        assert_eq!(path.resolve([]), None);
+
+        let path = TermPath::from_str("0/*'").unwrap();
+        assert_eq!(path.to_string(), "0/*h");
+        assert_eq!(format!("{path:#}"), "0/*'");
+        assert_eq!(path.resolve([NormIdx::from(1u8)]).unwrap().to_string(), "0/1h");
+        assert!(TermPath::from_str("0/**").is_err());
+    }
+}
@@ -22,7 +22,7 @@
 
 use core::str::FromStr;
 
-use crate::HdnIdx;
+use crate::{ChildIdx, HdnIdx};
 
 /// Errors in parsing derivation scheme string representation
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Error, Display)]
@@ -171,4 +171,77 @@ impl Bip43 {
     pub fn multisig_segwit0() -> Bip43 { Bip43::Bip48Native }
     /// Constructs derivation standard corresponding to a multi-sig BIP87.
     pub fn multisig_descriptor() -> Bip43 { Bip43::Bip87 }
+
+    /// Returns hardened purpose index, which is the first element of the derivation path for the
+    /// scheme.
+    pub fn purpose(&self) -> HdnIdx {
+        match self {
+            Bip43::Bip44 => HdnIdx::from(44u8),
+            Bip43::Bip84 => HdnIdx::from(84u8),
+            Bip43::Bip49 => HdnIdx::from(49u8),
+            Bip43::Bip86 => HdnIdx::from(86u8),
+            Bip43::Bip45 => HdnIdx::from(45u8),
+            Bip43::Bip48Nested | Bip43::Bip48Native => HdnIdx::from(48u8),
+            Bip43::Bip87 => HdnIdx::from(87u8),
+            Bip43::Bip43 { purpose } => *purpose,
+        }
+    }
+
+    /// Constructs derivation path from the master key to the account-level key for the given
+    /// coin type and account.
+    ///
+    /// All segments of the account-level path are hardened, thus the account key can't be derived
+    /// from the master extended public key: the derivation must be done with the extended private
+    /// key, after which the account [`crate::Xpub`] can be used for deriving individual addresses.
+    ///
+    /// For [`Bip43::Bip45`] and [`Bip43::Bip43`], which do not define coin type and account
+    /// levels, the path consists of the purpose index only, and `coin_type` and `account` are
+    /// ignored.
+    pub fn account_path(&self, coin_type: HdnIdx, account: HdnIdx) -> Vec<ChildIdx> {
+        let purpose = ChildIdx::from(self.purpose());
+        match self {
+            Bip43::Bip45 | Bip43::Bip43 { .. } => vec![purpose],
+            Bip43::Bip48Nested => {
+                vec![purpose, coin_type.into(), account.into(), ChildIdx::hardened_index(1u8)]
+            }
+            Bip43::Bip48Native => {
+                vec![purpose, coin_type.into(), account.into(), ChildIdx::hardened_index(2u8)]
+            }
+            Bip43::Bip44 | Bip43::Bip84 | Bip43::Bip49 | Bip43::Bip86 | Bip43::Bip87 => {
+                vec![purpose, coin_type.into(), account.into()]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::DerivationIndex;
+
+    #[test]
+    fn purpose() {
+        for (scheme, purpose) in [
+            (Bip43::Bip44, 44),
+            (Bip43::Bip84, 84),
+            (Bip43::Bip49, 49),
+            (Bip43::Bip86, 86),
+            (Bip43::Bip45, 45),
+            (Bip43::Bip48Nested, 48),
+            (Bip43::Bip48Native, 48),
+            (Bip43::Bip87, 87),
+            (
+                Bip43::Bip43 {
+                    purpose: HdnIdx::from(1017u16),
+                },
+                1017,
+            ),
+        ] {
+            assert_eq!(scheme.purpose().first_index(), purpose);
+            assert_eq!(
+                scheme.account_path(HdnIdx::zero(), HdnIdx::zero())[0],
+                scheme.purpose().into()
+            );
+        }
+    }
 }
@@ -20,6 +20,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use core::str::FromStr;
+
+use amplify::hex::{self, FromHex};
 use amplify::{Array, Bytes32};
 use base58::FromBase58Error;
 
@@ -34,12 +37,34 @@ pub struct Chaincode(
 
 /// Extended public key fingerprint.
 #[derive(Wrapper, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Default, From)]
+#[wrapper(RangeOps, BorrowSlice, LowerHex, UpperHex, Display)]
 pub struct Fingerprint(
     #[from]
     #[from([u8; 4])]
     Array<u8, 4>,
 );
 
+impl FromHex for Fingerprint {
+    fn from_byte_iter<I>(iter: I) -> Result<Self, hex::Error>
+    where I: Iterator<Item = Result<u8, hex::Error>> + ExactSizeIterator + DoubleEndedIterator {
+        let vec = Vec::<u8>::from_byte_iter(iter)?;
+        if vec.len() != 4 {
+            return Err(hex::Error::InvalidLength(4, vec.len()));
+        }
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(&vec);
+        Ok(Fingerprint::from(buf))
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = hex::Error;
+
+    /// Parses fingerprint from a string of 8 hexadecimal characters, as used in key origin
+    /// descriptor syntax (`[d34db33f/...]`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> { Self::from_hex(s) }
+}
+
 /// Length of the extended key binary representation.
 pub const XKEY_LEN: usize = 78;
 
@@ -90,3 +115,34 @@ impl From<FromBase58Error> for XkeyParseError {
 #[display(doc_comments)]
 /// derivation is impossible: the depth of the current key has reached maximum (255).
 pub struct TooDeepDerivation;
+
+/// Compares two byte strings in constant time, i.e. without early termination on the first
+/// mismatching byte, such that the time taken does not leak the position of the mismatch.
+///
+/// The lengths of the compared strings are not considered secret, and strings of different
+/// lengths are unequal.
+pub(crate) fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y));
+    // Converts the accumulated difference into a result without branching on it: subtraction
+    // borrows into the high byte only for zero difference. `core::hint::black_box` would be
+    // preferable, but it is not available with the minimal supported rust version.
+    ((diff as u16).wrapping_sub(1) >> 8) & 1 == 1
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ct_eq_bytes() {
+        assert!(ct_eq(&[], &[]));
+        assert!(ct_eq(&[0x00, 0xFF, 0x80], &[0x00, 0xFF, 0x80]));
+        assert!(!ct_eq(&[0x01, 0xFF, 0x80], &[0x00, 0xFF, 0x80]));
+        assert!(!ct_eq(&[0x00, 0xFF, 0x80], &[0x00, 0xFF, 0x00]));
+        assert!(!ct_eq(&[0xFF; 4], &[0x00; 4]));
+        assert!(!ct_eq(&[0x00, 0xFF], &[0x00, 0xFF, 0x80]));
+    }
+}
@@ -20,8 +20,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::BTreeMap;
+
 use amplify::{Array, RawArray, Wrapper};
-use bitcoin_hashes::{ripemd160, sha512, Hash, Hmac, HmacEngine};
+use bitcoin_hashes::{hash160, sha512, Hash, Hmac, HmacEngine};
 use secp256k1::{PublicKey, XOnlyPublicKey};
 
 use crate::{
@@ -48,7 +50,15 @@ impl Xpub {
 
     pub fn is_mainnet(&self) -> bool { !self.is_testnet() }
 
-    pub fn is_testnet(&self) -> bool { &self.0[..4] == &Self::MAGIC_TESTNET }
+    pub fn is_testnet(&self) -> bool { self.0[..4] == Self::MAGIC_TESTNET }
+
+    /// Converts the key to its serialization for mainnet (if `mainnet` is `true`) or testnet,
+    /// replacing just the version bytes and keeping all other key data intact.
+    pub fn to_network(mut self, mainnet: bool) -> Xpub {
+        let magic = if mainnet { Self::MAGIC_MAINNET } else { Self::MAGIC_TESTNET };
+        self.0[..4].copy_from_slice(&magic);
+        self
+    }
 
     /// How many derivations this key is from the master (which is 0).
     pub fn depth(&self) -> u8 { self.0[4] }
@@ -80,6 +90,11 @@ impl Xpub {
         PublicKey::from_slice(&self.0[45..78]).expect("public key is checked on deserialization")
     }
 
+    /// Detects whether two extended keys have the same chain code and public key, ignoring
+    /// network, depth, parent fingerprint and child number metadata, which may differ between
+    /// wallets describing the same key.
+    pub fn same_key(&self, other: &Xpub) -> bool { self.0[13..] == other.0[13..] }
+
     /// Constructs BIP340 x-only public key for BIP-340 signatures and Taproot use matching
     /// the internal public key representation.
     pub fn into_x_only_pk(self) -> XOnlyPublicKey { XOnlyPublicKey::from(self.public_key()) }
@@ -150,13 +165,9 @@ impl Xpub {
     /// Extended public key binary encoding according to BIP 32.
     pub fn encode_binary(&self) -> [u8; 78] { self.0 }
 
-    /// Returns the HASH160 of the chaincode.
+    /// Returns the HASH160 of the public key.
     pub fn identifier(&self) -> XpubIdentifier {
-        use std::io::Write;
-
-        let mut engine = ripemd160::Hash::engine();
-        engine.write_all(&self.public_key().serialize()).expect("engines don't error");
-        let hash = ripemd160::Hash::from_engine(engine);
+        let hash = hash160::Hash::hash(&self.public_key().serialize());
         XpubIdentifier::from_raw_array(hash.to_byte_array())
     }
 
@@ -168,6 +179,45 @@ impl Xpub {
     }
 }
 
+/// Cache of the change-branch keys derived from an account-level extended public key, which
+/// allows deriving many addresses (`<account>/<change>/<index>`) performing just a single
+/// derivation per address.
+///
+/// Scanning 10 000 addresses of a single branch with the cache takes about half the time of
+/// deriving each address from the account key with two [`Xpub::ckd_pub`] calls.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct XpubCache {
+    account: Xpub,
+    branches: BTreeMap<NormIdx, Xpub>,
+}
+
+impl XpubCache {
+    /// Constructs empty cache for the account-level extended public key.
+    pub fn new(account: Xpub) -> Self {
+        XpubCache {
+            account,
+            branches: empty!(),
+        }
+    }
+
+    /// Returns account-level extended public key from which the keys are derived.
+    pub fn account(&self) -> Xpub { self.account }
+
+    /// Derives extended public key at `<account>/<change>/<index>`, deriving the change branch
+    /// key only if it is not yet cached.
+    pub fn derive(&mut self, change: NormIdx, index: NormIdx) -> Result<Xpub, TooDeepDerivation> {
+        let branch = match self.branches.get(&change) {
+            Some(branch) => *branch,
+            None => {
+                let branch = self.account.ckd_pub(change)?;
+                self.branches.insert(change, branch);
+                branch
+            }
+        };
+        branch.ckd_pub(index)
+    }
+}
+
 mod display_from_str {
     use core::fmt::{self, Display, Formatter};
     use core::str::FromStr;
@@ -176,6 +226,7 @@ mod display_from_str {
     use bitcoin_hashes::sha256d;
 
     use super::*;
+    use crate::xkey::ct_eq;
     use crate::XkeyParseError;
 
     impl Display for Xpub {
@@ -203,7 +254,7 @@ mod display_from_str {
             let hash = sha256d::Hash::hash(&data[..data_len]);
             let mut actual = [0u8; 4];
             actual.copy_from_slice(&hash[..4]);
-            if actual != expected {
+            if !ct_eq(&actual, &expected) {
                 return Err(XkeyParseError::InvalidChecksum {
                     actual: actual.into(),
                     expected: expected.into(),
@@ -215,3 +266,69 @@ mod display_from_str {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::*;
+
+    // BIP-32 test vector 1: keys at m/0h and m/0h/1
+    const XPUB_0H: &str = "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw";
+    const XPUB_0H_1: &str = "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ";
+
+    #[test]
+    fn same_key_ignores_metadata() {
+        let xpub = Xpub::from_str(XPUB_0H_1).unwrap();
+        let derived = Xpub::from_str(XPUB_0H).unwrap().ckd_pub(1u8).unwrap();
+        assert_eq!(derived, xpub);
+
+        // Key exported without its origin metadata
+        let mut data = xpub.encode_binary();
+        data[4..13].fill(0);
+        let stripped = Xpub::decode_binary(&data).unwrap();
+        assert_ne!(stripped, xpub);
+        assert!(stripped.same_key(&xpub));
+
+        assert!(!xpub.same_key(&derived.ckd_pub(1u8).unwrap()));
+        data[13] ^= 0x01;
+        assert!(!Xpub::decode_binary(&data).unwrap().same_key(&xpub));
+    }
+
+    #[test]
+    fn to_network_round_trip() {
+        let xpub = Xpub::from_str(XPUB_0H).unwrap();
+        assert!(xpub.is_mainnet());
+
+        let testnet = xpub.to_network(false);
+        assert!(testnet.is_testnet());
+        assert!(testnet.same_key(&xpub));
+        assert_eq!(testnet.depth(), xpub.depth());
+        assert_eq!(testnet.parent_fingerprint(), xpub.parent_fingerprint());
+        let s = testnet.to_string();
+        assert!(s.starts_with("tpub"));
+        assert_eq!(Xpub::from_str(&s).unwrap(), testnet);
+
+        assert_eq!(testnet.to_network(true), xpub);
+        assert_eq!(testnet.to_network(true).to_string(), XPUB_0H);
+        assert_eq!(xpub.to_network(true), xpub);
+    }
+
+    #[test]
+    fn cache_matches_ckd_pub() {
+        let account = Xpub::from_str(XPUB_0H).unwrap();
+        let mut cache = XpubCache::new(account);
+        assert_eq!(cache.account(), account);
+
+        for change in [0u8, 1] {
+            let branch = account.ckd_pub(change).unwrap();
+            for index in [0u16, 1, 1000] {
+                let expected = branch.ckd_pub(index).unwrap();
+                assert_eq!(cache.derive(change.into(), index.into()).unwrap(), expected);
+                // Second lookup hits the cached branch
+                assert_eq!(cache.derive(change.into(), index.into()).unwrap(), expected);
+            }
+        }
+        assert_eq!(cache.account(), account);
+    }
+}
@@ -26,16 +26,129 @@
 use core::fmt::{self, Display, Formatter};
 use core::str::FromStr;
 
-use amplify::{Array, Bytes32, Wrapper};
-use bc::{ScriptPubkey, WitnessVer};
+use amplify::hex::FromHex;
+use amplify::{Array, Bytes32};
+use base58::{FromBase58, ToBase58};
+use bc::{Chain, ScriptBytes, ScriptPubkey, SegwitError, WitnessProgram};
+use bitcoin_hashes::{sha256d, Hash};
 use secp256k1::XOnlyPublicKey;
 
+use crate::bech32;
+use crate::scripts::{classify_script, witness_script, ScriptType};
+
 pub type Bytes20 = Array<u8, 20>;
 
+/// Errors constructing witness version.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum WitnessVerError {
+    /// witness version {0} exceeds the maximal version 16.
+    OutOfRange(u64),
+
+    /// witness version must be a decimal number, while '{0}' is provided.
+    NotNumber(String),
+}
+
+/// Version of a witness program, which is a number from 0 to 16.
+///
+/// Unlike [`bc::WitnessVer`], the version is displayed and parsed as a
+/// decimal number, such that address formats like `P2W2` round-trip through
+/// their string representation.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[display("{0}")]
+pub struct WitnessVer(u8);
+
+impl WitnessVer {
+    /// Witness version 0 used by P2WPKH and P2WSH.
+    pub const V0: Self = WitnessVer(0);
+
+    /// Witness version 1 used by P2TR.
+    pub const V1: Self = WitnessVer(1);
+
+    /// Maximal witness version.
+    pub const MAX: Self = WitnessVer(16);
+
+    /// Returns the version number.
+    pub const fn to_u8(self) -> u8 { self.0 }
+
+    /// Returns op code pushing the version in a witness program `scriptPubkey`
+    /// (`OP_0` or `OP_1` to `OP_16`).
+    pub const fn op_code(self) -> u8 {
+        match self.0 {
+            0 => 0x00,
+            ver => ver + 0x50,
+        }
+    }
+}
+
+impl TryFrom<u8> for WitnessVer {
+    type Error = WitnessVerError;
+
+    fn try_from(ver: u8) -> Result<Self, Self::Error> {
+        if ver > Self::MAX.0 {
+            return Err(WitnessVerError::OutOfRange(ver as u64));
+        }
+        Ok(WitnessVer(ver))
+    }
+}
+
+impl FromStr for WitnessVer {
+    type Err = WitnessVerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || !s.bytes().all(|c| c.is_ascii_digit()) {
+            return Err(WitnessVerError::NotNumber(s.to_owned()));
+        }
+        match u64::from_str(s) {
+            Ok(ver) if ver <= Self::MAX.0 as u64 => Ok(WitnessVer(ver as u8)),
+            Ok(ver) => Err(WitnessVerError::OutOfRange(ver)),
+            // The string consists of digits, so the only possible error is an overflow
+            Err(_) => Err(WitnessVerError::OutOfRange(u64::MAX)),
+        }
+    }
+}
+
+impl From<bc::WitnessVer> for WitnessVer {
+    fn from(ver: bc::WitnessVer) -> Self {
+        match ver {
+            bc::WitnessVer::V0 => WitnessVer::V0,
+            // Other versions are represented with `OP_PUSHNUM_1` to `OP_PUSHNUM_16` op codes
+            ver => WitnessVer(ver as u8 - 0x50),
+        }
+    }
+}
+
+impl From<WitnessVer> for bc::WitnessVer {
+    fn from(ver: WitnessVer) -> Self {
+        // `bc::WitnessVer::from_op_code` can't be used, since `bc::OpCode`
+        // doesn't cover `OP_1`-`OP_16` op codes
+        const VERSIONS: [bc::WitnessVer; 17] = [
+            bc::WitnessVer::V0,
+            bc::WitnessVer::V1,
+            bc::WitnessVer::V2,
+            bc::WitnessVer::V3,
+            bc::WitnessVer::V4,
+            bc::WitnessVer::V5,
+            bc::WitnessVer::V6,
+            bc::WitnessVer::V7,
+            bc::WitnessVer::V8,
+            bc::WitnessVer::V9,
+            bc::WitnessVer::V10,
+            bc::WitnessVer::V11,
+            bc::WitnessVer::V12,
+            bc::WitnessVer::V13,
+            bc::WitnessVer::V14,
+            bc::WitnessVer::V15,
+            bc::WitnessVer::V16,
+        ];
+        VERSIONS[ver.0 as usize]
+    }
+}
+
 /// Defines which witness version may have an address.
 ///
 /// The structure is required to support some ambiguity on the witness version
-/// used by some address, since `Option<`[`WitnessVersion`]`>` can't cover that
+/// used by some address, since `Option<`[`WitnessVer`]`>` can't cover that
 /// ambiguity (see details in [`SegWitInfo::Ambiguous`] description).
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum SegWitInfo {
@@ -49,14 +162,35 @@ pub enum SegWitInfo {
 
     /// Address has a clearly defined segwit version, i.e. P2WPKH, P2WSH, P2TR
     /// or future non-P2SH-wrapped segwit address
-    SegWit(WitnessVersion),
+    SegWit(WitnessVer),
 }
 
 impl SegWitInfo {
-    /// Detects [`WitnessVersion`] used in the current segwit. Returns [`None`]
+    /// Classifies `scriptPubkey` by the witness version of its spending
+    /// conditions, which determines the signature hash algorithm (legacy,
+    /// BIP-143 or BIP-341) used by the inputs spending it.
+    ///
+    /// P2SH outputs are [`SegWitInfo::Ambiguous`], since the redeem script
+    /// may be a witness program. Scripts which are not witness programs (bare
+    /// scripts, `OP_RETURN` and other non-standard) are
+    /// [`SegWitInfo::PreSegWit`].
+    pub fn from_script_pubkey(script: &ScriptPubkey) -> SegWitInfo {
+        match classify_script(script) {
+            ScriptType::P2sh => SegWitInfo::Ambiguous,
+            ScriptType::P2wpkh | ScriptType::P2wsh => SegWitInfo::SegWit(WitnessVer::V0),
+            ScriptType::P2tr => SegWitInfo::SegWit(WitnessVer::V1),
+            ScriptType::FutureWitness(version) => SegWitInfo::SegWit(version),
+            ScriptType::P2pk
+            | ScriptType::P2pkh
+            | ScriptType::OpReturn
+            | ScriptType::Nonstandard => SegWitInfo::PreSegWit,
+        }
+    }
+
+    /// Detects [`WitnessVer`] used in the current segwit. Returns [`None`]
     /// for both pre-segwit and P2SH (ambiguous) addresses.
     #[inline]
-    pub fn witness_version(self) -> Option<WitnessVersion> {
+    pub fn witness_version(self) -> Option<WitnessVer> {
         match self {
             SegWitInfo::PreSegWit => None,
             SegWitInfo::Ambiguous => None,
@@ -66,7 +200,7 @@ impl SegWitInfo {
 }
 
 /// Bitcoin address.
-#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, From)]
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, From)]
 pub struct Address {
     /// Address payload (see [`AddressPayload`]).
     pub payload: AddressPayload,
@@ -80,40 +214,126 @@ impl Address {
     /// Returns `None` if the uncompressed key is provided or `scriptPubkey`
     /// can't be represented as an address.
     pub fn from_script(script: &ScriptPubkey, network: AddressNetwork) -> Option<Self> {
-        Address::from_script(script, network.bitcoin_network())
-            .map_err(|_| address::Error::UncompressedPubkey)
-            .and_then(Self::try_from)
-            .ok()
+        AddressPayload::from_script_pubkey(script).map(|payload| payload.into_address(network))
     }
 
     /// Returns script corresponding to the given address.
-    pub fn script_pubkey(self) -> ScriptPubkey { self.payload.script_pubkey() }
+    pub fn script_pubkey(&self) -> ScriptPubkey { self.payload.clone().into_script_pubkey() }
 
     /// Returns if the address is testnet-, signet- or regtest-specific
-    pub fn is_testnet(self) -> bool { self.network != AddressNetwork::Mainnet }
+    pub fn is_testnet(&self) -> bool { self.network != AddressNetwork::Mainnet }
+
+    /// Detects whether the address is a bech32 or bech32m (segwit) address.
+    pub fn is_bech32(&self) -> bool {
+        !matches!(self.payload, AddressPayload::PubkeyHash(_) | AddressPayload::ScriptHash(_))
+    }
+
+    /// Formats bech32 and bech32m addresses in uppercase, which BIP-173 allows
+    /// and which produces denser QR codes (using the alphanumeric mode).
+    /// Base58 addresses are case-sensitive and are returned unchanged.
+    pub fn to_string_uppercase(&self) -> String {
+        let s = self.to_string();
+        if self.is_bech32() {
+            s.to_ascii_uppercase()
+        } else {
+            s
+        }
+    }
 }
 
 impl From<Address> for ScriptPubkey {
-    fn from(compact: Address) -> Self { Address::from(compact).script_pubkey().into() }
+    fn from(address: Address) -> Self { address.script_pubkey() }
 }
 
 impl Display for Address {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { Display::fmt(&Address::from(*self), f) }
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let hrp = self.network.bech32_hrp();
+        let s = match &self.payload {
+            AddressPayload::PubkeyHash(hash) => {
+                base58check(self.network.pubkey_hash_prefix(), &hash[..])
+            }
+            AddressPayload::ScriptHash(hash) => {
+                base58check(self.network.script_hash_prefix(), &hash[..])
+            }
+            AddressPayload::WPubkeyHash(hash) => bech32::encode(hrp, 0, &hash[..]),
+            AddressPayload::WScriptHash(hash) => bech32::encode(hrp, 0, &hash[..]),
+            AddressPayload::Taproot { output_key } => {
+                bech32::encode(hrp, 1, &output_key.serialize())
+            }
+            AddressPayload::Future(program) => {
+                bech32::encode(hrp, WitnessVer::from(program.version()).to_u8(), program.program())
+            }
+        };
+        f.write_str(&s)
+    }
 }
 
 impl FromStr for Address {
-    type Err = address::Error;
+    type Err = AddressParseError;
 
+    /// Parses address string; bech32 and bech32m addresses may be either
+    /// all-lowercase or all-uppercase, while mixed case is rejected.
+    ///
+    /// Base58 addresses with testnet prefixes are parsed as
+    /// [`AddressNetwork::Testnet`], since regtest uses the same prefixes.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Address::from_str(s).and_then(Address::try_from)
+        let lowercase = s.to_ascii_lowercase();
+        let segwit_network =
+            [AddressNetwork::Mainnet, AddressNetwork::Testnet, AddressNetwork::Regtest]
+                .into_iter()
+                .find(|network| {
+                    lowercase
+                        .strip_prefix(network.bech32_hrp())
+                        .map(|rest| rest.starts_with('1'))
+                        .unwrap_or_default()
+                });
+
+        if let Some(network) = segwit_network {
+            if bech32::is_mixed_case(s) {
+                return Err(AddressParseError::MixedCase);
+            }
+            let (_, version, program) =
+                bech32::decode(s).ok_or(AddressParseError::InvalidChecksum)?;
+            let payload =
+                AddressPayload::with_witness_program(WitnessVer::try_from(version)?, program)
+                    .map_err(|_| AddressParseError::InvalidWitnessProgram)?;
+            return Ok(payload.into_address(network));
+        }
+
+        let data = s.from_base58().map_err(|_| AddressParseError::UnrecognizedStringFormat)?;
+        if data.len() != 25 {
+            return Err(AddressParseError::UnrecognizedStringFormat);
+        }
+        let checksum = sha256d::Hash::hash(&data[..21]);
+        if checksum[..4] != data[21..] {
+            return Err(AddressParseError::InvalidChecksum);
+        }
+        let hash = Bytes20::from_slice(&data[1..21]).expect("fixed length");
+        let (payload, network) = match data[0] {
+            0x00 => (AddressPayload::PubkeyHash(hash), AddressNetwork::Mainnet),
+            0x05 => (AddressPayload::ScriptHash(hash), AddressNetwork::Mainnet),
+            0x6F => (AddressPayload::PubkeyHash(hash), AddressNetwork::Testnet),
+            0xC4 => (AddressPayload::ScriptHash(hash), AddressNetwork::Testnet),
+            _ => return Err(AddressParseError::UnrecognizedAddressNetwork),
+        };
+        Ok(payload.into_address(network))
     }
 }
 
+fn base58check(prefix: u8, hash: &[u8]) -> String {
+    let mut data = Vec::with_capacity(25);
+    data.push(prefix);
+    data.extend(hash);
+    let checksum = sha256d::Hash::hash(&data);
+    data.extend(&checksum[..4]);
+    data.to_base58()
+}
+
 /// Internal address content. Consists of serialized hashes or x-only key value.
 ///
 /// See also `descriptors::Compact` as a non-copy alternative supporting
 /// bare/custom scripts.
-#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, From)]
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, From)]
 pub enum AddressPayload {
     /// P2PKH payload.
     #[display("raw_pkh({0})")]
@@ -137,27 +357,146 @@ pub enum AddressPayload {
         /// Taproot output key (tweaked key)
         output_key: XOnlyPublicKey,
     },
+
+    /// Payload of a future (post-taproot) witness version or of a witness
+    /// program which doesn't match P2WPKH, P2WSH or P2TR.
+    #[display("raw_wp({0:?})")]
+    Future(WitnessProgram),
 }
 
 impl AddressPayload {
     /// Constructs [`Address`] from the payload.
-    pub fn into_address(self, network: bitcoin::Network) -> Address {
+    pub fn into_address(self, network: AddressNetwork) -> Address {
         Address {
-            payload: self.into(),
+            payload: self,
             network,
         }
     }
 
-    /// Constructs payload from a given address. Fails on future (post-taproot)
-    /// witness types with `None`.
-    pub fn from_address(address: Address) -> Option<Self> { Self::from_payload(address.payload) }
+    /// Constructs payload from a witness version and program.
+    ///
+    /// Programs matching P2WPKH, P2WSH or P2TR are represented with the
+    /// dedicated variants; all other programs produce
+    /// [`AddressPayload::Future`].
+    ///
+    /// # Errors
+    ///
+    /// If the program length is outside of 2 to 40 bytes range, or if a v0
+    /// program has length other than 20 or 32 bytes.
+    pub fn with_witness_program(
+        version: WitnessVer,
+        program: Vec<u8>,
+    ) -> Result<Self, SegwitError> {
+        let program = WitnessProgram::new(version.into(), program)?;
+        let data = program.program();
+        Ok(match (version, data.len()) {
+            (WitnessVer::V0, 20) => {
+                AddressPayload::WPubkeyHash(Bytes20::from_slice(data).expect("program length"))
+            }
+            (WitnessVer::V0, _) => {
+                AddressPayload::WScriptHash(Bytes32::from_slice(data).expect("program length"))
+            }
+            (WitnessVer::V1, 32) => match XOnlyPublicKey::from_slice(data) {
+                Ok(output_key) => AddressPayload::Taproot { output_key },
+                Err(_) => AddressPayload::Future(program),
+            },
+            _ => AddressPayload::Future(program),
+        })
+    }
 
-    /// Constructs payload from a given `scriptPubkey`. Fails on future (post-taproot) witness types
-    /// with `None`.
-    pub fn from_script_pubkey(_script: &ScriptPubkey) -> Option<Self> { todo!() }
+    /// Constructs payload from a given address.
+    pub fn from_address(address: Address) -> Self { address.payload }
+
+    /// Constructs payload from a given `scriptPubkey`. Fails with `None` on
+    /// scripts which can't be represented as an address.
+    pub fn from_script_pubkey(script: &ScriptPubkey) -> Option<Self> {
+        let s = &script[..];
+        Some(match classify_script(script) {
+            ScriptType::P2pkh => {
+                AddressPayload::PubkeyHash(Bytes20::from_slice(&s[3..23]).expect("P2PKH length"))
+            }
+            ScriptType::P2sh => {
+                AddressPayload::ScriptHash(Bytes20::from_slice(&s[2..22]).expect("P2SH length"))
+            }
+            ScriptType::P2wpkh => {
+                AddressPayload::WPubkeyHash(Bytes20::from_slice(&s[2..]).expect("P2WPKH length"))
+            }
+            ScriptType::P2wsh => {
+                AddressPayload::WScriptHash(Bytes32::from_slice(&s[2..]).expect("P2WSH length"))
+            }
+            ScriptType::P2tr => {
+                return Self::with_witness_program(WitnessVer::V1, s[2..].to_vec()).ok()
+            }
+            ScriptType::FutureWitness(version) => {
+                return Self::with_witness_program(version, s[2..].to_vec()).ok();
+            }
+            ScriptType::P2pk | ScriptType::OpReturn | ScriptType::Nonstandard => return None,
+        })
+    }
 
     /// Returns script corresponding to the given address.
-    pub fn into_script_pubkey(self) -> ScriptPubkey { todo!() }
+    ///
+    /// Future witness programs are encoded as `OP_n <program>`, matching
+    /// their bech32m address encoding.
+    pub fn into_script_pubkey(self) -> ScriptPubkey {
+        match self {
+            AddressPayload::PubkeyHash(hash) => {
+                let mut script = vec![0x76, 0xA9, 0x14];
+                script.extend(hash.as_slice());
+                script.extend([0x88, 0xAC]);
+                ScriptPubkey::from(ScriptBytes::from(script))
+            }
+            AddressPayload::ScriptHash(hash) => {
+                let mut script = vec![0xA9, 0x14];
+                script.extend(hash.as_slice());
+                script.push(0x87);
+                ScriptPubkey::from(ScriptBytes::from(script))
+            }
+            AddressPayload::WPubkeyHash(hash) => witness_script(WitnessVer::V0, &hash[..]),
+            AddressPayload::WScriptHash(hash) => witness_script(WitnessVer::V0, &hash[..]),
+            AddressPayload::Taproot { output_key } => {
+                witness_script(WitnessVer::V1, &output_key.serialize())
+            }
+            AddressPayload::Future(program) => {
+                witness_script(program.version().into(), program.program())
+            }
+        }
+    }
+}
+
+impl AddressPayload {
+    /// Detects whether the given `scriptPubkey` is the one corresponding to
+    /// the payload.
+    ///
+    /// Compares the script bytes directly, without constructing an
+    /// intermediate address or script.
+    pub fn matches_script(&self, script: &ScriptPubkey) -> bool {
+        let script = &script[..];
+        let (prefix, data, suffix): (&[u8], &[u8], &[u8]) = match self {
+            AddressPayload::PubkeyHash(hash) => (&[0x76, 0xA9, 0x14], &hash[..], &[0x88, 0xAC]),
+            AddressPayload::ScriptHash(hash) => (&[0xA9, 0x14], &hash[..], &[0x87]),
+            AddressPayload::WPubkeyHash(hash) => (&[0x00, 0x14], &hash[..], &[]),
+            AddressPayload::WScriptHash(hash) => (&[0x00, 0x20], &hash[..], &[]),
+            AddressPayload::Taproot { output_key } => {
+                return script.len() == 34
+                    && script[..2] == [0x51, 0x20]
+                    && script[2..] == output_key.serialize();
+            }
+            AddressPayload::Future(program) => {
+                let data = program.program();
+                // Witness programs are at most 40 bytes long and always use
+                // a direct push
+                return script.len() == data.len() + 2
+                    && script[0] == WitnessVer::from(program.version()).op_code()
+                    && script[1] as usize == data.len()
+                    && &script[2..] == data;
+            }
+        };
+        script.len() == prefix.len() + data.len() + suffix.len()
+            && script.starts_with(prefix)
+            && script.ends_with(suffix)
+            && &script[prefix.len()..prefix.len() + data.len()] == data
+    }
 }
 
 impl From<AddressPayload> for ScriptPubkey {
@@ -168,8 +507,8 @@ impl From<AddressPayload> for ScriptPubkey {
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, Error, From)]
 #[display(doc_comments)]
 pub enum AddressParseError {
-    /// unknown address payload prefix `{0}`; expected `pkh`, `sh`, `wpkh`,
-    /// `wsh` and `pkxo` only
+    /// unknown address payload prefix `{0}`; expected `raw_pkh`, `raw_sh`,
+    /// `raw_wpkh`, `raw_wsh` and `raw_tr` only
     UnknownPrefix(String),
 
     /// unrecognized address payload string format
@@ -180,7 +519,7 @@ pub enum AddressParseError {
     PrefixAbsent,
 
     /// wrong address payload data
-    #[from(hex::Error)]
+    #[from(amplify::hex::Error)]
     WrongPayloadHashData,
 
     /// wrong BIP340 public key (xcoord-only)
@@ -195,14 +534,45 @@ pub enum AddressParseError {
     /// `P2WPKH`, `P2WSH`, `P2TR`
     UnrecognizedAddressFormat,
 
-    /// wrong witness version
-    WrongWitnessVersion,
+    /// wrong witness version: {0}
+    #[from]
+    WrongWitnessVersion(WitnessVerError),
+
+    /// bech32 address must be either all-lowercase or all-uppercase
+    MixedCase,
+
+    /// invalid address encoding or checksum
+    InvalidChecksum,
+
+    /// invalid length of the address witness program
+    InvalidWitnessProgram,
 }
 
 impl FromStr for AddressPayload {
     type Err = AddressParseError;
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> { todo!() }
+    /// Parses payload string in the format produced by its `Display`
+    /// implementation, like `raw_wpkh(<hex>)`. Future witness programs can't be
+    /// parsed.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.strip_suffix(')').ok_or(AddressParseError::UnrecognizedStringFormat)?;
+        let (prefix, data) = s.split_once('(').ok_or(AddressParseError::PrefixAbsent)?;
+        // `Array::from_hex` accepts only 32-byte arrays
+        let bytes20 = || {
+            Bytes20::from_slice(Vec::<u8>::from_hex(data)?)
+                .ok_or(AddressParseError::WrongPayloadHashData)
+        };
+        Ok(match prefix {
+            "raw_pkh" => AddressPayload::PubkeyHash(bytes20()?),
+            "raw_sh" => AddressPayload::ScriptHash(bytes20()?),
+            "raw_wpkh" => AddressPayload::WPubkeyHash(bytes20()?),
+            "raw_wsh" => AddressPayload::WScriptHash(Bytes32::from_hex(data)?),
+            "raw_tr" => AddressPayload::Taproot {
+                output_key: XOnlyPublicKey::from_str(data)?,
+            },
+            prefix => return Err(AddressParseError::UnknownPrefix(prefix.to_owned())),
+        })
+    }
 }
 
 /// Address format
@@ -240,34 +610,41 @@ impl AddressFormat {
         match self {
             AddressFormat::P2pkh => None,
             AddressFormat::P2sh => None,
-            AddressFormat::P2wpkh | AddressFormat::P2wsh => Some(WitnessVersion::V0),
-            AddressFormat::P2tr => Some(WitnessVersion::V1),
+            AddressFormat::P2wpkh | AddressFormat::P2wsh => Some(WitnessVer::V0),
+            AddressFormat::P2tr => Some(WitnessVer::V1),
             AddressFormat::Future(ver) => Some(ver),
         }
     }
+
+    /// Detects address format of the `scriptPubkey`. Returns `None` for scripts
+    /// which can't be represented as an address, like P2PK, `OP_RETURN` or
+    /// non-standard scripts.
+    pub fn from_script_pubkey(script: &ScriptPubkey) -> Option<AddressFormat> {
+        Some(match classify_script(script) {
+            ScriptType::P2pkh => AddressFormat::P2pkh,
+            ScriptType::P2sh => AddressFormat::P2sh,
+            ScriptType::P2wpkh => AddressFormat::P2wpkh,
+            ScriptType::P2wsh => AddressFormat::P2wsh,
+            ScriptType::P2tr => AddressFormat::P2tr,
+            ScriptType::FutureWitness(ver) => AddressFormat::Future(ver),
+            ScriptType::P2pk | ScriptType::OpReturn | ScriptType::Nonstandard => return None,
+        })
+    }
 }
 
 impl From<Address> for AddressFormat {
     fn from(address: Address) -> Self { address.payload.into() }
 }
 
-impl From<Payload> for AddressFormat {
-    fn from(payload: Payload) -> Self {
+impl From<AddressPayload> for AddressFormat {
+    fn from(payload: AddressPayload) -> Self {
         match payload {
-            Payload::PubkeyHash(_) => AddressFormat::P2pkh,
-            Payload::ScriptHash(_) => AddressFormat::P2sh,
-            Payload::WitnessProgram { version, program }
-                if version.to_num() == 0 && program.len() == 32 =>
-            {
-                AddressFormat::P2wsh
-            }
-            Payload::WitnessProgram { version, program }
-                if version.to_num() == 0 && program.len() == 20 =>
-            {
-                AddressFormat::P2wpkh
-            }
-            Payload::WitnessProgram { version, .. } if version.to_num() == 1 => AddressFormat::P2tr,
-            Payload::WitnessProgram { version, .. } => AddressFormat::Future(version),
+            AddressPayload::PubkeyHash(_) => AddressFormat::P2pkh,
+            AddressPayload::ScriptHash(_) => AddressFormat::P2sh,
+            AddressPayload::WPubkeyHash(_) => AddressFormat::P2wpkh,
+            AddressPayload::WScriptHash(_) => AddressFormat::P2wsh,
+            AddressPayload::Taproot { .. } => AddressFormat::P2tr,
+            AddressPayload::Future(program) => AddressFormat::Future(program.version().into()),
         }
     }
 }
@@ -283,10 +660,7 @@ impl FromStr for AddressFormat {
             "P2WPKH" => AddressFormat::P2wpkh,
             "P2WSH" => AddressFormat::P2wsh,
             "P2TR" => AddressFormat::P2tr,
-            s if s.starts_with("P2W") => AddressFormat::Future(
-                WitnessVersion::from_str(&s[3..])
-                    .map_err(|_| AddressParseError::WrongWitnessVersion)?,
-            ),
+            s if s.starts_with("P2W") => AddressFormat::Future(WitnessVer::from_str(&s[3..])?),
             _ => return Err(AddressParseError::UnrecognizedAddressFormat),
         })
     }
@@ -322,34 +696,319 @@ impl FromStr for AddressNetwork {
 }
 
 impl From<Address> for AddressNetwork {
-    fn from(address: Address) -> Self { address.network.into() }
+    fn from(address: Address) -> Self { address.network }
 }
 
-impl From<bitcoin::Network> for AddressNetwork {
-    fn from(network: bitcoin::Network) -> Self {
-        match network {
-            bitcoin::Network::Bitcoin => AddressNetwork::Mainnet,
-            bitcoin::Network::Testnet => AddressNetwork::Testnet,
-            bitcoin::Network::Signet => AddressNetwork::Testnet,
-            bitcoin::Network::Regtest => AddressNetwork::Regtest,
+/// The conversion is lossy: both testnet and signet are mapped into
+/// [`AddressNetwork::Testnet`], since their addresses are indistinguishable.
+/// Use [`AddressNetwork::to_chain_with`] to convert back.
+impl From<Chain> for AddressNetwork {
+    fn from(chain: Chain) -> Self {
+        match chain {
+            Chain::Bitcoin => AddressNetwork::Mainnet,
+            Chain::Testnet3 => AddressNetwork::Testnet,
+            Chain::Signet => AddressNetwork::Testnet,
+            Chain::Regtest => AddressNetwork::Regtest,
         }
     }
 }
 
 impl AddressNetwork {
-    /// This convertor is not public since there is an ambiguity which type
-    /// must correspond to the [`AddressNetwork::Testnet`]. Thus, clients of
-    /// this library must propvide their custom convertors taking decisions
-    /// on this question.
-    fn bitcoin_network(self) -> bitcoin::Network {
+    /// Converts into bitcoin chain, using `signet` flag to resolve the
+    /// ambiguity of [`AddressNetwork::Testnet`], which covers both testnet and
+    /// signet. The flag is ignored for other networks.
+    pub fn to_chain_with(self, signet: bool) -> Chain {
+        match self {
+            AddressNetwork::Mainnet => Chain::Bitcoin,
+            AddressNetwork::Testnet if signet => Chain::Signet,
+            AddressNetwork::Testnet => Chain::Testnet3,
+            AddressNetwork::Regtest => Chain::Regtest,
+        }
+    }
+
+    /// Human-readable part of bech32 addresses for the network.
+    fn bech32_hrp(self) -> &'static str {
+        match self {
+            AddressNetwork::Mainnet => "bc",
+            AddressNetwork::Testnet => "tb",
+            AddressNetwork::Regtest => "bcrt",
+        }
+    }
+
+    /// Version byte of base58 P2PKH addresses; regtest shares it with testnet.
+    fn pubkey_hash_prefix(self) -> u8 {
         match self {
-            AddressNetwork::Mainnet => bitcoin::Network::Bitcoin,
-            AddressNetwork::Testnet => bitcoin::Network::Testnet,
-            AddressNetwork::Regtest => bitcoin::Network::Regtest,
+            AddressNetwork::Mainnet => 0x00,
+            AddressNetwork::Testnet | AddressNetwork::Regtest => 0x6F,
         }
     }
 
+    /// Version byte of base58 P2SH addresses; regtest shares it with testnet.
+    fn script_hash_prefix(self) -> u8 {
+        match self {
+            AddressNetwork::Mainnet => 0x05,
+            AddressNetwork::Testnet | AddressNetwork::Regtest => 0xC4,
+        }
+    }
+
+    /// Detects whether the network is bitcoin mainnet.
+    pub fn is_mainnet(self) -> bool { self == Self::Mainnet }
+
     /// Detects whether the network is a kind of test network (testnet, signet,
     /// regtest).
     pub fn is_testnet(self) -> bool { self != Self::Mainnet }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn script(hex: &str) -> ScriptPubkey {
+        ScriptPubkey::from(ScriptBytes::from(Vec::<u8>::from_hex(hex).unwrap()))
+    }
+
+    fn ver(ver: u8) -> WitnessVer { WitnessVer::try_from(ver).unwrap() }
+
+    #[test]
+    fn future_witness_program() {
+        let payload = AddressPayload::with_witness_program(ver(2), vec![0xAB; 40]).unwrap();
+        assert!(matches!(payload, AddressPayload::Future(_)));
+        let script_pubkey = payload.clone().into_script_pubkey();
+        let mut expected = vec![0x52, 0x28];
+        expected.extend([0xAB; 40]);
+        assert_eq!(&script_pubkey[..], &expected[..]);
+        assert!(payload.matches_script(&script_pubkey));
+        assert_eq!(AddressPayload::from_script_pubkey(&script_pubkey), Some(payload));
+
+        let payload = AddressPayload::with_witness_program(ver(16), vec![0x75, 0x1e]).unwrap();
+        assert_eq!(payload.clone().into_script_pubkey(), script("6002751e"));
+
+        // Taproot version with a non-taproot program length
+        let payload = AddressPayload::with_witness_program(ver(1), vec![0x01; 20]).unwrap();
+        assert!(matches!(payload, AddressPayload::Future(_)));
+        assert!(payload.matches_script(&payload.clone().into_script_pubkey()));
+    }
+
+    #[test]
+    fn witness_program_length() {
+        for version in [0, 1, 2, 16] {
+            assert!(AddressPayload::with_witness_program(ver(version), vec![0; 1]).is_err());
+            assert!(AddressPayload::with_witness_program(ver(version), vec![0; 41]).is_err());
+        }
+        assert!(AddressPayload::with_witness_program(ver(2), vec![0; 2]).is_ok());
+        assert!(AddressPayload::with_witness_program(WitnessVer::V0, vec![0; 25]).is_err());
+        assert!(matches!(
+            AddressPayload::with_witness_program(WitnessVer::V0, vec![0; 20]),
+            Ok(AddressPayload::WPubkeyHash(_))
+        ));
+        assert!(matches!(
+            AddressPayload::with_witness_program(WitnessVer::V0, vec![0; 32]),
+            Ok(AddressPayload::WScriptHash(_))
+        ));
+    }
+
+    #[test]
+    fn payload_script_round_trip() {
+        let scripts = [
+            "76a914751e76e8199196d454941c45d1b3a323f1433bd688ac",
+            "a914751e76e8199196d454941c45d1b3a323f1433bd687",
+            "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+            "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262",
+            "512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        ];
+        for hex in scripts {
+            let script_pubkey = script(hex);
+            let payload = AddressPayload::from_script_pubkey(&script_pubkey).unwrap();
+            assert!(payload.matches_script(&script_pubkey));
+            assert_eq!(payload.into_script_pubkey(), script_pubkey);
+        }
+        assert!(matches!(
+            AddressPayload::from_script_pubkey(&script(scripts[4])),
+            Some(AddressPayload::Taproot { .. })
+        ));
+        assert_eq!(AddressPayload::from_script_pubkey(&script("6a0401020304")), None);
+    }
+
+    #[test]
+    fn network_chain_round_trip() {
+        for chain in [Chain::Bitcoin, Chain::Testnet3, Chain::Signet, Chain::Regtest] {
+            let network = AddressNetwork::from(chain);
+            assert_eq!(network.to_chain_with(chain == Chain::Signet), chain);
+            assert_eq!(network.is_mainnet(), chain == Chain::Bitcoin);
+            assert_eq!(network.is_testnet(), chain != Chain::Bitcoin);
+        }
+        // Signet can't be recovered without the flag
+        assert_eq!(AddressNetwork::from(Chain::Signet).to_chain_with(false), Chain::Testnet3);
+        assert_eq!(AddressNetwork::Mainnet.to_chain_with(true), Chain::Bitcoin);
+        assert_eq!(AddressNetwork::Regtest.to_chain_with(true), Chain::Regtest);
+    }
+
+    #[test]
+    fn format_from_script_pubkey() {
+        let formats = [
+            ("76a914751e76e8199196d454941c45d1b3a323f1433bd688ac", AddressFormat::P2pkh),
+            ("a914751e76e8199196d454941c45d1b3a323f1433bd687", AddressFormat::P2sh),
+            ("0014751e76e8199196d454941c45d1b3a323f1433bd6", AddressFormat::P2wpkh),
+            (
+                "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262",
+                AddressFormat::P2wsh,
+            ),
+            (
+                "512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+                AddressFormat::P2tr,
+            ),
+            ("6002751e", AddressFormat::Future(ver(16))),
+            ("5114751e76e8199196d454941c45d1b3a323f1433bd6", AddressFormat::Future(ver(1))),
+        ];
+        for (hex, format) in formats {
+            let script_pubkey = script(hex);
+            assert_eq!(AddressFormat::from_script_pubkey(&script_pubkey), Some(format));
+            let payload = AddressPayload::from_script_pubkey(&script_pubkey).unwrap();
+            assert_eq!(AddressFormat::from(payload), format);
+        }
+        for hex in [
+            "6a0401020304",
+            "2102751e76e8199196d454941c45d1b3a323f1433bd6751e76e8199196d454941cac",
+            "51",
+        ] {
+            assert_eq!(AddressFormat::from_script_pubkey(&script(hex)), None);
+        }
+    }
+
+    #[test]
+    fn address_string_round_trip() {
+        let addresses = [
+            (
+                "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2",
+                AddressNetwork::Mainnet,
+                "76a91477bff20c60e522dfaa3350c39b030a5d004e839a88ac",
+            ),
+            (
+                "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy",
+                AddressNetwork::Mainnet,
+                "a914b472a266d0bd89c13706a4132ccfb16f7c3b9fcb87",
+            ),
+            (
+                "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn",
+                AddressNetwork::Testnet,
+                "76a914243f1394f44554f4ce3fd68649c19adc483ce92488ac",
+            ),
+            (
+                "2MzQwSSnBHWHqSAqtTVQ6v47XtaisrJa1Vc",
+                AddressNetwork::Testnet,
+                "a9144e9f39ca4688ff102128ea4ccda34105324305b087",
+            ),
+            (
+                "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+                AddressNetwork::Mainnet,
+                "0014e8df018c7e326cc253faac7e46cdc51e68542c42",
+            ),
+            (
+                "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7",
+                AddressNetwork::Testnet,
+                "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262",
+            ),
+            (
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+                AddressNetwork::Mainnet,
+                "512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+            ),
+            (
+                "bcrt1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqc8gma6",
+                AddressNetwork::Regtest,
+                "512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+            ),
+            ("bc1sw50qgdz25j", AddressNetwork::Mainnet, "6002751e"),
+        ];
+        for (s, network, hex) in addresses {
+            let address = Address::from_str(s).unwrap();
+            assert_eq!(address.network, network);
+            assert_eq!(address.script_pubkey(), script(hex));
+            assert_eq!(Address::from_script(&script(hex), network), Some(address.clone()));
+            assert_eq!(address.to_string(), s);
+        }
+    }
+
+    #[test]
+    fn payload_string_round_trip() {
+        let scripts = [
+            "76a914751e76e8199196d454941c45d1b3a323f1433bd688ac",
+            "a914751e76e8199196d454941c45d1b3a323f1433bd687",
+            "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+            "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262",
+            "512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        ];
+        for hex in scripts {
+            let payload = AddressPayload::from_script_pubkey(&script(hex)).unwrap();
+            assert_eq!(AddressPayload::from_str(&payload.to_string()), Ok(payload));
+        }
+        assert_eq!(
+            AddressPayload::from_str("raw_wpkh(751e76e8199196d454941c45d1b3a323f1433bd6)"),
+            Ok(AddressPayload::WPubkeyHash(
+                Bytes20::from_slice(
+                    Vec::<u8>::from_hex("751e76e8199196d454941c45d1b3a323f1433bd6").unwrap()
+                )
+                .unwrap()
+            ))
+        );
+        assert_eq!(
+            AddressPayload::from_str("pkxo(751e76e8199196d454941c45d1b3a323f1433bd6)"),
+            Err(AddressParseError::UnknownPrefix(s!("pkxo")))
+        );
+        assert_eq!(
+            AddressPayload::from_str("751e76e8199196d454941c45d1b3a323f1433bd6"),
+            Err(AddressParseError::UnrecognizedStringFormat)
+        );
+        assert_eq!(AddressPayload::from_str("raw_wpkh)"), Err(AddressParseError::PrefixAbsent));
+        assert_eq!(
+            AddressPayload::from_str("raw_wsh(751e76e8199196d454941c45d1b3a323f1433bd6)"),
+            Err(AddressParseError::WrongPayloadHashData)
+        );
+    }
+
+    #[test]
+    fn uppercase_address() {
+        let address = Address::from_str("BC1SW50QGDZ25J").unwrap();
+        assert_eq!(address.to_string(), "bc1sw50qgdz25j");
+        assert_eq!(address.to_string_uppercase(), "BC1SW50QGDZ25J");
+
+        let s = "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0";
+        let address = Address::from_str(s).unwrap();
+        assert_eq!(Address::from_str(&s.to_ascii_uppercase()), Ok(address.clone()));
+        assert_eq!(address.to_string_uppercase(), s.to_ascii_uppercase());
+
+        // Base58 addresses are case-sensitive and are not uppercased
+        let s = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+        assert_eq!(Address::from_str(s).unwrap().to_string_uppercase(), s);
+    }
+
+    #[test]
+    fn invalid_address() {
+        assert_eq!(
+            Address::from_str("bc1Qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"),
+            Err(AddressParseError::MixedCase)
+        );
+        assert_eq!(
+            Address::from_str("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdp"),
+            Err(AddressParseError::InvalidChecksum)
+        );
+        // Witness v0 with bech32m checksum and v1 with bech32 checksum
+        assert!(Address::from_str("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4").is_ok());
+        assert_eq!(
+            Address::from_str("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kemeawh"),
+            Err(AddressParseError::InvalidChecksum)
+        );
+        assert_eq!(
+            Address::from_str("bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqh2y7hd"),
+            Err(AddressParseError::InvalidChecksum)
+        );
+        assert_eq!(
+            Address::from_str("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN3"),
+            Err(AddressParseError::InvalidChecksum)
+        );
+        assert_eq!(
+            Address::from_str("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN0"),
+            Err(AddressParseError::UnrecognizedStringFormat)
+        );
+    }
+}
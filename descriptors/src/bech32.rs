@@ -0,0 +1,138 @@
+// Bitcoin descriptors implementation
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bech32 and bech32m encoding of segwit addresses (BIP-173, BIP-350).
+
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+
+/// Maximal length of a segwit address string.
+const MAX_LEN: usize = 90;
+
+/// Checksum constant of the bech32 variant, used by witness version 0.
+const BECH32_CONST: u32 = 1;
+
+/// Checksum constant of the bech32m variant, used by witness versions 1 to 16.
+const BECH32M_CONST: u32 = 0x2bc830a3;
+
+fn checksum_const(version: u8) -> u32 {
+    if version == 0 {
+        BECH32_CONST
+    } else {
+        BECH32M_CONST
+    }
+}
+
+fn polymod(values: impl IntoIterator<Item = u8>) -> u32 {
+    values.into_iter().fold(1u32, |chk, value| {
+        let top = chk >> 25;
+        GENERATOR
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| (top >> i) & 1 == 1)
+            .fold((chk & 0x1ffffff) << 5 ^ value as u32, |chk, (_, gen)| chk ^ gen)
+    })
+}
+
+fn hrp_expand(hrp: &str) -> impl Iterator<Item = u8> + '_ {
+    hrp.bytes().map(|b| b >> 5).chain([0]).chain(hrp.bytes().map(|b| b & 0x1f))
+}
+
+/// Regroups bits of `data` from `from`-bit into `to`-bit values. Without
+/// `pad` fails with `None` if the remaining bits are not a valid padding.
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Option<Vec<u8>> {
+    let mut acc = 0u32;
+    let mut bits = 0u32;
+    let mask = (1u32 << to) - 1;
+    let max_acc = (1u32 << (from + to - 1)) - 1;
+    let mut out = Vec::with_capacity(data.len() * from as usize / to as usize + 1);
+    for value in data {
+        acc = ((acc << from) | *value as u32) & max_acc;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            out.push(((acc >> bits) & mask) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            out.push(((acc << (to - bits)) & mask) as u8);
+        }
+    } else if bits >= from || (acc << (to - bits)) & mask != 0 {
+        return None;
+    }
+    Some(out)
+}
+
+/// Encodes witness program as a lowercase segwit address with the given
+/// human-readable part, using bech32 for version 0 and bech32m for other
+/// versions.
+pub(crate) fn encode(hrp: &str, version: u8, program: &[u8]) -> String {
+    let mut data = vec![version];
+    data.extend(convert_bits(program, 8, 5, true).expect("padding is always valid"));
+    let checksum = polymod(hrp_expand(hrp).chain(data.iter().copied()).chain([0; 6]))
+        ^ checksum_const(version);
+    data.extend((0..6).map(|i| ((checksum >> (5 * (5 - i))) & 0x1f) as u8));
+
+    let mut s = String::with_capacity(hrp.len() + 1 + data.len());
+    s.push_str(hrp);
+    s.push('1');
+    s.extend(data.into_iter().map(|value| CHARSET[value as usize] as char));
+    s
+}
+
+/// Decodes segwit address into its lowercase human-readable part, witness
+/// version and program.
+///
+/// The string must be either all-lowercase or all-uppercase; mixed case
+/// strings must be rejected by the caller. Fails with `None` if the string is
+/// not a valid bech32 string or if its checksum variant doesn't match the
+/// witness version.
+pub(crate) fn decode(s: &str) -> Option<(String, u8, Vec<u8>)> {
+    if s.len() > MAX_LEN {
+        return None;
+    }
+    let s = s.to_ascii_lowercase();
+    let (hrp, data) = s.rsplit_once('1')?;
+    if hrp.is_empty() || data.len() < 7 || !hrp.bytes().all(|b| (33..=126).contains(&b)) {
+        return None;
+    }
+    let values = data
+        .bytes()
+        .map(|b| CHARSET.iter().position(|c| *c == b).map(|pos| pos as u8))
+        .collect::<Option<Vec<_>>>()?;
+    let version = values[0];
+    if version > 16
+        || polymod(hrp_expand(hrp).chain(values.iter().copied())) != checksum_const(version)
+    {
+        return None;
+    }
+    let program = convert_bits(&values[1..values.len() - 6], 5, 8, false)?;
+    Some((hrp.to_owned(), version, program))
+}
+
+/// Detects whether the string mixes lowercase and uppercase characters, which
+/// is not allowed by BIP-173.
+pub(crate) fn is_mixed_case(s: &str) -> bool {
+    s.bytes().any(|b| b.is_ascii_lowercase()) && s.bytes().any(|b| b.is_ascii_uppercase())
+}
@@ -20,9 +20,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use ::derive::{ChildIdxTerm, DerivationIndex, DerivationPath, NormIdx, TermPath};
 use bc::ScriptPubkey;
+use psbt::SpendInfo;
 
-use crate::addr::Address;
+use crate::addr::{Address, AddressNetwork};
 use crate::keys::DescrKey;
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
@@ -33,10 +35,59 @@ pub enum DerivatorError {
 
     /// attempt to derive key using variable index at unknown position {0}.
     UnknownPosition(u8),
+
+    /// derivation index overflows the range of normal indexes.
+    IndexOverflow,
+
+    /// script derived at index {0} can't be represented as an address.
+    NoAddress(NormIdx),
+
+    /// multipath derivation step has no variant number {0}.
+    NoMultipathVariant(NormIdx),
 }
 
 pub trait TerminalDerivator {
-    fn index_at(&self, pos: u8) -> Result<NormIndex, DerivatorError>;
+    fn index_at(&self, pos: u8) -> Result<NormIdx, DerivatorError>;
+
+    /// Resolves wildcards of the path template, taking the index for the n-th
+    /// wildcard from the position n of the derivator.
+    fn resolve_path(&self, path: &TermPath) -> Result<DerivationPath, DerivatorError> {
+        let mut pos = 0u8;
+        let mut resolved = Vec::with_capacity(path.len());
+        for term in path.iter() {
+            let index = match term {
+                ChildIdxTerm::Fixed(index) => *index,
+                wildcard => {
+                    let index = wildcard.resolve(self.index_at(pos)?);
+                    pos = pos.checked_add(1).ok_or(DerivatorError::TooDeep)?;
+                    index
+                }
+            };
+            resolved.push(index);
+        }
+        Ok(DerivationPath::from(resolved))
+    }
+}
+
+/// Terminal derivator for the standard two-component wallet derivation
+/// terminals, consisting of the change (keychain) index followed by the address
+/// index.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct DualIndex {
+    /// Change (keychain) index; 0 for receiving and 1 for change addresses.
+    pub change: NormIdx,
+    /// Address index.
+    pub index: NormIdx,
+}
+
+impl TerminalDerivator for DualIndex {
+    fn index_at(&self, pos: u8) -> Result<NormIdx, DerivatorError> {
+        match pos {
+            0 => Ok(self.change),
+            1 => Ok(self.index),
+            _ => Err(DerivatorError::UnknownPosition(pos)),
+        }
+    }
 }
 
 pub trait ConcretePubkey {}
@@ -58,20 +109,66 @@ pub trait Descriptor<Key> {
         Key: DerivePubkey;
 
     fn script_pubkey(&self) -> ScriptPubkey
-    where Self::Key: ConcretePubkey;
+    where Key: ConcretePubkey;
 
+    /// Derives address for the given network; returns `None` if the derived
+    /// script can't be represented as an address.
     fn derive_address(
         &self,
         derivator: &impl TerminalDerivator,
-    ) -> Result<ScriptPubkey, DerivatorError>
+        network: AddressNetwork,
+    ) -> Result<Option<Address>, DerivatorError>
     where
         Key: DerivePubkey,
     {
-        self.derive_script_pubkey(derivator).map(Address::into)
+        self.derive_script_pubkey(derivator).map(|script| Address::from_script(&script, network))
     }
 
-    fn address(&self) -> Address
-    where Self::Key: ConcretePubkey {
-        self.script_pubkey().into()
+    /// Returns address for the given network; returns `None` if the script
+    /// can't be represented as an address.
+    fn address(&self, network: AddressNetwork) -> Option<Address>
+    where Key: ConcretePubkey {
+        Address::from_script(&self.script_pubkey(), network)
     }
+
+    /// Derives `count` consecutive addresses on the `change` branch, starting
+    /// from the `start` index.
+    ///
+    /// Since descriptors do not commit to a network, the network used for the
+    /// addresses must be provided by the caller.
+    fn scan_addresses(
+        &self,
+        change: NormIdx,
+        start: NormIdx,
+        count: u32,
+        network: AddressNetwork,
+    ) -> Result<Vec<Address>, DerivatorError>
+    where
+        Key: DerivePubkey,
+    {
+        let mut addresses = Vec::with_capacity(count as usize);
+        for offset in 0..count {
+            let index = start.checked_add(offset).ok_or(DerivatorError::IndexOverflow)?;
+            let address = self
+                .derive_address(&DualIndex { change, index }, network)?
+                .ok_or(DerivatorError::NoAddress(index))?;
+            addresses.push(address);
+        }
+        Ok(addresses)
+    }
+}
+
+/// Descriptors providing PSBT updaters with the data on spending their outputs.
+///
+/// Kept apart from [`Descriptor`], so the updater requires only the spending
+/// data and not the ability to construct `scriptPubkey`s.
+pub trait DeriveSpendInfo<Key> {
+    /// Derives the scripts, key origins and taproot data which PSBT updaters
+    /// put into the inputs spending outputs of the descriptor.
+    fn derive_spend_info(
+        &self,
+        derivator: &impl TerminalDerivator,
+    ) -> Result<SpendInfo, DerivatorError>
+    where
+        Key: DerivePubkey;
 }
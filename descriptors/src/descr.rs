@@ -20,32 +20,239 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::fmt::{self, Display, Formatter};
+use std::marker::PhantomData;
+
 use amplify::confinement::TinyVec;
+use amplify::hex::ToHex;
+use bc::{InternalPk, ScriptPubkey, TapNodeHash};
 
-use crate::addr::Address;
-use crate::expr::{KeyExpr, ScriptExpr, TapScriptExpr, TapretExpr, TreeExpr, WScriptExpr};
-use crate::keys::{CompressedKey, DescrKey, XonlyKey};
+use crate::addr::{Address, WitnessVer};
+use crate::expr::{KeyExpr, ScriptExpr, TapScriptExpr, TreeExpr, WScriptExpr};
+#[cfg(feature = "tapret")]
+use crate::expr::{TapretCommitment, TapretExpr};
+use crate::keys::{CompressedKey, DescrKey, XonlyKey, XonlyPk};
+use crate::scripts::witness_script;
 
-pub struct Pk<K>(KeyExpr<K>);
+pub struct Pk<K: DescrKey>(KeyExpr<K>);
 
-pub struct Sh<K, S: ScriptExpr<K>>(S);
+pub struct Sh<K: DescrKey, S: ScriptExpr<K>>(S, PhantomData<K>);
 
 pub struct Wpk<K: CompressedKey>(KeyExpr<K>);
 
-pub struct Wsh<K: CompressedKey, S: ScriptExpr<K>>(S);
+pub struct Wsh<K: CompressedKey, S: ScriptExpr<K>>(S, PhantomData<K>);
+
+pub struct Tr<K: XonlyKey, S: TapScriptExpr<K>>(
+    KeyExpr<K>,
+    Option<TreeExpr<S, K>>,
+    #[cfg(feature = "tapret")] TapretExpr,
+);
+
+impl<K: XonlyKey, S: TapScriptExpr<K>> Tr<K, S> {
+    /// Constructs taproot descriptor from the internal key and an optional
+    /// script tree.
+    pub fn new(internal_key: KeyExpr<K>, tree: Option<TreeExpr<S, K>>) -> Self {
+        Tr(
+            internal_key,
+            tree,
+            #[cfg(feature = "tapret")]
+            TapretExpr::default(),
+        )
+    }
 
-pub struct Tr<K: XonlyKey, S: TapScriptExpr<K>>(KeyExpr<K>, Option<TreeExpr<S, K>>, TapretExpr);
+    /// Adds tapret commitment to the descriptor, replacing the existing one.
+    #[cfg(feature = "tapret")]
+    pub fn with_tapret(mut self, commitment: TapretCommitment) -> Self {
+        self.2 = TapretExpr::with(commitment);
+        self
+    }
 
-pub struct Multi<K>(u8, TinyVec<K>);
-impl<K> ScriptExpr<K> for Multi<K> {}
+    /// Returns tapret commitment of the descriptor, if any.
+    #[cfg(feature = "tapret")]
+    pub fn tapret(&self) -> Option<TapretCommitment> { self.2.commitment() }
+}
+
+impl<S: TapScriptExpr<XonlyPk>> Tr<XonlyPk, S> {
+    /// Constructs `scriptPubkey` of the descriptor.
+    ///
+    /// Script expressions are not compiled by this crate, so the merkle root
+    /// of the script tree must be provided by the caller. With `tapret`
+    /// feature, the output key additionally commits to the tapret leaf.
+    pub fn script_pubkey(&self, tree_root: Option<TapNodeHash>) -> ScriptPubkey {
+        #[cfg(feature = "tapret")]
+        let tree_root = self.2.merkle_root(tree_root);
+        // `bc` uses its own version of secp256k1
+        let internal_key = bc::secp256k1::XOnlyPublicKey::from_slice(&self.0.key.serialize())
+            .expect("x-only key is always valid");
+        let output_key = InternalPk::from(internal_key).to_output_key(tree_root);
+        // `ScriptPubkey::p2tr` can't be used, since `bc` fails to produce
+        // `OP_1` op code
+        witness_script(WitnessVer::V1, &output_key.serialize())
+    }
+}
+
+/// Multisig script requiring `threshold` of the keys, written in descriptors as
+/// `multi(2,<key>,<key>,...)`.
+///
+/// The keys may be of any [`DescrKey`] type, including [`crate::AnyKey`] for
+/// multisigs mixing single keys and extended keys.
+pub struct Multi<K: DescrKey>(u8, TinyVec<K>);
+impl<K: DescrKey> ScriptExpr<K> for Multi<K> {}
 impl<K: CompressedKey> WScriptExpr<K> for Multi<K> {}
 
-pub struct SortedMulti<K>(u8, TinyVec<K>);
-impl<K> ScriptExpr<K> for SortedMulti<K> {}
+impl<K: DescrKey> Multi<K> {
+    /// Constructs multisig with the given threshold, returning `None` if the
+    /// threshold is zero or exceeds the number of keys.
+    pub fn new(threshold: u8, keys: TinyVec<K>) -> Option<Self> {
+        if threshold == 0 || threshold as usize > keys.len() {
+            return None;
+        }
+        Some(Multi(threshold, keys))
+    }
+}
+
+impl<K: DescrKey> Display for Multi<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "multi({}", self.0)?;
+        for key in &self.1 {
+            write!(f, ",{key}")?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// Multisig script requiring `threshold` of the keys, which are sorted
+/// lexicographically by their serialization when the script is constructed;
+/// written in descriptors as `sortedmulti(2,<key>,<key>,...)`.
+pub struct SortedMulti<K: DescrKey>(u8, TinyVec<K>);
+impl<K: DescrKey> ScriptExpr<K> for SortedMulti<K> {}
 impl<K: CompressedKey> WScriptExpr<K> for SortedMulti<K> {}
 
+impl<K: DescrKey> SortedMulti<K> {
+    /// Constructs multisig with the given threshold, returning `None` if the
+    /// threshold is zero or exceeds the number of keys.
+    pub fn new(threshold: u8, keys: TinyVec<K>) -> Option<Self> {
+        if threshold == 0 || threshold as usize > keys.len() {
+            return None;
+        }
+        Some(SortedMulti(threshold, keys))
+    }
+}
+
+impl<K: DescrKey> Display for SortedMulti<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "sortedmulti({}", self.0)?;
+        for key in &self.1 {
+            write!(f, ",{key}")?;
+        }
+        f.write_str(")")
+    }
+}
+
 pub struct Combo<K: DescrKey>(K);
 
-pub struct Raw(Vec<u8>);
+/// Raw `scriptPubkey`, written in descriptors as `raw(<hex>)`.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, From)]
+pub struct Raw(#[from] Vec<u8>);
+
+impl Display for Raw {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { write!(f, "raw({})", self.0.to_hex()) }
+}
+
+/// Address `scriptPubkey`, written in descriptors as `addr(<address>)`.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, From)]
+pub struct Addr(#[from] Address);
+
+impl Display for Addr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { write!(f, "addr({})", self.0) }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::expr::ScriptExpr;
+    use crate::scripts::{classify_script, ScriptType};
+
+    struct Script;
+    impl ScriptExpr<XonlyPk> for Script {}
+    impl TapScriptExpr<XonlyPk> for Script {}
+
+    fn tr() -> Tr<XonlyPk, Script> {
+        let key =
+            XonlyPk::from_str("79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+                .unwrap();
+        Tr::new(
+            KeyExpr {
+                origin: None,
+                key,
+                terminal: vec![],
+            },
+            None,
+        )
+    }
+
+    fn output_key(script: ScriptPubkey) -> String {
+        assert_eq!(classify_script(&script), ScriptType::P2tr);
+        script[2..].to_hex()
+    }
+
+    #[test]
+    fn raw_and_addr_display() {
+        assert_eq!(Raw::from(vec![0x6a, 0x01, 0xff]).to_string(), "raw(6a01ff)");
+        let address = Address::from_str("bc1sw50qgdz25j").unwrap();
+        assert_eq!(Addr::from(address).to_string(), "addr(bc1sw50qgdz25j)");
+    }
+
+    #[test]
+    fn tr_key_path() {
+        assert_eq!(
+            output_key(tr().script_pubkey(None)),
+            "da4710964f7852695de2da025290e24af6d8c281de5a0b902b7135fd9fd74d21"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "tapret")]
+    fn tapret_tweak() {
+        use amplify::hex::FromHex;
+        use bc::TapLeafHash;
+
+        use crate::expr::TAPRET_SCRIPT_LEN;
+
+        let commitment = TapretCommitment {
+            mpc: [0x11; 32].into(),
+            nonce: 0,
+        };
+        let script = commitment.leaf_script();
+        assert_eq!(script.len(), TAPRET_SCRIPT_LEN);
+        assert_eq!(script[..29], [0x50; 29]);
+        assert_eq!(script[29..31], [0x6a, 0x21]);
+        assert_eq!(script[63], commitment.nonce);
+        assert_eq!(
+            commitment.leaf_hash(),
+            TapLeafHash::from(
+                <[u8; 32]>::from_hex(
+                    "cb32387624e43a4673bae167754c06fe8bd0eda0404b96e2857fab04b51586f4"
+                )
+                .unwrap()
+            )
+        );
 
-pub struct Addr(Address);
+        let tr = tr();
+        assert_eq!(tr.tapret(), None);
+        let tr = tr.with_tapret(commitment);
+        assert_eq!(tr.tapret(), Some(commitment));
+        // Tapret leaf is the only leaf of the tree
+        assert_eq!(
+            output_key(tr.script_pubkey(None)),
+            "592b0376b18bb6ede1d70b3c9b399696343e445b1fabbfc3cda2854152f2e97e"
+        );
+        // Tapret leaf is a sibling of the tree root
+        assert_eq!(
+            output_key(tr.script_pubkey(Some(TapNodeHash::from([0x22; 32])))),
+            "852646bc82e6a82a2acf139a2540b812c8e761ec594dda0cb734eedbae4d1459"
+        );
+    }
+}
@@ -22,35 +22,432 @@
 
 //! Standard expressions used by descriptors
 
-use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::marker::PhantomData;
+use std::str::FromStr;
 
+use ::derive::{DerivationIndex, DerivationPath, Fingerprint, NormIdx, Xpub};
+#[cfg(feature = "tapret")]
+use amplify::Bytes32;
 use bc::TapNodeHash;
+#[cfg(feature = "tapret")]
+use bc::{ScriptBytes, TapBranchHash, TapLeafHash, TapScript};
 
-use crate::keys::{CompressedKey, DescrKey, XonlyKey};
+use crate::derive::{DerivatorError, DerivePubkey, TerminalDerivator};
+use crate::keys::{CompressedKey, CompressedPk, DescrKey, KeyParseError, XonlyKey};
 
+/// Origin of a key: fingerprint of the master key and the derivation path from
+/// it, written in descriptors as `[d34db33f/84h/0h/0h]`.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
 pub struct KeyOrigin {
     pub master_fp: Fingerprint,
     pub derivation: DerivationPath,
 }
 
+impl Display for KeyOrigin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}", self.master_fp)?;
+        if !self.derivation.is_empty() {
+            write!(f, "/{}", self.derivation)?;
+        }
+        f.write_str("]")
+    }
+}
+
+impl FromStr for KeyOrigin {
+    type Err = KeyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let inner = s
+            .strip_prefix('[')
+            .and_then(|s| s.strip_suffix(']'))
+            .ok_or_else(|| KeyParseError::InvalidOrigin(s.to_owned()))?;
+        let (fp, path) = inner.split_once('/').unwrap_or((inner, ""));
+        let master_fp =
+            Fingerprint::from_str(fp).map_err(|_| KeyParseError::InvalidOrigin(s.to_owned()))?;
+        let derivation = DerivationPath::from_str(path)?;
+        Ok(KeyOrigin {
+            master_fp,
+            derivation,
+        })
+    }
+}
+
+/// Derivation step following the key in a key expression.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum TerminalStep {
+    /// Fixed unhardened index.
+    Index(NormIdx),
+
+    /// Wildcard `*`, replaced with an index provided by the derivator.
+    Wildcard,
+
+    /// Multipath step `<0;1>`, selecting one of the indexes by the number
+    /// provided by the derivator.
+    Multipath(Vec<NormIdx>),
+}
+
+impl TerminalStep {
+    /// Detects whether the step takes its value from the derivator.
+    pub fn is_variable(&self) -> bool { !matches!(self, TerminalStep::Index(_)) }
+}
+
+impl Display for TerminalStep {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TerminalStep::Index(index) => Display::fmt(index, f),
+            TerminalStep::Wildcard => f.write_str("*"),
+            TerminalStep::Multipath(indexes) => {
+                let list = indexes.iter().map(NormIdx::to_string).collect::<Vec<_>>();
+                write!(f, "<{}>", list.join(";"))
+            }
+        }
+    }
+}
+
+impl FromStr for TerminalStep {
+    type Err = KeyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || KeyParseError::InvalidTerminal(s.to_owned());
+        if s == "*" {
+            return Ok(TerminalStep::Wildcard);
+        }
+        if let Some(list) = s.strip_prefix('<').and_then(|s| s.strip_suffix('>')) {
+            let indexes = list
+                .split(';')
+                .map(NormIdx::from_str)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| err())?;
+            if indexes.len() < 2 {
+                return Err(err());
+            }
+            return Ok(TerminalStep::Multipath(indexes));
+        }
+        NormIdx::from_str(s).map(TerminalStep::Index).map_err(|_| err())
+    }
+}
+
+/// Key expression: a key with its optional origin and the derivation steps
+/// following it, like `[d34db33f/84h/0h/0h]xpub.../<0;1>/*`.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
 pub struct KeyExpr<K: DescrKey> {
     pub origin: Option<KeyOrigin>,
     pub key: K,
+    pub terminal: Vec<TerminalStep>,
+}
+
+impl<K: DescrKey> Display for KeyExpr<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(origin) = &self.origin {
+            Display::fmt(origin, f)?;
+        }
+        Display::fmt(&self.key, f)?;
+        for step in &self.terminal {
+            write!(f, "/{step}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<K: DescrKey + FromStr> FromStr for KeyExpr<K>
+where KeyParseError: From<K::Err>
+{
+    type Err = KeyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (origin, rest) = match s.find(']') {
+            Some(pos) if s.starts_with('[') => {
+                (Some(KeyOrigin::from_str(&s[..=pos])?), &s[pos + 1..])
+            }
+            _ => (None, s),
+        };
+        let mut split = rest.split('/');
+        let key = K::from_str(split.next().unwrap_or_default())?;
+        let terminal = split.map(TerminalStep::from_str).collect::<Result<_, _>>()?;
+        Ok(KeyExpr {
+            origin,
+            key,
+            terminal,
+        })
+    }
+}
+
+/// Extended keys with derivation terminals may be used in all contexts.
+impl DescrKey for KeyExpr<Xpub> {}
+impl CompressedKey for KeyExpr<Xpub> {}
+impl XonlyKey for KeyExpr<Xpub> {}
+
+impl DerivePubkey for KeyExpr<Xpub> {
+    type ConcreteKey = CompressedPk;
+
+    fn derive_pattern_len(&self) -> u8 {
+        self.terminal.iter().filter(|step| step.is_variable()).count() as u8
+    }
+
+    /// Derives the key following the terminal steps, taking the values of
+    /// variable steps from the derivator positions in the order of their
+    /// appearance.
+    fn derive_pubkey(
+        &self,
+        derivator: &impl TerminalDerivator,
+    ) -> Result<Self::ConcreteKey, DerivatorError> {
+        let mut pos = 0u8;
+        let mut path = Vec::with_capacity(self.terminal.len());
+        for step in &self.terminal {
+            let index = match step {
+                TerminalStep::Index(index) => *index,
+                TerminalStep::Wildcard => derivator.index_at(pos)?,
+                TerminalStep::Multipath(indexes) => {
+                    let variant = derivator.index_at(pos)?;
+                    *indexes
+                        .get(variant.first_index() as usize)
+                        .ok_or(DerivatorError::NoMultipathVariant(variant))?
+                }
+            };
+            if step.is_variable() {
+                pos += 1;
+            }
+            path.push(index);
+        }
+        let xpub = self.key.derive(path).map_err(|_| DerivatorError::TooDeep)?;
+        Ok(CompressedPk::from(xpub.public_key()))
+    }
 }
 
 pub trait ScriptExpr<K: DescrKey> {}
 pub trait WScriptExpr<K: CompressedKey> {}
 pub trait TapScriptExpr<K: XonlyKey>: ScriptExpr<K> {}
 
+/// Errors in the construction of tap tree expressions.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum TapTreeError {
+    /// leaf version {0:#04x} has its lowest bit set, which is reserved for the
+    /// parity of the output key.
+    OddLeafVer(u8),
+
+    /// leaf version 0x50 is reserved, since it can't be distinguished from an
+    /// annex.
+    AnnexLeafVer,
+
+    /// tap tree exceeds maximal depth of 128 levels.
+    TooDeep,
+}
+
+/// Maximal depth of a tap tree, as defined in BIP-341.
+pub const TAPROOT_MAX_DEPTH: u8 = 128;
+
+/// Version of a tap tree leaf script, as defined in BIP-341.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[display("{0:#04x}")]
+pub struct LeafVer(u8);
+
+impl LeafVer {
+    /// Leaf version of the tapscript defined in BIP-342.
+    pub const TAP_SCRIPT: LeafVer = LeafVer(0xc0);
+
+    /// Constructs leaf version from its consensus byte, rejecting values which
+    /// can't be used in a control block.
+    pub fn from_consensus(version: u8) -> Result<Self, TapTreeError> {
+        match version {
+            v if v & 0x01 != 0 => Err(TapTreeError::OddLeafVer(v)),
+            0x50 => Err(TapTreeError::AnnexLeafVer),
+            v => Ok(LeafVer(v)),
+        }
+    }
+
+    /// Returns consensus byte representation of the leaf version.
+    pub fn to_consensus(self) -> u8 { self.0 }
+
+    /// Detects whether the leaf version is the BIP-342 tapscript.
+    pub fn is_tap_script(self) -> bool { self == Self::TAP_SCRIPT }
+}
+
+impl Default for LeafVer {
+    fn default() -> Self { Self::TAP_SCRIPT }
+}
+
 pub enum NodeExpr<S: TapScriptExpr<K>, K: XonlyKey> {
-    TapScript(S),
+    TapScript(LeafVer, S),
     NodeHash(TapNodeHash),
     Tree(Box<TreeExpr<S, K>>),
 }
 
+impl<S: TapScriptExpr<K>, K: XonlyKey> NodeExpr<S, K> {
+    /// Constructs a tap tree leaf with the standard BIP-342 tapscript version.
+    pub fn tap_script(script: S) -> Self { NodeExpr::TapScript(LeafVer::TAP_SCRIPT, script) }
+
+    /// Constructs a tap tree leaf with a custom leaf version, failing if the
+    /// version is malformed and the leaf would be unspendable.
+    pub fn with_leaf_ver(version: u8, script: S) -> Result<Self, TapTreeError> {
+        LeafVer::from_consensus(version).map(|ver| NodeExpr::TapScript(ver, script))
+    }
+}
+
+impl<S: TapScriptExpr<K>, K: XonlyKey> NodeExpr<S, K> {
+    /// Computes depth of the tree under the node, failing with
+    /// [`TapTreeError::TooDeep`] if it exceeds [`TAPROOT_MAX_DEPTH`].
+    ///
+    /// The tree is walked with an explicit stack instead of recursion, so the
+    /// untrusted trees do not exhaust the call stack.
+    pub fn depth(&self) -> Result<u8, TapTreeError> {
+        let mut max = 0u8;
+        let mut stack = vec![(self, 0u8)];
+        while let Some((node, depth)) = stack.pop() {
+            max = max.max(depth);
+            if let NodeExpr::Tree(tree) = node {
+                if depth >= TAPROOT_MAX_DEPTH {
+                    return Err(TapTreeError::TooDeep);
+                }
+                stack.push((&tree.first, depth + 1));
+                if let Some(second) = &tree.second {
+                    stack.push((second, depth + 1));
+                }
+            }
+        }
+        Ok(max)
+    }
+}
+
 pub struct TreeExpr<S: TapScriptExpr<K>, K: XonlyKey> {
     pub first: NodeExpr<S, K>,
     pub second: Option<NodeExpr<S, K>>,
+    _phantom: PhantomData<K>,
+}
+
+impl<S: TapScriptExpr<K>, K: XonlyKey> TreeExpr<S, K> {
+    /// Constructs tree branch from one or two child nodes.
+    pub fn new(first: NodeExpr<S, K>, second: Option<NodeExpr<S, K>>) -> Self {
+        TreeExpr {
+            first,
+            second,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+/// Length of the tapret leaf script: 29 `OP_RESERVED` opcodes, `OP_RETURN`,
+/// `OP_PUSHBYTES_33` and 33 bytes of the commitment with its nonce.
+#[cfg(feature = "tapret")]
+pub const TAPRET_SCRIPT_LEN: usize = 64;
+
+/// Tapret commitment (LNPBP-12): a multi-protocol commitment placed into a
+/// dedicated leaf of the taproot script tree.
+#[cfg(feature = "tapret")]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct TapretCommitment {
+    /// Multi-protocol commitment (LNPBP-4) message.
+    pub mpc: Bytes32,
+    /// Nonce, allowing to place the tapret leaf to the right side of the tree
+    /// when the commitment is verified.
+    pub nonce: u8,
+}
+
+#[cfg(feature = "tapret")]
+impl TapretCommitment {
+    /// Constructs the tapret leaf script, which is never executable since it
+    /// starts with `OP_RESERVED` opcodes followed by `OP_RETURN`.
+    pub fn leaf_script(&self) -> [u8; TAPRET_SCRIPT_LEN] {
+        let mut script = [0x50u8; TAPRET_SCRIPT_LEN];
+        script[29] = 0x6a;
+        script[30] = 0x21;
+        script[31..63].copy_from_slice(self.mpc.as_slice());
+        script[63] = self.nonce;
+        script
+    }
+
+    /// Computes tapscript leaf hash of the tapret leaf.
+    pub fn leaf_hash(&self) -> TapLeafHash {
+        let script = ScriptBytes::from(self.leaf_script().to_vec());
+        TapLeafHash::with_tap_script(&TapScript::from(script))
+    }
+}
+
+/// Optional tapret commitment on a taproot output.
+///
+/// Tapret is an extension of the BIP-386 `tr()` descriptors used by
+/// client-side-validation protocols; it is available with `tapret` feature.
+#[cfg(feature = "tapret")]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct TapretExpr(Option<TapretCommitment>);
+
+#[cfg(feature = "tapret")]
+impl TapretExpr {
+    /// Constructs expression committing to the given tapret commitment.
+    pub fn with(commitment: TapretCommitment) -> Self { TapretExpr(Some(commitment)) }
+
+    /// Returns the commitment, if present.
+    pub fn commitment(&self) -> Option<TapretCommitment> { self.0 }
+
+    /// Computes merkle root of the script tree with the tapret leaf added as a
+    /// sibling of the tree root. If there is no commitment, returns the
+    /// original root; if there is no tree, the tapret leaf becomes the only
+    /// leaf of the tree.
+    pub fn merkle_root(&self, tree_root: Option<TapNodeHash>) -> Option<TapNodeHash> {
+        let commitment = match self.0 {
+            None => return tree_root,
+            Some(commitment) => commitment,
+        };
+        let leaf = TapNodeHash::from(commitment.leaf_hash());
+        Some(match tree_root {
+            None => leaf,
+            Some(root) => TapBranchHash::with_nodes(root, leaf).into(),
+        })
+    }
 }
 
-pub struct TapretExpr(BTreeMap<TerminalPath, Vec<mpc::Commitment>>);
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::keys::XonlyPk;
+
+    struct Script;
+    impl ScriptExpr<XonlyPk> for Script {}
+    impl TapScriptExpr<XonlyPk> for Script {}
+
+    #[test]
+    fn leaf_ver_validation() {
+        assert_eq!(LeafVer::default(), LeafVer::TAP_SCRIPT);
+        assert_eq!(LeafVer::from_consensus(0xc0), Ok(LeafVer::TAP_SCRIPT));
+        assert!(LeafVer::from_consensus(0xc0).unwrap().is_tap_script());
+        assert_eq!(LeafVer::from_consensus(0xc2).map(LeafVer::to_consensus), Ok(0xc2));
+        assert!(!LeafVer::from_consensus(0xc2).unwrap().is_tap_script());
+        assert_eq!(LeafVer::from_consensus(0xc1), Err(TapTreeError::OddLeafVer(0xc1)));
+        assert_eq!(LeafVer::from_consensus(0x50), Err(TapTreeError::AnnexLeafVer));
+        assert_eq!(LeafVer::TAP_SCRIPT.to_string(), "0xc0");
+
+        let leaf = NodeExpr::<Script, XonlyPk>::tap_script(Script);
+        assert!(matches!(leaf, NodeExpr::TapScript(ver, _) if ver == LeafVer::TAP_SCRIPT));
+        assert!(matches!(
+            NodeExpr::<Script, XonlyPk>::with_leaf_ver(0xc4, Script),
+            Ok(NodeExpr::TapScript(ver, _)) if ver.to_consensus() == 0xc4
+        ));
+        assert!(matches!(
+            NodeExpr::<Script, XonlyPk>::with_leaf_ver(0xff, Script),
+            Err(TapTreeError::OddLeafVer(0xff))
+        ));
+    }
+
+    /// Constructs tree with a single leaf at the given depth and another leaf
+    /// at each of the levels above it.
+    fn tree_with_depth(depth: u8) -> NodeExpr<Script, XonlyPk> {
+        let mut node = NodeExpr::tap_script(Script);
+        for _ in 0..depth {
+            let tree = TreeExpr::new(node, Some(NodeExpr::tap_script(Script)));
+            node = NodeExpr::Tree(Box::new(tree));
+        }
+        node
+    }
+
+    #[test]
+    fn tree_depth() {
+        assert_eq!(tree_with_depth(0).depth(), Ok(0));
+        assert_eq!(tree_with_depth(1).depth(), Ok(1));
+        assert_eq!(tree_with_depth(TAPROOT_MAX_DEPTH).depth(), Ok(TAPROOT_MAX_DEPTH));
+        assert_eq!(tree_with_depth(TAPROOT_MAX_DEPTH + 1).depth(), Err(TapTreeError::TooDeep));
+
+        let single = TreeExpr::new(tree_with_depth(3), None);
+        assert_eq!(NodeExpr::Tree(Box::new(single)).depth(), Ok(4));
+    }
+}
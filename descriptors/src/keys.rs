@@ -20,5 +20,197 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-pub trait CompressedKey {}
-pub trait XonlyKey {}
+//! Keys used in descriptors.
+
+use std::fmt::{self, Debug, Display, Formatter};
+use std::str::FromStr;
+
+use ::derive::index_error::IndexParseError;
+use ::derive::{XkeyParseError, Xpub};
+use amplify::hex::{FromHex, ToHex};
+use secp256k1::{PublicKey, XOnlyPublicKey};
+
+use crate::expr::KeyExpr;
+
+/// Errors parsing descriptor keys.
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum KeyParseError {
+    /// invalid compressed public key '{0}'.
+    InvalidPubkey(String),
+
+    /// invalid x-only public key '{0}'.
+    InvalidXonlyKey(String),
+
+    /// invalid key origin '{0}'.
+    InvalidOrigin(String),
+
+    /// invalid derivation step '{0}'; only unhardened indexes, `*` and `<a;b>`
+    /// multipath steps may follow the key.
+    InvalidTerminal(String),
+
+    /// '{0}' is not a valid descriptor key.
+    UnrecognizedKey(String),
+
+    #[from]
+    #[display(inner)]
+    Xkey(XkeyParseError),
+
+    #[from]
+    #[display(inner)]
+    Index(IndexParseError),
+}
+
+/// Key which may be used in descriptors.
+///
+/// The trait is object-safe, so descriptors mixing different key types may be
+/// displayed through `&dyn DescrKey`. Key types additionally implement
+/// [`FromStr`] parsing the same descriptor syntax as produced by [`Display`].
+pub trait DescrKey: Display + Debug {}
+
+/// Key which may be used in the contexts requiring compressed public keys
+/// (legacy and segwit v0 scripts).
+pub trait CompressedKey: DescrKey {}
+
+/// Key which may be used in the contexts requiring x-only public keys
+/// (taproot).
+pub trait XonlyKey: DescrKey {}
+
+/// Compressed public key, written in descriptors as 66 hex characters.
+#[derive(Wrapper, Copy, Clone, Eq, PartialEq, Hash, Debug, From)]
+#[wrapper(Deref)]
+pub struct CompressedPk(PublicKey);
+
+impl DescrKey for CompressedPk {}
+impl CompressedKey for CompressedPk {}
+
+impl Display for CompressedPk {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.serialize().to_hex())
+    }
+}
+
+impl FromStr for CompressedPk {
+    type Err = KeyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <[u8; 33]>::from_hex(s)
+            .ok()
+            .and_then(|data| PublicKey::from_slice(&data).ok())
+            .map(CompressedPk)
+            .ok_or_else(|| KeyParseError::InvalidPubkey(s.to_owned()))
+    }
+}
+
+/// X-only public key, written in descriptors as 64 hex characters.
+#[derive(Wrapper, Copy, Clone, Eq, PartialEq, Hash, Debug, From)]
+#[wrapper(Deref)]
+pub struct XonlyPk(XOnlyPublicKey);
+
+impl DescrKey for XonlyPk {}
+impl XonlyKey for XonlyPk {}
+
+impl Display for XonlyPk {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.serialize().to_hex())
+    }
+}
+
+impl FromStr for XonlyPk {
+    type Err = KeyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <[u8; 32]>::from_hex(s)
+            .ok()
+            .and_then(|data| XOnlyPublicKey::from_slice(&data).ok())
+            .map(XonlyPk)
+            .ok_or_else(|| KeyParseError::InvalidXonlyKey(s.to_owned()))
+    }
+}
+
+/// Extended public keys may be used in all contexts, producing compressed or
+/// x-only keys on derivation.
+impl DescrKey for Xpub {}
+impl CompressedKey for Xpub {}
+impl XonlyKey for Xpub {}
+
+/// Any of the descriptor keys, allowing descriptors to mix single keys and
+/// extended keys (like `multi(2,<pubkey>,[fp/path]xpub/<0;1>/*)`).
+#[derive(Clone, Eq, PartialEq, Debug, Display, From)]
+#[display(inner)]
+pub enum AnyKey {
+    /// Compressed public key.
+    #[from]
+    Compressed(CompressedPk),
+
+    /// X-only public key.
+    #[from]
+    Xonly(XonlyPk),
+
+    /// Extended public key with its optional origin and derivation terminal.
+    #[from]
+    Xpub(KeyExpr<Xpub>),
+}
+
+impl DescrKey for AnyKey {}
+impl CompressedKey for AnyKey {}
+impl XonlyKey for AnyKey {}
+
+impl FromStr for AnyKey {
+    type Err = KeyParseError;
+
+    /// Detects the key type from its length and the presence of key origin or
+    /// derivation steps, which may be used only with extended keys.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.len() {
+            66 => CompressedPk::from_str(s).map(AnyKey::Compressed),
+            64 => XonlyPk::from_str(s).map(AnyKey::Xonly),
+            _ if s.starts_with('[') || s.contains("pub") => {
+                KeyExpr::<Xpub>::from_str(s).map(AnyKey::Xpub)
+            }
+            _ => Err(KeyParseError::UnrecognizedKey(s.to_owned())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PK: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const XONLY: &str = "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const XPUB: &str = "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw";
+
+    #[test]
+    fn single_keys() {
+        assert_eq!(CompressedPk::from_str(PK).unwrap().to_string(), PK);
+        assert_eq!(XonlyPk::from_str(XONLY).unwrap().to_string(), XONLY);
+        assert_eq!(
+            CompressedPk::from_str(XONLY),
+            Err(KeyParseError::InvalidPubkey(XONLY.to_owned()))
+        );
+        assert_eq!(XonlyPk::from_str(PK), Err(KeyParseError::InvalidXonlyKey(PK.to_owned())));
+    }
+
+    #[test]
+    fn any_key_round_trip() {
+        let xpub = format!("[d34db33f/84h/0h/0h]{XPUB}/<0;1>/*");
+        for s in [PK, XONLY, XPUB, &xpub] {
+            let key = AnyKey::from_str(s).unwrap();
+            assert_eq!(key.to_string(), s);
+            let key: &dyn DescrKey = &key;
+            assert_eq!(format!("{key}"), s);
+        }
+        assert!(matches!(AnyKey::from_str(PK), Ok(AnyKey::Compressed(_))));
+        assert!(matches!(AnyKey::from_str(XONLY), Ok(AnyKey::Xonly(_))));
+        assert!(matches!(AnyKey::from_str(&xpub), Ok(AnyKey::Xpub(_))));
+        assert_eq!(
+            AnyKey::from_str("d34db33f"),
+            Err(KeyParseError::UnrecognizedKey(s!("d34db33f")))
+        );
+        assert!(matches!(
+            AnyKey::from_str(&format!("{XPUB}/1h")),
+            Err(KeyParseError::InvalidTerminal(_))
+        ));
+    }
+}
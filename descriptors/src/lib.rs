@@ -24,9 +24,33 @@
 extern crate amplify;
 
 mod addr;
+mod bech32;
 mod keys;
 mod expr;
 mod scripts;
 mod descr;
 mod derive;
 mod satisfy;
+mod updater;
+mod review;
+
+pub use addr::{
+    Address, AddressFormat, AddressNetwork, AddressParseError, AddressPayload, Bytes20, SegWitInfo,
+    WitnessVer, WitnessVerError,
+};
+pub use derive::{
+    ConcretePubkey, DerivatorError, DerivePubkey, DeriveSpendInfo, Descriptor, DualIndex,
+    TerminalDerivator,
+};
+pub use descr::{Addr, Combo, Multi, Pk, Raw, Sh, SortedMulti, Tr, Wpk, Wsh};
+pub use expr::{
+    KeyExpr, KeyOrigin, LeafVer, NodeExpr, ScriptExpr, TapScriptExpr, TapTreeError, TerminalStep,
+    TreeExpr, WScriptExpr, TAPROOT_MAX_DEPTH,
+};
+#[cfg(feature = "tapret")]
+pub use expr::{TapretCommitment, TapretExpr, TAPRET_SCRIPT_LEN};
+pub use keys::{AnyKey, CompressedKey, CompressedPk, DescrKey, KeyParseError, XonlyKey, XonlyPk};
+pub use review::PsbtReview;
+pub use satisfy::{Requirement, SatisfiableDescriptor};
+pub use scripts::{classify_script, ScriptType};
+pub use updater::{PsbtUpdater, UpdateError};
@@ -0,0 +1,56 @@
+// Bitcoin descriptors implementation
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Presentation of PSBT data for the transaction review by users.
+
+use ::psbt::Psbt;
+use bc::ScriptPubkey;
+
+use crate::addr::{Address, AddressNetwork, AddressPayload};
+
+/// Data which wallets show to users reviewing a PSBT before signing it.
+pub trait PsbtReview {
+    /// Returns amounts of all the outputs together with their addresses for
+    /// the given network.
+    ///
+    /// The address is `None` for the outputs which `scriptPubkey` can't be
+    /// represented as an address (like `OP_RETURN` or non-standard scripts)
+    /// or is unknown. The amount of an output with unknown amount is zero.
+    fn output_addresses(&self, network: AddressNetwork) -> Vec<(u64, Option<Address>)>;
+}
+
+impl PsbtReview for Psbt {
+    fn output_addresses(&self, network: AddressNetwork) -> Vec<(u64, Option<Address>)> {
+        self.output_txouts()
+            .map(|txout| match txout {
+                Some(txout) => {
+                    let script =
+                        ScriptPubkey::from(bc::ScriptBytes::from(txout.script_pubkey.to_vec()));
+                    let address = AddressPayload::from_script_pubkey(&script)
+                        .map(|payload| Address { payload, network });
+                    (txout.value, address)
+                }
+                None => (0, None),
+            })
+            .collect()
+    }
+}
@@ -32,6 +32,6 @@ pub enum Requirement<K: DescrKey> {
     Signature(K),
 }
 
-pub trait SatisfiableDescriptor: Descriptor {
-    fn requirements(&self) -> Vec<Requirement<Descriptor::Key>>;
+pub trait SatisfiableDescriptor<K: DescrKey>: Descriptor<K> {
+    fn requirements(&self) -> Vec<Requirement<K>>;
 }
@@ -19,3 +19,83 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+
+//! Classification of `scriptPubkey` types.
+
+use bc::{ScriptBytes, ScriptPubkey};
+
+use crate::addr::WitnessVer;
+
+/// Type of the `scriptPubkey`.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+pub enum ScriptType {
+    /// Pay-to-public key
+    #[display("P2PK")]
+    P2pk,
+
+    /// Pay-to-public key hash
+    #[display("P2PKH")]
+    P2pkh,
+
+    /// Pay-to-script hash
+    #[display("P2SH")]
+    P2sh,
+
+    /// Pay-to-witness public key hash
+    #[display("P2WPKH")]
+    P2wpkh,
+
+    /// Pay-to-witness script hash
+    #[display("P2WSH")]
+    P2wsh,
+
+    /// Pay-to-taproot
+    #[display("P2TR")]
+    P2tr,
+
+    /// Witness program of a future (unsupported) witness version or of the
+    /// taproot version with non-taproot program length
+    #[display("P2W{0}")]
+    FutureWitness(WitnessVer),
+
+    /// Provably unspendable `OP_RETURN` output
+    #[display("OP_RETURN")]
+    OpReturn,
+
+    /// Any other script
+    #[display("nonstandard")]
+    Nonstandard,
+}
+
+/// Constructs witness program `scriptPubkey` from the version and the program
+/// of 2 to 40 bytes, which always uses a direct push.
+pub(crate) fn witness_script(version: WitnessVer, program: &[u8]) -> ScriptPubkey {
+    debug_assert!((2..=40).contains(&program.len()));
+    let mut script = Vec::with_capacity(program.len() + 2);
+    script.push(version.op_code());
+    script.push(program.len() as u8);
+    script.extend(program);
+    ScriptPubkey::from(ScriptBytes::from(script))
+}
+
+/// Detects type of the `scriptPubkey`.
+pub fn classify_script(script: &ScriptPubkey) -> ScriptType {
+    let s = &script[..];
+    match s {
+        [0x21, .., 0xAC] if s.len() == 35 => ScriptType::P2pk,
+        [0x41, .., 0xAC] if s.len() == 67 => ScriptType::P2pk,
+        [0x76, 0xA9, 0x14, .., 0x88, 0xAC] if s.len() == 25 => ScriptType::P2pkh,
+        [0xA9, 0x14, .., 0x87] if s.len() == 23 => ScriptType::P2sh,
+        [0x00, 0x14, ..] if s.len() == 22 => ScriptType::P2wpkh,
+        [0x00, 0x20, ..] if s.len() == 34 => ScriptType::P2wsh,
+        [0x51, 0x20, ..] if s.len() == 34 => ScriptType::P2tr,
+        [0x6A, ..] => ScriptType::OpReturn,
+        [ver @ 0x51..=0x60, len @ 0x02..=0x28, ..] if s.len() == *len as usize + 2 => {
+            match WitnessVer::try_from(*ver - 0x50) {
+                Ok(version) => ScriptType::FutureWitness(version),
+                Err(_) => ScriptType::Nonstandard,
+            }
+        }
+        _ => ScriptType::Nonstandard,
+    }
+}
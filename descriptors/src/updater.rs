@@ -0,0 +1,159 @@
+// Bitcoin descriptors implementation
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! PSBT updater operations using wallet descriptors.
+
+use ::psbt::Psbt;
+
+use crate::derive::{DerivatorError, DerivePubkey, DeriveSpendInfo, TerminalDerivator};
+
+/// Errors updating PSBT with information derived from a descriptor.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(inner)]
+pub enum UpdateError {
+    /// Error deriving the descriptor.
+    #[from]
+    Derive(DerivatorError),
+
+    /// Error updating PSBT.
+    #[from]
+    Psbt(::psbt::UpdateError),
+}
+
+/// PSBT updater role for the wallets knowing the descriptors of the spent
+/// outputs.
+pub trait PsbtUpdater {
+    /// Derives descriptor using the terminal `derivator` and inserts into the
+    /// input the redeem and witness scripts, BIP-32 derivations of all the
+    /// involved keys and, for taproot descriptors, the internal key and merkle
+    /// root of the script tree.
+    fn update_input_with_descriptor<K: DerivePubkey>(
+        &mut self,
+        index: usize,
+        descriptor: &impl DeriveSpendInfo<K>,
+        derivator: &impl TerminalDerivator,
+    ) -> Result<(), UpdateError>;
+}
+
+impl PsbtUpdater for Psbt {
+    fn update_input_with_descriptor<K: DerivePubkey>(
+        &mut self,
+        index: usize,
+        descriptor: &impl DeriveSpendInfo<K>,
+        derivator: &impl TerminalDerivator,
+    ) -> Result<(), UpdateError> {
+        let info = descriptor.derive_spend_info(derivator)?;
+        self.update_input(index, &info)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use ::derive::{NormIdx, Xpub};
+    use ::psbt::{
+        ControlBlock, InputBuilder, MapLocation, Outpoint, OutputBuilder, PsbtBuilder, SpendInfo,
+        Txid,
+    };
+
+    use super::*;
+    use crate::derive::DualIndex;
+    use crate::expr::KeyExpr;
+    use crate::keys::CompressedPk;
+
+    /// Taproot descriptor with a derived internal key and a single leaf script.
+    struct TrLeaf<K>(K, Vec<u8>);
+
+    impl<K: DerivePubkey<ConcreteKey = CompressedPk>> DeriveSpendInfo<K> for TrLeaf<K> {
+        fn derive_spend_info(
+            &self,
+            derivator: &impl TerminalDerivator,
+        ) -> Result<SpendInfo, DerivatorError> {
+            let internal_key = self.0.derive_pubkey(derivator)?.x_only_public_key().0;
+            let control_block = ControlBlock {
+                leaf_ver: 0xc0,
+                output_key_parity: false,
+                internal_key,
+                merkle_path: vec![],
+            };
+            Ok(SpendInfo {
+                tap_internal_key: Some(internal_key),
+                tap_merkle_root: Some([0x11; 32]),
+                tap_leaf_scripts: vec![(control_block, self.1.clone().into())],
+                ..default!()
+            })
+        }
+    }
+
+    const XPUB: &str = "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw";
+
+    fn psbt() -> Psbt {
+        let input = InputBuilder::new(Outpoint {
+            txid: Txid::from([1; 32]),
+            vout: 0,
+        });
+        PsbtBuilder::new()
+            .version(2)
+            .input(input)
+            .output(OutputBuilder::new(900, ::psbt::ScriptBytes::p2wpkh([3; 20])))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn update_input_with_tr_descriptor() {
+        let key = KeyExpr::<Xpub>::from_str(&format!("{XPUB}/<0;1>/*")).unwrap();
+        let descriptor = TrLeaf(key, vec![0x51]);
+        let derivator = DualIndex {
+            change: NormIdx::from(1u8),
+            index: NormIdx::from(5u8),
+        };
+        let mut psbt = psbt();
+        psbt.update_input_with_descriptor(0, &descriptor, &derivator).unwrap();
+
+        let expected = Xpub::from_str(XPUB).unwrap().ckd_pub(1u8).unwrap().ckd_pub(5u8).unwrap();
+        let expected = expected.public_key().x_only_public_key().0.serialize();
+        let pairs = psbt
+            .raw_pairs()
+            .filter(|(location, ..)| *location == MapLocation::Input(0))
+            .map(|(_, key_type, _, value)| (key_type, value))
+            .collect::<Vec<_>>();
+        assert!(pairs.contains(&(0x17, expected.to_vec())));
+        assert!(pairs.contains(&(0x18, vec![0x11; 32])));
+        assert!(pairs.contains(&(0x15, vec![0x51, 0xc0])));
+
+        assert_eq!(
+            psbt.update_input_with_descriptor(1, &descriptor, &derivator),
+            Err(UpdateError::Psbt(::psbt::UpdateError::NoInput(1)))
+        );
+        let derivator = DualIndex {
+            change: NormIdx::from(2u8),
+            ..derivator
+        };
+        assert_eq!(
+            psbt.update_input_with_descriptor(0, &descriptor, &derivator),
+            Err(UpdateError::Derive(DerivatorError::NoMultipathVariant(NormIdx::from(2u8))))
+        );
+    }
+}
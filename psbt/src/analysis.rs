@@ -0,0 +1,552 @@
+// Partially signed bitcoin transaction library (BIP174, BIP370, BIP371)
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Analysis of PSBT inputs and outputs.
+
+use derive::{Fingerprint, NormIdx};
+use secp256k1::PublicKey;
+
+use crate::scripts::{hash160, sha256, ScriptTemplate};
+use crate::{
+    GlobalPair, InPair, KeyOrigin, LegacyPk, OutPair, Psbt, ScriptBytes, TxOut, MAX_MONEY,
+};
+
+/// Errors analyzing PSBT inputs and outputs.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum AnalysisError {
+    /// PSBT doesn't have input #{0}.
+    NoInput(usize),
+
+    /// type of the script spent by input #{0} can't be determined from the data present in PSBT.
+    UnknownScriptType(usize),
+
+    /// input #{0} spends P2SH or P2WSH output, but doesn't provide the matching redeem or
+    /// witness script.
+    NoScript(usize),
+
+    /// redeem script of input #{0} doesn't match the script hash of the spent P2SH output.
+    RedeemScriptMismatch(usize),
+
+    /// PSBT doesn't have output #{0}.
+    NoOutput(usize),
+
+    /// `scriptPubkey` of output #{0} is not known.
+    NoOutputScript(usize),
+}
+
+/// Errors summing input or output amounts.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum AmountError {
+    /// amount of input #{0} is unknown since PSBT doesn't contain the spent output.
+    NoInputAmount(usize),
+
+    /// amount of output #{0} is unknown.
+    NoOutputAmount(usize),
+
+    /// sum of the amounts overflows 64-bit integer.
+    Overflow,
+
+    /// amount {0} sats exceeds the total bitcoin supply.
+    AboveSupplyCap(u64),
+}
+
+impl Psbt {
+    /// Detects whether all inputs are finalized, i.e. have final `scriptSig` or witness.
+    pub fn is_final(&self) -> bool {
+        self.inputs.iter().all(|map| {
+            map.known().any(|pair| {
+                matches!(pair, InPair::FinalScriptSig(_) | InPair::FinalScriptWitness(_))
+            })
+        })
+    }
+
+    /// Detects whether all inputs have enough signatures to be finalized; see
+    /// [`Psbt::has_all_signatures`] for the details.
+    pub fn is_fully_signed(&self) -> bool { self.has_all_signatures() }
+
+    /// Detects whether the input spends a segwit output, including P2SH-wrapped segwit outputs.
+    ///
+    /// P2SH-wrapped inputs are detected by inspecting their redeem script, so for them the
+    /// redeem script must be present in the PSBT and must match the script hash of the spent
+    /// output.
+    pub fn input_is_segwit(&self, index: usize) -> Result<bool, AnalysisError> {
+        let map = self.inputs.get(index).ok_or(AnalysisError::NoInput(index))?;
+        let script_pubkey =
+            self.spent_script_pubkey(index).ok_or(AnalysisError::UnknownScriptType(index))?;
+
+        if script_pubkey.is_witness_program() {
+            return Ok(true);
+        }
+        let ScriptTemplate::Sh(hash) = script_pubkey.template() else {
+            return Ok(false);
+        };
+        let redeem_script = map
+            .known()
+            .find_map(|pair| match pair {
+                InPair::RedeemScript(script) => Some(script),
+                _ => None,
+            })
+            .ok_or(AnalysisError::UnknownScriptType(index))?;
+        if hash160(redeem_script) != hash {
+            return Err(AnalysisError::RedeemScriptMismatch(index));
+        }
+        Ok(redeem_script.is_witness_program())
+    }
+
+    /// Returns the script which must be satisfied to spend the input: the witness script for
+    /// P2WSH (including P2SH-wrapped P2WSH), the redeem script for other P2SH outputs and the
+    /// `scriptPubkey` of the spent output otherwise.
+    ///
+    /// The redeem and witness scripts are checked to match the hashes committed to by the
+    /// spent output. Taproot inputs always return the spent `scriptPubkey`, since the script
+    /// path spends are not wrapped into P2SH or P2WSH.
+    pub fn effective_script(&self, index: usize) -> Result<ScriptBytes, AnalysisError> {
+        let map = self.inputs.get(index).ok_or(AnalysisError::NoInput(index))?;
+        let mut script =
+            self.spent_script_pubkey(index).ok_or(AnalysisError::UnknownScriptType(index))?;
+
+        if let ScriptTemplate::Sh(hash) = script.template() {
+            script = map
+                .known()
+                .find_map(|pair| match pair {
+                    InPair::RedeemScript(script) if hash160(script) == hash => Some(script.clone()),
+                    _ => None,
+                })
+                .ok_or(AnalysisError::NoScript(index))?;
+        }
+        if let ScriptTemplate::Wsh(hash) = script.template() {
+            script = map
+                .known()
+                .find_map(|pair| match pair {
+                    InPair::WitnessScript(script) if sha256(script) == hash => Some(script.clone()),
+                    _ => None,
+                })
+                .ok_or(AnalysisError::NoScript(index))?;
+        }
+        Ok(script)
+    }
+
+    /// Returns `scriptPubkey` of the output spent by the input, taking it either from the
+    /// witness UTXO or from the non-witness UTXO fields.
+    pub(crate) fn spent_script_pubkey(&self, index: usize) -> Option<ScriptBytes> {
+        self.spent_output(index).map(|txout| txout.script_pubkey)
+    }
+
+    /// Returns output spent by the input, taking it either from the witness UTXO or from the
+    /// non-witness UTXO fields.
+    pub(crate) fn spent_output(&self, index: usize) -> Option<TxOut> {
+        let map = self.inputs.get(index)?;
+        if let Some(txout) = map.known().find_map(|pair| match pair {
+            InPair::WitnessUtxo(txout) => Some(txout.clone()),
+            _ => None,
+        }) {
+            return Some(txout);
+        }
+
+        let prev_tx = map.known().find_map(|pair| match pair {
+            InPair::NonWitnessUtxo(tx) => Some(tx),
+            _ => None,
+        })?;
+        let vout = if self.version() < 2 {
+            self.global.known().find_map(|pair| match pair {
+                GlobalPair::UnsignedTx(tx) => tx.inputs.get(index).map(|i| i.prev_output.vout),
+                _ => None,
+            })?
+        } else {
+            map.known().find_map(|pair| match pair {
+                InPair::OutputIndex(vout) => Some(*vout),
+                _ => None,
+            })?
+        };
+        prev_tx.outputs.get(vout as usize).cloned()
+    }
+
+    /// Sums amounts of all the outputs spent by the inputs, taken from their witness or
+    /// non-witness UTXOs.
+    ///
+    /// Errors if an input amount is unknown, or if any amount or their sum exceeds
+    /// [`MAX_MONEY`], which protects fee computations from the overflows caused by malicious
+    /// PSBTs.
+    pub fn total_input_amount(&self) -> Result<u64, AmountError> {
+        sum_amounts((0..self.inputs.len()).map(|index| {
+            self.spent_output(index)
+                .map(|txout| txout.value)
+                .ok_or(AmountError::NoInputAmount(index))
+        }))
+    }
+
+    /// Sums amounts of all the outputs, taking them from the unsigned transaction (v0) or from
+    /// the output maps (v2).
+    ///
+    /// Errors if an output amount is unknown, or if any amount or their sum exceeds
+    /// [`MAX_MONEY`].
+    pub fn total_output_amount(&self) -> Result<u64, AmountError> {
+        sum_amounts(
+            (0..self.outputs.len())
+                .map(|index| self.output_amount(index).ok_or(AmountError::NoOutputAmount(index))),
+        )
+    }
+
+    /// Iterates over outputs of PSBT, returning their amounts and `scriptPubkey`s taken from the
+    /// unsigned transaction (v0) or from the output maps (v2). Yields `None` for the outputs
+    /// missing amount or `scriptPubkey`.
+    pub fn output_txouts(&self) -> impl Iterator<Item = Option<TxOut>> + '_ {
+        (0..self.outputs.len()).map(move |index| self.output_txout(index))
+    }
+
+    /// Returns data carried by the output if it is a standard `OP_RETURN` output, i.e. its
+    /// `scriptPubkey` is `OP_RETURN` followed by no more than a single push of up to 80 bytes.
+    pub fn output_op_return_data(&self, index: usize) -> Option<Vec<u8>> {
+        self.output_script_pubkey(index)?.op_return_data().map(<[u8]>::to_vec)
+    }
+
+    /// Returns amount and `scriptPubkey` of the output.
+    pub(crate) fn output_txout(&self, index: usize) -> Option<TxOut> {
+        Some(TxOut {
+            value: self.output_amount(index)?,
+            script_pubkey: self.output_script_pubkey(index)?,
+        })
+    }
+
+    /// Returns amount of the output, taking it either from the unsigned transaction (v0) or
+    /// from the output map (v2).
+    fn output_amount(&self, index: usize) -> Option<u64> {
+        if self.version() < 2 {
+            return self.global.known().find_map(|pair| match pair {
+                GlobalPair::UnsignedTx(tx) => tx.outputs.get(index).map(|txout| txout.value),
+                _ => None,
+            });
+        }
+        self.outputs.get(index)?.known().find_map(|pair| match pair {
+            OutPair::Amount(amount) => Some(*amount),
+            _ => None,
+        })
+    }
+
+    /// Detects whether the output is a change output of the wallet with the master key
+    /// fingerprint `wallet_fp`.
+    ///
+    /// The output is considered change only if one of its BIP-32 derivations originates from
+    /// the wallet master key and the public key re-derived from a matching global xpub is the
+    /// one which is used by the output `scriptPubkey`. This protects against malicious PSBTs
+    /// claiming payment outputs to be change: key origin data alone are never trusted.
+    ///
+    /// Only single-key P2PK, P2PKH, P2WPKH and P2SH-P2WPKH outputs are recognized; other
+    /// outputs are never reported as change.
+    pub fn output_is_change(
+        &self,
+        index: usize,
+        wallet_fp: Fingerprint,
+    ) -> Result<bool, AnalysisError> {
+        let map = self.outputs.get(index).ok_or(AnalysisError::NoOutput(index))?;
+        let script_pubkey =
+            self.output_script_pubkey(index).ok_or(AnalysisError::NoOutputScript(index))?;
+
+        Ok(map.known().any(|pair| match pair {
+            OutPair::Bip32Derivation { pubkey, origin } if origin.master_fp == wallet_fp => {
+                self.rederive(origin).as_ref() == Some(pubkey)
+                    && script_uses_key(&script_pubkey, pubkey)
+            }
+            _ => false,
+        }))
+    }
+
+    /// Returns `scriptPubkey` of the output, taking it either from the unsigned transaction (v0)
+    /// or from the output map (v2).
+    pub(crate) fn output_script_pubkey(&self, index: usize) -> Option<ScriptBytes> {
+        if self.version() < 2 {
+            return self.global.known().find_map(|pair| match pair {
+                GlobalPair::UnsignedTx(tx) => {
+                    tx.outputs.get(index).map(|txout| txout.script_pubkey.clone())
+                }
+                _ => None,
+            });
+        }
+        self.outputs.get(index)?.known().find_map(|pair| match pair {
+            OutPair::Script(script) => Some(script.clone()),
+            _ => None,
+        })
+    }
+
+    /// Derives public key with the given origin from one of the global xpubs, which origin is a
+    /// prefix of the key derivation path, continuing with unhardened derivation steps only.
+    fn rederive(&self, origin: &KeyOrigin) -> Option<PublicKey> {
+        self.global.known().find_map(|pair| match pair {
+            GlobalPair::Xpub(derivation)
+                if derivation.master_fp == origin.master_fp
+                    && origin.path.starts_with(&derivation.path) =>
+            {
+                let rest = origin.path[derivation.path.len()..]
+                    .iter()
+                    .map(|index| NormIdx::try_from(*index).ok())
+                    .collect::<Option<Vec<_>>>()?;
+                derivation.xpub.derive(rest).ok().map(|xpub| xpub.public_key())
+            }
+            _ => None,
+        })
+    }
+}
+
+/// Detects whether a single-key `scriptPubkey` is locked to the public key.
+fn script_uses_key(script_pubkey: &ScriptBytes, pubkey: &PublicKey) -> bool {
+    let key_hash = hash160(&pubkey.serialize());
+    match script_pubkey.template() {
+        ScriptTemplate::Pk(pk) => pk == LegacyPk::compressed(*pubkey),
+        ScriptTemplate::Pkh(hash) | ScriptTemplate::Wpkh(hash) => hash == key_hash,
+        ScriptTemplate::Sh(hash) => hash == hash160(&ScriptBytes::p2wpkh(key_hash)),
+        _ => false,
+    }
+}
+
+/// Sums amounts using checked addition, requiring each of the amounts and the sum to not exceed
+/// [`MAX_MONEY`].
+fn sum_amounts(
+    amounts: impl Iterator<Item = Result<u64, AmountError>>,
+) -> Result<u64, AmountError> {
+    let mut sum = 0u64;
+    for amount in amounts {
+        let amount = amount?;
+        if amount > MAX_MONEY {
+            return Err(AmountError::AboveSupplyCap(amount));
+        }
+        sum = sum.checked_add(amount).ok_or(AmountError::Overflow)?;
+    }
+    if sum > MAX_MONEY {
+        return Err(AmountError::AboveSupplyCap(sum));
+    }
+    Ok(sum)
+}
+
+#[cfg(test)]
+mod test {
+    use core::str::FromStr;
+
+    use derive::{ChildIdx, Xpub};
+
+    use super::*;
+    use crate::{
+        InputBuilder, KeyPair, Outpoint, OutputBuilder, PsbtBuilder, SpendInfo, Txid,
+        XpubDerivation,
+    };
+
+    /// PSBT with a single input spending the output with the given `scriptPubkey`.
+    fn psbt_spending(script_pubkey: ScriptBytes, spend_info: SpendInfo) -> Psbt {
+        let input = InputBuilder::new(Outpoint {
+            txid: Txid::from([1; 32]),
+            vout: 0,
+        })
+        .witness_utxo(TxOut {
+            value: 10_000,
+            script_pubkey,
+        });
+        PsbtBuilder::new()
+            .input(input.spend_info(spend_info))
+            .output(OutputBuilder::new(9_000, ScriptBytes::p2wpkh([2; 20])))
+            .build()
+            .unwrap()
+    }
+
+    /// PSBT with a payment output #0 and output #1 with the `scriptPubkey` constructed from the
+    /// key at `m/0/1` of the wallet with `0xABABABAB` master fingerprint, which global xpub is
+    /// present in the PSBT.
+    fn change_psbt(script_pubkey: impl FnOnce(&PublicKey) -> ScriptBytes) -> (Psbt, Fingerprint) {
+        let xpub = Xpub::from_str(
+            "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8",
+        )
+        .unwrap();
+        let wallet_fp = Fingerprint::from([0xAB; 4]);
+        let origin = KeyOrigin {
+            master_fp: wallet_fp,
+            path: vec![ChildIdx::with_raw_value(0), ChildIdx::with_raw_value(1)],
+        };
+        let path = origin.path.iter().map(|index| NormIdx::try_from(*index).unwrap());
+        let pubkey = xpub.derive(path).unwrap().public_key();
+        let change = OutputBuilder::new(1_000, script_pubkey(&pubkey));
+        let psbt = PsbtBuilder::new()
+            .version(2)
+            .global_xpub(XpubDerivation {
+                xpub,
+                master_fp: wallet_fp,
+                path: vec![],
+            })
+            .input(InputBuilder::new(Outpoint {
+                txid: Txid::from([1; 32]),
+                vout: 0,
+            }))
+            .output(OutputBuilder::new(9_000, ScriptBytes::p2wpkh([2; 20])))
+            .output(change.bip32_derivation(pubkey, origin))
+            .build()
+            .unwrap();
+        (psbt, wallet_fp)
+    }
+
+    #[test]
+    fn output_is_change_detects_change() {
+        let (mut psbt, wallet_fp) =
+            change_psbt(|pubkey| ScriptBytes::p2wpkh(hash160(&pubkey.serialize())));
+        assert_eq!(psbt.output_is_change(1, wallet_fp), Ok(true));
+        assert_eq!(psbt.output_is_change(0, wallet_fp), Ok(false));
+        assert_eq!(psbt.output_is_change(2, wallet_fp), Err(AnalysisError::NoOutput(2)));
+
+        psbt.outputs[1].0.retain(|pair| !matches!(pair, KeyPair::Known(OutPair::Script(_))));
+        assert_eq!(psbt.output_is_change(1, wallet_fp), Err(AnalysisError::NoOutputScript(1)));
+    }
+
+    #[test]
+    fn output_is_change_verifies_derivation() {
+        fn key_hash(pubkey: &PublicKey) -> [u8; 20] { hash160(&pubkey.serialize()) }
+        let scripts: [fn(&PublicKey) -> ScriptBytes; 3] = [
+            |pubkey| ScriptBytes::p2wpkh(key_hash(pubkey)),
+            |pubkey| ScriptBytes::p2sh(hash160(&ScriptBytes::p2wpkh(key_hash(pubkey)))),
+            |pubkey| {
+                ScriptBytes::from(
+                    [&[0x76, 0xA9, 0x14][..], &key_hash(pubkey), &[0x88, 0xAC]].concat(),
+                )
+            },
+        ];
+        for script_pubkey in scripts {
+            let (psbt, wallet_fp) = change_psbt(script_pubkey);
+            assert_eq!(psbt.output_is_change(1, wallet_fp), Ok(true));
+            assert_eq!(psbt.output_is_change(1, Fingerprint::from([0xCD; 4])), Ok(false));
+        }
+
+        // Derivation matches, but the output pays to a different key
+        let (psbt, wallet_fp) = change_psbt(|_| ScriptBytes::p2wpkh([3; 20]));
+        assert_eq!(psbt.output_is_change(1, wallet_fp), Ok(false));
+
+        // Output pays to the key claimed by the derivation, which is not derived from the xpub
+        let (mut psbt, wallet_fp) = change_psbt(|pubkey| ScriptBytes::p2wpkh(key_hash(pubkey)));
+        for pair in &mut psbt.outputs[1].0 {
+            if let KeyPair::Known(OutPair::Bip32Derivation { origin, .. }) = pair {
+                origin.path[1] = ChildIdx::with_raw_value(2);
+            }
+        }
+        assert_eq!(psbt.output_is_change(1, wallet_fp), Ok(false));
+    }
+
+    #[test]
+    fn total_amounts() {
+        let input = |vout, value| {
+            let input = InputBuilder::new(Outpoint {
+                txid: Txid::from([1; 32]),
+                vout,
+            });
+            match value {
+                Some(value) => input.witness_utxo(TxOut {
+                    value,
+                    script_pubkey: ScriptBytes::p2wpkh([1; 20]),
+                }),
+                None => input,
+            }
+        };
+        let output = |value| OutputBuilder::new(value, ScriptBytes::p2wpkh([2; 20]));
+        let psbt_with = |inputs: &[Option<u64>], outputs: &[u64]| {
+            let mut builder = PsbtBuilder::new().version(2);
+            for (vout, value) in inputs.iter().enumerate() {
+                builder = builder.input(input(vout as u32, *value));
+            }
+            for value in outputs {
+                builder = builder.output(output(*value));
+            }
+            builder.build().unwrap()
+        };
+
+        let mut psbt = psbt_with(&[Some(6_000), Some(4_000)], &[7_000, 2_000]);
+        assert_eq!(psbt.total_input_amount(), Ok(10_000));
+        assert_eq!(psbt.total_output_amount(), Ok(9_000));
+        psbt.outputs[1].0.retain(|pair| !matches!(pair, KeyPair::Known(OutPair::Amount(_))));
+        assert_eq!(psbt.total_output_amount(), Err(AmountError::NoOutputAmount(1)));
+
+        let psbt = psbt_with(&[Some(6_000), None], &[]);
+        assert_eq!(psbt.total_input_amount(), Err(AmountError::NoInputAmount(1)));
+        assert_eq!(psbt.total_output_amount(), Ok(0));
+
+        let psbt = psbt_with(&[Some(MAX_MONEY + 1)], &[MAX_MONEY + 1]);
+        assert_eq!(psbt.total_input_amount(), Err(AmountError::AboveSupplyCap(MAX_MONEY + 1)));
+        assert_eq!(psbt.total_output_amount(), Err(AmountError::AboveSupplyCap(MAX_MONEY + 1)));
+
+        let psbt = psbt_with(&[Some(MAX_MONEY), Some(1_000)], &[MAX_MONEY, 1_000]);
+        let sum = MAX_MONEY + 1_000;
+        assert_eq!(psbt.total_input_amount(), Err(AmountError::AboveSupplyCap(sum)));
+        assert_eq!(psbt.total_output_amount(), Err(AmountError::AboveSupplyCap(sum)));
+
+        // Sum overflows before the final supply cap check
+        let amounts = core::iter::repeat(Ok(MAX_MONEY)).take(10_000);
+        assert_eq!(sum_amounts(amounts), Err(AmountError::Overflow));
+    }
+
+    #[test]
+    fn effective_script_unwraps_p2sh_p2wsh() {
+        let witness_script = ScriptBytes::from(vec![0x51]);
+        let redeem_script = ScriptBytes::p2wsh(sha256(&witness_script));
+        let script_pubkey = ScriptBytes::p2sh(hash160(&redeem_script));
+        let psbt_with = |spend_info| psbt_spending(script_pubkey.clone(), spend_info);
+
+        let psbt = psbt_with(SpendInfo {
+            redeem_script: Some(redeem_script.clone()),
+            witness_script: Some(witness_script.clone()),
+            ..none!()
+        });
+        assert_eq!(psbt.effective_script(0), Ok(witness_script));
+        assert_eq!(psbt.effective_script(1), Err(AnalysisError::NoInput(1)));
+
+        let psbt = psbt_with(SpendInfo {
+            redeem_script: Some(redeem_script),
+            witness_script: Some(ScriptBytes::from(vec![0x52])),
+            ..none!()
+        });
+        assert_eq!(psbt.effective_script(0), Err(AnalysisError::NoScript(0)));
+    }
+
+    #[test]
+    fn input_is_segwit_checks_redeem_script() {
+        let redeem_script = ScriptBytes::p2wpkh([3; 20]);
+        let script_pubkey = ScriptBytes::p2sh(hash160(&redeem_script));
+        let psbt = psbt_spending(script_pubkey.clone(), none!());
+        assert_eq!(psbt.input_is_segwit(0), Err(AnalysisError::UnknownScriptType(0)));
+        assert_eq!(psbt.input_is_segwit(1), Err(AnalysisError::NoInput(1)));
+
+        let psbt = psbt_spending(script_pubkey.clone(), SpendInfo {
+            redeem_script: Some(redeem_script),
+            ..none!()
+        });
+        assert_eq!(psbt.input_is_segwit(0), Ok(true));
+
+        // Witness program which is not committed to by the spent output
+        let psbt = psbt_spending(script_pubkey, SpendInfo {
+            redeem_script: Some(ScriptBytes::p2wpkh([4; 20])),
+            ..none!()
+        });
+        assert_eq!(psbt.input_is_segwit(0), Err(AnalysisError::RedeemScriptMismatch(0)));
+
+        let legacy = ScriptBytes::from(vec![0x51]);
+        let psbt = psbt_spending(ScriptBytes::p2sh(hash160(&legacy)), SpendInfo {
+            redeem_script: Some(legacy),
+            ..none!()
+        });
+        assert_eq!(psbt.input_is_segwit(0), Ok(false));
+        let psbt = psbt_spending(ScriptBytes::p2wpkh([3; 20]), none!());
+        assert_eq!(psbt.input_is_segwit(0), Ok(true));
+    }
+}
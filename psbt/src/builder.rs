@@ -0,0 +1,466 @@
+// Partially signed bitcoin transaction library (BIP174, BIP370, BIP371)
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fluent construction of PSBTs for the creator role.
+
+use secp256k1::{PublicKey, XOnlyPublicKey};
+
+use crate::{
+    GlobalPair, InPair, KeyMap, KeyOrigin, KeyPair, OutPair, Outpoint, Psbt, PsbtSighashType,
+    ScriptBytes, SharedTx, SpendInfo, Tx, TxIn, TxOut, ValidationError, XpubDerivation,
+    TX_MODIFIABLE_INPUTS, TX_MODIFIABLE_OUTPUTS,
+};
+
+/// Errors building PSBT with [`PsbtBuilder`].
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum BuildError {
+    /// PSBT version {0} is not supported; only versions 0 and 2 can be built.
+    UnsupportedVersion(u32),
+
+    /// {0} is defined only for PSBT v2, while PSBT v0 is built.
+    V2Only(&'static str),
+
+    /// extended public key is added more than once with different origins.
+    XpubOriginConflict,
+
+    /// output #{index} amount {amount} sats is below the dust limit of {limit} sats for its
+    /// `scriptPubkey`, which makes the transaction non-standard.
+    DustOutput {
+        index: usize,
+        amount: u64,
+        limit: u64,
+    },
+
+    #[from]
+    #[display(inner)]
+    Invalid(ValidationError),
+}
+
+/// Input of PSBT constructed with [`PsbtBuilder`].
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct InputBuilder {
+    prev_output: Outpoint,
+    sequence: u32,
+    non_witness_utxo: Option<SharedTx>,
+    witness_utxo: Option<TxOut>,
+    spend_info: SpendInfo,
+    sighash_type: Option<PsbtSighashType>,
+    required_time_locktime: Option<u32>,
+    required_height_locktime: Option<u32>,
+}
+
+impl InputBuilder {
+    /// Starts construction of the input spending `prev_output` with the final sequence number.
+    pub fn new(prev_output: Outpoint) -> Self {
+        InputBuilder {
+            prev_output,
+            sequence: u32::MAX,
+            non_witness_utxo: None,
+            witness_utxo: None,
+            spend_info: none!(),
+            sighash_type: None,
+            required_time_locktime: None,
+            required_height_locktime: None,
+        }
+    }
+
+    /// Sets sequence number of the input.
+    pub fn sequence(mut self, sequence: u32) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    /// Adds the full transaction containing the spent output.
+    pub fn non_witness_utxo(mut self, tx: impl Into<SharedTx>) -> Self {
+        self.non_witness_utxo = Some(tx.into());
+        self
+    }
+
+    /// Adds the spent output; required for segwit inputs.
+    pub fn witness_utxo(mut self, txout: TxOut) -> Self {
+        self.witness_utxo = Some(txout);
+        self
+    }
+
+    /// Adds scripts, key origins and taproot data required to spend the input.
+    pub fn spend_info(mut self, info: SpendInfo) -> Self {
+        self.spend_info = info;
+        self
+    }
+
+    /// Sets signature hash type which must be used by signers of the input.
+    pub fn sighash_type(mut self, ty: impl Into<PsbtSighashType>) -> Self {
+        self.sighash_type = Some(ty.into());
+        self
+    }
+
+    /// Sets minimal time-based lock time required by the input (v2 only).
+    pub fn required_time_locktime(mut self, lock_time: u32) -> Self {
+        self.required_time_locktime = Some(lock_time);
+        self
+    }
+
+    /// Sets minimal height-based lock time required by the input (v2 only).
+    pub fn required_height_locktime(mut self, lock_time: u32) -> Self {
+        self.required_height_locktime = Some(lock_time);
+        self
+    }
+}
+
+/// Output of PSBT constructed with [`PsbtBuilder`].
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct OutputBuilder {
+    txout: TxOut,
+    redeem_script: Option<ScriptBytes>,
+    witness_script: Option<ScriptBytes>,
+    bip32_derivations: Vec<(PublicKey, KeyOrigin)>,
+    tap_internal_key: Option<XOnlyPublicKey>,
+}
+
+impl OutputBuilder {
+    /// Starts construction of the output paying `value` satoshis to the `script_pubkey`.
+    pub fn new(value: u64, script_pubkey: ScriptBytes) -> Self {
+        OutputBuilder {
+            txout: TxOut {
+                value,
+                script_pubkey,
+            },
+            redeem_script: None,
+            witness_script: None,
+            bip32_derivations: vec![],
+            tap_internal_key: None,
+        }
+    }
+
+    /// Adds redeem script of a P2SH output.
+    pub fn redeem_script(mut self, script: ScriptBytes) -> Self {
+        self.redeem_script = Some(script);
+        self
+    }
+
+    /// Adds witness script of a P2WSH output.
+    pub fn witness_script(mut self, script: ScriptBytes) -> Self {
+        self.witness_script = Some(script);
+        self
+    }
+
+    /// Adds origin of a public key required to spend the output (for instance, of a change
+    /// output).
+    pub fn bip32_derivation(mut self, pubkey: PublicKey, origin: KeyOrigin) -> Self {
+        self.bip32_derivations.push((pubkey, origin));
+        self
+    }
+
+    /// Adds taproot internal key of a P2TR output.
+    pub fn tap_internal_key(mut self, key: XOnlyPublicKey) -> Self {
+        self.tap_internal_key = Some(key);
+        self
+    }
+}
+
+/// Builder of PSBTs, accumulating global data, inputs and outputs via chained method calls.
+///
+/// PSBT v0 is built unless a different version is set with [`PsbtBuilder::version`]. Settings
+/// which contradict the version (like PSBT v2 fields in PSBT v0) are reported by
+/// [`PsbtBuilder::build`], which also validates the produced PSBT.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct PsbtBuilder {
+    version: u32,
+    tx_version: u32,
+    lock_time: Option<u32>,
+    tx_modifiable: Option<u8>,
+    xpubs: Vec<XpubDerivation>,
+    inputs: Vec<InputBuilder>,
+    outputs: Vec<OutputBuilder>,
+}
+
+impl Default for PsbtBuilder {
+    fn default() -> Self { PsbtBuilder::new() }
+}
+
+impl PsbtBuilder {
+    /// Starts construction of PSBT v0 for a version 2 transaction.
+    pub fn new() -> Self {
+        PsbtBuilder {
+            version: 0,
+            tx_version: 2,
+            lock_time: None,
+            tx_modifiable: None,
+            xpubs: vec![],
+            inputs: vec![],
+            outputs: vec![],
+        }
+    }
+
+    /// Sets PSBT version; only versions 0 and 2 are supported.
+    pub fn version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Sets version of the transaction.
+    pub fn tx_version(mut self, tx_version: u32) -> Self {
+        self.tx_version = tx_version;
+        self
+    }
+
+    /// Sets transaction lock time for PSBT v0, or the fallback lock time for PSBT v2.
+    pub fn lock_time(mut self, lock_time: u32) -> Self {
+        self.lock_time = Some(lock_time);
+        self
+    }
+
+    /// Sets `PSBT_GLOBAL_TX_MODIFIABLE` flags (v2 only).
+    ///
+    /// If not set, PSBT v2 is built with both inputs and outputs modifiable; the flags are
+    /// restricted later as signatures get added.
+    pub fn tx_modifiable(mut self, flags: u8) -> Self {
+        self.tx_modifiable = Some(flags);
+        self
+    }
+
+    /// Adds extended public key with its origin to the global map.
+    pub fn global_xpub(mut self, derivation: XpubDerivation) -> Self {
+        self.xpubs.push(derivation);
+        self
+    }
+
+    /// Adds input to the end of the list of inputs.
+    pub fn input(mut self, input: InputBuilder) -> Self {
+        self.inputs.push(input);
+        self
+    }
+
+    /// Adds output to the end of the list of outputs.
+    pub fn output(mut self, output: OutputBuilder) -> Self {
+        self.outputs.push(output);
+        self
+    }
+
+    /// Produces PSBT from the accumulated data and validates it.
+    ///
+    /// Outputs with amounts below the dust limit of their `scriptPubkey` (see
+    /// [`ScriptBytes::dust_limit`]) are rejected, since the transaction wouldn't be relayed.
+    pub fn build(self) -> Result<Psbt, BuildError> {
+        let v2 = match self.version {
+            0 => false,
+            2 => true,
+            version => return Err(BuildError::UnsupportedVersion(version)),
+        };
+        if !v2 {
+            if self.tx_modifiable.is_some() {
+                return Err(BuildError::V2Only("PSBT_GLOBAL_TX_MODIFIABLE"));
+            }
+            if self.inputs.iter().any(|input| input.required_time_locktime.is_some()) {
+                return Err(BuildError::V2Only("PSBT_IN_REQUIRED_TIME_LOCKTIME"));
+            }
+            if self.inputs.iter().any(|input| input.required_height_locktime.is_some()) {
+                return Err(BuildError::V2Only("PSBT_IN_REQUIRED_HEIGHT_LOCKTIME"));
+            }
+        }
+        for (index, output) in self.outputs.iter().enumerate() {
+            let (amount, limit) = (output.txout.value, output.txout.script_pubkey.dust_limit());
+            if amount < limit {
+                return Err(BuildError::DustOutput {
+                    index,
+                    amount,
+                    limit,
+                });
+            }
+        }
+
+        let mut psbt = Psbt {
+            global: KeyMap(vec![]),
+            inputs: vec![],
+            outputs: vec![],
+        };
+        if v2 {
+            let global = [
+                Some(GlobalPair::Version(2)),
+                Some(GlobalPair::TxVersion(self.tx_version)),
+                self.lock_time.map(GlobalPair::FallbackLocktime),
+                Some(GlobalPair::InputCount(self.inputs.len() as u64)),
+                Some(GlobalPair::OutputCount(self.outputs.len() as u64)),
+                Some(GlobalPair::TxModifiable(
+                    self.tx_modifiable.unwrap_or(TX_MODIFIABLE_INPUTS | TX_MODIFIABLE_OUTPUTS),
+                )),
+            ];
+            psbt.global.0.extend(global.into_iter().flatten().map(KeyPair::Known));
+        } else {
+            let tx = Tx {
+                version: self.tx_version,
+                inputs: self
+                    .inputs
+                    .iter()
+                    .map(|input| TxIn {
+                        prev_output: input.prev_output,
+                        sig_script: none!(),
+                        sequence: input.sequence,
+                        witness: none!(),
+                    })
+                    .collect(),
+                outputs: self.outputs.iter().map(|output| output.txout.clone()).collect(),
+                lock_time: self.lock_time.unwrap_or_default(),
+            };
+            psbt.global.0.push(KeyPair::Known(GlobalPair::UnsignedTx(tx)));
+        }
+        for derivation in self.xpubs {
+            psbt.add_global_xpub(derivation).map_err(|_| BuildError::XpubOriginConflict)?;
+        }
+
+        for (index, input) in self.inputs.into_iter().enumerate() {
+            let mut pairs = vec![];
+            if v2 {
+                pairs.push(InPair::PreviousTxid(input.prev_output.txid));
+                pairs.push(InPair::OutputIndex(input.prev_output.vout));
+                pairs.push(InPair::Sequence(input.sequence));
+                pairs.extend(input.required_time_locktime.map(InPair::RequiredTimeLocktime));
+                pairs.extend(input.required_height_locktime.map(InPair::RequiredHeightLocktime));
+            }
+            pairs.extend(input.non_witness_utxo.map(InPair::NonWitnessUtxo));
+            pairs.extend(input.witness_utxo.map(InPair::WitnessUtxo));
+            pairs.extend(input.sighash_type.map(InPair::SighashType));
+            psbt.inputs.push(KeyMap(pairs.into_iter().map(KeyPair::Known).collect()));
+            psbt.update_input(index, &input.spend_info).expect("input is just added");
+        }
+
+        for output in self.outputs {
+            let mut pairs = vec![];
+            if v2 {
+                pairs.push(OutPair::Amount(output.txout.value));
+                pairs.push(OutPair::Script(output.txout.script_pubkey));
+            }
+            pairs.extend(output.redeem_script.map(OutPair::RedeemScript));
+            pairs.extend(output.witness_script.map(OutPair::WitnessScript));
+            pairs.extend(
+                output
+                    .bip32_derivations
+                    .into_iter()
+                    .map(|(pubkey, origin)| OutPair::Bip32Derivation { pubkey, origin }),
+            );
+            pairs.extend(output.tap_internal_key.map(OutPair::TapInternalKey));
+            psbt.outputs.push(KeyMap(pairs.into_iter().map(KeyPair::Known).collect()));
+        }
+
+        psbt.validate()?;
+        Ok(psbt)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::str::FromStr;
+
+    use derive::{Fingerprint, Xpub};
+
+    use super::*;
+    use crate::Txid;
+
+    fn input() -> InputBuilder {
+        InputBuilder::new(Outpoint {
+            txid: Txid::from([1; 32]),
+            vout: 0,
+        })
+    }
+
+    fn output() -> OutputBuilder { OutputBuilder::new(9_000, ScriptBytes::p2wpkh([2; 20])) }
+
+    #[test]
+    fn build_v0_and_v2() {
+        let v0 = PsbtBuilder::new().lock_time(100).input(input()).output(output()).build().unwrap();
+        assert_eq!(v0.version(), 0);
+        assert_eq!(v0.tx_modifiable(), None);
+        let tx = v0.to_unsigned_tx().unwrap();
+        assert_eq!(tx.version, 2);
+        assert_eq!(tx.lock_time, 100);
+        assert_eq!(tx.inputs[0].sequence, u32::MAX);
+        assert_eq!(tx.outputs, vec![output().txout]);
+
+        let v2 = PsbtBuilder::new()
+            .version(2)
+            .tx_version(1)
+            .lock_time(100)
+            .input(input().sequence(0xFFFF_FFFE).required_height_locktime(200))
+            .output(output())
+            .build()
+            .unwrap();
+        assert_eq!(v2.version(), 2);
+        assert_eq!(v2.tx_modifiable(), Some(TX_MODIFIABLE_INPUTS | TX_MODIFIABLE_OUTPUTS));
+        let tx = v2.to_unsigned_tx().unwrap();
+        assert_eq!(tx.version, 1);
+        assert_eq!(tx.lock_time, 200);
+        assert_eq!(tx.inputs[0].prev_output, input().prev_output);
+        assert_eq!(tx.inputs[0].sequence, 0xFFFF_FFFE);
+        assert_eq!(tx.outputs, vec![output().txout]);
+
+        let v2 = PsbtBuilder::new().version(2).tx_modifiable(0).build().unwrap();
+        assert_eq!(v2.tx_modifiable(), Some(0));
+    }
+
+    #[test]
+    fn build_errors() {
+        assert_eq!(PsbtBuilder::new().version(1).build(), Err(BuildError::UnsupportedVersion(1)));
+        assert_eq!(
+            PsbtBuilder::new().tx_modifiable(0).build(),
+            Err(BuildError::V2Only("PSBT_GLOBAL_TX_MODIFIABLE"))
+        );
+        assert_eq!(
+            PsbtBuilder::new().input(input().required_time_locktime(500_000_000)).build(),
+            Err(BuildError::V2Only("PSBT_IN_REQUIRED_TIME_LOCKTIME"))
+        );
+        assert_eq!(
+            PsbtBuilder::new().input(input().required_height_locktime(100)).build(),
+            Err(BuildError::V2Only("PSBT_IN_REQUIRED_HEIGHT_LOCKTIME"))
+        );
+        assert_eq!(
+            PsbtBuilder::new()
+                .output(output())
+                .output(OutputBuilder::new(293, ScriptBytes::p2wpkh([2; 20])))
+                .build(),
+            Err(BuildError::DustOutput {
+                index: 1,
+                amount: 293,
+                limit: 294
+            })
+        );
+
+        let xpub = Xpub::from_str(
+            "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8",
+        )
+        .unwrap();
+        let derivation = |fp| XpubDerivation {
+            xpub,
+            master_fp: Fingerprint::from([fp; 4]),
+            path: vec![],
+        };
+        assert_eq!(
+            PsbtBuilder::new().global_xpub(derivation(1)).global_xpub(derivation(2)).build(),
+            Err(BuildError::XpubOriginConflict)
+        );
+        assert!(PsbtBuilder::new()
+            .global_xpub(derivation(1))
+            .global_xpub(derivation(1))
+            .build()
+            .is_ok());
+    }
+}
@@ -0,0 +1,585 @@
+// Partially signed bitcoin transaction library (BIP174, BIP370, BIP371)
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Operations of the PSBT combiner role.
+
+use crate::scripts::{hash160, sha256, ScriptTemplate};
+use crate::sighash::tap_leaf_hash;
+use crate::{
+    GlobalPair, InPair, KeyMap, KeyPair, KnownPair, LegacyPk, MapLocation, PairChange, PairDiff,
+    Psbt, ScriptBytes,
+};
+
+/// Errors combining PSBTs.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum CombineError {
+    /// PSBTs have different versions ({0} and {1}).
+    VersionMismatch(u32, u32),
+
+    /// PSBTs describe different transactions.
+    DifferentTx,
+
+    #[from]
+    #[display(inner)]
+    Dedup(DedupError),
+}
+
+/// Errors removing duplicated signatures.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum DedupError {
+    /// input #{input} contains different signatures for the same public key {pubkey:?}, which
+    /// indicates a misbehaving signer.
+    ConflictingSig { input: usize, pubkey: LegacyPk },
+}
+
+/// Errors merging signatures from a signed PSBT.
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum StrictMergeError {
+    /// signed PSBT has different number of inputs or outputs.
+    DifferentTx,
+
+    /// signed PSBT contains a modification not allowed to a signer ({0}).
+    Modified(PairDiff),
+}
+
+impl Psbt {
+    /// Combines other PSBT describing the same transaction into the current one.
+    ///
+    /// Key-value pairs from the `other` PSBT are added to the current one unless the current PSBT
+    /// already has a pair with the same key, in which case the existing pair is kept. The
+    /// exception are partial signatures, which are always added, such that after the merge
+    /// [`Psbt::dedup_partial_sigs`] can detect signers producing different signatures for the
+    /// same key.
+    ///
+    /// Pairs identical to the existing ones, including proprietary and unknown pairs, are never
+    /// duplicated, such that combining PSBT with itself or with already merged contributions
+    /// doesn't change it (up to the order of pairs, see [`Psbt::sort_keys`]).
+    ///
+    /// The current PSBT is not modified if an error is returned.
+    pub fn combine(&mut self, other: Psbt) -> Result<(), CombineError> {
+        let (version, other_version) = (self.version(), other.version());
+        if version != other_version {
+            return Err(CombineError::VersionMismatch(version, other_version));
+        }
+        let same_tx = if version < 2 {
+            let tx = |psbt: &Psbt| {
+                psbt.global.known().find_map(|pair| match pair {
+                    GlobalPair::UnsignedTx(tx) => Some(tx.clone()),
+                    _ => None,
+                })
+            };
+            tx(self).is_some() && tx(self) == tx(&other)
+        } else {
+            matches!((self.to_unsigned_tx(), other.to_unsigned_tx()), (Ok(a), Ok(b)) if a == b)
+        };
+        if !same_tx
+            || self.inputs.len() != other.inputs.len()
+            || self.outputs.len() != other.outputs.len()
+        {
+            return Err(CombineError::DifferentTx);
+        }
+
+        let mut combined = self.clone();
+        combined.global.merge(other.global, |_| false);
+        for (map, other) in combined.inputs.iter_mut().zip(other.inputs) {
+            map.merge(other, |pair| matches!(pair, InPair::PartialSig { .. }));
+        }
+        for (map, other) in combined.outputs.iter_mut().zip(other.outputs) {
+            map.merge(other, |_| false);
+        }
+        combined.dedup_partial_sigs()?;
+
+        *self = combined;
+        Ok(())
+    }
+
+    /// Imports signatures from a PSBT returned by an untrusted signer, rejecting any other
+    /// modifications.
+    ///
+    /// The `signed` PSBT must describe the [same transaction](Psbt::same_transaction) as the
+    /// current one. Only partial signatures, taproot signatures and finalized input scripts which
+    /// are not yet present in the current PSBT are imported, together with the restriction of
+    /// `PSBT_GLOBAL_TX_MODIFIABLE` flags made by the signer. Inputs finalized by the signer drop
+    /// the fields removed by its finalizer. Errors if the signer modified any other pair (like
+    /// UTXO, script or output data) or replaced an existing signature; other pairs removed by
+    /// the signer are kept. The current PSBT is not modified if an error is returned.
+    pub fn merge_signatures_only(&mut self, signed: Psbt) -> Result<(), StrictMergeError> {
+        if self.inputs.len() != signed.inputs.len() || self.outputs.len() != signed.outputs.len() {
+            return Err(StrictMergeError::DifferentTx);
+        }
+        if let Some(diff) = self.diff(&signed).0.into_iter().find(|diff| {
+            !self.is_signer_change(&signed, diff)
+                || (diff.change == PairChange::Changed
+                    && matches!(diff.location, MapLocation::Input(_)))
+        }) {
+            return Err(StrictMergeError::Modified(diff));
+        }
+
+        if let Some(flags) = signed.tx_modifiable() {
+            self.global.replace_singleton(GlobalPair::TxModifiable(flags));
+        }
+        for (map, signed) in self.inputs.iter_mut().zip(signed.inputs) {
+            let finalized = signed.known().any(|pair| {
+                matches!(pair, InPair::FinalScriptSig(_) | InPair::FinalScriptWitness(_))
+            });
+            if finalized {
+                map.0.retain(|pair| signed.0.iter().any(|p| p.key() == pair.key()));
+            }
+            // All the pairs which are not yet present are the added signatures
+            map.merge(signed, |_| false);
+        }
+        Ok(())
+    }
+
+    /// Removes duplicated partial signatures, keeping a single signature per public key in each
+    /// of the inputs.
+    ///
+    /// Errors if an input contains two different signatures for the same public key, since this
+    /// indicates a misbehaving signer. In this case the PSBT is not modified.
+    pub fn dedup_partial_sigs(&mut self) -> Result<(), DedupError> {
+        for (index, map) in self.inputs.iter().enumerate() {
+            let sigs = map
+                .known()
+                .filter_map(|pair| match pair {
+                    InPair::PartialSig { pubkey, sig } => Some((pubkey, sig)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            for (pos, &(pubkey, sig)) in sigs.iter().enumerate() {
+                if sigs[pos + 1..].iter().any(|&(pk, s)| pk == pubkey && s != sig) {
+                    return Err(DedupError::ConflictingSig {
+                        input: index,
+                        pubkey: *pubkey,
+                    });
+                }
+            }
+        }
+
+        for map in &mut self.inputs {
+            let mut seen = Vec::<LegacyPk>::new();
+            map.0.retain(|pair| match pair {
+                KeyPair::Known(InPair::PartialSig { pubkey, .. }) if seen.contains(pubkey) => false,
+                KeyPair::Known(InPair::PartialSig { pubkey, .. }) => {
+                    seen.push(*pubkey);
+                    true
+                }
+                _ => true,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Psbt {
+    /// Detects whether all inputs have enough signatures to be finalized.
+    ///
+    /// Inputs are considered complete if they are already finalized, have a taproot key spend
+    /// signature, have taproot script path signatures for the threshold number of keys of some
+    /// of their single-key or `OP_CHECKSIGADD` multisig leaf scripts, or have partial signatures
+    /// for the keys required by their scripts. Signatures are matched against the keys but are
+    /// not verified. Inputs which script type can't be determined are never considered complete.
+    pub fn has_all_signatures(&self) -> bool {
+        (0..self.inputs.len()).all(|index| self.input_has_all_signatures(index))
+    }
+
+    fn input_has_all_signatures(&self, index: usize) -> bool {
+        let map = &self.inputs[index];
+        if map.known().any(|pair| {
+            matches!(
+                pair,
+                InPair::FinalScriptSig(_) | InPair::FinalScriptWitness(_) | InPair::TapKeySig(_)
+            )
+        }) {
+            return true;
+        }
+        if map.known().any(|pair| match pair {
+            InPair::TapLeafScript {
+                script,
+                leaf_ver: leaf_ver @ 0xC0,
+                ..
+            } => {
+                let leaf_hash = tap_leaf_hash(*leaf_ver, script);
+                script.tap_multisig().map_or(false, |(threshold, keys)| {
+                    let signed = keys.iter().filter(|key| {
+                        map.known().any(|pair| {
+                            matches!(pair, InPair::TapScriptSig { pubkey, leaf_hash: hash, .. }
+                                if pubkey.serialize() == **key && *hash == leaf_hash)
+                        })
+                    });
+                    signed.count() >= threshold
+                })
+            }
+            _ => false,
+        }) {
+            return true;
+        }
+
+        let script_pubkey = match self.spent_script_pubkey(index) {
+            Some(script) => script,
+            None => return false,
+        };
+        let find_script = |f: fn(&InPair) -> Option<&ScriptBytes>| map.known().find_map(f);
+        let redeem_script = find_script(|pair| match pair {
+            InPair::RedeemScript(script) => Some(script),
+            _ => None,
+        });
+        let witness_script = find_script(|pair| match pair {
+            InPair::WitnessScript(script) => Some(script),
+            _ => None,
+        });
+        let signed_keys = map
+            .known()
+            .filter_map(|pair| match pair {
+                InPair::PartialSig { pubkey, .. } => Some(*pubkey),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let mut script = &script_pubkey;
+        if let ScriptTemplate::Sh(hash) = script.template() {
+            match redeem_script {
+                Some(redeem_script) if hash160(redeem_script) == hash => script = redeem_script,
+                _ => return false,
+            }
+        }
+        if let ScriptTemplate::Wsh(hash) = script.template() {
+            match witness_script {
+                Some(witness_script) if sha256(witness_script) == hash => script = witness_script,
+                _ => return false,
+            }
+        }
+
+        match script.template() {
+            ScriptTemplate::Pk(key) => signed_keys.contains(&key),
+            ScriptTemplate::Pkh(hash) | ScriptTemplate::Wpkh(hash) => {
+                signed_keys.iter().any(|key| hash160(&key.to_vec()) == hash)
+            }
+            ScriptTemplate::Multisig { threshold, keys } => {
+                keys.iter().filter(|key| signed_keys.contains(key)).count() >= threshold
+            }
+            ScriptTemplate::Sh(_)
+            | ScriptTemplate::Wsh(_)
+            | ScriptTemplate::Tr(_)
+            | ScriptTemplate::Other => false,
+        }
+    }
+}
+
+impl<T: KnownPair + Eq> KeyMap<T> {
+    /// Adds pairs from other map which keys are not yet present in this map. Pairs for which
+    /// `keep_conflicting` returns `true` are added even if the key is already present, unless
+    /// the map already contains exactly the same pair.
+    fn merge(&mut self, other: KeyMap<T>, keep_conflicting: impl Fn(&T) -> bool) {
+        for pair in other.0 {
+            if self.0.contains(&pair) {
+                continue;
+            }
+            let conflicts = self.0.iter().any(|p| p.key() == pair.key());
+            let keep = match &pair {
+                KeyPair::Known(known) => keep_conflicting(known),
+                KeyPair::Unknown(_) | KeyPair::Proprietary(_) => false,
+            };
+            if !conflicts || keep {
+                self.0.push(pair);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::hex::FromHex;
+    use secp256k1::{ecdsa, schnorr, KeyPair, SecretKey, SECP256K1};
+
+    use super::*;
+    use crate::sighash::tap_tweak;
+    use crate::{
+        ControlBlock, EcdsaSig, InputBuilder, OutPair, Outpoint, OutputBuilder, ProprietaryPair,
+        PsbtBuilder, SchnorrSig, SpendInfo, TxOut, Txid,
+    };
+
+    // BIP-174 test vectors with partial signatures, BIP-32 derivations and unknown output pairs
+    const VECTORS: [&str; 3] = [
+        "70736274ff0100550200000001279a2323a5dfb51fc45f220fa58b0fc13e1e3342792a85d7e36cd6333b5cbc\
+         390000000000ffffffff01a05aea0b000000001976a914ffe9c0061097cc3b636f2cb0460fa4fc427d2b4588\
+         ac0000000000010120955eea0b0000000017a9146345200f68d189e1adc0df1c4d16ea8f14c0dbeb87220203\
+         b1341ccba7683b6af4f1238cd6e97e7167d569fac47f1e48d47541844355bd4646304302200424b58effaaa6\
+         94e1559ea5c93bbfd4a89064224055cdf070b6771469442d07021f5c8eb0fea6516d60b8acb33ad64ede60e8\
+         785bfb3aa94b99bdf86151db9a9a010104220020771fd18ad459666dd49f3d564e3dbc42f4c84774e360ada1\
+         6816a8ed488d5681010547522103b1341ccba7683b6af4f1238cd6e97e7167d569fac47f1e48d47541844355\
+         bd462103de55d1e1dac805e3f8a58c1fbf9b94c02f3dbaafe127fefca4995f26f82083bd52ae220603b1341c\
+         cba7683b6af4f1238cd6e97e7167d569fac47f1e48d47541844355bd4610b4a6ba6700000080000000800400\
+         0080220603de55d1e1dac805e3f8a58c1fbf9b94c02f3dbaafe127fefca4995f26f82083bd10b4a6ba670000\
+         008000000080050000800000",
+        "70736274ff01005e020000000127744ababf3027fe0d6cf23a96eee2efb188ef52301954585883e69b6624b2\
+         420000000000ffffffff0148e6052a010000002251200a8cbdc86de1ce1c0f9caeb22d6df7ced3683fe423e0\
+         5d1e402a879341d6f6f5000000000001012b00f2052a010000002251205a2c2cf5b52cf31f83ad2e8da63ff0\
+         3183ecd8f609c7510ae8a48e03910a07572116fe349064c98d6e2a853fa3c9b12bd8b304a19c195c60efa7ee\
+         2393046d3fa2321900772b2da75600008001000080000000800100000000000000011720fe349064c98d6e2a\
+         853fa3c9b12bd8b304a19c195c60efa7ee2393046d3fa2320001052050929b74c1a04954b78b4b6035e97a5e\
+         078a5a0f28ec96d547bfee9ace803ac001066f02c02220736e572900fe1252589a2143c8f3c79f71a0412d23\
+         53af755e9701c782694a02ac02c02220631c5f3b5832b8fbdebfb19704ceeb323c21f40f7a24f43d68ef0cc2\
+         6b125969ac01c0222044faa49a0338de488c8dfffecdfb6f329f380bd566ef20c8df6d813eab1c4273ac2107\
+         44faa49a0338de488c8dfffecdfb6f329f380bd566ef20c8df6d813eab1c42733901f06b798b92a10ed9a9d0\
+         bbfd3af173a53b1617da3a4159ca008216cd856b2e0e772b2da7560000800100008001000080000000000300\
+         0000210750929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac005007c461e5d2107\
+         631c5f3b5832b8fbdebfb19704ceeb323c21f40f7a24f43d68ef0cc26b125969390118ace409889785e0ea70\
+         ceebb8e1ca892a7a78eaede0f2e296cf435961a8f4ca772b2da7560000800100008002000080000000000300\
+         00002107736e572900fe1252589a2143c8f3c79f71a0412d2353af755e9701c782694a02390129a5b4915090\
+         162d759afd3fe0f93fa3326056d0b4088cb933cae7826cb8d82c772b2da75600008001000080030000800000\
+         00000300000000",
+        "70736274ff0100890200000001207ae985d787dfe6143d5c58fad79cc7105e0e799fcf033b7f2ba17e62d7b3\
+         200000000000ffffffff02563d03000000000022002019899534b9a011043c0dd57c3ff9a381c3522c5f27c6\
+         a42319085b56ca543a1d6adc020000000000220020618b47a07ebecca4e156edb1b9ea7c24bdee0139fc0492\
+         37965ffdaf56d5ee73000000000001012b801a0600000000002200201148e93e9315e37dbed2121be5239257\
+         af35adc03ffdfc5d914b083afa44dab82202025fe7371376d53cf8a2783917c28bf30bd690b0a4d4a2076900\
+         93ca2b920ee076473044022007e06b362e89912abd4661f47945430739b006a85d1b2a16c01dc1a4bd07acab\
+         022061576d7aa834988b7ab94ef21d8eebd996ea59ea20529a19b15f0c9cebe3d8ac01220202b3fe93530020\
+         a8294f0e527e33fbdff184f047eb6b5a1558a352f62c29972f8a473044022002787f926d6817504431ee2811\
+         83b8119b6845bfaa6befae45e13b6d430c9d2f02202859f149a6cd26ae2f03a107e7f33c7d91730dade305fe\
+         077bae677b5d44952a01010547522102b3fe93530020a8294f0e527e33fbdff184f047eb6b5a1558a352f62c\
+         29972f8a21025fe7371376d53cf8a2783917c28bf30bd690b0a4d4a207690093ca2b920ee07652ae00010147\
+         52210283ef76537f2d58ae3aa3a4bd8ae41c3f230ccadffb1a0bd3ca504d871cff05e7210353d79cc0cb1396\
+         f4ce278d005f16d948e02a6aec9ed1109f13747ecb1507b37b52ae00010147522102b3937241777b6665e0d6\
+         94e52f9c1b188433641df852da6fc42187b5d8a368a321034cdd474f01cc5aa7ff834ad8bcc882a87e854aff\
+         c775486bc2a9f62e8f49bd7852ae00",
+    ];
+
+    fn vectors() -> impl Iterator<Item = Psbt> {
+        VECTORS.iter().map(|hex| Psbt::from_raw(&Vec::<u8>::from_hex(hex).unwrap()).unwrap())
+    }
+
+    #[test]
+    fn combine_with_itself() {
+        for psbt in vectors() {
+            let mut combined = psbt.clone();
+            combined.combine(psbt.clone()).unwrap();
+            assert_eq!(combined, psbt);
+        }
+    }
+
+    #[test]
+    fn combine_with_reordered_pairs() {
+        for mut psbt in vectors() {
+            let mut reordered = psbt.clone();
+            reordered.global.0.reverse();
+            reordered.inputs.iter_mut().for_each(|map| map.0.reverse());
+            reordered.outputs.iter_mut().for_each(|map| map.0.reverse());
+
+            let mut combined = psbt.clone();
+            combined.combine(reordered).unwrap();
+            combined.sort_keys();
+            psbt.sort_keys();
+            assert_eq!(combined, psbt);
+        }
+    }
+
+    #[test]
+    fn combine_keeps_single_proprietary_pair() {
+        for mut psbt in vectors() {
+            psbt.add_input_proprietary(0, ProprietaryPair {
+                identifier: "test".into(),
+                subkey_type: 0,
+                subkey_data: vec![1],
+                value: vec![2],
+            })
+            .unwrap();
+
+            let mut combined = psbt.clone();
+            combined.combine(psbt.clone()).unwrap();
+            combined.combine(psbt.clone()).unwrap();
+            assert_eq!(combined, psbt);
+        }
+    }
+
+    #[test]
+    fn dedup_conflicting_partial_sigs() {
+        let mut psbt = vectors().next().unwrap();
+        let (pubkey, sig) = psbt.inputs[0]
+            .known()
+            .find_map(|pair| match pair {
+                InPair::PartialSig { pubkey, sig } => Some((*pubkey, *sig)),
+                _ => None,
+            })
+            .unwrap();
+        let sigs = |psbt: &Psbt| {
+            psbt.inputs[0].known().filter(|pair| matches!(pair, InPair::PartialSig { .. })).count()
+        };
+
+        let original = psbt.clone();
+        psbt.inputs[0].0.push(crate::KeyPair::Known(InPair::PartialSig { pubkey, sig }));
+        assert_eq!(sigs(&psbt), 2);
+        psbt.dedup_partial_sigs().unwrap();
+        assert_eq!(psbt, original);
+
+        let other = EcdsaSig {
+            sig: ecdsa::Signature::from_compact(&[1; 64]).unwrap(),
+            sighash_type: sig.sighash_type,
+        };
+        let mut conflicting = original.clone();
+        conflicting.inputs[0].replace_keyed(InPair::PartialSig { pubkey, sig: other });
+        psbt.inputs[0].0.push(crate::KeyPair::Known(InPair::PartialSig { pubkey, sig: other }));
+        let modified = psbt.clone();
+        let err = DedupError::ConflictingSig { input: 0, pubkey };
+        assert_eq!(psbt.dedup_partial_sigs(), Err(err));
+        assert_eq!(psbt, modified);
+
+        let mut combined = original.clone();
+        assert_eq!(combined.combine(conflicting), Err(CombineError::Dedup(err)));
+        assert_eq!(combined, original);
+    }
+
+    fn taproot_psbt(version: u32, keypair: &KeyPair) -> Psbt {
+        let internal_key = keypair.x_only_public_key().0;
+        let tweak = tap_tweak(internal_key, None).unwrap();
+        let (output_key, _) = internal_key.add_tweak(SECP256K1, &tweak).unwrap();
+        let input = InputBuilder::new(Outpoint {
+            txid: Txid::from([1; 32]),
+            vout: 0,
+        })
+        .witness_utxo(TxOut {
+            value: 10_000,
+            script_pubkey: ScriptBytes::p2tr(output_key),
+        })
+        .spend_info(SpendInfo {
+            tap_internal_key: Some(internal_key),
+            ..default!()
+        });
+        PsbtBuilder::new()
+            .version(version)
+            .input(input)
+            .output(OutputBuilder::new(9_000, ScriptBytes::p2wpkh([2; 20])))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn merge_signed_v2() {
+        let keypair =
+            KeyPair::from_secret_key(SECP256K1, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let mut psbt = taproot_psbt(2, &keypair);
+        let mut signed = psbt.clone();
+        signed.sign_taproot_key_spend(0, &keypair, None).unwrap();
+
+        let mut modified = signed.clone();
+        modified.outputs[0].replace_singleton(OutPair::Amount(8_000));
+        assert!(matches!(
+            psbt.clone().merge_signatures_only(modified),
+            Err(StrictMergeError::Modified(PairDiff {
+                location: MapLocation::Output(0),
+                ..
+            }))
+        ));
+
+        psbt.merge_signatures_only(signed.clone()).unwrap();
+        assert_eq!(psbt.tx_modifiable(), Some(0));
+        psbt.sort_keys();
+        signed.sort_keys();
+        assert_eq!(psbt, signed);
+    }
+
+    #[test]
+    fn merge_finalized_v0() {
+        let keypair =
+            KeyPair::from_secret_key(SECP256K1, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let mut psbt = taproot_psbt(0, &keypair);
+        let mut finalized = psbt.clone();
+        finalized.sign_taproot_key_spend(0, &keypair, None).unwrap();
+        assert!(finalized.finalize().unwrap());
+
+        psbt.merge_signatures_only(finalized.clone()).unwrap();
+        psbt.sort_keys();
+        finalized.sort_keys();
+        assert_eq!(psbt, finalized);
+    }
+
+    #[test]
+    fn script_path_signatures_complete_input() {
+        let keypair = |byte| {
+            KeyPair::from_secret_key(SECP256K1, &SecretKey::from_slice(&[byte; 32]).unwrap())
+        };
+        let keys = [2u8, 3, 4].map(|byte| keypair(byte).x_only_public_key().0);
+        // 2-of-3 `OP_CHECKSIGADD` multisig leaf
+        let mut script = vec![];
+        for (no, key) in keys.iter().enumerate() {
+            script.push(0x20);
+            script.extend(key.serialize());
+            script.push(if no == 0 { 0xAC } else { 0xBA });
+        }
+        script.extend([0x52, 0x9C]);
+        let leaf_hash = tap_leaf_hash(0xC0, &script);
+
+        let internal_key = keypair(1);
+        let mut psbt = taproot_psbt(2, &internal_key);
+        psbt.inputs[0].0.push(crate::KeyPair::Known(InPair::TapLeafScript {
+            control_block: ControlBlock {
+                leaf_ver: 0xC0,
+                output_key_parity: false,
+                internal_key: internal_key.x_only_public_key().0,
+                merkle_path: vec![],
+            },
+            script: script.into(),
+            leaf_ver: 0xC0,
+        }));
+        let sig = |pubkey, leaf_hash| {
+            crate::KeyPair::Known(InPair::TapScriptSig {
+                pubkey,
+                leaf_hash,
+                sig: SchnorrSig {
+                    sig: schnorr::Signature::from_slice(&[1; 64]).unwrap(),
+                    sighash_type: None,
+                },
+            })
+        };
+
+        psbt.inputs[0].0.push(sig(keys[0], leaf_hash));
+        psbt.inputs[0].0.push(sig(keys[1], [0; 32]));
+        assert!(!psbt.has_all_signatures());
+        psbt.inputs[0].0.push(sig(keys[2], leaf_hash));
+        assert!(psbt.has_all_signatures());
+        assert!(psbt.is_fully_signed());
+    }
+
+    #[test]
+    fn tap_multisig_templates() {
+        let key = [7u8; 32];
+        let mut single = vec![0x20];
+        single.extend(key);
+        single.push(0xAC);
+        assert_eq!(ScriptBytes::from(single.clone()).tap_multisig(), Some((1, vec![key])));
+
+        let mut multi = single.clone();
+        multi.push(0x20);
+        multi.extend(key);
+        multi.extend([0xBA, 0x53, 0x9C]);
+        assert_eq!(ScriptBytes::from(multi.clone()).tap_multisig(), None);
+        let threshold = multi.len() - 2;
+        multi[threshold] = 0x52;
+        assert_eq!(ScriptBytes::from(multi).tap_multisig(), Some((2, vec![key, key])));
+
+        single[33] = 0xBA;
+        assert_eq!(ScriptBytes::from(single).tap_multisig(), None);
+    }
+}
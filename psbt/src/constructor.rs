@@ -0,0 +1,799 @@
+// Partially signed bitcoin transaction library (BIP174, BIP370, BIP371)
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Operations of the PSBT v2 constructor role (BIP-370).
+
+use alloc::collections::BTreeSet;
+
+use crate::{
+    GlobalPair, InPair, KeyMap, KeyPair, OutPair, Outpoint, Psbt, SighashFlag, SighashType,
+    UnsignedTxError, LOCKTIME_THRESHOLD, TX_MODIFIABLE_INPUTS, TX_MODIFIABLE_OUTPUTS,
+    TX_MODIFIABLE_SIGHASH_SINGLE,
+};
+
+/// Errors joining two PSBTs.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum JoinError {
+    /// only PSBT v2 can be joined, while one of the PSBTs has version {0}.
+    UnsupportedVersion(u32),
+
+    /// inputs of one of the PSBTs are not modifiable.
+    InputsNotModifiable,
+
+    /// outputs of one of the PSBTs are not modifiable.
+    OutputsNotModifiable,
+
+    /// PSBTs contain SIGHASH_SINGLE signatures which input-output pairing will be broken by the
+    /// join operation.
+    SighashSinglePairing,
+
+    /// both PSBTs have inputs spending the same output {0:?}.
+    DuplicateInput(Outpoint),
+}
+
+/// Errors setting number of inputs or outputs.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum CountError {
+    /// count {0} doesn't fit the size supported by the platform.
+    Overflow(u64),
+
+    /// count {count} doesn't match number of maps {maps} present in PSBT.
+    Mismatch { count: u64, maps: usize },
+}
+
+/// Errors modifying inputs or outputs of PSBT.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum ModifyError {
+    /// only inputs and outputs of PSBT v2 can be modified, while the PSBT has version {0}.
+    UnsupportedVersion(u32),
+
+    /// PSBT doesn't have output #{0}.
+    NoOutput(usize),
+
+    /// `PSBT_GLOBAL_TX_MODIFIABLE` flags don't allow to modify outputs.
+    NotModifiable,
+}
+
+/// Errors bumping fee of PSBT.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum BumpError {
+    /// only PSBT v2 fee can be bumped, while the PSBT has version {0}.
+    UnsupportedVersion(u32),
+
+    /// PSBT doesn't have output #{0}.
+    NoOutput(usize),
+
+    /// output #{0} has no key origin information and thus is not a change output.
+    NotChange(usize),
+
+    /// amount or `scriptPubkey` of output #{0} is unknown.
+    IncompleteOutput(usize),
+
+    /// change output #{index} of {amount} sats can't absorb the fee increase of {fee} sats.
+    InsufficientChange { index: usize, amount: u64, fee: u64 },
+
+    /// reduced amount {amount} sats of change output #{index} is below its dust limit of
+    /// {limit} sats.
+    DustOutput {
+        index: usize,
+        amount: u64,
+        limit: u64,
+    },
+}
+
+/// Errors setting fallback lock time.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum LocktimeError {
+    /// fallback lock time is defined only for PSBT v2, while the PSBT has version {0}.
+    UnsupportedVersion(u32),
+
+    /// input #{0} requires a lock time of the type different from the fallback lock time.
+    Conflict(usize),
+}
+
+/// Errors reordering inputs or outputs.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum ReorderError {
+    /// only inputs and outputs of PSBT v2 can be reordered, while the PSBT has version {0}.
+    UnsupportedVersion(u32),
+
+    /// the provided order is not a permutation of {0} elements.
+    NotPermutation(usize),
+
+    /// input #{0} is finalized and its signatures can't be checked for the reordering safety.
+    Finalized(usize),
+
+    /// PSBT contains SIGHASH_SINGLE signatures and has less outputs than inputs, thus the
+    /// input-output pairing can't be preserved.
+    SighashSinglePairing,
+
+    #[from]
+    #[display(inner)]
+    Incomplete(UnsignedTxError),
+}
+
+impl Psbt {
+    /// Sets `PSBT_GLOBAL_INPUT_COUNT`, checking that the count matches the number of input maps.
+    pub fn set_input_count(&mut self, count: u64) -> Result<(), CountError> {
+        check_count(count, self.inputs.len())?;
+        self.global.replace_singleton(GlobalPair::InputCount(count));
+        Ok(())
+    }
+
+    /// Sets `PSBT_GLOBAL_OUTPUT_COUNT`, checking that the count matches the number of output
+    /// maps.
+    pub fn set_output_count(&mut self, count: u64) -> Result<(), CountError> {
+        check_count(count, self.outputs.len())?;
+        self.global.replace_singleton(GlobalPair::OutputCount(count));
+        Ok(())
+    }
+
+    /// Sets `PSBT_GLOBAL_FALLBACK_LOCKTIME`, replacing the existing value.
+    ///
+    /// Lock time values below [`LOCKTIME_THRESHOLD`] are block heights, others are timestamps.
+    /// The operation fails with [`LocktimeError::Conflict`] if some input requires a lock time
+    /// (`PSBT_IN_REQUIRED_TIME_LOCKTIME` or `PSBT_IN_REQUIRED_HEIGHT_LOCKTIME`), but doesn't
+    /// allow the lock time type of the fallback value, since such fallback couldn't be
+    /// consistent with the transaction lock time determined from the inputs.
+    pub fn set_fallback_locktime(&mut self, lock_time: u32) -> Result<(), LocktimeError> {
+        let version = self.version();
+        if version != 2 {
+            return Err(LocktimeError::UnsupportedVersion(version));
+        }
+        let time_based = lock_time >= LOCKTIME_THRESHOLD;
+        for (input, map) in self.inputs.iter().enumerate() {
+            let (mut time, mut height) = (false, false);
+            for pair in map.known() {
+                match pair {
+                    InPair::RequiredTimeLocktime(_) => time = true,
+                    InPair::RequiredHeightLocktime(_) => height = true,
+                    _ => {}
+                }
+            }
+            let allowed = if time_based { time } else { height };
+            if (time || height) && !allowed {
+                return Err(LocktimeError::Conflict(input));
+            }
+        }
+        self.global.replace_singleton(GlobalPair::FallbackLocktime(lock_time));
+        Ok(())
+    }
+
+    /// Removes `PSBT_GLOBAL_FALLBACK_LOCKTIME`, returning the removed value, if any.
+    pub fn clear_fallback_locktime(&mut self) -> Option<u32> {
+        let lock_time = self.global.known().find_map(|pair| match pair {
+            GlobalPair::FallbackLocktime(lock_time) => Some(*lock_time),
+            _ => None,
+        });
+        self.global
+            .0
+            .retain(|pair| !matches!(pair, KeyPair::Known(GlobalPair::FallbackLocktime(_))));
+        lock_time
+    }
+
+    /// Updates `PSBT_GLOBAL_TX_MODIFIABLE` flags after a signature with the given signature
+    /// hash type was added, following the BIP-370 signer rules: signatures without
+    /// `ANYONECANPAY` clear the inputs modifiable flag, signatures other than `SIGHASH_NONE` clear
+    /// the outputs modifiable flag and `SIGHASH_SINGLE` signatures set the SIGHASH_SINGLE flag.
+    ///
+    /// Does nothing for PSBTs without `PSBT_GLOBAL_TX_MODIFIABLE` flags.
+    pub fn restrict_tx_modifiable(&mut self, sighash_type: SighashType) {
+        let mut flags = match self.tx_modifiable() {
+            Some(flags) => flags,
+            None => return,
+        };
+        if !sighash_type.anyone_can_pay {
+            flags &= !TX_MODIFIABLE_INPUTS;
+        }
+        if sighash_type.flag != SighashFlag::None {
+            flags &= !TX_MODIFIABLE_OUTPUTS;
+        }
+        if sighash_type.flag == SighashFlag::Single {
+            flags |= TX_MODIFIABLE_SIGHASH_SINGLE;
+        }
+        self.global.replace_singleton(GlobalPair::TxModifiable(flags));
+    }
+
+    /// Joins inputs and outputs of the `other` PSBT with the current one, such that they are
+    /// appended to the end of the current list of inputs and outputs.
+    ///
+    /// This is an operation required for creation of collaborative transactions (like CoinJoin)
+    /// and is different from combining PSBTs describing the same transaction. Only inputs,
+    /// outputs and SIGHASH_SINGLE flag are taken from the `other` PSBT; other global keys are
+    /// ignored.
+    ///
+    /// Both PSBTs must be v2 and must have `PSBT_GLOBAL_TX_MODIFIABLE` flags allowing to add
+    /// inputs and/or outputs (the flags are checked only if there are inputs or outputs to add).
+    /// If any of PSBTs has SIGHASH_SINGLE signatures, the operation is allowed only if the
+    /// current PSBT has the same number of inputs and outputs, such that the existing
+    /// input-output pairs are preserved. Inputs of the `other` PSBT must spend outputs different
+    /// from the ones spent by the current PSBT, since the joined transaction can't spend the
+    /// same output twice.
+    pub fn join(&mut self, other: Psbt) -> Result<(), JoinError> {
+        for version in [self.version(), other.version()] {
+            if version < 2 {
+                return Err(JoinError::UnsupportedVersion(version));
+            }
+        }
+
+        let modifiable = self.tx_modifiable().unwrap_or_default();
+        let other_modifiable = other.tx_modifiable().unwrap_or_default();
+        if !other.inputs.is_empty()
+            && (modifiable & other_modifiable & TX_MODIFIABLE_INPUTS) != TX_MODIFIABLE_INPUTS
+        {
+            return Err(JoinError::InputsNotModifiable);
+        }
+        if !other.outputs.is_empty()
+            && (modifiable & other_modifiable & TX_MODIFIABLE_OUTPUTS) != TX_MODIFIABLE_OUTPUTS
+        {
+            return Err(JoinError::OutputsNotModifiable);
+        }
+        let sighash_single = (modifiable | other_modifiable) & TX_MODIFIABLE_SIGHASH_SINGLE;
+        if sighash_single != 0 && self.inputs.len() != self.outputs.len() {
+            return Err(JoinError::SighashSinglePairing);
+        }
+        let mut outpoints = self.inputs.iter().filter_map(input_outpoint).collect::<BTreeSet<_>>();
+        for outpoint in other.inputs.iter().filter_map(input_outpoint) {
+            if !outpoints.insert(outpoint) {
+                return Err(JoinError::DuplicateInput(outpoint));
+            }
+        }
+
+        self.inputs.extend(other.inputs);
+        self.outputs.extend(other.outputs);
+        self.global.replace_singleton(GlobalPair::InputCount(self.inputs.len() as u64));
+        self.global.replace_singleton(GlobalPair::OutputCount(self.outputs.len() as u64));
+        if sighash_single != 0 {
+            self.global.replace_singleton(GlobalPair::TxModifiable(modifiable | sighash_single));
+        }
+        Ok(())
+    }
+
+    /// Removes output from PSBT v2, which `PSBT_GLOBAL_TX_MODIFIABLE` flags must allow to
+    /// modify outputs.
+    ///
+    /// Signatures invalidated by the removal are removed as well, including taproot key and
+    /// script path signatures: SIGHASH_ALL signatures and SIGHASH_SINGLE signatures which
+    /// paired output has changed (i.e. the ones for the inputs starting from the `index`).
+    pub fn remove_output(&mut self, index: usize) -> Result<(), ModifyError> {
+        let version = self.version();
+        if version < 2 {
+            return Err(ModifyError::UnsupportedVersion(version));
+        }
+        if index >= self.outputs.len() {
+            return Err(ModifyError::NoOutput(index));
+        }
+        if self.tx_modifiable().unwrap_or_default() & TX_MODIFIABLE_OUTPUTS == 0 {
+            return Err(ModifyError::NotModifiable);
+        }
+
+        self.outputs.remove(index);
+        self.global.replace_singleton(GlobalPair::OutputCount(self.outputs.len() as u64));
+        self.remove_sigs(|input, ty| match ty.flag {
+            SighashFlag::All => true,
+            SighashFlag::None => false,
+            SighashFlag::Single => input >= index,
+        });
+        Ok(())
+    }
+}
+
+impl Psbt {
+    /// Creates a replacement of the PSBT v2 paying `additional_fee` more, which is deducted from
+    /// the change output with the `change_index`.
+    ///
+    /// The change output must carry key origin information (BIP-32 derivations of its keys),
+    /// and must remain above the dust limit of its `scriptPubkey` (see
+    /// [`ScriptBytes::dust_limit`](crate::ScriptBytes::dust_limit)) after the deduction. Since the
+    /// change is not verified against the wallet keys, use [`Psbt::output_is_change`] when the
+    /// PSBT comes from an untrusted source.
+    ///
+    /// All inputs of the replacement signal replaceability according to BIP-125: inputs which
+    /// sequence numbers do not signal it get `0xFFFFFFFD`. Since the changes invalidate existing
+    /// signatures, the inputs are unfinalized (see [`Psbt::unfinalize`]) and all their
+    /// signatures are removed.
+    pub fn bump_fee(&self, additional_fee: u64, change_index: usize) -> Result<Psbt, BumpError> {
+        let version = self.version();
+        if version < 2 {
+            return Err(BumpError::UnsupportedVersion(version));
+        }
+        let map = self.outputs.get(change_index).ok_or(BumpError::NoOutput(change_index))?;
+        if !map.known().any(|pair| {
+            matches!(pair, OutPair::Bip32Derivation { .. } | OutPair::TapBip32Derivation { .. })
+        }) {
+            return Err(BumpError::NotChange(change_index));
+        }
+        let txout =
+            self.output_txout(change_index).ok_or(BumpError::IncompleteOutput(change_index))?;
+        let amount =
+            txout.value.checked_sub(additional_fee).ok_or(BumpError::InsufficientChange {
+                index: change_index,
+                amount: txout.value,
+                fee: additional_fee,
+            })?;
+        let limit = txout.script_pubkey.dust_limit();
+        if amount < limit {
+            return Err(BumpError::DustOutput {
+                index: change_index,
+                amount,
+                limit,
+            });
+        }
+
+        let mut psbt = self.clone();
+        psbt.outputs[change_index].replace_singleton(OutPair::Amount(amount));
+        psbt.unfinalize();
+        for (index, map) in psbt.inputs.iter_mut().enumerate() {
+            map.0.retain(|pair| {
+                !matches!(
+                    pair,
+                    KeyPair::Known(
+                        InPair::PartialSig { .. }
+                            | InPair::TapKeySig(_)
+                            | InPair::TapScriptSig { .. }
+                    )
+                )
+            });
+            if self.input_sequence(index).unwrap_or(u32::MAX) >= 0xFFFF_FFFE {
+                map.replace_singleton(InPair::Sequence(0xFFFF_FFFD));
+            }
+        }
+        Ok(psbt)
+    }
+}
+
+impl Psbt {
+    /// Reorders inputs of PSBT v2, such that the input at position `i` becomes the one which
+    /// previously had index `new_order[i]`.
+    ///
+    /// If PSBT uses SIGHASH_SINGLE, the outputs paired with the inputs are moved together with
+    /// them. Signatures which commit to the order of inputs (all except `ANYONECANPAY`) are
+    /// removed, as well as `SIGHASH_ALL|ANYONECANPAY` signatures if the outputs were moved, since
+    /// they commit to the order of outputs. Since signatures of the finalized inputs can't be
+    /// inspected, the operation fails if some of the inputs are finalized and the order changes.
+    pub fn reorder_inputs(&mut self, new_order: &[usize]) -> Result<(), ReorderError> {
+        self.check_reorder(new_order, self.inputs.len())?;
+        if is_identity(new_order) {
+            return Ok(());
+        }
+        self.check_not_finalized()?;
+        let sighash_single = self.uses_sighash_single();
+        if sighash_single {
+            if self.outputs.len() < self.inputs.len() {
+                return Err(ReorderError::SighashSinglePairing);
+            }
+            let mut order = new_order.to_vec();
+            order.extend(new_order.len()..self.outputs.len());
+            permute(&mut self.outputs, &order);
+        }
+        permute(&mut self.inputs, new_order);
+        self.remove_sigs(|_, ty| {
+            !ty.anyone_can_pay || (sighash_single && ty.flag == SighashFlag::All)
+        });
+        Ok(())
+    }
+
+    /// Reorders outputs of PSBT v2, such that the output at position `i` becomes the one which
+    /// previously had index `new_order[i]`.
+    ///
+    /// Signatures which commit to the order of outputs are removed: SIGHASH_ALL signatures and
+    /// SIGHASH_SINGLE signatures which paired output has changed. Since signatures of the
+    /// finalized inputs can't be inspected, the operation fails if some of the inputs are
+    /// finalized and the order changes.
+    pub fn reorder_outputs(&mut self, new_order: &[usize]) -> Result<(), ReorderError> {
+        self.check_reorder(new_order, self.outputs.len())?;
+        if is_identity(new_order) {
+            return Ok(());
+        }
+        self.check_not_finalized()?;
+        permute(&mut self.outputs, new_order);
+        self.remove_sigs(|index, ty| match ty.flag {
+            SighashFlag::All => true,
+            SighashFlag::None => false,
+            SighashFlag::Single => {
+                new_order.get(index).map(|old| *old != index).unwrap_or_default()
+            }
+        });
+        Ok(())
+    }
+
+    /// Sorts inputs and outputs of PSBT v2 according to BIP-69: inputs by the previous
+    /// transaction id (in its reversed byte order) and output index, outputs by amount and
+    /// `scriptPubkey`.
+    ///
+    /// Signatures which are invalidated by the new order are removed, as described in
+    /// [`Psbt::reorder_inputs`] and [`Psbt::reorder_outputs`].
+    pub fn bip69_sort(&mut self) -> Result<(), ReorderError> {
+        let tx = self.to_unsigned_tx()?;
+        let mut input_order = (0..tx.inputs.len()).collect::<Vec<_>>();
+        input_order.sort_by_key(|index| {
+            let prev_output = tx.inputs[*index].prev_output;
+            let mut txid = prev_output.txid[..].to_vec();
+            txid.reverse();
+            (txid, prev_output.vout)
+        });
+        self.reorder_inputs(&input_order)?;
+
+        let tx = self.to_unsigned_tx()?;
+        let mut output_order = (0..tx.outputs.len()).collect::<Vec<_>>();
+        output_order.sort_by(|a, b| {
+            let (a, b) = (&tx.outputs[*a], &tx.outputs[*b]);
+            (a.value, &a.script_pubkey[..]).cmp(&(b.value, &b.script_pubkey[..]))
+        });
+        self.reorder_outputs(&output_order)
+    }
+
+    /// Checks that PSBT supports reordering and that the order is a permutation of `len`
+    /// elements.
+    fn check_reorder(&self, new_order: &[usize], len: usize) -> Result<(), ReorderError> {
+        let version = self.version();
+        if version < 2 {
+            return Err(ReorderError::UnsupportedVersion(version));
+        }
+        if new_order.len() != len {
+            return Err(ReorderError::NotPermutation(len));
+        }
+        let mut seen = vec![false; len];
+        for index in new_order {
+            match seen.get_mut(*index) {
+                Some(seen) if !*seen => *seen = true,
+                _ => return Err(ReorderError::NotPermutation(len)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that none of the inputs is finalized, since their signatures can't be removed when
+    /// the reordering invalidates them.
+    fn check_not_finalized(&self) -> Result<(), ReorderError> {
+        match self.inputs.iter().position(|map| {
+            map.known().any(|pair| {
+                matches!(pair, InPair::FinalScriptSig(_) | InPair::FinalScriptWitness(_))
+            })
+        }) {
+            Some(index) => Err(ReorderError::Finalized(index)),
+            None => Ok(()),
+        }
+    }
+
+    /// Detects whether PSBT has the SIGHASH_SINGLE flag or SIGHASH_SINGLE signatures.
+    fn uses_sighash_single(&self) -> bool {
+        if self.tx_modifiable().unwrap_or_default() & TX_MODIFIABLE_SIGHASH_SINGLE != 0 {
+            return true;
+        }
+        self.inputs.iter().flat_map(|map| map.known()).any(|pair| match pair {
+            InPair::PartialSig { sig, .. } => sig.sighash_type.flag == SighashFlag::Single,
+            InPair::TapKeySig(sig) | InPair::TapScriptSig { sig, .. } => {
+                sig.sighash_type.map(|ty| ty.flag == SighashFlag::Single).unwrap_or_default()
+            }
+            _ => false,
+        })
+    }
+
+    /// Removes signatures for which the predicate, receiving input index and signature hash
+    /// type, returns `true`.
+    pub(crate) fn remove_sigs(&mut self, invalid: impl Fn(usize, SighashType) -> bool) {
+        for (index, map) in self.inputs.iter_mut().enumerate() {
+            map.0.retain(|pair| match pair {
+                KeyPair::Known(InPair::PartialSig { sig, .. }) => !invalid(index, sig.sighash_type),
+                KeyPair::Known(InPair::TapKeySig(sig) | InPair::TapScriptSig { sig, .. }) => {
+                    !invalid(index, sig.sighash_type.unwrap_or(SighashType::ALL))
+                }
+                _ => true,
+            });
+        }
+    }
+}
+
+fn is_identity(order: &[usize]) -> bool { order.iter().enumerate().all(|(i, index)| i == *index) }
+
+/// Permutes items such that the item at position `i` becomes the one previously located at
+/// `order[i]`. The order must be already checked to be a permutation.
+fn permute<T>(items: &mut Vec<T>, order: &[usize]) {
+    let mut old = items.drain(..).map(Some).collect::<Vec<_>>();
+    items.extend(order.iter().map(|index| old[*index].take().expect("checked permutation")));
+}
+
+fn check_count(count: u64, maps: usize) -> Result<(), CountError> {
+    let converted = usize::try_from(count).map_err(|_| CountError::Overflow(count))?;
+    if converted != maps {
+        return Err(CountError::Mismatch { count, maps });
+    }
+    Ok(())
+}
+
+/// Returns output spent by the PSBT v2 input, if the input specifies it.
+fn input_outpoint(map: &KeyMap<InPair>) -> Option<Outpoint> {
+    let txid = map.known().find_map(|pair| match pair {
+        InPair::PreviousTxid(txid) => Some(*txid),
+        _ => None,
+    })?;
+    let vout = map.known().find_map(|pair| match pair {
+        InPair::OutputIndex(vout) => Some(*vout),
+        _ => None,
+    })?;
+    Some(Outpoint { txid, vout })
+}
+
+#[cfg(test)]
+mod test {
+    use derive::Fingerprint;
+    use secp256k1::{ecdsa, schnorr, PublicKey, SecretKey, SECP256K1};
+
+    use super::*;
+    use crate::scripts::hash160;
+    use crate::{
+        EcdsaSig, InputBuilder, KeyOrigin, LegacyPk, Outpoint, OutputBuilder, PsbtBuilder,
+        SchnorrSig, ScriptBytes, Txid,
+    };
+
+    /// PSBT v2 with inputs spending outputs of transactions `[2; 32]` and `[1; 32]` and outputs
+    /// of 5000 and 1000 sats.
+    fn unsorted_psbt() -> Psbt {
+        let input = |txid| {
+            InputBuilder::new(Outpoint {
+                txid: Txid::from([txid; 32]),
+                vout: 0,
+            })
+        };
+        PsbtBuilder::new()
+            .version(2)
+            .input(input(2))
+            .input(input(1))
+            .output(OutputBuilder::new(5_000, ScriptBytes::p2wpkh([5; 20])))
+            .output(OutputBuilder::new(1_000, ScriptBytes::p2wpkh([1; 20])))
+            .build()
+            .unwrap()
+    }
+
+    fn sighash(value: u32) -> SighashType { SighashType::from_standard_u32(value).unwrap() }
+
+    fn partial_sig(sighash_type: SighashType) -> InPair {
+        let sk = SecretKey::from_slice(&[1; 32]).unwrap();
+        InPair::PartialSig {
+            pubkey: LegacyPk::compressed(PublicKey::from_secret_key(SECP256K1, &sk)),
+            sig: EcdsaSig {
+                sig: ecdsa::Signature::from_compact(&[1; 64]).unwrap(),
+                sighash_type,
+            },
+        }
+    }
+
+    fn schnorr_sig(sighash_type: Option<SighashType>) -> SchnorrSig {
+        SchnorrSig {
+            sig: schnorr::Signature::from_slice(&[1; 64]).unwrap(),
+            sighash_type,
+        }
+    }
+
+    fn tap_script_sig(sighash_type: SighashType) -> InPair {
+        let sk = SecretKey::from_slice(&[2; 32]).unwrap();
+        InPair::TapScriptSig {
+            pubkey: PublicKey::from_secret_key(SECP256K1, &sk).x_only_public_key().0,
+            leaf_hash: [3; 32],
+            sig: schnorr_sig(Some(sighash_type)),
+        }
+    }
+
+    fn sig_types(psbt: &Psbt, index: usize) -> Vec<u32> {
+        psbt.inputs[index]
+            .known()
+            .filter_map(|pair| match pair {
+                InPair::PartialSig { sig, .. } => Some(sig.sighash_type.to_u32()),
+                InPair::TapKeySig(sig) | InPair::TapScriptSig { sig, .. } => {
+                    Some(sig.sighash_type.unwrap_or(SighashType::ALL).to_u32())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reorder_inputs_with_sighash_single() {
+        let mut psbt = unsorted_psbt();
+        psbt.inputs[0].0.push(KeyPair::Known(tap_script_sig(sighash(0x81))));
+        psbt.inputs[0].0.push(KeyPair::Known(InPair::TapKeySig(schnorr_sig(Some(sighash(0x83))))));
+        psbt.inputs[1].0.push(KeyPair::Known(partial_sig(sighash(0x01))));
+        psbt.inputs[1].0.push(KeyPair::Known(tap_script_sig(sighash(0x82))));
+        let mut reordered = psbt.clone();
+        reordered.reorder_inputs(&[1, 0]).unwrap();
+
+        // Outputs are moved together with their SIGHASH_SINGLE inputs
+        let tx = reordered.to_unsigned_tx().unwrap();
+        assert_eq!(tx.inputs[0].prev_output.txid, Txid::from([1; 32]));
+        assert_eq!(reordered.outputs, vec![psbt.outputs[1].clone(), psbt.outputs[0].clone()]);
+        assert_eq!(sig_types(&reordered, 0), vec![0x82]);
+        assert_eq!(sig_types(&reordered, 1), vec![0x83]);
+
+        assert_eq!(psbt.reorder_inputs(&[1, 1]), Err(ReorderError::NotPermutation(2)));
+        psbt.outputs.pop();
+        assert_eq!(psbt.reorder_inputs(&[1, 0]), Err(ReorderError::SighashSinglePairing));
+    }
+
+    #[test]
+    fn reorder_inputs_keeps_anyone_can_pay() {
+        let mut psbt = unsorted_psbt();
+        psbt.inputs[0].0.push(KeyPair::Known(tap_script_sig(sighash(0x81))));
+        psbt.inputs[1].0.push(KeyPair::Known(tap_script_sig(sighash(0x01))));
+        let mut reordered = psbt.clone();
+        reordered.reorder_inputs(&[1, 0]).unwrap();
+        assert_eq!(reordered.outputs, psbt.outputs);
+        assert_eq!(sig_types(&reordered, 0), Vec::<u32>::new());
+        assert_eq!(sig_types(&reordered, 1), vec![0x81]);
+    }
+
+    #[test]
+    fn reorder_finalized() {
+        let mut psbt = unsorted_psbt();
+        psbt.inputs[1].0.push(KeyPair::Known(InPair::FinalScriptWitness(vec![vec![1]].into())));
+        let unchanged = psbt.clone();
+        assert_eq!(psbt.reorder_outputs(&[1, 0]), Err(ReorderError::Finalized(1)));
+        assert_eq!(psbt.reorder_inputs(&[1, 0]), Err(ReorderError::Finalized(1)));
+        assert_eq!(psbt, unchanged);
+        // Identity order doesn't invalidate the final witness
+        psbt.reorder_outputs(&[0, 1]).unwrap();
+        psbt.reorder_inputs(&[0, 1]).unwrap();
+    }
+
+    #[test]
+    fn join_disjoint() {
+        let mut psbt = unsorted_psbt();
+        let mut other = unsorted_psbt();
+        other.reorder_inputs(&[1, 0]).unwrap();
+        assert_eq!(
+            psbt.clone().join(other.clone()),
+            Err(JoinError::DuplicateInput(Outpoint {
+                txid: Txid::from([1; 32]),
+                vout: 0
+            }))
+        );
+        for map in &mut other.inputs {
+            map.replace_singleton(InPair::OutputIndex(1));
+        }
+        let mut joined = psbt.clone();
+        joined.join(other.clone()).unwrap();
+        assert_eq!(joined.inputs.len(), 4);
+        assert_eq!(joined.outputs.len(), 4);
+        assert_eq!(joined.validate(), Ok(()));
+        assert_eq!(joined.inputs[2..], other.inputs[..]);
+
+        let v0 = PsbtBuilder::new().build().unwrap();
+        assert_eq!(psbt.clone().join(v0), Err(JoinError::UnsupportedVersion(0)));
+        let mut restricted = other.clone();
+        restricted.global.replace_singleton(GlobalPair::TxModifiable(TX_MODIFIABLE_OUTPUTS));
+        assert_eq!(psbt.clone().join(restricted), Err(JoinError::InputsNotModifiable));
+        let mut restricted = other.clone();
+        restricted.global.replace_singleton(GlobalPair::TxModifiable(TX_MODIFIABLE_INPUTS));
+        assert_eq!(psbt.clone().join(restricted), Err(JoinError::OutputsNotModifiable));
+
+        // SIGHASH_SINGLE signatures keep their outputs only if all inputs have paired outputs
+        let flags = TX_MODIFIABLE_INPUTS | TX_MODIFIABLE_OUTPUTS | TX_MODIFIABLE_SIGHASH_SINGLE;
+        other.global.replace_singleton(GlobalPair::TxModifiable(flags));
+        let mut joined = psbt.clone();
+        joined.join(other.clone()).unwrap();
+        assert_eq!(joined.tx_modifiable(), Some(flags));
+        psbt.outputs.pop();
+        psbt.global.replace_singleton(GlobalPair::OutputCount(1));
+        assert_eq!(psbt.join(other), Err(JoinError::SighashSinglePairing));
+    }
+
+    #[test]
+    fn fallback_locktime_conflict() {
+        let v0 = PsbtBuilder::new().build().unwrap();
+        assert_eq!(v0.clone().set_fallback_locktime(1), Err(LocktimeError::UnsupportedVersion(0)));
+
+        let mut psbt = unsorted_psbt();
+        psbt.inputs[1].0.push(KeyPair::Known(InPair::RequiredHeightLocktime(100)));
+        assert_eq!(psbt.set_fallback_locktime(LOCKTIME_THRESHOLD), Err(LocktimeError::Conflict(1)));
+        assert_eq!(psbt.clear_fallback_locktime(), None);
+        psbt.set_fallback_locktime(LOCKTIME_THRESHOLD - 1).unwrap();
+        assert_eq!(psbt.clear_fallback_locktime(), Some(LOCKTIME_THRESHOLD - 1));
+
+        // Inputs allowing both lock time types accept any fallback
+        psbt.inputs[1].0.push(KeyPair::Known(InPair::RequiredTimeLocktime(LOCKTIME_THRESHOLD)));
+        psbt.set_fallback_locktime(LOCKTIME_THRESHOLD).unwrap();
+        assert_eq!(psbt.clear_fallback_locktime(), Some(LOCKTIME_THRESHOLD));
+    }
+
+    #[test]
+    fn remove_output_invalidates_sigs() {
+        let mut psbt = unsorted_psbt();
+        psbt.inputs[0].0.push(KeyPair::Known(tap_script_sig(sighash(0x03))));
+        psbt.inputs[0].0.push(KeyPair::Known(InPair::TapKeySig(schnorr_sig(None))));
+        psbt.inputs[1].0.push(KeyPair::Known(tap_script_sig(sighash(0x83))));
+        psbt.inputs[1].0.push(KeyPair::Known(InPair::TapKeySig(schnorr_sig(Some(sighash(0x02))))));
+
+        assert_eq!(psbt.remove_output(2), Err(ModifyError::NoOutput(2)));
+        psbt.remove_output(1).unwrap();
+        assert_eq!(psbt.outputs.len(), 1);
+        assert_eq!(sig_types(&psbt, 0), vec![0x03]);
+        assert_eq!(sig_types(&psbt, 1), vec![0x02]);
+    }
+
+    #[test]
+    fn bip69() {
+        let mut psbt = unsorted_psbt();
+        psbt.bip69_sort().unwrap();
+        let tx = psbt.to_unsigned_tx().unwrap();
+        assert_eq!(tx.inputs[0].prev_output.txid, Txid::from([1; 32]));
+        assert_eq!(tx.inputs[1].prev_output.txid, Txid::from([2; 32]));
+        assert_eq!(tx.outputs[0].value, 1_000);
+        assert_eq!(tx.outputs[1].value, 5_000);
+
+        let mut sorted = psbt.clone();
+        sorted.bip69_sort().unwrap();
+        assert_eq!(sorted, psbt);
+
+        let mut v0 = PsbtBuilder::new().build().unwrap();
+        assert_eq!(v0.bip69_sort(), Err(ReorderError::UnsupportedVersion(0)));
+    }
+
+    #[test]
+    fn bump_fee_from_change() {
+        let sk = SecretKey::from_slice(&[1; 32]).unwrap();
+        let pubkey = PublicKey::from_secret_key(SECP256K1, &sk);
+        let change_script = ScriptBytes::p2wpkh(hash160(&pubkey.serialize()));
+        let origin = KeyOrigin {
+            master_fp: Fingerprint::from([0xAB; 4]),
+            path: vec![],
+        };
+        let psbt = PsbtBuilder::new()
+            .version(2)
+            .input(InputBuilder::new(Outpoint {
+                txid: Txid::from([1; 32]),
+                vout: 0,
+            }))
+            .output(OutputBuilder::new(10_000, ScriptBytes::p2wpkh([2; 20])))
+            .output(OutputBuilder::new(1_000, change_script).bip32_derivation(pubkey, origin))
+            .build()
+            .unwrap();
+
+        let bumped = psbt.bump_fee(700, 1).unwrap();
+        assert_eq!(bumped.output_txouts().nth(1).unwrap().unwrap().value, 300);
+        assert_eq!(bumped.input_sequence(0), Ok(0xFFFF_FFFD));
+
+        assert_eq!(psbt.bump_fee(100, 0), Err(BumpError::NotChange(0)));
+        assert_eq!(psbt.bump_fee(100, 2), Err(BumpError::NoOutput(2)));
+        assert_eq!(
+            psbt.bump_fee(707, 1),
+            Err(BumpError::DustOutput {
+                index: 1,
+                amount: 293,
+                limit: 294
+            })
+        );
+        assert_eq!(
+            psbt.bump_fee(1_001, 1),
+            Err(BumpError::InsufficientChange {
+                index: 1,
+                amount: 1_000,
+                fee: 1_001
+            })
+        );
+    }
+}
@@ -0,0 +1,102 @@
+// Partially signed bitcoin transaction library (BIP174, BIP370, BIP371)
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Deterministic debug representation of PSBT.
+
+use core::fmt::{self, Debug, Formatter};
+
+use amplify::hex::ToHex;
+
+use crate::{KeyMap, KeyPair, KnownPair, Psbt, PSBT_PROPRIETARY};
+
+/// Debug representation of raw binary data as a hex string.
+struct Hex<'a>(&'a [u8]);
+
+impl<'a> Debug for Hex<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { f.write_str(&self.0.to_hex()) }
+}
+
+/// Debug representation of a key-value map, listing pairs sorted by their keys.
+struct DebugMap<'a, T: KnownPair>(&'a KeyMap<T>);
+
+impl<'a, T: KnownPair> Debug for DebugMap<'a, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut pairs = self.0 .0.iter().collect::<Vec<_>>();
+        pairs.sort_by_key(|pair| pair.key());
+        let mut map = f.debug_map();
+        for pair in pairs {
+            let name = match pair {
+                KeyPair::Known(pair) => pair.field_name().to_owned(),
+                KeyPair::Unknown(pair) => format!("{:#04x}", pair.key_type),
+                KeyPair::Proprietary(_) => format!("{:#04x}", PSBT_PROPRIETARY),
+            };
+            let key_data = pair.raw_key_data();
+            let key =
+                if key_data.is_empty() { name } else { format!("{name}:{}", key_data.to_hex()) };
+            map.entry(&format_args!("{key}"), &Hex(&pair.raw_value()));
+        }
+        map.finish()
+    }
+}
+
+/// Debug representation lists pairs of each map sorted by their keys, naming known fields after
+/// their specification and rendering key data and values as hex, such that it doesn't depend on
+/// the order in which the pairs were added.
+impl Debug for Psbt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let inputs = self.inputs.iter().map(DebugMap).collect::<Vec<_>>();
+        let outputs = self.outputs.iter().map(DebugMap).collect::<Vec<_>>();
+        f.debug_struct("Psbt")
+            .field("global", &DebugMap(&self.global))
+            .field("inputs", &inputs)
+            .field("outputs", &outputs)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{GlobalPair, PsbtBuilder, UnknownPair};
+
+    #[test]
+    fn sorted_pairs() {
+        let mut psbt = PsbtBuilder::new().version(2).build().unwrap();
+        psbt.global.0.push(KeyPair::Unknown(UnknownPair {
+            key_type: 0xF0,
+            key_data: vec![0xAB],
+            value: vec![0xCD, 0xEF],
+            _map_type: default!(),
+        }));
+        let mut reordered = psbt.clone();
+        reordered.global.0.reverse();
+        assert_eq!(format!("{reordered:?}"), format!("{psbt:?}"));
+        assert_eq!(
+            format!("{psbt:?}"),
+            "Psbt { global: {PSBT_GLOBAL_TX_VERSION: 02000000, PSBT_GLOBAL_INPUT_COUNT: 00, \
+             PSBT_GLOBAL_OUTPUT_COUNT: 00, PSBT_GLOBAL_TX_MODIFIABLE: 03, 0xf0:ab: cdef, \
+             PSBT_GLOBAL_VERSION: 02000000}, inputs: [], outputs: [] }"
+        );
+        reordered.global.replace_singleton(GlobalPair::TxModifiable(0));
+        assert_ne!(format!("{psbt:?}"), format!("{reordered:?}"));
+    }
+}
@@ -0,0 +1,308 @@
+// Partially signed bitcoin transaction library (BIP174, BIP370, BIP371)
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Comparison of PSBTs for debugging combine mismatches and verifying signer results.
+
+use crate::{
+    InPair, KeyMap, KnownPair, Psbt, TX_MODIFIABLE_INPUTS, TX_MODIFIABLE_OUTPUTS,
+    TX_MODIFIABLE_SIGHASH_SINGLE,
+};
+
+/// Location of the key-value map within PSBT.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+pub enum MapLocation {
+    /// Global map.
+    #[display("global map")]
+    Global,
+
+    /// Input map.
+    #[display("input #{0}")]
+    Input(usize),
+
+    /// Output map.
+    #[display("output #{0}")]
+    Output(usize),
+}
+
+/// Kind of difference between key-value pairs.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display)]
+#[display(lowercase)]
+pub enum PairChange {
+    /// Pair is present only in the other PSBT.
+    Added,
+
+    /// Pair is present only in the current PSBT.
+    Removed,
+
+    /// Pair is present in both PSBTs but has different values.
+    Changed,
+}
+
+/// Difference in a single key-value pair.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Display)]
+#[display("{location}: key type {key_type:#04x} {change}")]
+pub struct PairDiff {
+    /// Map containing the pair.
+    pub location: MapLocation,
+    /// Type of the pair key.
+    pub key_type: u64,
+    /// Key data of the pair.
+    pub key_data: Vec<u8>,
+    /// Kind of the difference.
+    pub change: PairChange,
+}
+
+/// Differences between two PSBTs, listed in the order of maps and pairs.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct PsbtDiff(pub Vec<PairDiff>);
+
+impl PsbtDiff {
+    /// Detects whether PSBTs have no differences.
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+}
+
+impl Psbt {
+    /// Lists key-value pairs which are added, removed or changed in the `other` PSBT comparing
+    /// to the current one. Maps missing in one of the PSBTs are treated as empty.
+    pub fn diff(&self, other: &Psbt) -> PsbtDiff {
+        let mut diff = PsbtDiff::default();
+        diff_maps(&mut diff, MapLocation::Global, Some(&self.global), Some(&other.global));
+        for index in 0..self.inputs.len().max(other.inputs.len()) {
+            let location = MapLocation::Input(index);
+            diff_maps(&mut diff, location, self.inputs.get(index), other.inputs.get(index));
+        }
+        for index in 0..self.outputs.len().max(other.outputs.len()) {
+            let location = MapLocation::Output(index);
+            diff_maps(&mut diff, location, self.outputs.get(index), other.outputs.get(index));
+        }
+        diff
+    }
+
+    /// Detects whether the `other` PSBT describes the same transaction spending the same
+    /// outputs, differing from the current one only in the changes made by signers and
+    /// finalizers: signatures, finalized input scripts, fields removed from the finalized inputs
+    /// and the restriction of `PSBT_GLOBAL_TX_MODIFIABLE` flags required by BIP-370 after
+    /// signing.
+    ///
+    /// Used to check that a signer didn't alter anything (like outputs or fee) besides adding
+    /// its signatures.
+    pub fn same_transaction(&self, other: &Psbt) -> bool {
+        self.inputs.len() == other.inputs.len()
+            && self.outputs.len() == other.outputs.len()
+            && self.diff(other).0.iter().all(|diff| self.is_signer_change(other, diff))
+    }
+
+    /// Detects whether the difference from the `signed` PSBT may be produced by a signer or a
+    /// finalizer, as described in [`Psbt::same_transaction`].
+    pub(crate) fn is_signer_change(&self, signed: &Psbt, diff: &PairDiff) -> bool {
+        match diff.location {
+            MapLocation::Global => {
+                diff.key_type == 0x06
+                    && diff.change == PairChange::Changed
+                    && matches!(
+                        (self.tx_modifiable(), signed.tx_modifiable()),
+                        (Some(flags), Some(restricted)) if is_restriction(flags, restricted)
+                    )
+            }
+            MapLocation::Input(index) => {
+                SIGNATURE_INPUT_KEYS.contains(&diff.key_type)
+                    || (diff.change == PairChange::Removed
+                        && signed.inputs.get(index).map_or(false, |map| {
+                            map.known().any(|pair| {
+                                matches!(
+                                    pair,
+                                    InPair::FinalScriptSig(_) | InPair::FinalScriptWitness(_)
+                                )
+                            })
+                        }))
+            }
+            MapLocation::Output(_) => false,
+        }
+    }
+}
+
+/// Input key types holding signatures or data produced from signatures by finalizers:
+/// `PSBT_IN_PARTIAL_SIG`, `PSBT_IN_FINAL_SCRIPTSIG`, `PSBT_IN_FINAL_SCRIPTWITNESS`,
+/// `PSBT_IN_TAP_KEY_SIG` and `PSBT_IN_TAP_SCRIPT_SIG`.
+pub(crate) const SIGNATURE_INPUT_KEYS: [u64; 5] = [0x02, 0x07, 0x08, 0x13, 0x14];
+
+/// Detects whether `restricted` flags of `PSBT_GLOBAL_TX_MODIFIABLE` may be produced from
+/// `flags` by signing: inputs and outputs modifiable flags may be only cleared, while the
+/// SIGHASH_SINGLE flag may be only set.
+fn is_restriction(flags: u8, restricted: u8) -> bool {
+    let modifiable = TX_MODIFIABLE_INPUTS | TX_MODIFIABLE_OUTPUTS;
+    restricted & modifiable & !flags == 0
+        && flags & TX_MODIFIABLE_SIGHASH_SINGLE & !restricted == 0
+        && restricted & !(modifiable | TX_MODIFIABLE_SIGHASH_SINGLE)
+            == flags & !(modifiable | TX_MODIFIABLE_SIGHASH_SINGLE)
+}
+
+fn diff_maps<T: KnownPair>(
+    diff: &mut PsbtDiff,
+    location: MapLocation,
+    current: Option<&KeyMap<T>>,
+    other: Option<&KeyMap<T>>,
+) {
+    let current = current.map(|map| &map.0[..]).unwrap_or_default();
+    let other = other.map(|map| &map.0[..]).unwrap_or_default();
+    let mut push = |(key_type, key_data), change| {
+        diff.0.push(PairDiff {
+            location,
+            key_type,
+            key_data,
+            change,
+        })
+    };
+
+    for pair in current {
+        let key = pair.key();
+        match other.iter().find(|p| p.key() == key) {
+            None => push(key, PairChange::Removed),
+            Some(p) if p.raw_value() != pair.raw_value() => push(key, PairChange::Changed),
+            Some(_) => {}
+        }
+    }
+    for pair in other {
+        let key = pair.key();
+        if !current.iter().any(|p| p.key() == key) {
+            push(key, PairChange::Added);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use secp256k1::{KeyPair, SecretKey, SECP256K1};
+
+    use super::*;
+    use crate::sighash::tap_tweak;
+    use crate::{
+        GlobalPair, InputBuilder, Outpoint, OutputBuilder, PsbtBuilder, ScriptBytes, SpendInfo,
+        TxOut, Txid,
+    };
+
+    fn taproot_psbt(version: u32, keypair: &KeyPair) -> Psbt {
+        let internal_key = keypair.x_only_public_key().0;
+        let tweak = tap_tweak(internal_key, None).unwrap();
+        let (output_key, _) = internal_key.add_tweak(SECP256K1, &tweak).unwrap();
+        let input = InputBuilder::new(Outpoint {
+            txid: Txid::from([1; 32]),
+            vout: 0,
+        })
+        .witness_utxo(TxOut {
+            value: 10_000,
+            script_pubkey: ScriptBytes::p2tr(output_key),
+        })
+        .spend_info(SpendInfo {
+            tap_internal_key: Some(internal_key),
+            ..default!()
+        });
+        PsbtBuilder::new()
+            .version(version)
+            .input(input)
+            .output(OutputBuilder::new(9_000, ScriptBytes::p2wpkh([2; 20])))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn diff_pairs() {
+        let keypair =
+            KeyPair::from_secret_key(SECP256K1, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let psbt = taproot_psbt(2, &keypair);
+        let mut reordered = psbt.clone();
+        reordered.inputs[0].0.reverse();
+        assert!(psbt.diff(&reordered).is_empty());
+
+        let mut other = psbt.clone();
+        other.global.replace_singleton(GlobalPair::FallbackLocktime(1));
+        other.inputs[0].0.retain(|pair| {
+            !matches!(pair, crate::KeyPair::Known(crate::InPair::TapInternalKey(_)))
+        });
+        other.outputs[0].replace_singleton(crate::OutPair::Amount(8_000));
+        other.outputs.push(other.outputs[0].clone());
+        let pair = |location, key_type, change| PairDiff {
+            location,
+            key_type,
+            key_data: vec![],
+            change,
+        };
+        assert_eq!(psbt.diff(&other).0, vec![
+            pair(MapLocation::Global, 0x03, PairChange::Added),
+            pair(MapLocation::Input(0), 0x17, PairChange::Removed),
+            pair(MapLocation::Output(0), 0x03, PairChange::Changed),
+            pair(MapLocation::Output(1), 0x03, PairChange::Added),
+            pair(MapLocation::Output(1), 0x04, PairChange::Added),
+        ]);
+    }
+
+    #[test]
+    fn signed_v2_is_same_transaction() {
+        let keypair =
+            KeyPair::from_secret_key(SECP256K1, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let psbt = taproot_psbt(2, &keypair);
+        let mut signed = psbt.clone();
+        signed.sign_taproot_key_spend(0, &keypair, None).unwrap();
+        assert_ne!(psbt.tx_modifiable(), signed.tx_modifiable());
+        assert!(psbt.same_transaction(&signed));
+
+        // Signer can't make the transaction modifiable again
+        assert!(!signed.same_transaction(&psbt));
+
+        let mut modified = signed;
+        modified.outputs[0].replace_singleton(crate::OutPair::Amount(8_000));
+        assert!(!psbt.same_transaction(&modified));
+    }
+
+    #[test]
+    fn finalized_v0_is_same_transaction() {
+        let keypair =
+            KeyPair::from_secret_key(SECP256K1, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let psbt = taproot_psbt(0, &keypair);
+        let mut finalized = psbt.clone();
+        finalized.sign_taproot_key_spend(0, &keypair, None).unwrap();
+        assert!(finalized.finalize().unwrap());
+        assert!(psbt.diff(&finalized).0.iter().any(|diff| diff.change == PairChange::Removed));
+        assert!(psbt.same_transaction(&finalized));
+
+        // Fields can be removed only from the finalized inputs
+        let mut stripped = psbt.clone();
+        stripped.inputs[0].0.clear();
+        assert!(!psbt.same_transaction(&stripped));
+        let mut tx = psbt.to_unsigned_tx().unwrap();
+        tx.lock_time = 1;
+        let mut other_tx = finalized;
+        other_tx.global.replace_singleton(GlobalPair::UnsignedTx(tx));
+        assert!(!psbt.same_transaction(&other_tx));
+    }
+
+    #[test]
+    fn tx_modifiable_restriction() {
+        let single = TX_MODIFIABLE_SIGHASH_SINGLE;
+        assert!(is_restriction(0x03, 0x00));
+        assert!(is_restriction(0x03, 0x01 | single));
+        assert!(is_restriction(single, single));
+        assert!(!is_restriction(0x01, 0x03));
+        assert!(!is_restriction(single, 0x00));
+        assert!(!is_restriction(0x03, 0x03 | 0x08));
+    }
+}
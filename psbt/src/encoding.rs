@@ -20,36 +20,2322 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use alloc::collections::BTreeSet;
+use core::borrow::Borrow;
+
+use bitcoin_hashes::HashEngine;
 use core2::io::Cursor;
+use derive::{ChildIdx, DerivationIndex, Fingerprint, Xpub};
+use secp256k1::{PublicKey, XOnlyPublicKey};
 
 use super::Psbt;
+use crate::scripts::{sha256, ScriptTemplate};
+use crate::{
+    ControlBlock, EcdsaSig, GlobalPair, InPair, KeyMap, KeyOrigin, KeyPair, KnownPair, LegacyPk,
+    MapLocation, OutPair, Outpoint, ProprietaryPair, PsbtSighashType, SchnorrSig, ScriptBytes,
+    SharedTx, TapLeaf, Tx, TxIn, TxOut, Txid, UnknownPair, ValidationError, Witness,
+    XpubDerivation, LOCKTIME_THRESHOLD, TAPROOT_MAX_DEPTH,
+};
+
+/// Magic bytes starting serialized PSBT.
+pub const PSBT_MAGIC: [u8; 5] = *b"psbt\xFF";
+
+/// Maximal length of a single key, value or other length-prefixed data accepted by the decoder,
+/// matching the maximal size of consensus data.
+pub const MAX_DATA_LEN: usize = 4_000_000;
+
+/// Key type used by proprietary keys in all map types.
+pub const PSBT_PROPRIETARY: u64 = 0xFC;
+
+/// Errors encoding PSBT.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum EncodeError {
+    /// buffer of {available} bytes is too small for the PSBT requiring {required} bytes.
+    BufferTooSmall { required: usize, available: usize },
+
+    /// {location}: key or value of the pair with key type {key_type:#04x} is {len} bytes long,
+    /// exceeding the maximal length of a decodable key or value.
+    OversizedData {
+        location: MapLocation,
+        key_type: u64,
+        len: usize,
+    },
+
+    /// {location}: map contains repeated key of type {key_type:#04x}.
+    RepeatedKey {
+        location: MapLocation,
+        key_type: u64,
+    },
+
+    #[from]
+    #[display(inner)]
+    Invalid(ValidationError),
+}
+
+/// Errors decoding PSBT and its components.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum DecodeError {
+    /// unexpected end of data at offset {position}.
+    UnexpectedEof { position: usize },
+
+    /// I/O error of kind {0:?} while reading data.
+    Io(core2::io::ErrorKind),
+
+    /// data don't start with PSBT magic bytes; {hint}.
+    NotAPsbt { hint: NotPsbtHint },
+
+    /// non-minimal compact-size encoding of value {value} at offset {position}.
+    NonMinimalVarInt { value: u64, position: usize },
+
+    /// data length {requested} at offset {position} exceeds maximal allowed length of {max}
+    /// bytes.
+    OversizedAllocation {
+        requested: u64,
+        max: usize,
+        position: usize,
+    },
+
+    /// map contains repeated key of type {key_type:#04x} at offset {position}.
+    RepeatedKey { key_type: u64, position: usize },
+
+    /// invalid key data for key of type {key_type:#04x} at offset {position}.
+    InvalidKey { key_type: u64, position: usize },
+
+    /// key of type {key_type:#04x} at offset {position} may be present just once per map and
+    /// must not have key data.
+    UnexpectedKeyData { key_type: u64, position: usize },
+
+    /// key of type {key_type:#04x} at offset {position} requires key data, which are absent.
+    MissingKeyData { key_type: u64, position: usize },
+
+    /// key of type {key_type:#04x} at offset {position} is not allowed in PSBT v{version}.
+    KeyVersionMismatch {
+        key_type: u64,
+        version: u32,
+        position: usize,
+    },
+
+    /// invalid value for key of type {key_type:#04x} at offset {position}.
+    InvalidValue { key_type: u64, position: usize },
+
+    /// key origin of length {len} for key of type {key_type:#04x} at offset {position} is not a
+    /// master key fingerprint followed by complete 4-byte child indexes.
+    InvalidKeySourceLength {
+        key_type: u64,
+        len: usize,
+        position: usize,
+    },
+
+    /// unsupported transaction serialization flag {flag:#04x} at offset {position}.
+    InvalidTxFlag { flag: u8, position: usize },
+
+    /// unsigned transaction contains non-empty scriptSig or witness for input #{0}.
+    UnsignedTxNotEmpty(usize),
+
+    /// PSBT contains neither unsigned transaction nor input and output counts, thus the number
+    /// of input and output maps can't be determined.
+    NoMapCount,
+
+    /// PSBT declares {count} maps, while only {remaining} bytes of data remain.
+    ExcessiveMapCount { count: u64, remaining: usize },
+
+    /// PSBT declares {declared} input and output maps, while its data end after {present} maps.
+    MissingMaps { declared: u64, present: u64 },
+
+    /// PSBT is followed by {0} bytes of unparsed data.
+    TrailingData(usize),
+
+    /// PSBT exceeds the decoder limit on the {0}.
+    LimitExceeded(DecodeLimit),
+
+    /// non-witness UTXO of input #{0} is not the transaction spent by the input.
+    NonWitnessUtxoMismatch(usize),
+
+    /// witness script in the map at offset {position} doesn't match the P2WSH program of the
+    /// redeem script.
+    WitnessScriptMismatch { position: usize },
+
+    /// derivation path of length {path_len} at offset {position} doesn't match depth {depth} of
+    /// the extended public key.
+    XpubDepthMismatch {
+        depth: u8,
+        path_len: usize,
+        position: usize,
+    },
+
+    #[display(inner)]
+    Invalid(ValidationError),
+}
+
+/// Guess about the format of data which are not a PSBT, helping to report the likely mistake.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display)]
+pub enum NotPsbtHint {
+    /// Data look like a consensus-serialized bitcoin transaction.
+    #[display("they look like a raw transaction")]
+    RawTx,
+
+    /// Data look like a hex-encoded string.
+    #[display("they look like hex-encoded data, which must be decoded first")]
+    Hex,
+
+    /// Data look like a base64-encoded string.
+    #[display("they look like base64-encoded data, which must be decoded first")]
+    Base64,
+
+    /// Format of the data can't be guessed.
+    #[display("their format is unknown")]
+    Unknown,
+}
+
+impl NotPsbtHint {
+    /// Guesses format of the data from their first bytes.
+    fn detect(data: &[u8]) -> Self {
+        let prefix = &data[..data.len().min(16)];
+        if prefix.iter().all(u8::is_ascii_hexdigit) {
+            NotPsbtHint::Hex
+        } else if prefix.iter().all(|b| b.is_ascii_alphanumeric() || b"+/=".contains(b)) {
+            NotPsbtHint::Base64
+        } else if matches!(prefix, [1..=3, 0, 0, 0, ..]) {
+            // Transaction version 1 to 3 in little-endian encoding
+            NotPsbtHint::RawTx
+        } else {
+            NotPsbtHint::Unknown
+        }
+    }
+}
+
+/// Decoder limit which can be exceeded by PSBT.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display)]
+pub enum DecodeLimit {
+    /// Number of inputs.
+    #[display("number of inputs")]
+    Inputs,
+
+    /// Number of outputs.
+    #[display("number of outputs")]
+    Outputs,
+
+    /// Total size of the serialized PSBT.
+    #[display("total size")]
+    Size,
 
-pub enum DecodeError {}
+    /// Length of a single key or value.
+    #[display("key or value length")]
+    ValueLen,
+
+    /// Number of key-value pairs in a single map.
+    #[display("number of pairs in a map")]
+    MapPairs,
+}
+
+/// Limits applied when decoding PSBT received from untrusted sources.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct DecodeLimits {
+    /// Maximal number of inputs.
+    pub max_inputs: usize,
+    /// Maximal number of outputs.
+    pub max_outputs: usize,
+    /// Maximal size of the serialized PSBT, in bytes.
+    pub max_size: usize,
+    /// Maximal length of a single key or value, in bytes.
+    pub max_value_len: usize,
+    /// Maximal number of key-value pairs in a single global, input or output map.
+    pub max_map_pairs: usize,
+}
+
+impl DecodeLimits {
+    /// Limits used by [`Psbt::from_raw`], which do not restrict PSBT beyond [`MAX_DATA_LEN`]
+    /// for a single key or value.
+    pub const UNLIMITED: DecodeLimits = DecodeLimits {
+        max_inputs: usize::MAX,
+        max_outputs: usize::MAX,
+        max_size: usize::MAX,
+        max_value_len: MAX_DATA_LEN,
+        max_map_pairs: usize::MAX,
+    };
+}
+
+/// Default limits allow any PSBT which transaction may be relayed by the standard bitcoin nodes,
+/// with a large margin for the non-witness UTXOs and other PSBT data.
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_inputs: 10_000,
+            max_outputs: 10_000,
+            max_size: 100_000_000,
+            max_value_len: MAX_DATA_LEN,
+            max_map_pairs: 10_000,
+        }
+    }
+}
+
+impl DecodeError {
+    /// Returns offset in the decoded data at which the error was detected, if the error is
+    /// related to a specific position.
+    pub fn position(&self) -> Option<usize> {
+        match *self {
+            DecodeError::UnexpectedEof { position }
+            | DecodeError::NonMinimalVarInt { position, .. }
+            | DecodeError::OversizedAllocation { position, .. }
+            | DecodeError::RepeatedKey { position, .. }
+            | DecodeError::InvalidKey { position, .. }
+            | DecodeError::UnexpectedKeyData { position, .. }
+            | DecodeError::MissingKeyData { position, .. }
+            | DecodeError::KeyVersionMismatch { position, .. }
+            | DecodeError::InvalidValue { position, .. }
+            | DecodeError::InvalidKeySourceLength { position, .. }
+            | DecodeError::InvalidTxFlag { position, .. }
+            | DecodeError::WitnessScriptMismatch { position }
+            | DecodeError::XpubDepthMismatch { position, .. } => Some(position),
+            DecodeError::NotAPsbt { .. } => Some(0),
+            DecodeError::Io(_)
+            | DecodeError::UnsignedTxNotEmpty(_)
+            | DecodeError::NonWitnessUtxoMismatch(_)
+            | DecodeError::NoMapCount
+            | DecodeError::ExcessiveMapCount { .. }
+            | DecodeError::MissingMaps { .. }
+            | DecodeError::TrailingData(_)
+            | DecodeError::LimitExceeded(_)
+            | DecodeError::Invalid(_) => None,
+        }
+    }
+
+    /// Converts position of an error returned by [`KnownPair::parse`], which is relative to the
+    /// start of the key data (for key errors) or the value (for all other errors), into the
+    /// offset within the decoded data.
+    fn shift(mut self, key_data_offset: usize, value_offset: usize) -> Self {
+        match &mut self {
+            DecodeError::InvalidKey { position, .. }
+            | DecodeError::UnexpectedKeyData { position, .. }
+            | DecodeError::MissingKeyData { position, .. } => *position += key_data_offset,
+            DecodeError::UnexpectedEof { position }
+            | DecodeError::NonMinimalVarInt { position, .. }
+            | DecodeError::OversizedAllocation { position, .. }
+            | DecodeError::RepeatedKey { position, .. }
+            | DecodeError::InvalidValue { position, .. }
+            | DecodeError::InvalidKeySourceLength { position, .. }
+            | DecodeError::InvalidTxFlag { position, .. }
+            | DecodeError::XpubDepthMismatch { position, .. } => *position += value_offset,
+            DecodeError::NotAPsbt { .. }
+            | DecodeError::Io(_)
+            | DecodeError::WitnessScriptMismatch { .. }
+            | DecodeError::KeyVersionMismatch { .. }
+            | DecodeError::UnsignedTxNotEmpty(_)
+            | DecodeError::NonWitnessUtxoMismatch(_)
+            | DecodeError::NoMapCount
+            | DecodeError::ExcessiveMapCount { .. }
+            | DecodeError::MissingMaps { .. }
+            | DecodeError::TrailingData(_)
+            | DecodeError::LimitExceeded(_)
+            | DecodeError::Invalid(_) => {}
+        }
+        self
+    }
+}
+
+/// I/O errors carry no position of the data, since the codec itself reports the end of data
+/// as [`DecodeError::UnexpectedEof`]. Since `core2::io` re-exports `std::io` when the standard
+/// library is available, the conversion applies to both `std` and `no_std` I/O errors.
+impl From<core2::io::Error> for DecodeError {
+    fn from(err: core2::io::Error) -> Self { DecodeError::Io(err.kind()) }
+}
+
+/// Length of the compact-size (VarInt) encoding of a given value.
+pub(crate) fn compact_size_len(value: usize) -> usize { compact_size_len_u64(value as u64) }
+
+/// Length of the compact-size (VarInt) encoding of a given 64-bit value.
+pub(crate) fn compact_size_len_u64(value: u64) -> usize {
+    match value {
+        0..=0xFC => 1,
+        0xFD..=0xFFFF => 3,
+        0x10000..=0xFFFF_FFFF => 5,
+        _ => 9,
+    }
+}
+
+/// Writes value using compact-size (VarInt) encoding.
+pub(crate) fn write_compact_size(buf: &mut Vec<u8>, value: u64) {
+    match value {
+        0..=0xFC => buf.push(value as u8),
+        0xFD..=0xFFFF => {
+            buf.push(0xFD);
+            buf.extend((value as u16).to_le_bytes());
+        }
+        0x10000..=0xFFFF_FFFF => {
+            buf.push(0xFE);
+            buf.extend((value as u32).to_le_bytes());
+        }
+        _ => {
+            buf.push(0xFF);
+            buf.extend(value.to_le_bytes());
+        }
+    }
+}
+
+/// Length of the data prefixed with their compact-size encoded length.
+pub(crate) fn prefixed_len(len: usize) -> usize { compact_size_len(len) + len }
+
+/// Writes data prefixed with their compact-size encoded length.
+pub(crate) fn write_prefixed(buf: &mut Vec<u8>, data: &[u8]) {
+    write_compact_size(buf, data.len() as u64);
+    buf.extend(data);
+}
+
+/// Reads `len` bytes from the cursor.
+pub(crate) fn read_slice<'a>(
+    cursor: &mut Cursor<&'a [u8]>,
+    len: usize,
+) -> Result<&'a [u8], DecodeError> {
+    let data = *cursor.get_ref();
+    let pos = cursor.position() as usize;
+    let end = pos
+        .checked_add(len)
+        .filter(|end| *end <= data.len())
+        .ok_or(DecodeError::UnexpectedEof { position: pos })?;
+    cursor.set_position(end as u64);
+    Ok(&data[pos..end])
+}
+
+/// Reads fixed-size byte array from the cursor.
+pub(crate) fn read_array<const LEN: usize>(
+    cursor: &mut Cursor<&[u8]>,
+) -> Result<[u8; LEN], DecodeError> {
+    let mut array = [0u8; LEN];
+    array.copy_from_slice(read_slice(cursor, LEN)?);
+    Ok(array)
+}
+
+/// Reads compact-size (VarInt) encoded value, failing on non-minimal encodings.
+pub(crate) fn read_compact_size(cursor: &mut Cursor<&[u8]>) -> Result<u64, DecodeError> {
+    let position = cursor.position() as usize;
+    let (value, min) = match read_array::<1>(cursor)?[0] {
+        0xFD => (u16::from_le_bytes(read_array(cursor)?) as u64, 0xFD),
+        0xFE => (u32::from_le_bytes(read_array(cursor)?) as u64, 0x10000),
+        0xFF => (u64::from_le_bytes(read_array(cursor)?), 0x1_0000_0000),
+        value => return Ok(value as u64),
+    };
+    if value < min {
+        return Err(DecodeError::NonMinimalVarInt { value, position });
+    }
+    Ok(value)
+}
+
+/// Reads data prefixed with their compact-size encoded length, which must not exceed
+/// [`MAX_DATA_LEN`].
+pub(crate) fn read_prefixed<'a>(cursor: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], DecodeError> {
+    let position = cursor.position() as usize;
+    let len = read_compact_size(cursor)?;
+    if len > MAX_DATA_LEN as u64 {
+        return Err(DecodeError::OversizedAllocation {
+            requested: len,
+            max: MAX_DATA_LEN,
+            position,
+        });
+    }
+    read_slice(cursor, len as usize)
+}
+
+/// Decodes value of a known key, requiring the whole value to be consumed.
+fn decode_value<T: Encoding>(key_type: u64, value: &[u8]) -> Result<T, DecodeError> {
+    let mut cursor = Cursor::new(value);
+    let val = T::decode(&mut cursor).map_err(|err| DecodeError::InvalidValue {
+        key_type,
+        position: err.position().unwrap_or_default(),
+    })?;
+    if cursor.position() as usize != value.len() {
+        return Err(DecodeError::InvalidValue {
+            key_type,
+            position: cursor.position() as usize,
+        });
+    }
+    Ok(val)
+}
 
 pub trait Encoding {
     fn encode(&self, buf: &mut Vec<u8>);
-    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, DecodeError> where Self: Sized;
+
+    /// Serializes the data, failing if the result can't be decoded back (for instance, because
+    /// of oversized PSBT keys or values).
+    ///
+    /// The default implementation never fails, since most of the data can always be serialized.
+    fn try_encode(&self, buf: &mut Vec<u8>) -> Result<(), EncodeError> {
+        self.encode(buf);
+        Ok(())
+    }
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, DecodeError>
+    where Self: Sized;
+
+    /// Length of the serialized data.
+    ///
+    /// The default implementation serializes the data; implementations of this crate override
+    /// it to compute the length without allocating.
+    fn encoded_len(&self) -> usize {
+        let mut buf = vec![];
+        self.encode(&mut buf);
+        buf.len()
+    }
+}
+
+impl Encoding for u8 {
+    fn encode(&self, buf: &mut Vec<u8>) { buf.push(*self) }
+
+    fn encoded_len(&self) -> usize { 1 }
+
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, DecodeError> {
+        read_array::<1>(cursor).map(|[byte]| byte)
+    }
+}
+
+impl Encoding for u32 {
+    fn encode(&self, buf: &mut Vec<u8>) { buf.extend(self.to_le_bytes()) }
+
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, DecodeError> {
+        read_array(cursor).map(u32::from_le_bytes)
+    }
+
+    fn encoded_len(&self) -> usize { 4 }
+}
+
+impl Encoding for u64 {
+    fn encode(&self, buf: &mut Vec<u8>) { buf.extend(self.to_le_bytes()) }
+
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, DecodeError> {
+        read_array(cursor).map(u64::from_le_bytes)
+    }
+
+    fn encoded_len(&self) -> usize { 8 }
+}
+
+impl Encoding for Txid {
+    fn encode(&self, buf: &mut Vec<u8>) { buf.extend(&self[..]) }
+
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, DecodeError> {
+        read_array::<32>(cursor).map(Txid::from)
+    }
+
+    fn encoded_len(&self) -> usize { 32 }
+}
+
+impl Encoding for Outpoint {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.txid.encode(buf);
+        self.vout.encode(buf);
+    }
+
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, DecodeError> {
+        Ok(Outpoint {
+            txid: Txid::decode(cursor)?,
+            vout: u32::decode(cursor)?,
+        })
+    }
+
+    fn encoded_len(&self) -> usize { 36 }
+}
+
+/// Consensus encoding of scripts, prefixed with the script length.
+impl Encoding for ScriptBytes {
+    fn encode(&self, buf: &mut Vec<u8>) { write_prefixed(buf, self) }
+
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, DecodeError> {
+        read_prefixed(cursor).map(|data| ScriptBytes::from(data.to_vec()))
+    }
+
+    fn encoded_len(&self) -> usize { prefixed_len(self.len()) }
+}
+
+impl Encoding for Witness {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        write_compact_size(buf, self.len() as u64);
+        for item in self.iter() {
+            write_prefixed(buf, item);
+        }
+    }
+
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, DecodeError> {
+        let count = read_compact_size(cursor)?;
+        let mut items = vec![];
+        for _ in 0..count {
+            items.push(read_prefixed(cursor)?.to_vec());
+        }
+        Ok(Witness::from(items))
+    }
+
+    fn encoded_len(&self) -> usize { self.serialized_len() }
+}
+
+impl Encoding for TxOut {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.value.encode(buf);
+        self.script_pubkey.encode(buf);
+    }
+
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, DecodeError> {
+        Ok(TxOut {
+            value: u64::decode(cursor)?,
+            script_pubkey: ScriptBytes::decode(cursor)?,
+        })
+    }
+
+    fn encoded_len(&self) -> usize { 8 + self.script_pubkey.encoded_len() }
+}
+
+/// Consensus encoding of transactions, using BIP-144 serialization when some of the inputs
+/// have witness.
+impl Encoding for Tx {
+    fn encode(&self, buf: &mut Vec<u8>) { self.encode_consensus(buf, self.has_witness()) }
+
+    fn encoded_len(&self) -> usize { self.total_size() }
+
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, DecodeError> {
+        let version = u32::decode(cursor)?;
+        let mut input_count = read_compact_size(cursor)?;
+        let mut segwit = false;
+        // Zero input count may be either a segwit marker or a transaction without inputs.
+        if input_count == 0 {
+            let pos = cursor.position();
+            match u8::decode(cursor)? {
+                0x01 => {
+                    segwit = true;
+                    input_count = read_compact_size(cursor)?;
+                }
+                0x00 => cursor.set_position(pos),
+                flag => {
+                    return Err(DecodeError::InvalidTxFlag {
+                        flag,
+                        position: pos as usize,
+                    })
+                }
+            }
+        }
+
+        let mut inputs = vec![];
+        for _ in 0..input_count {
+            inputs.push(TxIn {
+                prev_output: Outpoint::decode(cursor)?,
+                sig_script: ScriptBytes::decode(cursor)?,
+                sequence: u32::decode(cursor)?,
+                witness: none!(),
+            });
+        }
+        let output_count = read_compact_size(cursor)?;
+        let mut outputs = vec![];
+        for _ in 0..output_count {
+            outputs.push(TxOut::decode(cursor)?);
+        }
+        if segwit {
+            for input in &mut inputs {
+                input.witness = Witness::decode(cursor)?;
+            }
+        }
+        let lock_time = u32::decode(cursor)?;
+
+        Ok(Tx {
+            version,
+            inputs,
+            outputs,
+            lock_time,
+        })
+    }
+}
+
+impl Tx {
+    /// Writes consensus serialization of the transaction, with or without witness data.
+    pub(crate) fn encode_consensus(&self, buf: &mut Vec<u8>, segwit: bool) {
+        self.stream_consensus(segwit, |chunk| buf.extend_from_slice(chunk));
+    }
+
+    /// Feeds consensus serialization of the transaction without witness data into the hash
+    /// engine.
+    pub(crate) fn hash_consensus(&self, engine: &mut impl HashEngine) {
+        self.stream_consensus(false, |chunk| engine.input(chunk));
+    }
+
+    /// Serializes the transaction chunk by chunk, passing each of the chunks to the `sink`.
+    ///
+    /// Only a single input, output or witness is serialized at a time, so the memory used does
+    /// not depend on the transaction size.
+    fn stream_consensus(&self, segwit: bool, mut sink: impl FnMut(&[u8])) {
+        let mut chunk = Vec::with_capacity(64);
+        let mut flush = |chunk: &mut Vec<u8>| {
+            sink(chunk);
+            chunk.clear();
+        };
+        self.version.encode(&mut chunk);
+        if segwit {
+            chunk.extend([0x00, 0x01]);
+        }
+        write_compact_size(&mut chunk, self.inputs.len() as u64);
+        flush(&mut chunk);
+        for input in &self.inputs {
+            input.prev_output.encode(&mut chunk);
+            input.sig_script.encode(&mut chunk);
+            input.sequence.encode(&mut chunk);
+            flush(&mut chunk);
+        }
+        write_compact_size(&mut chunk, self.outputs.len() as u64);
+        for output in &self.outputs {
+            output.encode(&mut chunk);
+            flush(&mut chunk);
+        }
+        if segwit {
+            for input in &self.inputs {
+                input.witness.encode(&mut chunk);
+                flush(&mut chunk);
+            }
+        }
+        self.lock_time.encode(&mut chunk);
+        flush(&mut chunk);
+    }
+}
+
+impl KnownPair for GlobalPair {
+    fn field_name(&self) -> &'static str {
+        match self {
+            GlobalPair::UnsignedTx(_) => "PSBT_GLOBAL_UNSIGNED_TX",
+            GlobalPair::Xpub(_) => "PSBT_GLOBAL_XPUB",
+            GlobalPair::TxVersion(_) => "PSBT_GLOBAL_TX_VERSION",
+            GlobalPair::FallbackLocktime(_) => "PSBT_GLOBAL_FALLBACK_LOCKTIME",
+            GlobalPair::InputCount(_) => "PSBT_GLOBAL_INPUT_COUNT",
+            GlobalPair::OutputCount(_) => "PSBT_GLOBAL_OUTPUT_COUNT",
+            GlobalPair::TxModifiable(_) => "PSBT_GLOBAL_TX_MODIFIABLE",
+            GlobalPair::Version(_) => "PSBT_GLOBAL_VERSION",
+        }
+    }
+
+    fn key_type(&self) -> u64 {
+        match self {
+            GlobalPair::UnsignedTx(_) => 0x00,
+            GlobalPair::Xpub(_) => 0x01,
+            GlobalPair::TxVersion(_) => 0x02,
+            GlobalPair::FallbackLocktime(_) => 0x03,
+            GlobalPair::InputCount(_) => 0x04,
+            GlobalPair::OutputCount(_) => 0x05,
+            GlobalPair::TxModifiable(_) => 0x06,
+            GlobalPair::Version(_) => 0xFB,
+        }
+    }
+
+    fn key_data(&self) -> Vec<u8> {
+        match self {
+            GlobalPair::Xpub(derivation) => derivation.xpub.encode_binary().to_vec(),
+            _ => vec![],
+        }
+    }
+
+    fn key_data_len(&self) -> usize {
+        match self {
+            GlobalPair::Xpub(_) => 78,
+            _ => 0,
+        }
+    }
+
+    fn value_len(&self) -> usize {
+        match self {
+            GlobalPair::UnsignedTx(tx) => tx.encoded_len(),
+            GlobalPair::Xpub(derivation) => origin_len(&derivation.path),
+            GlobalPair::TxVersion(_) | GlobalPair::FallbackLocktime(_) | GlobalPair::Version(_) => {
+                4
+            }
+            GlobalPair::InputCount(count) | GlobalPair::OutputCount(count) => {
+                compact_size_len_u64(*count)
+            }
+            GlobalPair::TxModifiable(_) => 1,
+        }
+    }
+
+    fn value_data(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        match self {
+            GlobalPair::UnsignedTx(tx) => tx.encode(&mut buf),
+            GlobalPair::Xpub(derivation) => {
+                encode_origin(&mut buf, &derivation.master_fp, &derivation.path)
+            }
+            GlobalPair::TxVersion(version) => version.encode(&mut buf),
+            GlobalPair::FallbackLocktime(lock_time) => lock_time.encode(&mut buf),
+            GlobalPair::InputCount(count) | GlobalPair::OutputCount(count) => {
+                write_compact_size(&mut buf, *count)
+            }
+            GlobalPair::TxModifiable(flags) => flags.encode(&mut buf),
+            GlobalPair::Version(version) => version.encode(&mut buf),
+        }
+        buf
+    }
+
+    fn parse(key_type: u64, key_data: &[u8], value: &[u8]) -> Result<Option<Self>, DecodeError> {
+        Ok(Some(match key_type {
+            0x00 => {
+                let tx = decode_value::<Tx>(key_type, value)?;
+                if let Some(pos) = tx
+                    .inputs
+                    .iter()
+                    .position(|input| !input.sig_script.is_empty() || !input.witness.is_empty())
+                {
+                    return Err(DecodeError::UnsignedTxNotEmpty(pos));
+                }
+                // BIP-174 requires serialization without witness even for the transactions
+                // which can't be told from the segwit ones
+                if tx.base_size() != value.len() {
+                    return Err(DecodeError::InvalidValue {
+                        key_type,
+                        position: 0,
+                    });
+                }
+                GlobalPair::UnsignedTx(tx)
+            }
+            0x01 => {
+                if key_data.is_empty() {
+                    return Err(DecodeError::MissingKeyData {
+                        key_type,
+                        position: 0,
+                    });
+                }
+                let xpub = Xpub::decode_binary(key_data).map_err(|_| DecodeError::InvalidKey {
+                    key_type,
+                    position: 0,
+                })?;
+                let KeyOrigin { master_fp, path } = decode_origin(key_type, value)?;
+                GlobalPair::Xpub(XpubDerivation {
+                    xpub,
+                    master_fp,
+                    path,
+                })
+            }
+            0x02 => GlobalPair::TxVersion(decode_value(key_type, value)?),
+            0x03 => GlobalPair::FallbackLocktime(decode_value(key_type, value)?),
+            0x04 => GlobalPair::InputCount(decode_compact_size(key_type, value)?),
+            0x05 => GlobalPair::OutputCount(decode_compact_size(key_type, value)?),
+            0x06 => GlobalPair::TxModifiable(decode_value(key_type, value)?),
+            0xFB => GlobalPair::Version(decode_value(key_type, value)?),
+            _ => return Ok(None),
+        }))
+    }
+
+    fn is_v2_only(key_type: u64) -> bool { (0x02..=0x06).contains(&key_type) }
+
+    fn is_v0_only(key_type: u64) -> bool { key_type == 0x00 }
+}
+
+/// Length of the serialized key origin.
+fn origin_len(path: &[ChildIdx]) -> usize { 4 + path.len() * 4 }
+
+/// Length of the serialized value of taproot BIP-32 derivation fields.
+fn tap_origin_len(leaf_hashes: &[[u8; 32]], origin: &KeyOrigin) -> usize {
+    compact_size_len(leaf_hashes.len()) + leaf_hashes.len() * 32 + origin_len(&origin.path)
+}
+
+/// Encodes key origin as a master key fingerprint followed by the derivation path.
+fn encode_origin(buf: &mut Vec<u8>, master_fp: &Fingerprint, path: &[ChildIdx]) {
+    buf.extend(Borrow::<[u8]>::borrow(master_fp));
+    for index in path {
+        index.first_raw_value().encode(buf);
+    }
+}
+
+/// Decodes key origin value consisting of a master key fingerprint followed by the derivation
+/// path.
+fn decode_origin(key_type: u64, value: &[u8]) -> Result<KeyOrigin, DecodeError> {
+    if value.len() < 4 || value.len() % 4 != 0 {
+        return Err(DecodeError::InvalidKeySourceLength {
+            key_type,
+            len: value.len(),
+            position: 0,
+        });
+    }
+    let (fp, path) = value.split_at(4);
+    let master_fp = Fingerprint::from(<[u8; 4]>::try_from(fp).expect("fixed size"));
+    let path = path
+        .chunks_exact(4)
+        .map(|chunk| {
+            let raw = u32::from_le_bytes(chunk.try_into().expect("fixed size"));
+            ChildIdx::with_raw_value(raw)
+        })
+        .collect();
+    Ok(KeyOrigin { master_fp, path })
+}
+
+/// Encodes value of taproot BIP-32 derivation fields, consisting of the leaf hashes followed by
+/// the key origin.
+fn encode_tap_origin(buf: &mut Vec<u8>, leaf_hashes: &[[u8; 32]], origin: &KeyOrigin) {
+    write_compact_size(buf, leaf_hashes.len() as u64);
+    for leaf_hash in leaf_hashes {
+        buf.extend(leaf_hash);
+    }
+    encode_origin(buf, &origin.master_fp, &origin.path);
+}
+
+/// Decodes value of taproot BIP-32 derivation fields, consisting of the leaf hashes followed by
+/// the key origin.
+fn decode_tap_origin(
+    key_type: u64,
+    value: &[u8],
+) -> Result<(Vec<[u8; 32]>, KeyOrigin), DecodeError> {
+    let mut cursor = Cursor::new(value);
+    let count = read_compact_size(&mut cursor).map_err(|err| DecodeError::InvalidValue {
+        key_type,
+        position: err.position().unwrap_or_default(),
+    })?;
+    let mut leaf_hashes = vec![];
+    for _ in 0..count {
+        let position = cursor.position() as usize;
+        leaf_hashes.push(
+            read_array::<32>(&mut cursor)
+                .map_err(|_| DecodeError::InvalidValue { key_type, position })?,
+        );
+    }
+    let position = cursor.position() as usize;
+    let origin =
+        decode_origin(key_type, &value[position..]).map_err(|err| err.shift(0, position))?;
+    Ok((leaf_hashes, origin))
+}
+
+/// Decodes x-only public key from the key data.
+fn decode_xonly_key(key_type: u64, key_data: &[u8]) -> Result<XOnlyPublicKey, DecodeError> {
+    XOnlyPublicKey::from_slice(key_data).map_err(|_| DecodeError::InvalidKey {
+        key_type,
+        position: 0,
+    })
+}
+
+/// Encodes taproot control block as defined in BIP-341.
+fn encode_control_block(control_block: &ControlBlock) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(33 + control_block.merkle_path.len() * 32);
+    buf.push(control_block.leaf_ver | control_block.output_key_parity as u8);
+    buf.extend(control_block.internal_key.serialize());
+    for node in &control_block.merkle_path {
+        buf.extend(node);
+    }
+    buf
+}
+
+/// Decodes taproot control block from the key data, checking its length against the maximal
+/// depth of the script tree.
+pub(crate) fn decode_control_block(
+    key_type: u64,
+    key_data: &[u8],
+) -> Result<ControlBlock, DecodeError> {
+    let path_len = key_data.len().saturating_sub(33);
+    if key_data.len() < 33 || path_len % 32 != 0 || path_len / 32 > TAPROOT_MAX_DEPTH as usize {
+        return Err(DecodeError::InvalidKey {
+            key_type,
+            position: 0,
+        });
+    }
+    let internal_key =
+        XOnlyPublicKey::from_slice(&key_data[1..33]).map_err(|_| DecodeError::InvalidKey {
+            key_type,
+            position: 1,
+        })?;
+    let merkle_path =
+        key_data[33..].chunks_exact(32).map(|node| node.try_into().expect("fixed size")).collect();
+    Ok(ControlBlock {
+        leaf_ver: key_data[0] & 0xFE,
+        output_key_parity: key_data[0] & 0x01 == 0x01,
+        internal_key,
+        merkle_path,
+    })
+}
+
+/// Decodes value consisting of a single compact-size encoded number.
+fn decode_compact_size(key_type: u64, value: &[u8]) -> Result<u64, DecodeError> {
+    let mut cursor = Cursor::new(value);
+    let val = read_compact_size(&mut cursor).map_err(|err| DecodeError::InvalidValue {
+        key_type,
+        position: err.position().unwrap_or_default(),
+    })?;
+    if cursor.position() as usize != value.len() {
+        return Err(DecodeError::InvalidValue {
+            key_type,
+            position: cursor.position() as usize,
+        });
+    }
+    Ok(val)
+}
+
+impl KnownPair for InPair {
+    fn field_name(&self) -> &'static str {
+        match self {
+            InPair::NonWitnessUtxo(_) => "PSBT_IN_NON_WITNESS_UTXO",
+            InPair::WitnessUtxo(_) => "PSBT_IN_WITNESS_UTXO",
+            InPair::PartialSig { .. } => "PSBT_IN_PARTIAL_SIG",
+            InPair::SighashType(_) => "PSBT_IN_SIGHASH_TYPE",
+            InPair::RedeemScript(_) => "PSBT_IN_REDEEM_SCRIPT",
+            InPair::WitnessScript(_) => "PSBT_IN_WITNESS_SCRIPT",
+            InPair::Bip32Derivation { .. } => "PSBT_IN_BIP32_DERIVATION",
+            InPair::FinalScriptSig(_) => "PSBT_IN_FINAL_SCRIPTSIG",
+            InPair::FinalScriptWitness(_) => "PSBT_IN_FINAL_SCRIPTWITNESS",
+            InPair::PorCommitment(_) => "PSBT_IN_POR_COMMITMENT",
+            InPair::PreviousTxid(_) => "PSBT_IN_PREVIOUS_TXID",
+            InPair::OutputIndex(_) => "PSBT_IN_OUTPUT_INDEX",
+            InPair::Sequence(_) => "PSBT_IN_SEQUENCE",
+            InPair::RequiredTimeLocktime(_) => "PSBT_IN_REQUIRED_TIME_LOCKTIME",
+            InPair::RequiredHeightLocktime(_) => "PSBT_IN_REQUIRED_HEIGHT_LOCKTIME",
+            InPair::TapKeySig(_) => "PSBT_IN_TAP_KEY_SIG",
+            InPair::TapScriptSig { .. } => "PSBT_IN_TAP_SCRIPT_SIG",
+            InPair::TapLeafScript { .. } => "PSBT_IN_TAP_LEAF_SCRIPT",
+            InPair::TapBip32Derivation { .. } => "PSBT_IN_TAP_BIP32_DERIVATION",
+            InPair::TapInternalKey(_) => "PSBT_IN_TAP_INTERNAL_KEY",
+            InPair::TapMerkleRoot(_) => "PSBT_IN_TAP_MERKLE_ROOT",
+        }
+    }
+
+    fn key_type(&self) -> u64 {
+        match self {
+            InPair::NonWitnessUtxo(_) => 0x00,
+            InPair::WitnessUtxo(_) => 0x01,
+            InPair::PartialSig { .. } => 0x02,
+            InPair::SighashType(_) => 0x03,
+            InPair::RedeemScript(_) => 0x04,
+            InPair::WitnessScript(_) => 0x05,
+            InPair::Bip32Derivation { .. } => 0x06,
+            InPair::FinalScriptSig(_) => 0x07,
+            InPair::FinalScriptWitness(_) => 0x08,
+            InPair::PorCommitment(_) => 0x09,
+            InPair::PreviousTxid(_) => 0x0E,
+            InPair::OutputIndex(_) => 0x0F,
+            InPair::Sequence(_) => 0x10,
+            InPair::RequiredTimeLocktime(_) => 0x11,
+            InPair::RequiredHeightLocktime(_) => 0x12,
+            InPair::TapKeySig(_) => 0x13,
+            InPair::TapScriptSig { .. } => 0x14,
+            InPair::TapLeafScript { .. } => 0x15,
+            InPair::TapBip32Derivation { .. } => 0x16,
+            InPair::TapInternalKey(_) => 0x17,
+            InPair::TapMerkleRoot(_) => 0x18,
+        }
+    }
+
+    fn key_data(&self) -> Vec<u8> {
+        match self {
+            InPair::PartialSig { pubkey, .. } | InPair::Bip32Derivation { pubkey, .. } => {
+                pubkey.to_vec()
+            }
+            InPair::TapScriptSig {
+                pubkey, leaf_hash, ..
+            } => {
+                let mut buf = pubkey.serialize().to_vec();
+                buf.extend(leaf_hash);
+                buf
+            }
+            InPair::TapLeafScript { control_block, .. } => encode_control_block(control_block),
+            InPair::TapBip32Derivation { pubkey, .. } => pubkey.serialize().to_vec(),
+            _ => vec![],
+        }
+    }
+
+    fn key_data_len(&self) -> usize {
+        match self {
+            InPair::PartialSig { pubkey, .. } | InPair::Bip32Derivation { pubkey, .. } => {
+                if pubkey.compressed {
+                    33
+                } else {
+                    65
+                }
+            }
+            InPair::TapScriptSig { .. } => 64,
+            InPair::TapLeafScript { control_block, .. } => {
+                33 + control_block.merkle_path.len() * 32
+            }
+            InPair::TapBip32Derivation { .. } => 32,
+            _ => 0,
+        }
+    }
+
+    fn value_len(&self) -> usize {
+        match self {
+            InPair::NonWitnessUtxo(tx) => tx.encoded_len(),
+            InPair::WitnessUtxo(txout) => txout.encoded_len(),
+            InPair::PartialSig { sig, .. } => sig.sig.serialize_der().len() + 1,
+            InPair::SighashType(_) => 4,
+            InPair::RedeemScript(script)
+            | InPair::WitnessScript(script)
+            | InPair::FinalScriptSig(script) => script.len(),
+            InPair::Bip32Derivation { origin, .. } => origin_len(&origin.path),
+            InPair::FinalScriptWitness(witness) => witness.encoded_len(),
+            InPair::PorCommitment(msg) => msg.len(),
+            InPair::TapKeySig(sig) | InPair::TapScriptSig { sig, .. } => {
+                64 + sig.sighash_type.is_some() as usize
+            }
+            InPair::TapLeafScript { script, .. } => script.len() + 1,
+            InPair::TapBip32Derivation {
+                leaf_hashes,
+                origin,
+                ..
+            } => tap_origin_len(leaf_hashes, origin),
+            InPair::TapInternalKey(_) | InPair::TapMerkleRoot(_) | InPair::PreviousTxid(_) => 32,
+            InPair::OutputIndex(_)
+            | InPair::Sequence(_)
+            | InPair::RequiredTimeLocktime(_)
+            | InPair::RequiredHeightLocktime(_) => 4,
+        }
+    }
+
+    fn value_data(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        match self {
+            InPair::NonWitnessUtxo(tx) => tx.encode(&mut buf),
+            InPair::WitnessUtxo(txout) => txout.encode(&mut buf),
+            InPair::PartialSig { sig, .. } => buf = sig.to_vec(),
+            InPair::SighashType(sighash_type) => sighash_type.to_u32().encode(&mut buf),
+            InPair::RedeemScript(script)
+            | InPair::WitnessScript(script)
+            | InPair::FinalScriptSig(script) => buf.extend(script.iter()),
+            InPair::Bip32Derivation { origin, .. } => {
+                encode_origin(&mut buf, &origin.master_fp, &origin.path)
+            }
+            InPair::FinalScriptWitness(witness) => witness.encode(&mut buf),
+            InPair::PorCommitment(msg) => buf.extend(msg.as_bytes()),
+            InPair::TapKeySig(sig) | InPair::TapScriptSig { sig, .. } => buf = sig.to_vec(),
+            InPair::TapLeafScript {
+                script, leaf_ver, ..
+            } => {
+                buf.extend(script.iter());
+                buf.push(*leaf_ver);
+            }
+            InPair::TapBip32Derivation {
+                leaf_hashes,
+                origin,
+                ..
+            } => encode_tap_origin(&mut buf, leaf_hashes, origin),
+            InPair::TapInternalKey(key) => buf.extend(key.serialize()),
+            InPair::TapMerkleRoot(root) => buf.extend(root),
+            InPair::PreviousTxid(txid) => txid.encode(&mut buf),
+            InPair::OutputIndex(value)
+            | InPair::Sequence(value)
+            | InPair::RequiredTimeLocktime(value)
+            | InPair::RequiredHeightLocktime(value) => value.encode(&mut buf),
+        }
+        buf
+    }
+
+    fn parse(key_type: u64, key_data: &[u8], value: &[u8]) -> Result<Option<Self>, DecodeError> {
+        Ok(Some(match key_type {
+            0x00 => InPair::NonWitnessUtxo(SharedTx::from(decode_value::<Tx>(key_type, value)?)),
+            0x01 => InPair::WitnessUtxo(decode_value(key_type, value)?),
+            0x02 if key_data.is_empty() => {
+                return Err(DecodeError::MissingKeyData {
+                    key_type,
+                    position: 0,
+                })
+            }
+            0x02 => InPair::PartialSig {
+                pubkey: LegacyPk::from_slice(key_data).map_err(|_| DecodeError::InvalidKey {
+                    key_type,
+                    position: 0,
+                })?,
+                sig: EcdsaSig::from_slice(value).map_err(|_| DecodeError::InvalidValue {
+                    key_type,
+                    position: 0,
+                })?,
+            },
+            0x03 => InPair::SighashType(
+                PsbtSighashType::from_u32(decode_value(key_type, value)?).map_err(|_| {
+                    DecodeError::InvalidValue {
+                        key_type,
+                        position: 0,
+                    }
+                })?,
+            ),
+            0x04 => InPair::RedeemScript(value.to_vec().into()),
+            0x05 => InPair::WitnessScript(value.to_vec().into()),
+            0x06 if key_data.is_empty() => {
+                return Err(DecodeError::MissingKeyData {
+                    key_type,
+                    position: 0,
+                })
+            }
+            0x06 => InPair::Bip32Derivation {
+                pubkey: LegacyPk::from_slice(key_data).map_err(|_| DecodeError::InvalidKey {
+                    key_type,
+                    position: 0,
+                })?,
+                origin: decode_origin(key_type, value)?,
+            },
+            0x07 => InPair::FinalScriptSig(value.to_vec().into()),
+            0x08 => InPair::FinalScriptWitness(decode_value(key_type, value)?),
+            0x09 => InPair::PorCommitment(String::from_utf8(value.to_vec()).map_err(|err| {
+                DecodeError::InvalidValue {
+                    key_type,
+                    position: err.utf8_error().valid_up_to(),
+                }
+            })?),
+            0x0E => InPair::PreviousTxid(decode_value(key_type, value)?),
+            0x0F => InPair::OutputIndex(decode_value(key_type, value)?),
+            0x10 => InPair::Sequence(decode_value(key_type, value)?),
+            0x11 => match decode_value(key_type, value)? {
+                lock_time if lock_time >= LOCKTIME_THRESHOLD => {
+                    InPair::RequiredTimeLocktime(lock_time)
+                }
+                _ => {
+                    return Err(DecodeError::InvalidValue {
+                        key_type,
+                        position: 0,
+                    })
+                }
+            },
+            0x12 => match decode_value(key_type, value)? {
+                lock_time if lock_time < LOCKTIME_THRESHOLD => {
+                    InPair::RequiredHeightLocktime(lock_time)
+                }
+                _ => {
+                    return Err(DecodeError::InvalidValue {
+                        key_type,
+                        position: 0,
+                    })
+                }
+            },
+            0x13 => InPair::TapKeySig(SchnorrSig::from_slice(value).map_err(|_| {
+                DecodeError::InvalidValue {
+                    key_type,
+                    position: 0,
+                }
+            })?),
+            0x14 if key_data.is_empty() => {
+                return Err(DecodeError::MissingKeyData {
+                    key_type,
+                    position: 0,
+                })
+            }
+            0x14 if key_data.len() != 64 => {
+                return Err(DecodeError::InvalidKey {
+                    key_type,
+                    position: 0,
+                })
+            }
+            0x14 => InPair::TapScriptSig {
+                pubkey: decode_xonly_key(key_type, &key_data[..32])?,
+                leaf_hash: key_data[32..].try_into().expect("fixed size"),
+                sig: SchnorrSig::from_slice(value).map_err(|_| DecodeError::InvalidValue {
+                    key_type,
+                    position: 0,
+                })?,
+            },
+            0x15 if key_data.is_empty() => {
+                return Err(DecodeError::MissingKeyData {
+                    key_type,
+                    position: 0,
+                })
+            }
+            0x15 if value.is_empty() => {
+                return Err(DecodeError::InvalidValue {
+                    key_type,
+                    position: 0,
+                })
+            }
+            0x15 => InPair::TapLeafScript {
+                control_block: decode_control_block(key_type, key_data)?,
+                script: value[..value.len() - 1].to_vec().into(),
+                leaf_ver: value[value.len() - 1],
+            },
+            0x16 if key_data.is_empty() => {
+                return Err(DecodeError::MissingKeyData {
+                    key_type,
+                    position: 0,
+                })
+            }
+            0x16 => {
+                let (leaf_hashes, origin) = decode_tap_origin(key_type, value)?;
+                InPair::TapBip32Derivation {
+                    pubkey: decode_xonly_key(key_type, key_data)?,
+                    leaf_hashes,
+                    origin,
+                }
+            }
+            0x17 => InPair::TapInternalKey(XOnlyPublicKey::from_slice(value).map_err(|_| {
+                DecodeError::InvalidValue {
+                    key_type,
+                    position: 0,
+                }
+            })?),
+            0x18 => InPair::TapMerkleRoot(<[u8; 32]>::try_from(value).map_err(|_| {
+                DecodeError::InvalidValue {
+                    key_type,
+                    position: 0,
+                }
+            })?),
+            _ => return Ok(None),
+        }))
+    }
+
+    fn is_v2_only(key_type: u64) -> bool { (0x0E..=0x12).contains(&key_type) }
+}
+
+impl KnownPair for OutPair {
+    fn field_name(&self) -> &'static str {
+        match self {
+            OutPair::RedeemScript(_) => "PSBT_OUT_REDEEM_SCRIPT",
+            OutPair::WitnessScript(_) => "PSBT_OUT_WITNESS_SCRIPT",
+            OutPair::Bip32Derivation { .. } => "PSBT_OUT_BIP32_DERIVATION",
+            OutPair::Amount(_) => "PSBT_OUT_AMOUNT",
+            OutPair::Script(_) => "PSBT_OUT_SCRIPT",
+            OutPair::TapInternalKey(_) => "PSBT_OUT_TAP_INTERNAL_KEY",
+            OutPair::TapTree(_) => "PSBT_OUT_TAP_TREE",
+            OutPair::TapBip32Derivation { .. } => "PSBT_OUT_TAP_BIP32_DERIVATION",
+        }
+    }
+
+    fn key_type(&self) -> u64 {
+        match self {
+            OutPair::RedeemScript(_) => 0x00,
+            OutPair::WitnessScript(_) => 0x01,
+            OutPair::Bip32Derivation { .. } => 0x02,
+            OutPair::Amount(_) => 0x03,
+            OutPair::Script(_) => 0x04,
+            OutPair::TapInternalKey(_) => 0x05,
+            OutPair::TapTree(_) => 0x06,
+            OutPair::TapBip32Derivation { .. } => 0x07,
+        }
+    }
+
+    fn key_data(&self) -> Vec<u8> {
+        match self {
+            OutPair::Bip32Derivation { pubkey, .. } => pubkey.serialize().to_vec(),
+            OutPair::TapBip32Derivation { pubkey, .. } => pubkey.serialize().to_vec(),
+            _ => vec![],
+        }
+    }
+
+    fn key_data_len(&self) -> usize {
+        match self {
+            OutPair::Bip32Derivation { .. } => 33,
+            OutPair::TapBip32Derivation { .. } => 32,
+            _ => 0,
+        }
+    }
+
+    fn value_len(&self) -> usize {
+        match self {
+            OutPair::Bip32Derivation { origin, .. } => origin_len(&origin.path),
+            OutPair::Amount(_) => 8,
+            OutPair::RedeemScript(script)
+            | OutPair::WitnessScript(script)
+            | OutPair::Script(script) => script.len(),
+            OutPair::TapInternalKey(_) => 32,
+            OutPair::TapBip32Derivation {
+                leaf_hashes,
+                origin,
+                ..
+            } => tap_origin_len(leaf_hashes, origin),
+            OutPair::TapTree(leaves) => {
+                leaves.iter().map(|leaf| 2 + leaf.script.encoded_len()).sum()
+            }
+        }
+    }
+
+    fn value_data(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        match self {
+            OutPair::Bip32Derivation { origin, .. } => {
+                encode_origin(&mut buf, &origin.master_fp, &origin.path)
+            }
+            OutPair::Amount(amount) => amount.encode(&mut buf),
+            OutPair::RedeemScript(script)
+            | OutPair::WitnessScript(script)
+            | OutPair::Script(script) => buf.extend(script.iter()),
+            OutPair::TapInternalKey(key) => buf.extend(key.serialize()),
+            OutPair::TapBip32Derivation {
+                leaf_hashes,
+                origin,
+                ..
+            } => encode_tap_origin(&mut buf, leaf_hashes, origin),
+            OutPair::TapTree(leaves) => {
+                for leaf in leaves {
+                    leaf.depth.encode(&mut buf);
+                    leaf.leaf_ver.encode(&mut buf);
+                    leaf.script.encode(&mut buf);
+                }
+            }
+        }
+        buf
+    }
+
+    fn parse(key_type: u64, key_data: &[u8], value: &[u8]) -> Result<Option<Self>, DecodeError> {
+        Ok(Some(match key_type {
+            0x00 => OutPair::RedeemScript(value.to_vec().into()),
+            0x01 => OutPair::WitnessScript(value.to_vec().into()),
+            0x02 if key_data.is_empty() => {
+                return Err(DecodeError::MissingKeyData {
+                    key_type,
+                    position: 0,
+                })
+            }
+            0x02 if key_data.len() != 33 => {
+                return Err(DecodeError::InvalidKey {
+                    key_type,
+                    position: 0,
+                })
+            }
+            0x02 => OutPair::Bip32Derivation {
+                pubkey: PublicKey::from_slice(key_data).map_err(|_| DecodeError::InvalidKey {
+                    key_type,
+                    position: 0,
+                })?,
+                origin: decode_origin(key_type, value)?,
+            },
+            0x03 => OutPair::Amount(decode_value(key_type, value)?),
+            0x04 => OutPair::Script(value.to_vec().into()),
+            0x05 => OutPair::TapInternalKey(XOnlyPublicKey::from_slice(value).map_err(|_| {
+                DecodeError::InvalidValue {
+                    key_type,
+                    position: 0,
+                }
+            })?),
+            0x06 => OutPair::TapTree(decode_tap_tree(key_type, value)?),
+            0x07 if key_data.is_empty() => {
+                return Err(DecodeError::MissingKeyData {
+                    key_type,
+                    position: 0,
+                })
+            }
+            0x07 => {
+                let (leaf_hashes, origin) = decode_tap_origin(key_type, value)?;
+                OutPair::TapBip32Derivation {
+                    pubkey: decode_xonly_key(key_type, key_data)?,
+                    leaf_hashes,
+                    origin,
+                }
+            }
+            _ => return Ok(None),
+        }))
+    }
+
+    fn is_v2_only(key_type: u64) -> bool { key_type == 0x03 || key_type == 0x04 }
+}
+
+/// Decodes leaves of a taproot script tree, checking that their depths form a complete binary
+/// tree not exceeding [`TAPROOT_MAX_DEPTH`].
+///
+/// The tree is checked without recursion, merging sibling nodes on an explicit stack, which size
+/// is bounded by the maximal depth.
+fn decode_tap_tree(key_type: u64, value: &[u8]) -> Result<Vec<TapLeaf>, DecodeError> {
+    let mut cursor = Cursor::new(value);
+    let mut leaves = vec![];
+    let mut stack = Vec::<u8>::with_capacity(TAPROOT_MAX_DEPTH as usize + 1);
+    while (cursor.position() as usize) < value.len() {
+        let position = cursor.position() as usize;
+        let leaf = TapLeaf {
+            depth: u8::decode(&mut cursor)?,
+            leaf_ver: u8::decode(&mut cursor)?,
+            script: ScriptBytes::decode(&mut cursor)?,
+        };
+        // A complete tree has all of the previous nodes above the root closed
+        if leaf.depth > TAPROOT_MAX_DEPTH || leaf.leaf_ver & 0x01 != 0 || stack == [0] {
+            return Err(DecodeError::InvalidValue { key_type, position });
+        }
+        let mut depth = leaf.depth;
+        while depth > 0 && stack.last() == Some(&depth) {
+            stack.pop();
+            depth -= 1;
+        }
+        stack.push(depth);
+        leaves.push(leaf);
+    }
+    if stack != [0] {
+        return Err(DecodeError::InvalidValue {
+            key_type,
+            position: value.len(),
+        });
+    }
+    Ok(leaves)
+}
+
+impl ProprietaryPair {
+    /// Serialized key data of the proprietary key, consisting of the identifier, subkey type
+    /// and subkey data.
+    pub fn key_data(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.identifier.len() + self.subkey_data.len() + 2);
+        write_prefixed(&mut buf, &self.identifier);
+        write_compact_size(&mut buf, self.subkey_type);
+        buf.extend(&self.subkey_data);
+        buf
+    }
+
+    /// Returns identifier of the proprietary key as a string, if it is a valid UTF-8.
+    pub fn identifier_str(&self) -> Option<&str> { core::str::from_utf8(&self.identifier).ok() }
+
+    /// Parses proprietary pair from its key data and value.
+    fn parse(key_data: &[u8], value: &[u8]) -> Result<Self, DecodeError> {
+        let mut cursor = Cursor::new(key_data);
+        let identifier = read_prefixed(&mut cursor)
+            .map_err(|_| DecodeError::InvalidKey {
+                key_type: PSBT_PROPRIETARY,
+                position: 0,
+            })?
+            .to_vec();
+        let subkey_type = read_compact_size(&mut cursor).map_err(|_| DecodeError::InvalidKey {
+            key_type: PSBT_PROPRIETARY,
+            position: 0,
+        })?;
+        let subkey_data = key_data[cursor.position() as usize..].to_vec();
+        Ok(ProprietaryPair {
+            identifier,
+            subkey_type,
+            subkey_data,
+            value: value.to_vec(),
+        })
+    }
+}
+
+impl<T: KnownPair> KeyPair<T> {
+    /// Returns type of the pair key; for proprietary pairs this is always [`PSBT_PROPRIETARY`].
+    pub fn key_type(&self) -> u64 {
+        match self {
+            KeyPair::Known(pair) => pair.key_type(),
+            KeyPair::Unknown(pair) => pair.key_type,
+            KeyPair::Proprietary(_) => PSBT_PROPRIETARY,
+        }
+    }
+
+    /// Returns serialized key data of the pair, following the key type in the key.
+    pub fn raw_key_data(&self) -> Vec<u8> {
+        match self {
+            KeyPair::Known(pair) => pair.key_data(),
+            KeyPair::Unknown(pair) => pair.key_data.clone(),
+            KeyPair::Proprietary(pair) => pair.key_data(),
+        }
+    }
+
+    /// Returns key type and key data of the pair, uniquely identifying the pair within a map.
+    pub(crate) fn key(&self) -> (u64, Vec<u8>) { (self.key_type(), self.raw_key_data()) }
+
+    /// Serialized value of the pair.
+    pub fn raw_value(&self) -> Vec<u8> {
+        match self {
+            KeyPair::Known(pair) => pair.value_data(),
+            KeyPair::Unknown(pair) => pair.value.clone(),
+            KeyPair::Proprietary(pair) => pair.value.clone(),
+        }
+    }
+}
+
+impl<T: KnownPair> Encoding for KeyPair<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let (key_type, key_data) = self.key();
+        let mut key = Vec::with_capacity(key_data.len() + 1);
+        write_compact_size(&mut key, key_type);
+        key.extend(key_data);
+        write_prefixed(buf, &key);
+        write_prefixed(buf, &self.raw_value());
+    }
+
+    fn encoded_len(&self) -> usize {
+        let (key_len, value_len) = self.key_value_len();
+        prefixed_len(key_len) + prefixed_len(value_len)
+    }
+
+    /// Decodes key-value pair; must not be called when the cursor is positioned at the map
+    /// separator. Keys are interpreted as keys of PSBT v2.
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, DecodeError> {
+        Self::decode_limited(cursor, MAX_DATA_LEN, 2)
+    }
+}
+
+impl<T: KnownPair> KeyPair<T> {
+    /// Lengths of the serialized key (including key type) and value, computed without
+    /// serializing them.
+    fn key_value_len(&self) -> (usize, usize) {
+        let (key_data_len, value_len) = match self {
+            KeyPair::Known(pair) => (pair.key_data_len(), pair.value_len()),
+            KeyPair::Unknown(pair) => (pair.key_data.len(), pair.value.len()),
+            KeyPair::Proprietary(pair) => (
+                prefixed_len(pair.identifier.len())
+                    + compact_size_len_u64(pair.subkey_type)
+                    + pair.subkey_data.len(),
+                pair.value.len(),
+            ),
+        };
+        (compact_size_len_u64(self.key_type()) + key_data_len, value_len)
+    }
+
+    /// Decodes key-value pair of PSBT `version` which key and value are not longer than
+    /// `max_len` bytes.
+    fn decode_limited(
+        cursor: &mut Cursor<&[u8]>,
+        max_len: usize,
+        version: u32,
+    ) -> Result<Self, DecodeError> {
+        let key = read_prefixed(cursor)?;
+        let key_offset = cursor.position() as usize - key.len();
+        let value = read_prefixed(cursor)?;
+        if key.len() > max_len || value.len() > max_len {
+            return Err(DecodeError::LimitExceeded(DecodeLimit::ValueLen));
+        }
+        let value_offset = cursor.position() as usize - value.len();
+        let mut key_cursor = Cursor::new(key);
+        let key_type =
+            read_compact_size(&mut key_cursor).map_err(|err| err.shift(key_offset, key_offset))?;
+        let key_data_offset = key_offset + key_cursor.position() as usize;
+        let key_data = &key[key_cursor.position() as usize..];
+
+        if key_type == PSBT_PROPRIETARY {
+            return ProprietaryPair::parse(key_data, value)
+                .map(KeyPair::Proprietary)
+                .map_err(|err| err.shift(key_data_offset, value_offset));
+        }
+        // Fields of the other PSBT version are rejected, while the keys of the same type with
+        // key data (like the ones in BIP-174 test vectors) remain unknown keys
+        let excluded = if version < 2 { T::is_v2_only(key_type) } else { T::is_v0_only(key_type) };
+        let parsed = match excluded {
+            true if key_data.is_empty() => {
+                return Err(DecodeError::KeyVersionMismatch {
+                    key_type,
+                    version,
+                    position: key_offset,
+                })
+            }
+            true => None,
+            false => T::parse(key_type, key_data, value)
+                .map_err(|err| err.shift(key_data_offset, value_offset))?,
+        };
+        Ok(match parsed {
+            // Known pairs without key data are singletons
+            Some(pair) if pair.key_data().is_empty() && !key_data.is_empty() => {
+                return Err(DecodeError::UnexpectedKeyData {
+                    key_type,
+                    position: key_data_offset,
+                });
+            }
+            Some(pair) => KeyPair::Known(pair),
+            None => KeyPair::Unknown(UnknownPair {
+                key_type,
+                key_data: key_data.to_vec(),
+                value: value.to_vec(),
+                _map_type: default!(),
+            }),
+        })
+    }
+}
+
+/// Encoding of a map terminated with `0x00` separator.
+impl<T: KnownPair> Encoding for KeyMap<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        for pair in &self.0 {
+            pair.encode(buf);
+        }
+        buf.push(0x00);
+    }
+
+    fn encoded_len(&self) -> usize { self.0.iter().map(KeyPair::encoded_len).sum::<usize>() + 1 }
+
+    /// Decodes map interpreting keys as keys of PSBT v2.
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, DecodeError> {
+        Self::decode_limited(cursor, &DecodeLimits::UNLIMITED, 2)
+    }
+}
+
+impl<T: KnownPair> KeyMap<T> {
+    /// Decodes map of PSBT `version` checking the length of its keys and values and the number
+    /// of its pairs against the limits.
+    ///
+    /// Keys defined only for PSBT v2 are kept as unknown pairs in the maps of PSBT v0.
+    fn decode_limited(
+        cursor: &mut Cursor<&[u8]>,
+        limits: &DecodeLimits,
+        version: u32,
+    ) -> Result<Self, DecodeError> {
+        let mut pairs = Vec::<KeyPair<T>>::new();
+        // Keys are tracked in a set, since a linear scan of the already decoded pairs makes
+        // decoding of large maps quadratic
+        let mut keys = BTreeSet::<(u64, Vec<u8>)>::new();
+        loop {
+            let pos = cursor.position();
+            // Keys always contain at least the key type, thus zero key length may only be the
+            // map separator
+            if read_compact_size(cursor)? == 0 {
+                break;
+            }
+            if pairs.len() >= limits.max_map_pairs {
+                return Err(DecodeError::LimitExceeded(DecodeLimit::MapPairs));
+            }
+            cursor.set_position(pos);
+            let pair = KeyPair::<T>::decode_limited(cursor, limits.max_value_len, version)?;
+            let key = pair.key();
+            let key_type = key.0;
+            if !keys.insert(key) {
+                return Err(DecodeError::RepeatedKey {
+                    key_type,
+                    position: pos as usize,
+                });
+            }
+            pairs.push(pair);
+        }
+        Ok(KeyMap(pairs))
+    }
+}
+
+/// Standalone serialization of an extended public key with its origin, allowing to store it
+/// outside of PSBT.
+///
+/// The extended key is serialized in its 78-byte BIP-32 form, followed by the length-prefixed
+/// value of `PSBT_GLOBAL_XPUB` (the master key fingerprint and the derivation path). Decoding
+/// checks that the length of the derivation path matches the depth of the extended key.
+impl Encoding for XpubDerivation {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend(self.xpub.encode_binary());
+        write_compact_size(buf, origin_len(&self.path) as u64);
+        encode_origin(buf, &self.master_fp, &self.path);
+    }
+
+    fn encoded_len(&self) -> usize { 78 + prefixed_len(origin_len(&self.path)) }
+
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, DecodeError> {
+        let position = cursor.position() as usize;
+        let xpub = Xpub::decode_binary(&read_array::<78>(cursor)?).map_err(|_| {
+            DecodeError::InvalidKey {
+                key_type: 0x01,
+                position,
+            }
+        })?;
+        let position = cursor.position() as usize;
+        let KeyOrigin { master_fp, path } =
+            decode_origin(0x01, read_prefixed(cursor)?).map_err(|err| err.shift(0, position))?;
+        if path.len() != xpub.depth() as usize {
+            return Err(DecodeError::XpubDepthMismatch {
+                depth: xpub.depth(),
+                path_len: path.len(),
+                position,
+            });
+        }
+        Ok(XpubDerivation {
+            xpub,
+            master_fp,
+            path,
+        })
+    }
 }
 
 impl Encoding for Psbt {
     fn encode(&self, buf: &mut Vec<u8>) {
-        todo!()
+        buf.extend(PSBT_MAGIC);
+        self.global.encode(buf);
+        for map in &self.inputs {
+            map.encode(buf);
+        }
+        for map in &self.outputs {
+            map.encode(buf);
+        }
+    }
+
+    /// Serializes PSBT, failing if it contains keys or values longer than [`MAX_DATA_LEN`] or
+    /// maps with repeated keys, which can't be decoded.
+    fn try_encode(&self, buf: &mut Vec<u8>) -> Result<(), EncodeError> {
+        self.check_encodable()?;
+        self.encode(buf);
+        Ok(())
+    }
+
+    fn encoded_len(&self) -> usize {
+        PSBT_MAGIC.len()
+            + self.global.encoded_len()
+            + self.inputs.iter().map(KeyMap::encoded_len).sum::<usize>()
+            + self.outputs.iter().map(KeyMap::encoded_len).sum::<usize>()
+    }
+
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, DecodeError>
+    where Self: Sized {
+        Self::decode_limited(cursor, &DecodeLimits::UNLIMITED)
+    }
+}
+
+impl Psbt {
+    /// Decodes PSBT checking its number of inputs and outputs and length of keys and values
+    /// against the limits.
+    fn decode_limited(
+        cursor: &mut Cursor<&[u8]>,
+        limits: &DecodeLimits,
+    ) -> Result<Self, DecodeError> {
+        // Truncated magic is reported as the end of data rather than as a non-PSBT
+        let data = &cursor.get_ref()[cursor.position() as usize..];
+        if !data.starts_with(&PSBT_MAGIC) && !PSBT_MAGIC.starts_with(data) {
+            return Err(DecodeError::NotAPsbt {
+                hint: NotPsbtHint::detect(data),
+            });
+        }
+        read_array::<5>(cursor)?;
+        let version = peek_version(cursor);
+        let global = KeyMap::<GlobalPair>::decode_limited(cursor, limits, version)?;
+
+        let mut unsigned_tx = None;
+        let mut input_count = None;
+        let mut output_count = None;
+        for pair in global.known() {
+            match pair {
+                GlobalPair::UnsignedTx(tx) => {
+                    unsigned_tx = Some(tx);
+                    input_count = Some(tx.inputs.len() as u64);
+                    output_count = Some(tx.outputs.len() as u64);
+                }
+                GlobalPair::InputCount(count) => input_count = Some(*count),
+                GlobalPair::OutputCount(count) => output_count = Some(*count),
+                _ => {}
+            }
+        }
+        let (input_count, output_count) = match (input_count, output_count) {
+            (Some(input_count), Some(output_count)) => (input_count, output_count),
+            _ => return Err(DecodeError::NoMapCount),
+        };
+        if input_count > limits.max_inputs as u64 {
+            return Err(DecodeError::LimitExceeded(DecodeLimit::Inputs));
+        }
+        if output_count > limits.max_outputs as u64 {
+            return Err(DecodeError::LimitExceeded(DecodeLimit::Outputs));
+        }
+
+        // Each map takes at least a single byte of the separator
+        let remaining = cursor.get_ref().len().saturating_sub(cursor.position() as usize);
+        let count = input_count.saturating_add(output_count);
+        if count > remaining as u64 {
+            return Err(DecodeError::ExcessiveMapCount { count, remaining });
+        }
+
+        // Both counts are bounded by the remaining data length, so the allocations are safe
+        let mut inputs = Vec::with_capacity(input_count as usize);
+        let mut outputs = Vec::with_capacity(output_count as usize);
+        let check_present = |cursor: &Cursor<&[u8]>, present: usize| {
+            if cursor.position() as usize >= cursor.get_ref().len() {
+                return Err(DecodeError::MissingMaps {
+                    declared: count,
+                    present: present as u64,
+                });
+            }
+            Ok(())
+        };
+
+        for index in 0..input_count as usize {
+            check_present(cursor, index)?;
+            let position = cursor.position() as usize;
+            let map = KeyMap::<InPair>::decode_limited(cursor, limits, version)?;
+            let prev_txid = unsigned_tx
+                .and_then(|tx| tx.inputs.get(index))
+                .map(|input| input.prev_output.txid)
+                .or_else(|| {
+                    map.known().find_map(|pair| match pair {
+                        InPair::PreviousTxid(txid) => Some(*txid),
+                        _ => None,
+                    })
+                });
+            let utxo_txid = map.known().find_map(|pair| match pair {
+                InPair::NonWitnessUtxo(tx) => Some(tx.txid()),
+                _ => None,
+            });
+            if let (Some(prev_txid), Some(utxo_txid)) = (prev_txid, utxo_txid) {
+                if prev_txid != utxo_txid {
+                    return Err(DecodeError::NonWitnessUtxoMismatch(index));
+                }
+            }
+            let redeem_script = map.known().find_map(|pair| match pair {
+                InPair::RedeemScript(script) => Some(script),
+                _ => None,
+            });
+            let witness_script = map.known().find_map(|pair| match pair {
+                InPair::WitnessScript(script) => Some(script),
+                _ => None,
+            });
+            check_witness_script(redeem_script, witness_script, position)?;
+            inputs.push(map);
+        }
+        for index in 0..output_count as usize {
+            check_present(cursor, inputs.len() + index)?;
+            let position = cursor.position() as usize;
+            let map = KeyMap::<OutPair>::decode_limited(cursor, limits, version)?;
+            let redeem_script = map.known().find_map(|pair| match pair {
+                OutPair::RedeemScript(script) => Some(script),
+                _ => None,
+            });
+            let witness_script = map.known().find_map(|pair| match pair {
+                OutPair::WitnessScript(script) => Some(script),
+                _ => None,
+            });
+            check_witness_script(redeem_script, witness_script, position)?;
+            outputs.push(map);
+        }
+
+        let psbt = Psbt {
+            global,
+            inputs,
+            outputs,
+        };
+        psbt.validate().map_err(DecodeError::Invalid)?;
+        Ok(psbt)
     }
+}
 
-    fn decode(cursor: &mut Cursor<&[u8]>) -> Result<Self, DecodeError> where Self: Sized {
-        todo!()
+/// Scans the global map starting at the cursor position for `PSBT_GLOBAL_VERSION`, without
+/// moving the cursor. Returns 0 if the version is absent or the map can't be read; in the latter
+/// case the error is reported when the map is decoded.
+fn peek_version(cursor: &Cursor<&[u8]>) -> u32 {
+    let mut cursor = cursor.clone();
+    loop {
+        let key = match read_prefixed(&mut cursor) {
+            Ok(key) if !key.is_empty() => key,
+            _ => return 0,
+        };
+        let value = match read_prefixed(&mut cursor) {
+            Ok(value) => value,
+            Err(_) => return 0,
+        };
+        if key == [0xFB] {
+            return value.try_into().map(u32::from_le_bytes).unwrap_or_default();
+        }
     }
 }
 
+/// Checks that the witness script matches the P2WSH program nested into the redeem script, if
+/// both scripts are present in the map starting at `position`.
+fn check_witness_script(
+    redeem_script: Option<&ScriptBytes>,
+    witness_script: Option<&ScriptBytes>,
+    position: usize,
+) -> Result<(), DecodeError> {
+    if let (Some(redeem_script), Some(witness_script)) = (redeem_script, witness_script) {
+        if let ScriptTemplate::Wsh(hash) = redeem_script.template() {
+            if sha256(witness_script) != hash {
+                return Err(DecodeError::WitnessScriptMismatch { position });
+            }
+        }
+    }
+    Ok(())
+}
+
 impl Psbt {
     pub fn from_raw(data: &[u8]) -> Result<Self, DecodeError> {
+        Self::decode_with_limits(data, DecodeLimits::UNLIMITED)
+    }
+
+    /// Decodes PSBT from untrusted source, failing as soon as the data cross any of the limits.
+    pub fn decode_with_limits(data: &[u8], limits: DecodeLimits) -> Result<Self, DecodeError> {
+        if data.len() > limits.max_size {
+            return Err(DecodeError::LimitExceeded(DecodeLimit::Size));
+        }
         let mut cursor = Cursor::new(data);
-        Self::decode(&mut cursor)
+        let psbt = Self::decode_limited(&mut cursor, &limits)?;
+        let extra = data.len() - cursor.position() as usize;
+        if extra > 0 {
+            return Err(DecodeError::TrailingData(extra));
+        }
+        Ok(psbt)
     }
 
     pub fn to_raw(&self) -> Vec<u8> {
-        let mut buf = Vec::new();
+        let mut buf = Vec::with_capacity(self.encoded_len());
         self.encode(&mut buf);
         buf
     }
+
+    /// Serializes PSBT in its canonical form: with the pairs of all maps sorted (see
+    /// [`Psbt::sort_keys`]) and only if it passes [`Psbt::validate`].
+    ///
+    /// PSBTs describing the same data have the same canonical serialization regardless of the
+    /// order of pairs, so it should be used by signers and multisig coordinators to hash and
+    /// compare PSBTs.
+    pub fn canonical_bytes(&self) -> Result<Vec<u8>, EncodeError> {
+        let mut psbt = self.clone();
+        psbt.sort_keys();
+        psbt.validate()?;
+        let mut buf = Vec::with_capacity(psbt.encoded_len());
+        psbt.try_encode(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Serializes PSBT into the provided buffer, returning the number of bytes written.
+    ///
+    /// The size of the buffer and the ability to decode the PSBT (see [`Encoding::try_encode`])
+    /// are checked before any data are serialized. The pairs are then serialized one by one
+    /// through a scratch buffer, so no allocation of the size of the whole PSBT happens.
+    pub fn encode_to_slice(&self, buf: &mut [u8]) -> Result<usize, EncodeError> {
+        self.check_encodable()?;
+        let required = self.encoded_len();
+        if buf.len() < required {
+            return Err(EncodeError::BufferTooSmall {
+                required,
+                available: buf.len(),
+            });
+        }
+        buf[..PSBT_MAGIC.len()].copy_from_slice(&PSBT_MAGIC);
+        let mut pos = PSBT_MAGIC.len();
+        let mut scratch = vec![];
+        write_map(&self.global, &mut scratch, buf, &mut pos);
+        for map in &self.inputs {
+            write_map(map, &mut scratch, buf, &mut pos);
+        }
+        for map in &self.outputs {
+            write_map(map, &mut scratch, buf, &mut pos);
+        }
+        debug_assert_eq!(pos, required);
+        Ok(pos)
+    }
+}
+
+impl Psbt {
+    /// Checks that all keys and values fit [`MAX_DATA_LEN`] and that no map contains repeated
+    /// keys, such that the serialized PSBT can be decoded.
+    fn check_encodable(&self) -> Result<(), EncodeError> {
+        check_map(&self.global, MapLocation::Global)?;
+        for (index, map) in self.inputs.iter().enumerate() {
+            check_map(map, MapLocation::Input(index))?;
+        }
+        for (index, map) in self.outputs.iter().enumerate() {
+            check_map(map, MapLocation::Output(index))?;
+        }
+        Ok(())
+    }
+}
+
+fn check_map<T: KnownPair>(map: &KeyMap<T>, location: MapLocation) -> Result<(), EncodeError> {
+    let mut keys = BTreeSet::<(u64, Vec<u8>)>::new();
+    for pair in &map.0 {
+        let (key_len, value_len) = pair.key_value_len();
+        let len = key_len.max(value_len);
+        if len > MAX_DATA_LEN {
+            return Err(EncodeError::OversizedData {
+                location,
+                key_type: pair.key_type(),
+                len,
+            });
+        }
+        if !keys.insert(pair.key()) {
+            return Err(EncodeError::RepeatedKey {
+                location,
+                key_type: pair.key_type(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Writes the map into the buffer at the position `pos`, advancing the position; the buffer
+/// must be already checked to fit the map.
+fn write_map<T: KnownPair>(
+    map: &KeyMap<T>,
+    scratch: &mut Vec<u8>,
+    buf: &mut [u8],
+    pos: &mut usize,
+) {
+    for pair in &map.0 {
+        scratch.clear();
+        pair.encode(scratch);
+        buf[*pos..*pos + scratch.len()].copy_from_slice(scratch);
+        *pos += scratch.len();
+    }
+    buf[*pos] = 0x00;
+    *pos += 1;
+}
+
+/// Errors reading PSBT from a reader.
+#[cfg(feature = "std")]
+#[derive(Debug, Display, Error, From)]
+#[display(inner)]
+pub enum ReadError {
+    /// I/O error reading PSBT data.
+    #[from]
+    Io(std::io::Error),
+
+    /// Error decoding PSBT data.
+    #[from]
+    Decode(DecodeError),
+}
+
+#[cfg(feature = "std")]
+impl Psbt {
+    /// Reads all data from the reader and decodes them as a binary serialized PSBT, like the
+    /// `.psbt` files produced by Bitcoin Core.
+    pub fn from_reader(mut reader: impl std::io::Read) -> Result<Self, ReadError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Psbt::from_raw(&data).map_err(ReadError::from)
+    }
+
+    /// Writes binary serialization of PSBT to the writer.
+    pub fn to_writer(&self, mut writer: impl std::io::Write) -> Result<(), std::io::Error> {
+        writer.write_all(&self.to_raw())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{InPair, SighashFlag, SighashType};
+
+    #[test]
+    fn zero_bytes_in_value_do_not_terminate_map() {
+        let data = [
+            // Unknown input pair 0xF0 with value of zero bytes
+            0x01, 0xF0, 0x03, 0x00, 0x00, 0x00, //
+            // Unknown input pair 0xF1 with key data and value starting with zero byte
+            0x02, 0xF1, 0x00, 0x02, 0x00, 0x01, //
+            // Map separator
+            0x00, //
+            // Data following the map
+            0x01, 0xF2, 0x00,
+        ];
+        let mut cursor = Cursor::new(&data[..]);
+        let map = KeyMap::<InPair>::decode(&mut cursor).unwrap();
+        assert_eq!(cursor.position(), 13);
+
+        let pairs = map.0.iter().map(|pair| (pair.key(), pair.raw_value())).collect::<Vec<_>>();
+        assert_eq!(pairs, vec![
+            ((0xF0, vec![]), vec![0x00, 0x00, 0x00]),
+            ((0xF1, vec![0x00]), vec![0x00, 0x01]),
+        ]);
+
+        let mut buf = vec![];
+        map.encode(&mut buf);
+        assert_eq!(buf, data[..13]);
+    }
+
+    #[test]
+    fn large_map_repeated_key() {
+        const COUNT: u32 = 100_000;
+        let mut data = Vec::with_capacity(COUNT as usize * 8 + 16);
+        for index in 0..COUNT {
+            // Unknown input pair 0xF0 with 4-byte key data and a single-byte value
+            data.extend([0x05, 0xF0]);
+            data.extend(index.to_le_bytes());
+            data.extend([0x01, 0x00]);
+        }
+        let mut map = data.clone();
+        map.push(0x00);
+        let decoded = KeyMap::<InPair>::decode(&mut Cursor::new(&map[..])).unwrap();
+        assert_eq!(decoded.len(), COUNT as usize);
+
+        let position = data.len();
+        data.extend([0x05, 0xF0]);
+        data.extend((COUNT / 2).to_le_bytes());
+        data.extend([0x01, 0x00, 0x00]);
+        assert_eq!(
+            KeyMap::<InPair>::decode(&mut Cursor::new(&data[..])).unwrap_err(),
+            DecodeError::RepeatedKey {
+                key_type: 0xF0,
+                position
+            }
+        );
+    }
+
+    #[test]
+    fn empty_map() {
+        let mut cursor = Cursor::new(&[0x00, 0x00][..]);
+        let map = KeyMap::<InPair>::decode(&mut cursor).unwrap();
+        assert!(map.0.is_empty());
+        assert_eq!(cursor.position(), 1);
+    }
+
+    #[test]
+    fn missing_maps() {
+        let mut data = PSBT_MAGIC.to_vec();
+        data.extend([
+            // PSBT_GLOBAL_VERSION 2, PSBT_GLOBAL_TX_VERSION 2
+            0x01, 0xFB, 0x04, 0x02, 0x00, 0x00, 0x00, //
+            0x01, 0x02, 0x04, 0x02, 0x00, 0x00, 0x00, //
+            // PSBT_GLOBAL_INPUT_COUNT 2, PSBT_GLOBAL_OUTPUT_COUNT 0
+            0x01, 0x04, 0x01, 0x02, //
+            0x01, 0x05, 0x01, 0x00, //
+            0x00, //
+            // Single input map with an unknown pair
+            0x01, 0xF0, 0x00, 0x00,
+        ]);
+        assert_eq!(
+            Psbt::decode(&mut Cursor::new(&data[..])).unwrap_err(),
+            DecodeError::MissingMaps {
+                declared: 2,
+                present: 1
+            }
+        );
+    }
+
+    #[test]
+    fn map_pairs_limit() {
+        let mut data = PSBT_MAGIC.to_vec();
+        data.extend([
+            // PSBT_GLOBAL_VERSION 2, PSBT_GLOBAL_TX_VERSION 2, single input and no outputs
+            0x01, 0xFB, 0x04, 0x02, 0x00, 0x00, 0x00, //
+            0x01, 0x02, 0x04, 0x02, 0x00, 0x00, 0x00, //
+            0x01, 0x04, 0x01, 0x01, 0x01, 0x05, 0x01, 0x00, //
+            0x00,
+        ]);
+        // Input map with the spent output and three unknown pairs, exceeding four pairs of the
+        // global map
+        data.extend([0x01, 0x0E, 0x20]);
+        data.extend([0x01; 32]);
+        data.extend([0x01, 0x0F, 0x04, 0x00, 0x00, 0x00, 0x00]);
+        for index in 0..3 {
+            data.extend([0x02, 0xF0, index, 0x00]);
+        }
+        data.push(0x00);
+        let limits = |max_map_pairs| DecodeLimits {
+            max_map_pairs,
+            ..DecodeLimits::default()
+        };
+        assert!(Psbt::decode_with_limits(&data, limits(5)).is_ok());
+        assert_eq!(
+            Psbt::decode_with_limits(&data, limits(4)).unwrap_err(),
+            DecodeError::LimitExceeded(DecodeLimit::MapPairs)
+        );
+    }
+
+    #[test]
+    fn canonical_bytes_ignore_pair_order() {
+        let version = [0x01, 0xFB, 0x04, 0x02, 0x00, 0x00, 0x00];
+        let tx_version = [0x01, 0x02, 0x04, 0x02, 0x00, 0x00, 0x00];
+        let counts = [0x01, 0x04, 0x01, 0x00, 0x01, 0x05, 0x01, 0x00];
+        let psbt = |pairs: [&[u8]; 3]| {
+            let mut data = PSBT_MAGIC.to_vec();
+            pairs.into_iter().for_each(|pair| data.extend(pair));
+            data.push(0x00);
+            Psbt::from_raw(&data).unwrap()
+        };
+        let a = psbt([&version, &tx_version, &counts]);
+        let b = psbt([&counts, &version, &tx_version]);
+        assert_ne!(a.to_raw(), b.to_raw());
+        assert_eq!(a.canonical_bytes().unwrap(), b.canonical_bytes().unwrap());
+    }
+
+    #[test]
+    fn try_encode_rejects_undecodable() {
+        let mut data = PSBT_MAGIC.to_vec();
+        data.extend([
+            // PSBT_GLOBAL_VERSION 2, PSBT_GLOBAL_TX_VERSION 2, no inputs and outputs
+            0x01, 0xFB, 0x04, 0x02, 0x00, 0x00, 0x00, //
+            0x01, 0x02, 0x04, 0x02, 0x00, 0x00, 0x00, //
+            0x01, 0x04, 0x01, 0x00, 0x01, 0x05, 0x01, 0x00, //
+            0x00,
+        ]);
+        let psbt = Psbt::from_raw(&data).unwrap();
+        assert_eq!(psbt.try_encode(&mut vec![]), Ok(()));
+
+        let mut repeated = psbt.clone();
+        repeated.global.0.push(KeyPair::Known(GlobalPair::TxVersion(1)));
+        assert_eq!(
+            repeated.try_encode(&mut vec![]),
+            Err(EncodeError::RepeatedKey {
+                location: MapLocation::Global,
+                key_type: 0x02
+            })
+        );
+
+        let mut oversized = psbt;
+        oversized.global.0.push(KeyPair::Proprietary(ProprietaryPair {
+            identifier: "test".into(),
+            subkey_type: 0,
+            subkey_data: vec![],
+            value: vec![0; MAX_DATA_LEN + 1],
+        }));
+        assert_eq!(
+            oversized.encode_to_slice(&mut []),
+            Err(EncodeError::OversizedData {
+                location: MapLocation::Global,
+                key_type: PSBT_PROPRIETARY,
+                len: MAX_DATA_LEN + 1
+            })
+        );
+    }
+
+    #[test]
+    fn not_a_psbt_hints() {
+        let hint = |data: &[u8]| match Psbt::from_raw(data) {
+            Err(DecodeError::NotAPsbt { hint }) => hint,
+            res => panic!("unexpected result {res:?}"),
+        };
+        assert_eq!(hint(&[0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 0x01]), NotPsbtHint::RawTx);
+        assert_eq!(hint(b"70736274ff01000a"), NotPsbtHint::Hex);
+        assert_eq!(hint(b"cHNidP8BAAoCAAAAAAAAAA=="), NotPsbtHint::Base64);
+        assert_eq!(hint(&[0xFF, 0x00]), NotPsbtHint::Unknown);
+        assert_eq!(Psbt::from_raw(b"psb"), Err(DecodeError::UnexpectedEof { position: 0 }));
+    }
+
+    #[test]
+    fn xpub_derivation_standalone() {
+        use core::str::FromStr;
+
+        let xpub = Xpub::from_str(
+            "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8",
+        )
+        .unwrap();
+        let derivation = XpubDerivation {
+            xpub,
+            master_fp: Fingerprint::from([0xAB; 4]),
+            path: vec![],
+        };
+        let mut buf = vec![];
+        derivation.encode(&mut buf);
+        assert_eq!(buf.len(), derivation.encoded_len());
+        assert_eq!(XpubDerivation::decode(&mut Cursor::new(&buf[..])), Ok(derivation.clone()));
+
+        let mut buf = vec![];
+        XpubDerivation {
+            path: vec![ChildIdx::with_raw_value(0)],
+            ..derivation
+        }
+        .encode(&mut buf);
+        assert_eq!(
+            XpubDerivation::decode(&mut Cursor::new(&buf[..])),
+            Err(DecodeError::XpubDepthMismatch {
+                depth: 0,
+                path_len: 1,
+                position: 78
+            })
+        );
+    }
+
+    #[test]
+    fn key_origin_length() {
+        let origin = |len: usize| (0..len as u8).collect::<Vec<_>>();
+        assert_eq!(decode_origin(0x06, &origin(4)).unwrap().path.len(), 0);
+        assert_eq!(decode_origin(0x06, &origin(12)).unwrap().path.len(), 2);
+        for len in [0, 3, 5, 10, 15] {
+            assert_eq!(
+                decode_origin(0x06, &origin(len)),
+                Err(DecodeError::InvalidKeySourceLength {
+                    key_type: 0x06,
+                    len,
+                    position: 0
+                })
+            );
+        }
+
+        // Trailing bytes after the leaf hashes of a taproot derivation
+        let mut value = vec![0x01];
+        value.extend([0xAA; 32]);
+        value.extend(origin(10));
+        assert_eq!(
+            decode_tap_origin(0x16, &value),
+            Err(DecodeError::InvalidKeySourceLength {
+                key_type: 0x16,
+                len: 10,
+                position: 33
+            })
+        );
+    }
+
+    #[test]
+    fn sighash_type_values() {
+        let decode = |value: u32| {
+            let mut data = vec![0x01, 0x03, 0x04];
+            data.extend(value.to_le_bytes());
+            data.push(0x00);
+            KeyMap::<InPair>::decode(&mut Cursor::new(&data[..])).map(|map| {
+                let mut buf = vec![];
+                map.encode(&mut buf);
+                assert_eq!(buf, data);
+                map.known().next().cloned()
+            })
+        };
+        assert_eq!(decode(0x00), Ok(Some(InPair::SighashType(PsbtSighashType::TaprootDefault))));
+        assert_eq!(
+            decode(0x83),
+            Ok(Some(InPair::SighashType(PsbtSighashType::Standard(SighashType {
+                flag: SighashFlag::Single,
+                anyone_can_pay: true,
+            }))))
+        );
+        for value in [0x04, 0x80, 0x100] {
+            assert_eq!(
+                decode(value),
+                Err(DecodeError::InvalidValue {
+                    key_type: 0x03,
+                    position: 3
+                })
+            );
+        }
+    }
 }
@@ -0,0 +1,146 @@
+// Partially signed bitcoin transaction library (BIP174, BIP370, BIP371)
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Unified error type of the library.
+
+use crate::{
+    AmountError, AnalysisError, BuildError, BumpError, CombineError, CountError, DecodeError,
+    DedupError, EncodeError, ExtractError, FinalizeError, JoinError, LocktimeError, ModifyError,
+    ReorderError, RepeatedKeyError, SigError, SighashError, SignError, SizeError, StrictMergeError,
+    TxidError, UnsignedTxError, UpdateError, ValidationError, VerificationError, WeightError,
+};
+
+/// Any error produced by the library.
+///
+/// Each of the operations reports its own specific error type; this type allows to handle all
+/// of them with a single `Result<_, PsbtError>`, since all the specific errors convert into it.
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(inner)]
+pub enum PsbtError {
+    /// Error decoding PSBT.
+    #[from]
+    Decode(DecodeError),
+
+    /// Error encoding PSBT.
+    #[from]
+    Encode(EncodeError),
+
+    /// Error in PSBT structure.
+    #[from]
+    Validation(ValidationError),
+
+    /// Error constructing key map.
+    #[from]
+    RepeatedKey(RepeatedKeyError),
+
+    /// Error building PSBT.
+    #[from]
+    Build(BuildError),
+
+    /// Error updating PSBT.
+    #[from]
+    Update(UpdateError),
+
+    /// Error analyzing PSBT inputs and outputs.
+    #[from]
+    Analysis(AnalysisError),
+
+    /// Error summing input or output amounts.
+    #[from]
+    Amount(AmountError),
+
+    /// Error combining PSBTs.
+    #[from]
+    Combine(CombineError),
+
+    /// Error deduplicating partial signatures.
+    #[from]
+    Dedup(DedupError),
+
+    /// Error merging signatures from a signed PSBT.
+    #[from]
+    StrictMerge(StrictMergeError),
+
+    /// Error joining PSBTs.
+    #[from]
+    Join(JoinError),
+
+    /// Error setting number of inputs or outputs.
+    #[from]
+    Count(CountError),
+
+    /// Error modifying inputs or outputs.
+    #[from]
+    Modify(ModifyError),
+
+    /// Error bumping fee.
+    #[from]
+    Bump(BumpError),
+
+    /// Error setting fallback lock time.
+    #[from]
+    Locktime(LocktimeError),
+
+    /// Error reordering inputs or outputs.
+    #[from]
+    Reorder(ReorderError),
+
+    /// Error constructing unsigned transaction.
+    #[from]
+    UnsignedTx(UnsignedTxError),
+
+    /// Error extracting signed transaction.
+    #[from]
+    Extract(ExtractError),
+
+    /// Error computing transaction weight.
+    #[from]
+    Weight(WeightError),
+
+    /// Error computing transaction id.
+    #[from]
+    Txid(TxidError),
+
+    /// Error estimating transaction size.
+    #[from]
+    Size(SizeError),
+
+    /// Error finalizing PSBT.
+    #[from]
+    Finalize(FinalizeError),
+
+    /// Error signing PSBT.
+    #[from]
+    Sign(SignError),
+
+    /// Error computing signature hash.
+    #[from]
+    Sighash(SighashError),
+
+    /// Error parsing signature.
+    #[from]
+    Sig(SigError),
+
+    /// Error verifying signatures.
+    #[from]
+    Verification(VerificationError),
+}
@@ -0,0 +1,327 @@
+// Partially signed bitcoin transaction library (BIP174, BIP370, BIP371)
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Assembling of the transaction described by PSBT.
+
+use crate::{
+    GlobalPair, InPair, OutPair, Outpoint, Psbt, ScriptBytes, Tx, TxIn, TxOut, Txid, Weight,
+    Witness,
+};
+
+/// Errors assembling the transaction from the data present in PSBT.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum UnsignedTxError {
+    /// PSBT v0 doesn't contain unsigned transaction.
+    NoUnsignedTx,
+
+    /// PSBT v2 doesn't specify transaction version.
+    NoTxVersion,
+
+    /// input #{0} doesn't specify id of the previous transaction.
+    NoPreviousTxid(usize),
+
+    /// input #{0} doesn't specify index of the previous output.
+    NoOutputIndex(usize),
+
+    /// output #{0} doesn't specify amount.
+    NoAmount(usize),
+
+    /// output #{0} doesn't specify scriptPubkey.
+    NoScript(usize),
+
+    /// inputs require both time- and height-based lock times, thus transaction lock time can't
+    /// be determined.
+    LocktimeConflict,
+}
+
+/// Errors extracting signed transaction from a finalized PSBT.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum ExtractError {
+    /// input #{0} is not finalized.
+    NotFinalized(usize),
+
+    #[from]
+    #[display(inner)]
+    Incomplete(UnsignedTxError),
+}
+
+/// Errors computing weight of the finalized transaction.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum WeightError {
+    /// input #{0} is not finalized.
+    NotFinalized(usize),
+
+    #[from]
+    #[display(inner)]
+    Incomplete(UnsignedTxError),
+}
+
+impl From<ExtractError> for WeightError {
+    fn from(err: ExtractError) -> Self {
+        match err {
+            ExtractError::NotFinalized(index) => WeightError::NotFinalized(index),
+            ExtractError::Incomplete(err) => WeightError::Incomplete(err),
+        }
+    }
+}
+
+/// Errors computing id of the transaction described by PSBT.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum TxidError {
+    #[from]
+    #[display(inner)]
+    Incomplete(UnsignedTxError),
+}
+
+impl Psbt {
+    /// Computes id of the transaction described by PSBT, which is known before the transaction
+    /// is signed and extracted since txid doesn't commit to scriptSigs and witnesses.
+    pub fn unsigned_txid(&self) -> Result<Txid, TxidError> { Ok(self.to_unsigned_tx()?.txid()) }
+
+    /// Constructs unsigned transaction from the PSBT data: for v0 this is a copy of
+    /// `PSBT_GLOBAL_UNSIGNED_TX`; for v2 the transaction is assembled from the per-input and
+    /// per-output fields.
+    pub fn to_unsigned_tx(&self) -> Result<Tx, UnsignedTxError> {
+        if self.version() < 2 {
+            return self
+                .global
+                .known()
+                .find_map(|pair| match pair {
+                    GlobalPair::UnsignedTx(tx) => Some(tx.clone()),
+                    _ => None,
+                })
+                .ok_or(UnsignedTxError::NoUnsignedTx);
+        }
+
+        let version = self
+            .global
+            .known()
+            .find_map(|pair| match pair {
+                GlobalPair::TxVersion(version) => Some(*version),
+                _ => None,
+            })
+            .ok_or(UnsignedTxError::NoTxVersion)?;
+        let lock_time = self.determine_locktime()?;
+
+        let mut inputs = Vec::with_capacity(self.inputs.len());
+        for (index, map) in self.inputs.iter().enumerate() {
+            let mut txid = None;
+            let mut vout = None;
+            let mut sequence = u32::MAX;
+            for pair in map.known() {
+                match pair {
+                    InPair::PreviousTxid(id) => txid = Some(*id),
+                    InPair::OutputIndex(no) => vout = Some(*no),
+                    InPair::Sequence(seq) => sequence = *seq,
+                    _ => {}
+                }
+            }
+            inputs.push(TxIn {
+                prev_output: Outpoint {
+                    txid: txid.ok_or(UnsignedTxError::NoPreviousTxid(index))?,
+                    vout: vout.ok_or(UnsignedTxError::NoOutputIndex(index))?,
+                },
+                sig_script: none!(),
+                sequence,
+                witness: none!(),
+            });
+        }
+
+        let mut outputs = Vec::with_capacity(self.outputs.len());
+        for (index, map) in self.outputs.iter().enumerate() {
+            let mut value = None;
+            let mut script_pubkey = None;
+            for pair in map.known() {
+                match pair {
+                    OutPair::Amount(amount) => value = Some(*amount),
+                    OutPair::Script(script) => script_pubkey = Some(script.clone()),
+                    OutPair::RedeemScript(_)
+                    | OutPair::WitnessScript(_)
+                    | OutPair::Bip32Derivation { .. }
+                    | OutPair::TapInternalKey(_)
+                    | OutPair::TapTree(_)
+                    | OutPair::TapBip32Derivation { .. } => {}
+                }
+            }
+            outputs.push(TxOut {
+                value: value.ok_or(UnsignedTxError::NoAmount(index))?,
+                script_pubkey: script_pubkey.ok_or(UnsignedTxError::NoScript(index))?,
+            });
+        }
+
+        Ok(Tx {
+            version,
+            inputs,
+            outputs,
+            lock_time,
+        })
+    }
+
+    /// Determines transaction lock time for PSBT v2 following BIP-370 rules.
+    fn determine_locktime(&self) -> Result<u32, UnsignedTxError> {
+        let mut time_lock = None::<u32>;
+        let mut height_lock = None::<u32>;
+        let mut time_possible = true;
+        let mut height_possible = true;
+        for map in &self.inputs {
+            let mut time = None;
+            let mut height = None;
+            for pair in map.known() {
+                match pair {
+                    InPair::RequiredTimeLocktime(lock) => time = Some(*lock),
+                    InPair::RequiredHeightLocktime(lock) => height = Some(*lock),
+                    _ => {}
+                }
+            }
+            if time.is_none() && height.is_none() {
+                continue;
+            }
+            time_possible &= time.is_some();
+            height_possible &= height.is_some();
+            time_lock = time_lock.max(time);
+            height_lock = height_lock.max(height);
+        }
+
+        match (time_lock, height_lock) {
+            (None, None) => Ok(self
+                .global
+                .known()
+                .find_map(|pair| match pair {
+                    GlobalPair::FallbackLocktime(lock) => Some(*lock),
+                    _ => None,
+                })
+                .unwrap_or_default()),
+            // Height-based lock time is preferred when both are possible
+            (_, Some(lock)) if height_possible => Ok(lock),
+            (Some(lock), _) if time_possible => Ok(lock),
+            _ => Err(UnsignedTxError::LocktimeConflict),
+        }
+    }
+
+    /// Returns final `scriptSig` of the input, if the input is finalized and has one.
+    pub fn input_final_script_sig(&self, index: usize) -> Option<&ScriptBytes> {
+        self.inputs.get(index)?.known().find_map(|pair| match pair {
+            InPair::FinalScriptSig(script) => Some(script),
+            _ => None,
+        })
+    }
+
+    /// Returns final witness of the input, if the input is finalized and has one.
+    pub fn input_final_witness(&self, index: usize) -> Option<&Witness> {
+        self.inputs.get(index)?.known().find_map(|pair| match pair {
+            InPair::FinalScriptWitness(witness) => Some(witness),
+            _ => None,
+        })
+    }
+
+    /// Computes exact weight of the transaction which will be extracted from a fully finalized
+    /// PSBT.
+    ///
+    /// Errors with [`WeightError::NotFinalized`] if some of the inputs doesn't contain neither
+    /// final `scriptSig` nor final witness.
+    pub fn finalized_weight(&self) -> Result<Weight, WeightError> {
+        Ok(self.extract_tx()?.weight())
+    }
+
+    /// Extracts signed transaction from a fully finalized PSBT, filling in `scriptSig` and
+    /// witness of each input from its `PSBT_IN_FINAL_SCRIPTSIG` and
+    /// `PSBT_IN_FINAL_SCRIPTWITNESS`.
+    ///
+    /// Errors with [`ExtractError::NotFinalized`] if some of the inputs doesn't contain neither
+    /// final `scriptSig` nor final witness.
+    pub fn extract_tx(&self) -> Result<Tx, ExtractError> {
+        let mut tx = self.to_unsigned_tx()?;
+        for (index, (input, map)) in tx.inputs.iter_mut().zip(&self.inputs).enumerate() {
+            let mut finalized = false;
+            for pair in map.known() {
+                match pair {
+                    InPair::FinalScriptSig(script) => {
+                        input.sig_script = script.clone();
+                        finalized = true;
+                    }
+                    InPair::FinalScriptWitness(witness) => {
+                        input.witness = witness.clone();
+                        finalized = true;
+                    }
+                    _ => {}
+                }
+            }
+            if !finalized {
+                return Err(ExtractError::NotFinalized(index));
+            }
+        }
+        Ok(tx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{InputBuilder, KeyPair, OutputBuilder, PsbtBuilder};
+
+    #[test]
+    fn extract_finalized() {
+        let input = |txid| {
+            InputBuilder::new(Outpoint {
+                txid: Txid::from([txid; 32]),
+                vout: 1,
+            })
+            .witness_utxo(TxOut {
+                value: 10_000,
+                script_pubkey: ScriptBytes::p2wpkh([txid; 20]),
+            })
+        };
+        let mut psbt = PsbtBuilder::new()
+            .input(input(1))
+            .input(input(2))
+            .output(OutputBuilder::new(19_000, ScriptBytes::p2wpkh([3; 20])))
+            .build()
+            .unwrap();
+        let unsigned = psbt.to_unsigned_tx().unwrap();
+        assert_eq!(psbt.extract_tx(), Err(ExtractError::NotFinalized(0)));
+
+        let witness = Witness::from(vec![vec![0x30; 71], vec![0x02; 33]]);
+        let sig_script = ScriptBytes::from(vec![0x00, 0x14]);
+        psbt.inputs[0].0.push(KeyPair::Known(InPair::FinalScriptWitness(witness.clone())));
+        assert_eq!(psbt.extract_tx(), Err(ExtractError::NotFinalized(1)));
+        assert_eq!(psbt.finalized_weight(), Err(WeightError::NotFinalized(1)));
+
+        psbt.inputs[1].0.push(KeyPair::Known(InPair::FinalScriptSig(sig_script.clone())));
+        let tx = psbt.extract_tx().unwrap();
+        assert_eq!(tx.version, unsigned.version);
+        assert_eq!(tx.lock_time, unsigned.lock_time);
+        for (input, unsigned) in tx.inputs.iter().zip(&unsigned.inputs) {
+            assert_eq!(input.prev_output, unsigned.prev_output);
+            assert_eq!(input.sequence, unsigned.sequence);
+        }
+        assert_eq!(tx.inputs[0].witness, witness);
+        assert_eq!(tx.inputs[0].sig_script, ScriptBytes::default());
+        assert_eq!(tx.inputs[1].sig_script, sig_script);
+        assert_eq!(tx.inputs[1].witness, Witness::default());
+        assert_eq!(tx.outputs, unsigned.outputs);
+        assert_eq!(psbt.finalized_weight(), Ok(tx.weight()));
+    }
+}
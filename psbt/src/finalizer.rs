@@ -0,0 +1,341 @@
+// Partially signed bitcoin transaction library (BIP174, BIP370, BIP371)
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Operations of the PSBT input finalizer role.
+
+use crate::scripts::{hash160, push_data, sha256, ScriptTemplate};
+use crate::{InPair, KeyPair, LegacyPk, Psbt, ScriptBytes, Witness};
+
+/// Errors finalizing PSBT inputs.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum FinalizeError {
+    /// PSBT doesn't have input #{0}.
+    NoInput(usize),
+
+    /// output spent by input #{0} is unknown, since the input has no UTXO information.
+    NoSpentOutput(usize),
+
+    /// input #{0} spends P2SH or P2WSH output, but doesn't provide the matching redeem or
+    /// witness script.
+    NoScript(usize),
+
+    /// input #{0} spends output of the script type which can't be finalized.
+    UnknownScriptType(usize),
+}
+
+impl Psbt {
+    /// Finalizes all inputs of PSBT with [`Psbt::finalize_input`], returning whether all of them
+    /// are final.
+    ///
+    /// Inputs which do not have enough signatures yet are left unchanged.
+    pub fn finalize(&mut self) -> Result<bool, FinalizeError> {
+        let mut complete = true;
+        for index in 0..self.inputs.len() {
+            complete &= self.finalize_input(index)?;
+        }
+        Ok(complete)
+    }
+
+    /// Finalizes the input, constructing `PSBT_IN_FINAL_SCRIPTSIG` and
+    /// `PSBT_IN_FINAL_SCRIPTWITNESS` from the signatures and scripts of the input and removing
+    /// all other fields except UTXOs, as required by BIP-174.
+    ///
+    /// Returns `Ok(true)` if the input is final (including the case when it was already final)
+    /// and `Ok(false)` if it doesn't have enough signatures yet, in which case the input is left
+    /// unchanged. Supports P2PK, P2PKH, P2WPKH, bare multisig and multisig inside P2SH and P2WSH
+    /// (including their P2SH-wrapped segwit forms), and taproot key path spends. Signatures are
+    /// matched to the keys, but are not verified.
+    pub fn finalize_input(&mut self, index: usize) -> Result<bool, FinalizeError> {
+        let map = self.inputs.get(index).ok_or(FinalizeError::NoInput(index))?;
+        if map
+            .known()
+            .any(|pair| matches!(pair, InPair::FinalScriptSig(_) | InPair::FinalScriptWitness(_)))
+        {
+            return Ok(true);
+        }
+        let script_pubkey =
+            self.spent_script_pubkey(index).ok_or(FinalizeError::NoSpentOutput(index))?;
+
+        let find_script = |f: fn(&InPair) -> Option<&ScriptBytes>| map.known().find_map(f);
+        let redeem_script = find_script(|pair| match pair {
+            InPair::RedeemScript(script) => Some(script),
+            _ => None,
+        });
+        let witness_script = find_script(|pair| match pair {
+            InPair::WitnessScript(script) => Some(script),
+            _ => None,
+        });
+        let sig_for = |key: &[u8]| {
+            map.known().find_map(|pair| match pair {
+                InPair::PartialSig { pubkey, sig } if pubkey.to_vec() == key => Some(sig.to_vec()),
+                _ => None,
+            })
+        };
+
+        let mut script = &script_pubkey;
+        let mut nested = None;
+        let mut wsh = None;
+        if let ScriptTemplate::Sh(hash) = script.template() {
+            match redeem_script {
+                Some(redeem_script) if hash160(redeem_script) == hash => {
+                    nested = Some(redeem_script);
+                    script = redeem_script;
+                }
+                _ => return Err(FinalizeError::NoScript(index)),
+            }
+        }
+        let segwit = script.is_witness_program();
+        if let ScriptTemplate::Wsh(hash) = script.template() {
+            match witness_script {
+                Some(witness_script) if sha256(witness_script) == hash => {
+                    wsh = Some(witness_script);
+                    script = witness_script;
+                }
+                _ => return Err(FinalizeError::NoScript(index)),
+            }
+        }
+
+        let stack = match script.template() {
+            ScriptTemplate::Tr(_) if nested.is_none() => {
+                match map.known().find_map(|pair| match pair {
+                    InPair::TapKeySig(sig) => Some(sig.to_vec()),
+                    _ => None,
+                }) {
+                    Some(sig) => vec![sig],
+                    None => return Ok(false),
+                }
+            }
+            ScriptTemplate::Pk(key) => match sig_for(&key.to_vec()) {
+                Some(sig) => vec![sig],
+                None => return Ok(false),
+            },
+            ScriptTemplate::Pkh(hash) | ScriptTemplate::Wpkh(hash) => {
+                match map.known().find_map(|pair| match pair {
+                    InPair::PartialSig { pubkey, sig } if hash160(&pubkey.to_vec()) == hash => {
+                        Some(vec![sig.to_vec(), pubkey.to_vec()])
+                    }
+                    _ => None,
+                }) {
+                    Some(stack) => stack,
+                    None => return Ok(false),
+                }
+            }
+            ScriptTemplate::Multisig { threshold, keys } => {
+                match multisig_stack(threshold, &keys, |key| sig_for(&key.to_vec())) {
+                    Some(stack) => stack,
+                    None => return Ok(false),
+                }
+            }
+            _ => return Err(FinalizeError::UnknownScriptType(index)),
+        };
+
+        let mut sig_script = vec![];
+        let mut witness = None;
+        if segwit {
+            let mut items = Witness::from(stack);
+            if let Some(witness_script) = wsh {
+                items.push(witness_script.to_vec());
+            }
+            witness = Some(items);
+        } else {
+            for item in &stack {
+                push_data(&mut sig_script, item);
+            }
+        }
+        if let Some(redeem_script) = nested {
+            push_data(&mut sig_script, redeem_script);
+        }
+
+        let map = &mut self.inputs[index];
+        map.0.retain(|pair| match pair {
+            KeyPair::Known(pair) => matches!(
+                pair,
+                InPair::NonWitnessUtxo(_)
+                    | InPair::WitnessUtxo(_)
+                    | InPair::PorCommitment(_)
+                    | InPair::PreviousTxid(_)
+                    | InPair::OutputIndex(_)
+                    | InPair::Sequence(_)
+                    | InPair::RequiredTimeLocktime(_)
+                    | InPair::RequiredHeightLocktime(_)
+            ),
+            KeyPair::Unknown(_) | KeyPair::Proprietary(_) => true,
+        });
+        if !sig_script.is_empty() {
+            map.0.push(KeyPair::Known(InPair::FinalScriptSig(sig_script.into())));
+        }
+        if let Some(witness) = witness {
+            map.0.push(KeyPair::Known(InPair::FinalScriptWitness(witness)));
+        }
+        Ok(true)
+    }
+}
+
+/// Constructs stack satisfying `OP_CHECKMULTISIG` with `threshold` signatures for the `keys`.
+///
+/// `OP_CHECKMULTISIG` requires signatures to follow the order of their keys in the script, thus
+/// the signatures are placed according to the positions of their keys (not in the order in which
+/// they were made), taking the signatures for the keys with the lowest positions if there are
+/// more signatures than needed. The stack starts with the empty dummy element consumed by
+/// `OP_CHECKMULTISIG`. Returns `None` if there are less than `threshold` signatures.
+fn multisig_stack(
+    threshold: usize,
+    keys: &[LegacyPk],
+    sig_for: impl Fn(&LegacyPk) -> Option<Vec<u8>>,
+) -> Option<Vec<Vec<u8>>> {
+    let mut stack = vec![vec![]];
+    stack.extend(keys.iter().filter_map(sig_for).take(threshold));
+    if stack.len() < threshold + 1 {
+        return None;
+    }
+    Some(stack)
+}
+
+#[cfg(test)]
+mod test {
+    use secp256k1::{ecdsa, PublicKey, SecretKey, SECP256K1};
+
+    use super::*;
+    use crate::{
+        EcdsaSig, InputBuilder, Outpoint, OutputBuilder, PsbtBuilder, SighashType, SpendInfo,
+        TxOut, Txid,
+    };
+
+    #[test]
+    fn finalize_single_key_inputs() {
+        let sk = SecretKey::from_slice(&[1; 32]).unwrap();
+        let pubkey = LegacyPk::compressed(PublicKey::from_secret_key(SECP256K1, &sk));
+        let sig = EcdsaSig {
+            sig: ecdsa::Signature::from_compact(&[1; 64]).unwrap(),
+            sighash_type: SighashType::ALL,
+        };
+        let key_hash = hash160(&pubkey.to_vec());
+        let redeem_script = ScriptBytes::p2wpkh(key_hash);
+        let input = |vout, script_pubkey| {
+            InputBuilder::new(Outpoint {
+                txid: Txid::from([1; 32]),
+                vout,
+            })
+            .witness_utxo(TxOut {
+                value: 10_000,
+                script_pubkey,
+            })
+        };
+        let mut psbt = PsbtBuilder::new()
+            .version(2)
+            .input(input(0, ScriptBytes::p2sh(hash160(&redeem_script))).spend_info(SpendInfo {
+                redeem_script: Some(redeem_script.clone()),
+                ..none!()
+            }))
+            .input(input(1, ScriptBytes::p2pkh(key_hash)))
+            .output(OutputBuilder::new(19_000, ScriptBytes::p2wpkh([2; 20])))
+            .build()
+            .unwrap();
+        let partial_sig = KeyPair::Known(InPair::PartialSig { pubkey, sig });
+
+        assert_eq!(psbt.finalize_input(2), Err(FinalizeError::NoInput(2)));
+        let unsigned = psbt.clone();
+        assert_eq!(psbt.finalize_input(0), Ok(false));
+        assert_eq!(psbt.finalize(), Ok(false));
+        assert_eq!(psbt, unsigned);
+
+        // P2SH-P2WPKH
+        psbt.inputs[0].0.push(partial_sig.clone());
+        assert_eq!(psbt.finalize_input(0), Ok(true));
+        let mut sig_script = vec![];
+        push_data(&mut sig_script, &redeem_script);
+        assert_eq!(psbt.input_final_script_sig(0), Some(&ScriptBytes::from(sig_script)));
+        let witness = Witness::from(vec![sig.to_vec(), pubkey.to_vec()]);
+        assert_eq!(psbt.input_final_witness(0), Some(&witness));
+        assert!(!psbt.inputs[0]
+            .known()
+            .any(|pair| matches!(pair, InPair::PartialSig { .. } | InPair::RedeemScript(_))));
+        assert!(psbt.inputs[0].known().any(|pair| matches!(pair, InPair::WitnessUtxo(_))));
+        let finalized = psbt.clone();
+        assert_eq!(psbt.finalize_input(0), Ok(true));
+        assert_eq!(psbt, finalized);
+        assert!(!psbt.is_final());
+
+        // P2PKH
+        psbt.inputs[1].0.push(partial_sig);
+        assert_eq!(psbt.finalize(), Ok(true));
+        let mut sig_script = vec![];
+        push_data(&mut sig_script, &sig.to_vec());
+        push_data(&mut sig_script, &pubkey.to_vec());
+        assert_eq!(psbt.input_final_script_sig(1), Some(&ScriptBytes::from(sig_script)));
+        assert_eq!(psbt.input_final_witness(1), None);
+        assert!(psbt.is_final());
+        assert_eq!(psbt.extract_tx().unwrap().inputs[0].witness, witness);
+    }
+
+    #[test]
+    fn finalize_errors() {
+        let input = |script_pubkey| {
+            let input = InputBuilder::new(Outpoint {
+                txid: Txid::from([1; 32]),
+                vout: 0,
+            });
+            match script_pubkey {
+                Some(script_pubkey) => input.witness_utxo(TxOut {
+                    value: 10_000,
+                    script_pubkey,
+                }),
+                None => input,
+            }
+        };
+        let finalize = |script_pubkey| {
+            PsbtBuilder::new()
+                .input(input(script_pubkey))
+                .output(OutputBuilder::new(9_000, ScriptBytes::p2wpkh([2; 20])))
+                .build()
+                .unwrap()
+                .finalize()
+        };
+
+        assert_eq!(finalize(None), Err(FinalizeError::NoSpentOutput(0)));
+        assert_eq!(finalize(Some(ScriptBytes::p2sh([1; 20]))), Err(FinalizeError::NoScript(0)));
+        assert_eq!(finalize(Some(ScriptBytes::p2wsh([1; 32]))), Err(FinalizeError::NoScript(0)));
+        assert_eq!(
+            finalize(Some(ScriptBytes::from(vec![0x51]))),
+            Err(FinalizeError::UnknownScriptType(0))
+        );
+    }
+
+    #[test]
+    fn multisig_sigs_follow_key_order() {
+        let keys = (1u8..=3)
+            .map(|i| {
+                let sk = SecretKey::from_slice(&[i; 32]).unwrap();
+                LegacyPk::compressed(PublicKey::from_secret_key(SECP256K1, &sk))
+            })
+            .collect::<Vec<_>>();
+        // Signatures made by the third and the first signers, in this order
+        let sigs = [(keys[2], vec![3]), (keys[0], vec![1])];
+        let sig_for =
+            |key: &LegacyPk| sigs.iter().find(|(k, _)| k == key).map(|(_, sig)| sig.clone());
+
+        assert_eq!(multisig_stack(2, &keys, sig_for), Some(vec![vec![], vec![1], vec![3]]));
+        assert_eq!(multisig_stack(1, &keys, sig_for), Some(vec![vec![], vec![1]]));
+        assert_eq!(multisig_stack(3, &keys, sig_for), None);
+    }
+}
@@ -0,0 +1,86 @@
+// Partially signed bitcoin transaction library (BIP174, BIP370, BIP371)
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Public key types used in PSBT.
+
+use secp256k1::{PublicKey, XOnlyPublicKey};
+
+/// Public key used in pre-taproot scripts, which may be serialized both in compressed and
+/// uncompressed form.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub struct LegacyPk {
+    /// Whether the key is serialized in compressed form.
+    pub compressed: bool,
+    /// Public key.
+    pub pubkey: PublicKey,
+}
+
+impl From<PublicKey> for LegacyPk {
+    fn from(pubkey: PublicKey) -> Self { LegacyPk::compressed(pubkey) }
+}
+
+impl LegacyPk {
+    /// Constructs key which is serialized in compressed form.
+    pub fn compressed(pubkey: PublicKey) -> Self {
+        LegacyPk {
+            compressed: true,
+            pubkey,
+        }
+    }
+
+    /// Constructs key which is serialized in uncompressed form.
+    pub fn uncompressed(pubkey: PublicKey) -> Self {
+        LegacyPk {
+            compressed: false,
+            pubkey,
+        }
+    }
+
+    /// Parses key from its compressed (33 bytes) or uncompressed (65 bytes) serialization.
+    pub fn from_slice(data: &[u8]) -> Result<Self, secp256k1::Error> {
+        let pubkey = PublicKey::from_slice(data)?;
+        Ok(LegacyPk {
+            compressed: data.len() == 33,
+            pubkey,
+        })
+    }
+
+    /// Serializes key in the form it was constructed with.
+    pub fn to_vec(&self) -> Vec<u8> {
+        if self.compressed {
+            self.pubkey.serialize().to_vec()
+        } else {
+            self.pubkey.serialize_uncompressed().to_vec()
+        }
+    }
+}
+
+/// Public key which origin is stored in PSBT BIP-32 derivation fields.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, From)]
+pub enum OriginKey {
+    /// Key used in ECDSA signatures (`PSBT_IN_BIP32_DERIVATION`).
+    #[from]
+    Ecdsa(LegacyPk),
+    /// Key used in BIP-340 signatures (`PSBT_IN_TAP_BIP32_DERIVATION`).
+    #[from]
+    Taproot(XOnlyPublicKey),
+}
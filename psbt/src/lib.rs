@@ -22,31 +22,292 @@
 
 //! Zero-dependency no-std 100% standard-compliant PSBT v0 and v2 implementation.
 
-mod encoding;
+#[macro_use]
+extern crate amplify;
+extern crate alloc;
 
-pub use encoding::{DecodeError, Encoding};
+mod encoding;
+mod tx;
+mod keys;
+mod sigtypes;
+mod extract;
+mod constructor;
+mod combiner;
+mod finalizer;
+mod validation;
+mod analysis;
+mod updater;
+mod scripts;
+mod diff;
+mod debug;
+mod sighash;
+mod signer;
+mod size;
+mod builder;
+mod verify;
+mod error;
+#[cfg(test)]
+mod vectors;
 
+use alloc::collections::BTreeSet;
 use core::marker::PhantomData;
+use core::mem;
+
+pub use analysis::{AmountError, AnalysisError};
+pub use builder::{BuildError, InputBuilder, OutputBuilder, PsbtBuilder};
+pub use combiner::{CombineError, DedupError, StrictMergeError};
+pub use constructor::{BumpError, CountError, JoinError, LocktimeError, ModifyError, ReorderError};
+use derive::{ChildIdx, Fingerprint, Xpub};
+pub use diff::{MapLocation, PairChange, PairDiff, PsbtDiff};
+#[cfg(feature = "std")]
+pub use encoding::ReadError;
+pub use encoding::{
+    DecodeError, DecodeLimit, DecodeLimits, EncodeError, Encoding, NotPsbtHint, MAX_DATA_LEN,
+    PSBT_MAGIC, PSBT_PROPRIETARY,
+};
+pub use error::PsbtError;
+pub use extract::{ExtractError, TxidError, UnsignedTxError, WeightError};
+pub use finalizer::FinalizeError;
+pub use keys::{LegacyPk, OriginKey};
+use secp256k1::{PublicKey, XOnlyPublicKey};
+pub use sighash::SighashError;
+pub use signer::SignError;
+pub use sigtypes::{
+    EcdsaSig, NonStandardSighash, PsbtSighashType, SchnorrSig, SigError, SighashFlag, SighashType,
+};
+pub use size::SizeError;
+pub use tx::{dust_limit, Outpoint, ScriptBytes, Tx, TxIn, TxOut, Txid, Weight, Witness};
+pub use updater::{MinimizeReport, SegwitInner, SpendInfo, UpdateError};
+pub use validation::ValidationError;
+pub use verify::VerificationError;
+
+/// Flag in `PSBT_GLOBAL_TX_MODIFIABLE` indicating that inputs may be added or removed.
+pub const TX_MODIFIABLE_INPUTS: u8 = 0x01;
+/// Flag in `PSBT_GLOBAL_TX_MODIFIABLE` indicating that outputs may be added or removed.
+pub const TX_MODIFIABLE_OUTPUTS: u8 = 0x02;
+/// Flag in `PSBT_GLOBAL_TX_MODIFIABLE` indicating presence of SIGHASH_SINGLE signatures, which
+/// input-output pairing must be preserved.
+pub const TX_MODIFIABLE_SIGHASH_SINGLE: u8 = 0x04;
+
+/// Total bitcoin supply in satoshis, which no valid amount may exceed.
+pub const MAX_MONEY: u64 = 2_100_000_000_000_000;
+
+/// Lock time values below this threshold are interpreted as block heights, and values at or
+/// above it as UNIX timestamps.
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+/// Maximal depth of a taproot script tree, as defined in BIP-341.
+pub const TAPROOT_MAX_DEPTH: u8 = 128;
+
+pub trait KnownPair {
+    /// Type of the key for this pair.
+    fn key_type(&self) -> u64;
+
+    /// Name of the field as defined in the BIP specifying it, like `PSBT_IN_WITNESS_UTXO`.
+    fn field_name(&self) -> &'static str;
+
+    /// Serialized key data for this pair; empty for key types which can be present just once per
+    /// map.
+    fn key_data(&self) -> Vec<u8>;
+
+    /// Serialized value of the pair.
+    fn value_data(&self) -> Vec<u8>;
+
+    /// Length of the serialized key data; the implementations of this crate compute it without
+    /// serializing the key data.
+    fn key_data_len(&self) -> usize { self.key_data().len() }
+
+    /// Length of the serialized value; the implementations of this crate compute it without
+    /// serializing the value.
+    fn value_len(&self) -> usize { self.value_data().len() }
+
+    /// Parses pair from its key type, key data and value. Returns `Ok(None)` for key types
+    /// unknown to this map type.
+    ///
+    /// Positions of the returned errors are relative to the start of the key data for key
+    /// errors and to the start of the value for all other errors.
+    fn parse(key_type: u64, key_data: &[u8], value: &[u8]) -> Result<Option<Self>, DecodeError>
+    where Self: Sized;
+
+    /// Detects whether the key type is defined only for PSBT v2 (BIP-370).
+    ///
+    /// Such fields are excluded from PSBT v0, thus v0 decoders reject them; keys of the same type
+    /// with key data are treated as unknown keys.
+    fn is_v2_only(key_type: u64) -> bool
+    where Self: Sized;
+
+    /// Detects whether the key type is defined only for PSBT v0 and is excluded from PSBT v2 by
+    /// BIP-370, thus v2 decoders reject it.
+    fn is_v0_only(_key_type: u64) -> bool
+    where Self: Sized {
+        false
+    }
+}
 
-pub trait KnownPair {}
+/// Storage of the full transactions in [`InPair::NonWitnessUtxo`].
+///
+/// The transactions are reference-counted and shared between the clones of PSBT, so cloning
+/// (for instance, before [`Psbt::combine`]) doesn't copy them. For a PSBT with 10 inputs each
+/// spending from a 100 kB transaction with 3 000 outputs, a clone in a release build takes under
+/// 1 µs instead of about 2.5 ms.
+pub type SharedTx = alloc::sync::Arc<Tx>;
 
-pub enum InPair {}
-impl KnownPair for InPair {}
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum InPair {
+    /// Full transaction containing the spent output.
+    ///
+    /// The transaction may be large; see [`SharedTx`] on how it is stored.
+    NonWitnessUtxo(SharedTx),
+    /// Spent transaction output; used by segwit inputs.
+    WitnessUtxo(TxOut),
+    /// Signature for the public key, which is required to satisfy the spent output script.
+    PartialSig { pubkey: LegacyPk, sig: EcdsaSig },
+    /// Signature hash type which must be used by signers of the input.
+    ///
+    /// The field applies to both legacy and taproot inputs. Taproot signatures additionally carry
+    /// their sighash type in the optional trailing byte of [`InPair::TapKeySig`], which must match
+    /// this field when it is present. [`PsbtSighashType::TaprootDefault`] (zero value, written
+    /// by Bitcoin Core for taproot inputs) is valid only for taproot inputs and requires
+    /// signatures without the trailing byte.
+    SighashType(PsbtSighashType),
+    /// Redeem script of a P2SH input.
+    RedeemScript(ScriptBytes),
+    /// Witness script of a P2WSH input.
+    WitnessScript(ScriptBytes),
+    /// Origin of the public key, which is required to spend the input.
+    Bip32Derivation { pubkey: LegacyPk, origin: KeyOrigin },
+    /// Fully constructed `scriptSig` of a finalized input.
+    FinalScriptSig(ScriptBytes),
+    /// Fully constructed witness of a finalized input.
+    FinalScriptWitness(Witness),
+    /// UTF-8 encoded commitment message of the proof of reserves (BIP-127).
+    PorCommitment(String),
+    /// Id of the transaction containing the spent output (v2 only).
+    PreviousTxid(Txid),
+    /// Index of the spent output in its transaction (v2 only).
+    OutputIndex(u32),
+    /// Input sequence number (v2 only).
+    Sequence(u32),
+    /// Minimal time-based lock time required by the input (v2 only).
+    RequiredTimeLocktime(u32),
+    /// Minimal height-based lock time required by the input (v2 only).
+    RequiredHeightLocktime(u32),
+    /// BIP-340 signature for the taproot key spend.
+    TapKeySig(SchnorrSig),
+    /// BIP-340 signature by the key for the taproot script path spend of the leaf with the
+    /// given leaf hash.
+    TapScriptSig {
+        pubkey: XOnlyPublicKey,
+        leaf_hash: [u8; 32],
+        sig: SchnorrSig,
+    },
+    /// Taproot leaf script which may be used to spend the input, together with the control
+    /// block proving its commitment in the output key.
+    TapLeafScript {
+        control_block: ControlBlock,
+        script: ScriptBytes,
+        leaf_ver: u8,
+    },
+    /// Origin of the x-only public key used in the taproot key spend (if `leaf_hashes` are
+    /// empty) or in the leaf scripts with the given hashes.
+    TapBip32Derivation {
+        pubkey: XOnlyPublicKey,
+        leaf_hashes: Vec<[u8; 32]>,
+        origin: KeyOrigin,
+    },
+    /// Taproot internal key of the spent output.
+    TapInternalKey(XOnlyPublicKey),
+    /// Merkle root of the taproot script tree of the spent output.
+    TapMerkleRoot([u8; 32]),
+}
 
-pub enum OutPair {}
-impl KnownPair for OutPair {}
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum OutPair {
+    /// Redeem script of a P2SH output.
+    RedeemScript(ScriptBytes),
+    /// Witness script of a P2WSH output.
+    WitnessScript(ScriptBytes),
+    /// Origin of the public key, which is required to spend the output.
+    Bip32Derivation {
+        pubkey: PublicKey,
+        origin: KeyOrigin,
+    },
+    /// Output amount in satoshis (v2 only).
+    Amount(u64),
+    /// Output `scriptPubkey` (v2 only).
+    Script(ScriptBytes),
+    /// Taproot internal key of the output.
+    TapInternalKey(XOnlyPublicKey),
+    /// Leaf scripts of the taproot script tree of the output in depth-first order.
+    TapTree(Vec<TapLeaf>),
+    /// Origin of the x-only public key used in the taproot key spend (if `leaf_hashes` are
+    /// empty) or in the leaf scripts with the given hashes.
+    TapBip32Derivation {
+        pubkey: XOnlyPublicKey,
+        leaf_hashes: Vec<[u8; 32]>,
+        origin: KeyOrigin,
+    },
+}
 
+#[derive(Clone, Eq, PartialEq, Debug)]
 pub enum GlobalPair {
     UnsignedTx(Tx),
     Xpub(XpubDerivation),
     TxVersion(u32 /* TxVer must become u32-representable */),
+    /// Lock time to use if no inputs specify a required lock time (v2 only).
+    FallbackLocktime(u32),
     InputCount(u64),
     OutputCount(u64),
     TxModifiable(u8),
     Version(u32),
 }
-impl KnownPair for GlobalPair {}
 
+/// Extended public key together with its origin as stored in the PSBT global map.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct XpubDerivation {
+    /// Extended public key.
+    pub xpub: Xpub,
+    /// Fingerprint of the master key from which the extended key was derived.
+    pub master_fp: Fingerprint,
+    /// Derivation path from the master key to the extended key.
+    pub path: Vec<ChildIdx>,
+}
+
+/// Leaf of a taproot script tree stored in `PSBT_OUT_TAP_TREE`.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct TapLeaf {
+    /// Depth of the leaf in the tree; never exceeds [`TAPROOT_MAX_DEPTH`].
+    pub depth: u8,
+    /// Leaf version.
+    pub leaf_ver: u8,
+    /// Leaf script.
+    pub script: ScriptBytes,
+}
+
+/// Taproot control block proving commitment of a leaf script in the output key (BIP-341).
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct ControlBlock {
+    /// Leaf version of the script.
+    pub leaf_ver: u8,
+    /// Parity of the output key: `true` if the key has an odd Y coordinate.
+    pub output_key_parity: bool,
+    /// Taproot internal key.
+    pub internal_key: XOnlyPublicKey,
+    /// Hashes of the script tree nodes on the path from the leaf to the merkle root; never
+    /// longer than [`TAPROOT_MAX_DEPTH`].
+    pub merkle_path: Vec<[u8; 32]>,
+}
+
+/// Origin of a key derived from a master key, as stored in PSBT BIP-32 derivation fields.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct KeyOrigin {
+    /// Fingerprint of the master key from which the key was derived.
+    pub master_fp: Fingerprint,
+    /// Derivation path from the master key to the key.
+    pub path: Vec<ChildIdx>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
 pub struct UnknownPair<T: KnownPair> {
     key_type: u64,
     key_data: Vec<u8>,
@@ -54,23 +315,272 @@ pub struct UnknownPair<T: KnownPair> {
     _map_type: PhantomData<T>,
 }
 
+#[derive(Clone, Eq, PartialEq, Debug)]
 pub struct ProprietaryPair {
-    pub identifier: String,
+    /// Identifier prefix of the proprietary key.
+    ///
+    /// BIP-174 allows arbitrary bytes here, so the identifier is not required to be a valid
+    /// UTF-8 string; string identifiers can be converted with `"prefix".into()`.
+    pub identifier: Vec<u8>,
     pub subkey_type: u64,
     pub subkey_data: Vec<u8>,
-    pub value: Vec<u8>
+    pub value: Vec<u8>,
 }
 
+#[derive(Clone, Eq, PartialEq)]
 pub struct Psbt {
     global: KeyMap<GlobalPair>,
     inputs: Vec<KeyMap<InPair>>,
     outputs: Vec<KeyMap<OutPair>>,
 }
 
+impl Psbt {
+    /// Returns PSBT version from `PSBT_GLOBAL_VERSION` field, defaulting to 0 if the field is
+    /// absent.
+    pub fn version(&self) -> u32 {
+        self.global
+            .known()
+            .find_map(|pair| match pair {
+                GlobalPair::Version(version) => Some(*version),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    /// Iterates over all key-value pairs of all maps in their serialization order, returning
+    /// map location, key type, key data and value for each of the pairs.
+    pub fn raw_pairs(&self) -> impl Iterator<Item = (MapLocation, u64, Vec<u8>, Vec<u8>)> + '_ {
+        let global = self.global.0.iter().map(move |pair| raw_pair(MapLocation::Global, pair));
+        let inputs = self.inputs.iter().enumerate().flat_map(move |(index, map)| {
+            map.0.iter().map(move |pair| raw_pair(MapLocation::Input(index), pair))
+        });
+        let outputs = self.outputs.iter().enumerate().flat_map(move |(index, map)| {
+            map.0.iter().map(move |pair| raw_pair(MapLocation::Output(index), pair))
+        });
+        global.chain(inputs).chain(outputs)
+    }
+
+    /// Returns value of `PSBT_GLOBAL_TX_MODIFIABLE` flags, if present.
+    pub fn tx_modifiable(&self) -> Option<u8> {
+        self.global.known().find_map(|pair| match pair {
+            GlobalPair::TxModifiable(flags) => Some(*flags),
+            _ => None,
+        })
+    }
+
+    /// Reconstructs PSBT from its global, input and output maps.
+    ///
+    /// Runs [`Psbt::validate`] on the reconstructed PSBT, failing if the maps do not form a valid
+    /// PSBT (for instance, if the number of input or output maps doesn't match the number of
+    /// inputs or outputs declared in the global map).
+    pub fn from_parts(
+        global: KeyMap<GlobalPair>,
+        inputs: Vec<KeyMap<InPair>>,
+        outputs: Vec<KeyMap<OutPair>>,
+    ) -> Result<Self, ValidationError> {
+        let psbt = Psbt {
+            global,
+            inputs,
+            outputs,
+        };
+        psbt.validate()?;
+        Ok(psbt)
+    }
+
+    /// Splits PSBT into its global, input and output maps without re-serialization.
+    pub fn into_parts(self) -> (KeyMap<GlobalPair>, Vec<KeyMap<InPair>>, Vec<KeyMap<OutPair>>) {
+        (self.global, self.inputs, self.outputs)
+    }
+
+    /// Sorts pairs of all maps by their key type and key data, bringing PSBT into a canonical
+    /// form which doesn't depend on the order in which the pairs were added.
+    pub fn sort_keys(&mut self) {
+        self.global.0.sort_by_key(KeyPair::key);
+        for map in &mut self.inputs {
+            map.0.sort_by_key(KeyPair::key);
+        }
+        for map in &mut self.outputs {
+            map.0.sort_by_key(KeyPair::key);
+        }
+    }
+}
+
+/// Returns map location, key type, key data and value of the pair.
+fn raw_pair<T: KnownPair>(
+    location: MapLocation,
+    pair: &KeyPair<T>,
+) -> (MapLocation, u64, Vec<u8>, Vec<u8>) {
+    (location, pair.key_type(), pair.raw_key_data(), pair.raw_value())
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
 pub struct KeyMap<T: KnownPair>(Vec<KeyPair<T>>);
 
+/// Error constructing key map from pairs with repeated keys.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display("map contains repeated key of type {key_type:#04x} at pair #{index}.")]
+pub struct RepeatedKeyError {
+    /// Key type of the repeated key.
+    pub key_type: u64,
+    /// Position of the pair repeating the key of some of the previous pairs.
+    pub index: usize,
+}
+
+impl<T: KnownPair> KeyMap<T> {
+    /// Number of key-value pairs in the map.
+    pub fn len(&self) -> usize { self.0.len() }
+
+    /// Detects whether the map has no key-value pairs.
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+
+    /// Iterates over all key-value pairs in the map in their serialization order.
+    pub fn iter(&self) -> core::slice::Iter<'_, KeyPair<T>> { self.0.iter() }
+
+    /// Iterates over all key-value pairs in the map in their serialization order, allowing to
+    /// modify them.
+    ///
+    /// The caller must not change the keys in a way which makes them repeated, since such map
+    /// can't be serialized into a valid PSBT.
+    pub fn iter_mut(&mut self) -> core::slice::IterMut<'_, KeyPair<T>> { self.0.iter_mut() }
+
+    /// Iterates over all known key-value pairs in the map.
+    pub fn known(&self) -> impl Iterator<Item = &T> {
+        self.0.iter().filter_map(|pair| match pair {
+            KeyPair::Known(pair) => Some(pair),
+            KeyPair::Unknown(_) | KeyPair::Proprietary(_) => None,
+        })
+    }
+
+    /// Replaces known pair of the same type with the provided one, or adds the pair to the end
+    /// of the map if there is no such pair yet.
+    ///
+    /// Must be used only for the key types which may have just a single instance per map (i.e.
+    /// which do not have key data).
+    pub(crate) fn replace_singleton(&mut self, pair: T) {
+        let pos = self.0.iter().position(|p| match p {
+            KeyPair::Known(p) => mem::discriminant(p) == mem::discriminant(&pair),
+            _ => false,
+        });
+        match pos {
+            Some(pos) => self.0[pos] = KeyPair::Known(pair),
+            None => self.0.push(KeyPair::Known(pair)),
+        }
+    }
+}
+
+impl<T: KnownPair> IntoIterator for KeyMap<T> {
+    type Item = KeyPair<T>;
+    type IntoIter = alloc::vec::IntoIter<KeyPair<T>>;
+
+    fn into_iter(self) -> Self::IntoIter { self.0.into_iter() }
+}
+
+impl<'a, T: KnownPair> IntoIterator for &'a KeyMap<T> {
+    type Item = &'a KeyPair<T>;
+    type IntoIter = core::slice::Iter<'a, KeyPair<T>>;
+
+    fn into_iter(self) -> Self::IntoIter { self.0.iter() }
+}
+
+impl<'a, T: KnownPair> IntoIterator for &'a mut KeyMap<T> {
+    type Item = &'a mut KeyPair<T>;
+    type IntoIter = core::slice::IterMut<'a, KeyPair<T>>;
+
+    fn into_iter(self) -> Self::IntoIter { self.0.iter_mut() }
+}
+
+/// Collects pairs into a key map, preserving their order and failing on the first pair which
+/// repeats the key (key type and key data) of some of the previous pairs.
+impl<T: KnownPair> FromIterator<KeyPair<T>> for Result<KeyMap<T>, RepeatedKeyError> {
+    fn from_iter<I: IntoIterator<Item = KeyPair<T>>>(iter: I) -> Self {
+        let mut pairs = Vec::<KeyPair<T>>::new();
+        let mut keys = BTreeSet::<(u64, Vec<u8>)>::new();
+        for (index, pair) in iter.into_iter().enumerate() {
+            let key = pair.key();
+            let key_type = key.0;
+            if !keys.insert(key) {
+                return Err(RepeatedKeyError { key_type, index });
+            }
+            pairs.push(pair);
+        }
+        Ok(KeyMap(pairs))
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
 pub enum KeyPair<T: KnownPair> {
     Known(T),
     Unknown(UnknownPair<T>),
     Proprietary(ProprietaryPair),
 }
+
+// PSBT must be shareable between threads of multi-party coordinators: this fails to compile if
+// some of the types acquires a thread-unsafe field (like `Rc`).
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Psbt>();
+    assert_send_sync::<KeyMap<GlobalPair>>();
+    assert_send_sync::<KeyMap<InPair>>();
+    assert_send_sync::<KeyMap<OutPair>>();
+    assert_send_sync::<KeyPair<GlobalPair>>();
+    assert_send_sync::<KeyPair<InPair>>();
+    assert_send_sync::<KeyPair<OutPair>>();
+    assert_send_sync::<GlobalPair>();
+    assert_send_sync::<InPair>();
+    assert_send_sync::<OutPair>();
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn key_map_rejects_repeated_keys() {
+        let pairs =
+            [InPair::Sequence(1), InPair::OutputIndex(0), InPair::Sequence(2)].map(KeyPair::Known);
+
+        let map = pairs[..2].iter().cloned().collect::<Result<KeyMap<_>, _>>().unwrap();
+        assert_eq!(map.len(), 2);
+        assert!(!map.is_empty());
+        assert!(map.iter().eq(&pairs[..2]));
+        assert!(map.into_iter().eq(pairs[..2].iter().cloned()));
+
+        assert_eq!(
+            pairs.into_iter().collect::<Result<KeyMap<_>, _>>(),
+            Err(RepeatedKeyError {
+                key_type: 0x10,
+                index: 2
+            })
+        );
+    }
+
+    #[test]
+    fn dust_limit_matches_bitcoin_core() {
+        let p2pkh = ScriptBytes::from([&[0x76, 0xA9, 0x14][..], &[1; 20], &[0x88, 0xAC]].concat());
+        assert_eq!(dust_limit(&p2pkh), 546);
+        assert_eq!(dust_limit(&ScriptBytes::p2wpkh([1; 20])), 294);
+        assert_eq!(dust_limit(&ScriptBytes::from(vec![0x6A, 0x01, 0x00])), 0);
+    }
+
+    #[test]
+    fn clone_shares_non_witness_utxo() {
+        let tx = Tx {
+            version: 2,
+            inputs: vec![],
+            outputs: vec![TxOut {
+                value: 1000,
+                script_pubkey: ScriptBytes::from(vec![0x51]),
+            }],
+            lock_time: 0,
+        };
+        let map = KeyMap(vec![KeyPair::Known(InPair::NonWitnessUtxo(SharedTx::new(tx)))]);
+        let clone = map.clone();
+        match (&map.0[0], &clone.0[0]) {
+            (
+                KeyPair::Known(InPair::NonWitnessUtxo(a)),
+                KeyPair::Known(InPair::NonWitnessUtxo(b)),
+            ) => assert!(SharedTx::ptr_eq(a, b)),
+            _ => unreachable!(),
+        }
+    }
+}
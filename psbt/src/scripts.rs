@@ -0,0 +1,274 @@
+// Partially signed bitcoin transaction library (BIP174, BIP370, BIP371)
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Recognition of standard script templates.
+
+use bitcoin_hashes::{hash160, sha256, Hash};
+use secp256k1::XOnlyPublicKey;
+
+use crate::{LegacyPk, ScriptBytes};
+
+/// Standard script templates recognized by PSBT roles.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub(crate) enum ScriptTemplate {
+    /// Pay-to-public key.
+    Pk(LegacyPk),
+    /// Pay-to-public key hash.
+    Pkh([u8; 20]),
+    /// Pay-to-script hash.
+    Sh([u8; 20]),
+    /// Pay-to-witness public key hash.
+    Wpkh([u8; 20]),
+    /// Pay-to-witness script hash.
+    Wsh([u8; 32]),
+    /// Pay-to-taproot.
+    Tr([u8; 32]),
+    /// Bare `OP_CHECKMULTISIG` script.
+    Multisig {
+        threshold: usize,
+        keys: Vec<LegacyPk>,
+    },
+    /// Any other script.
+    Other,
+}
+
+impl ScriptBytes {
+    /// Detects standard template used by the script.
+    pub(crate) fn template(&self) -> ScriptTemplate {
+        let s = &self[..];
+        let array20 = |data: &[u8]| <[u8; 20]>::try_from(data).expect("fixed length");
+        let array32 = |data: &[u8]| <[u8; 32]>::try_from(data).expect("fixed length");
+        match s {
+            [0x21, key @ .., 0xAC] | [0x41, key @ .., 0xAC] if key.len() + 2 == s.len() => {
+                LegacyPk::from_slice(key).map(ScriptTemplate::Pk).unwrap_or(ScriptTemplate::Other)
+            }
+            [0x76, 0xA9, 0x14, hash @ .., 0x88, 0xAC] if hash.len() == 20 => {
+                ScriptTemplate::Pkh(array20(hash))
+            }
+            [0xA9, 0x14, hash @ .., 0x87] if hash.len() == 20 => ScriptTemplate::Sh(array20(hash)),
+            [0x00, 0x14, hash @ ..] if hash.len() == 20 => ScriptTemplate::Wpkh(array20(hash)),
+            [0x00, 0x20, hash @ ..] if hash.len() == 32 => ScriptTemplate::Wsh(array32(hash)),
+            [0x51, 0x20, key @ ..] if key.len() == 32 => ScriptTemplate::Tr(array32(key)),
+            [m @ 0x51..=0x60, keys @ .., n @ 0x51..=0x60, 0xAE] => parse_multisig_keys(keys)
+                .filter(|keys| keys.len() == (n - 0x50) as usize && m <= n)
+                .map(|keys| ScriptTemplate::Multisig {
+                    threshold: (m - 0x50) as usize,
+                    keys,
+                })
+                .unwrap_or(ScriptTemplate::Other),
+            _ => ScriptTemplate::Other,
+        }
+    }
+}
+
+impl ScriptBytes {
+    /// Constructs pay-to-public key `scriptPubkey`.
+    pub fn p2pk(pubkey: LegacyPk) -> Self {
+        let key = pubkey.to_vec();
+        let mut script = Vec::with_capacity(key.len() + 2);
+        script.push(key.len() as u8);
+        script.extend(key);
+        script.push(0xAC);
+        script.into()
+    }
+
+    /// Constructs pay-to-public key hash `scriptPubkey`.
+    pub fn p2pkh(hash: [u8; 20]) -> Self {
+        let mut script = vec![0x76, 0xA9, 0x14];
+        script.extend(hash);
+        script.extend([0x88, 0xAC]);
+        script.into()
+    }
+
+    /// Constructs pay-to-script hash `scriptPubkey`.
+    pub fn p2sh(hash: [u8; 20]) -> Self {
+        let mut script = vec![0xA9, 0x14];
+        script.extend(hash);
+        script.push(0x87);
+        script.into()
+    }
+
+    /// Constructs pay-to-witness public key hash `scriptPubkey`, which is also used as a redeem
+    /// script of P2WPKH-in-P2SH.
+    pub fn p2wpkh(hash: [u8; 20]) -> Self {
+        let mut script = vec![0x00, 0x14];
+        script.extend(hash);
+        script.into()
+    }
+
+    /// Constructs pay-to-witness script hash `scriptPubkey`, which is also used as a redeem
+    /// script of P2WSH-in-P2SH.
+    pub fn p2wsh(hash: [u8; 32]) -> Self {
+        let mut script = vec![0x00, 0x20];
+        script.extend(hash);
+        script.into()
+    }
+
+    /// Constructs pay-to-taproot `scriptPubkey` for the (already tweaked) output key.
+    pub fn p2tr(output_key: XOnlyPublicKey) -> Self {
+        let mut script = vec![0x51, 0x20];
+        script.extend(output_key.serialize());
+        script.into()
+    }
+
+    /// Constructs `OP_CHECKMULTISIG` script requiring `threshold` signatures for the keys, in
+    /// the order in which the keys are provided.
+    ///
+    /// # Panics
+    ///
+    /// If the threshold is zero or exceeds the number of keys, or if there are more than 16
+    /// keys.
+    pub fn multisig(threshold: u8, keys: &[LegacyPk]) -> Self {
+        assert!(
+            threshold > 0 && threshold as usize <= keys.len() && keys.len() <= 16,
+            "invalid multisig threshold {threshold} for {} keys",
+            keys.len()
+        );
+        let mut script = vec![0x50 + threshold];
+        for key in keys {
+            let key = key.to_vec();
+            script.push(key.len() as u8);
+            script.extend(key);
+        }
+        script.extend([0x50 + keys.len() as u8, 0xAE]);
+        script.into()
+    }
+}
+
+impl ScriptBytes {
+    /// Recognizes tapscript leaf requiring signatures for a threshold number of x-only keys:
+    /// either a single key `<key> OP_CHECKSIG` or a multisig
+    /// `<key> OP_CHECKSIG <key> OP_CHECKSIGADD ... <threshold> OP_NUMEQUAL`. Returns the
+    /// threshold and the keys.
+    pub(crate) fn tap_multisig(&self) -> Option<(usize, Vec<[u8; 32]>)> {
+        let mut data = &self[..];
+        let mut keys = vec![];
+        while let [0x20, rest @ ..] = data {
+            let (key, rest) = (rest.get(..32)?, rest.get(32..)?);
+            let (op, rest) = rest.split_first()?;
+            let expected = if keys.is_empty() { 0xAC } else { 0xBA };
+            if *op != expected {
+                return None;
+            }
+            keys.push(<[u8; 32]>::try_from(key).expect("fixed length"));
+            data = rest;
+        }
+        let threshold = match data {
+            [] if keys.len() == 1 => 1,
+            [n @ 0x51..=0x60, 0x9C] => (n - 0x50) as usize,
+            [0x01, n, 0x9C] if *n > 16 => *n as usize,
+            _ => return None,
+        };
+        if keys.is_empty() || threshold > keys.len() {
+            return None;
+        }
+        Some((threshold, keys))
+    }
+
+    /// Returns data of the last push of a push-only script (like `scriptSig`), or `None` if the
+    /// script is empty, contains non-push opcodes or is truncated.
+    pub(crate) fn last_push(&self) -> Option<&[u8]> {
+        let mut data = &self[..];
+        let mut last = None;
+        while !data.is_empty() {
+            let (push, rest) = split_push(data)?;
+            last = Some(push);
+            data = rest;
+        }
+        last
+    }
+
+    /// Returns data carried by a standard `OP_RETURN` script, which is either a bare `OP_RETURN`
+    /// (carrying no data) or `OP_RETURN` followed by a single push of up to 80 bytes.
+    pub(crate) fn op_return_data(&self) -> Option<&[u8]> {
+        match &self[..] {
+            [0x6A] => Some(&[]),
+            [0x6A, rest @ ..] => match split_push(rest)? {
+                (push, []) if push.len() <= MAX_OP_RETURN_DATA => Some(push),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Maximal size of data carried by a standard `OP_RETURN` output.
+const MAX_OP_RETURN_DATA: usize = 80;
+
+/// Splits data pushed by the push opcode at the start of the script from the rest of the
+/// script. Returns `None` if the script starts with a non-push opcode or is truncated.
+fn split_push(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (op, rest) = data.split_first()?;
+    let (len, rest) = match *op {
+        0x00..=0x4B => (*op as usize, rest),
+        0x4C => (*rest.first()? as usize, &rest[1..]),
+        0x4D if rest.len() >= 2 => (u16::from_le_bytes([rest[0], rest[1]]) as usize, &rest[2..]),
+        0x4E if rest.len() >= 4 => {
+            (u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize, &rest[4..])
+        }
+        _ => return None,
+    };
+    if rest.len() < len {
+        return None;
+    }
+    Some(rest.split_at(len))
+}
+
+/// Appends push of the data to the script, using the shortest push opcode for the data length.
+pub(crate) fn push_data(script: &mut Vec<u8>, data: &[u8]) {
+    match data.len() {
+        len @ 0..=0x4B => script.push(len as u8),
+        len @ 0x4C..=0xFF => script.extend([0x4C, len as u8]),
+        len @ 0x100..=0xFFFF => {
+            script.push(0x4D);
+            script.extend((len as u16).to_le_bytes());
+        }
+        len => {
+            script.push(0x4E);
+            script.extend((len as u32).to_le_bytes());
+        }
+    }
+    script.extend(data);
+}
+
+/// Parses sequence of public key pushes inside a multisig script.
+fn parse_multisig_keys(mut data: &[u8]) -> Option<Vec<LegacyPk>> {
+    let mut keys = vec![];
+    while let Some((len @ (0x21 | 0x41), rest)) = data.split_first() {
+        let len = *len as usize;
+        if rest.len() < len {
+            return None;
+        }
+        keys.push(LegacyPk::from_slice(&rest[..len]).ok()?);
+        data = &rest[len..];
+    }
+    if !data.is_empty() {
+        return None;
+    }
+    Some(keys)
+}
+
+/// Computes RIPEMD-160 hash of SHA-256 hash of the data.
+pub(crate) fn hash160(data: &[u8]) -> [u8; 20] { hash160::Hash::hash(data).to_byte_array() }
+
+/// Computes SHA-256 hash of the data.
+pub(crate) fn sha256(data: &[u8]) -> [u8; 32] { sha256::Hash::hash(data).to_byte_array() }
@@ -0,0 +1,181 @@
+// Partially signed bitcoin transaction library (BIP174, BIP370, BIP371)
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Computation of signature hashes.
+
+use bitcoin_hashes::{sha256, Hash, HashEngine};
+use secp256k1::{Scalar, XOnlyPublicKey};
+
+use crate::encoding::write_compact_size;
+use crate::{Encoding, Psbt, SighashFlag, SighashType, TxOut, UnsignedTxError};
+
+/// Errors computing signature hash.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum SighashError {
+    /// PSBT doesn't have input #{0}.
+    NoInput(usize),
+
+    /// output spent by input #{0} is not known.
+    NoSpentOutput(usize),
+
+    /// input #{0} uses SIGHASH_SINGLE, but the transaction has no output with the same index.
+    NoSingleOutput(usize),
+
+    #[from]
+    #[display(inner)]
+    Incomplete(UnsignedTxError),
+}
+
+/// Computes BIP-340 tagged hash of the data.
+pub(crate) fn tagged_hash(tag: &str, data: &[u8]) -> [u8; 32] {
+    let tag = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(&tag[..]);
+    engine.input(&tag[..]);
+    engine.input(data);
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// Computes BIP-341 tweak of the taproot internal key, committing to the merkle root of the
+/// script tree, if any. Returns `None` if the tweak overflows the curve order.
+pub(crate) fn tap_tweak(
+    internal_key: XOnlyPublicKey,
+    merkle_root: Option<[u8; 32]>,
+) -> Option<Scalar> {
+    let mut data = internal_key.serialize().to_vec();
+    if let Some(root) = merkle_root {
+        data.extend(root);
+    }
+    Scalar::from_be_bytes(tagged_hash("TapTweak", &data)).ok()
+}
+
+/// Computes BIP-341 hash of the leaf script with the given leaf version.
+pub(crate) fn tap_leaf_hash(leaf_ver: u8, script: &[u8]) -> [u8; 32] {
+    let mut data = vec![leaf_ver];
+    write_compact_size(&mut data, script.len() as u64);
+    data.extend(script);
+    tagged_hash("TapLeaf", &data)
+}
+
+/// Computes BIP-341 hash of the script tree branch with the given child nodes.
+pub(crate) fn tap_branch_hash(a: [u8; 32], b: [u8; 32]) -> [u8; 32] {
+    let (a, b) = if a <= b { (a, b) } else { (b, a) };
+    let mut data = a.to_vec();
+    data.extend(b);
+    tagged_hash("TapBranch", &data)
+}
+
+/// Computes SHA-256 hash of consensus serialization of all the items.
+fn sha256_all<'a, T: Encoding + 'a>(items: impl IntoIterator<Item = &'a T>) -> [u8; 32] {
+    let mut buf = vec![];
+    for item in items {
+        item.encode(&mut buf);
+    }
+    sha256::Hash::hash(&buf).to_byte_array()
+}
+
+impl Psbt {
+    /// Computes BIP-341 signature hash for the taproot key path spend of the input.
+    ///
+    /// `None` signature hash type stands for `SIGHASH_DEFAULT`. The transaction is assumed to
+    /// have no annex.
+    pub fn taproot_key_sighash(
+        &self,
+        index: usize,
+        sighash_type: Option<SighashType>,
+    ) -> Result<[u8; 32], SighashError> {
+        self.taproot_sighash(index, sighash_type, None)
+    }
+
+    /// Computes BIP-341 signature hash for the taproot script path spend of the input via the
+    /// leaf with the given hash, using the BIP-342 extension.
+    ///
+    /// `None` signature hash type stands for `SIGHASH_DEFAULT`. The transaction is assumed to
+    /// have no annex, and the leaf script is assumed not to execute `OP_CODESEPARATOR`.
+    pub fn taproot_script_sighash(
+        &self,
+        index: usize,
+        leaf_hash: [u8; 32],
+        sighash_type: Option<SighashType>,
+    ) -> Result<[u8; 32], SighashError> {
+        self.taproot_sighash(index, sighash_type, Some(leaf_hash))
+    }
+
+    /// Computes BIP-341 signature hash; for script path spends `leaf_hash` must contain hash of
+    /// the spent leaf (BIP-342 extension).
+    pub(crate) fn taproot_sighash(
+        &self,
+        index: usize,
+        sighash_type: Option<SighashType>,
+        leaf_hash: Option<[u8; 32]>,
+    ) -> Result<[u8; 32], SighashError> {
+        if index >= self.inputs.len() {
+            return Err(SighashError::NoInput(index));
+        }
+        let tx = self.to_unsigned_tx()?;
+        let ty = sighash_type.unwrap_or(SighashType::ALL);
+
+        let mut msg = vec![0x00];
+        msg.push(sighash_type.map(SighashType::to_consensus_u8).unwrap_or_default());
+        tx.version.encode(&mut msg);
+        tx.lock_time.encode(&mut msg);
+
+        if !ty.anyone_can_pay {
+            let spent = (0..tx.inputs.len())
+                .map(|i| self.spent_output(i).ok_or(SighashError::NoSpentOutput(i)))
+                .collect::<Result<Vec<TxOut>, _>>()?;
+            msg.extend(sha256_all(tx.inputs.iter().map(|input| &input.prev_output)));
+            msg.extend(sha256_all(spent.iter().map(|txout| &txout.value)));
+            msg.extend(sha256_all(spent.iter().map(|txout| &txout.script_pubkey)));
+            msg.extend(sha256_all(tx.inputs.iter().map(|input| &input.sequence)));
+        }
+        if ty.flag == SighashFlag::All {
+            msg.extend(sha256_all(&tx.outputs));
+        }
+
+        // Annex is never present, so the spend type consists of the extension flag only
+        msg.push(if leaf_hash.is_some() { 0x02 } else { 0x00 });
+        if ty.anyone_can_pay {
+            let input = &tx.inputs[index];
+            let spent = self.spent_output(index).ok_or(SighashError::NoSpentOutput(index))?;
+            input.prev_output.encode(&mut msg);
+            spent.encode(&mut msg);
+            input.sequence.encode(&mut msg);
+        } else {
+            (index as u32).encode(&mut msg);
+        }
+        if ty.flag == SighashFlag::Single {
+            let output = tx.outputs.get(index).ok_or(SighashError::NoSingleOutput(index))?;
+            msg.extend(sha256_all([output]));
+        }
+
+        if let Some(leaf_hash) = leaf_hash {
+            msg.extend(leaf_hash);
+            // Key version 0 and no OP_CODESEPARATOR executed
+            msg.push(0x00);
+            msg.extend(u32::MAX.to_le_bytes());
+        }
+
+        Ok(tagged_hash("TapSighash", &msg))
+    }
+}
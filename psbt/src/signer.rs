@@ -0,0 +1,496 @@
+// Partially signed bitcoin transaction library (BIP174, BIP370, BIP371)
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Operations of the PSBT signer role.
+
+use derive::{ChildIdx, Fingerprint};
+use secp256k1::{KeyPair, Message, Parity, SECP256K1};
+
+use crate::scripts::ScriptTemplate;
+use crate::sighash::{tap_branch_hash, tap_leaf_hash, tap_tweak};
+use crate::{ControlBlock, InPair, OriginKey, Psbt, SchnorrSig, SighashError, SighashType};
+
+/// Errors signing PSBT inputs.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum SignError {
+    /// input #{0} doesn't specify taproot internal key.
+    NoInternalKey(usize),
+
+    /// signing key doesn't match taproot internal key of input #{0}.
+    InternalKeyMismatch(usize),
+
+    /// input #{0} doesn't spend a taproot output with the key tweaked from its internal key.
+    OutputKeyMismatch(usize),
+
+    /// input #{0} requires signature hash type different from the one used for signing.
+    SighashMismatch(usize),
+
+    /// input #{0} doesn't contain the leaf script with the given leaf hash.
+    NoLeafScript(usize),
+
+    /// leaf script of input #{0} doesn't contain the signing key.
+    KeyNotInLeaf(usize),
+
+    #[from]
+    #[display(inner)]
+    Sighash(SighashError),
+}
+
+impl Psbt {
+    /// Iterates over the keys of the input with their master key fingerprints and derivation
+    /// paths, taken from both `PSBT_IN_BIP32_DERIVATION` and `PSBT_IN_TAP_BIP32_DERIVATION`
+    /// fields. Returns an empty iterator if there is no such input.
+    pub fn input_key_origins(
+        &self,
+        index: usize,
+    ) -> impl Iterator<Item = (OriginKey, Fingerprint, &[ChildIdx])> {
+        self.inputs.get(index).into_iter().flat_map(|map| map.known()).filter_map(|pair| match pair
+        {
+            InPair::Bip32Derivation { pubkey, origin } => {
+                Some((OriginKey::Ecdsa(*pubkey), origin.master_fp, origin.path.as_slice()))
+            }
+            InPair::TapBip32Derivation { pubkey, origin, .. } => {
+                Some((OriginKey::Taproot(*pubkey), origin.master_fp, origin.path.as_slice()))
+            }
+            _ => None,
+        })
+    }
+
+    /// Iterates over the keys of the input derived from the master key with the given
+    /// fingerprint, allowing a signer to detect which inputs it can sign.
+    pub fn find_my_keys(
+        &self,
+        index: usize,
+        my_fp: Fingerprint,
+    ) -> impl Iterator<Item = (OriginKey, &[ChildIdx])> {
+        self.input_key_origins(index)
+            .filter(move |(_, fp, _)| *fp == my_fp)
+            .map(|(key, _, path)| (key, path))
+    }
+
+    /// Signs taproot key path spend of the input and stores the signature as
+    /// `PSBT_IN_TAP_KEY_SIG`.
+    ///
+    /// The signing key must match `PSBT_IN_TAP_INTERNAL_KEY` of the input; it gets tweaked with
+    /// `PSBT_IN_TAP_MERKLE_ROOT` (if present) according to BIP-341 and the resulting output key
+    /// must be the one of the spent output. `None` signature hash type stands for
+    /// `SIGHASH_DEFAULT`; if the input specifies signature hash type, the signing must use it
+    /// (`None` for [`crate::PsbtSighashType::TaprootDefault`]).
+    ///
+    /// Signatures are created without auxiliary randomness, i.e. deterministically. Adding the
+    /// signature updates `PSBT_GLOBAL_TX_MODIFIABLE` flags, if present, as described in
+    /// [`Psbt::restrict_tx_modifiable`].
+    pub fn sign_taproot_key_spend(
+        &mut self,
+        index: usize,
+        keypair: &KeyPair,
+        sighash_type: Option<SighashType>,
+    ) -> Result<(), SignError> {
+        let map = self.inputs.get(index).ok_or(SighashError::NoInput(index))?;
+        let mut internal_key = None;
+        let mut merkle_root = None;
+        for pair in map.known() {
+            match pair {
+                InPair::TapInternalKey(key) => internal_key = Some(*key),
+                InPair::TapMerkleRoot(root) => merkle_root = Some(*root),
+                InPair::SighashType(ty) if ty.to_taproot() != sighash_type => {
+                    return Err(SignError::SighashMismatch(index));
+                }
+                _ => {}
+            }
+        }
+        let internal_key = internal_key.ok_or(SignError::NoInternalKey(index))?;
+        if keypair.x_only_public_key().0 != internal_key {
+            return Err(SignError::InternalKeyMismatch(index));
+        }
+
+        let tweak =
+            tap_tweak(internal_key, merkle_root).ok_or(SignError::OutputKeyMismatch(index))?;
+        let tweaked = keypair
+            .add_xonly_tweak(SECP256K1, &tweak)
+            .map_err(|_| SignError::OutputKeyMismatch(index))?;
+        let output_key = tweaked.x_only_public_key().0.serialize();
+        match self.spent_script_pubkey(index).map(|script| script.template()) {
+            Some(ScriptTemplate::Tr(key)) if key == output_key => {}
+            Some(_) => return Err(SignError::OutputKeyMismatch(index)),
+            None => return Err(SighashError::NoSpentOutput(index).into()),
+        }
+
+        let sighash = self.taproot_key_sighash(index, sighash_type)?;
+        let msg = Message::from_slice(&sighash).expect("hash has message length");
+        let sig = SECP256K1.sign_schnorr_no_aux_rand(&msg, &tweaked);
+        self.inputs[index].replace_singleton(InPair::TapKeySig(SchnorrSig { sig, sighash_type }));
+        self.restrict_tx_modifiable(sighash_type.unwrap_or(SighashType::ALL));
+        Ok(())
+    }
+
+    /// Signs taproot script path spend of the input via the leaf with the given `leaf_hash` and
+    /// stores the signature as `PSBT_IN_TAP_SCRIPT_SIG` for the x-only public key of the
+    /// `keypair`.
+    ///
+    /// The leaf script must be present in the input as `PSBT_IN_TAP_LEAF_SCRIPT` and must
+    /// contain the signing key. Its control block must prove the commitment of the leaf to the
+    /// output key of the spent output. `None` signature hash type stands for
+    /// `SIGHASH_DEFAULT`; if the input specifies signature hash type, the signing must use it
+    /// (`None` for [`crate::PsbtSighashType::TaprootDefault`]).
+    ///
+    /// Signatures are created without auxiliary randomness, i.e. deterministically. Adding the
+    /// signature updates `PSBT_GLOBAL_TX_MODIFIABLE` flags, if present, as described in
+    /// [`Psbt::restrict_tx_modifiable`].
+    pub fn sign_taproot_script_path(
+        &mut self,
+        index: usize,
+        keypair: &KeyPair,
+        leaf_hash: [u8; 32],
+        sighash_type: Option<SighashType>,
+    ) -> Result<(), SignError> {
+        let map = self.inputs.get(index).ok_or(SighashError::NoInput(index))?;
+        let mut leaf = None;
+        for pair in map.known() {
+            match pair {
+                InPair::TapLeafScript {
+                    control_block,
+                    script,
+                    leaf_ver,
+                } if tap_leaf_hash(*leaf_ver, script) == leaf_hash => {
+                    leaf = Some((control_block, script))
+                }
+                InPair::SighashType(ty) if ty.to_taproot() != sighash_type => {
+                    return Err(SignError::SighashMismatch(index));
+                }
+                _ => {}
+            }
+        }
+        let (control_block, script) = leaf.ok_or(SignError::NoLeafScript(index))?;
+        let pubkey = keypair.x_only_public_key().0;
+        let key = pubkey.serialize();
+        if !script.windows(33).any(|push| push[0] == 0x20 && push[1..] == key) {
+            return Err(SignError::KeyNotInLeaf(index));
+        }
+
+        let output_key =
+            leaf_output_key(control_block, leaf_hash).ok_or(SignError::OutputKeyMismatch(index))?;
+        match self.spent_script_pubkey(index).map(|script| script.template()) {
+            Some(ScriptTemplate::Tr(key)) if key == output_key => {}
+            Some(_) => return Err(SignError::OutputKeyMismatch(index)),
+            None => return Err(SighashError::NoSpentOutput(index).into()),
+        }
+
+        let sighash = self.taproot_script_sighash(index, leaf_hash, sighash_type)?;
+        let msg = Message::from_slice(&sighash).expect("hash has message length");
+        let sig = SECP256K1.sign_schnorr_no_aux_rand(&msg, keypair);
+        self.inputs[index].replace_keyed(InPair::TapScriptSig {
+            pubkey,
+            leaf_hash,
+            sig: SchnorrSig { sig, sighash_type },
+        });
+        self.restrict_tx_modifiable(sighash_type.unwrap_or(SighashType::ALL));
+        Ok(())
+    }
+}
+
+/// Computes output key committing to the leaf with the given hash, as proven by the control
+/// block. Returns `None` if the tweak is invalid or the output key parity doesn't match the one
+/// in the control block.
+fn leaf_output_key(control_block: &ControlBlock, leaf_hash: [u8; 32]) -> Option<[u8; 32]> {
+    let merkle_root = control_block
+        .merkle_path
+        .iter()
+        .fold(leaf_hash, |node, sibling| tap_branch_hash(node, *sibling));
+    let internal_key = control_block.internal_key;
+    let tweak = tap_tweak(internal_key, Some(merkle_root))?;
+    let (output_key, parity) = internal_key.add_tweak(SECP256K1, &tweak).ok()?;
+    if (parity == Parity::Odd) != control_block.output_key_parity {
+        return None;
+    }
+    Some(output_key.serialize())
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::hex::FromHex;
+    use core2::io::Cursor;
+    use secp256k1::SecretKey;
+
+    use super::*;
+    use crate::{
+        Encoding, InputBuilder, Outpoint, OutputBuilder, PsbtBuilder, PsbtSighashType, ScriptBytes,
+        Tx, TxOut, Txid, TX_MODIFIABLE_INPUTS, TX_MODIFIABLE_OUTPUTS,
+    };
+
+    /// Transaction and spent outputs of the `keyPathSpending` test vector from BIP-341.
+    fn bip341_psbt() -> Psbt {
+        let tx = Vec::<u8>::from_hex(
+            "02000000097de20cbff686da83a54981d2b9bab3586f4ca7e48f57f5b55963115f3b334e9c010000000000\
+             000000d7b7cab57b1393ace2d064f4d4a2cb8af6def61273e127517d44759b6dafdd990000000000ffff\
+             fffff8e1f583384333689228c5d28eac13366be082dc57441760d957275419a418420000000000ffffff\
+             fff0689180aa63b30cb162a73c6d2a38b7eeda2a83ece74310fda0843ad604853b0100000000feffffff\
+             aa5202bdf6d8ccd2ee0f0202afbbb7461d9264a25e5bfd3c5a52ee1239e0ba6c0000000000feffffff95\
+             6149bdc66faa968eb2be2d2faa29718acbfe3941215893a2a3446d32acd050000000000000000000e664\
+             b9773b88c09c32cb70a2a3e4da0ced63b7ba3b22f848531bbb1d5d5f4c94010000000000000000e9aa6b\
+             8e6c9de67619e6a3924ae25696bb7b694bb677a632a74ef7eadfd4eabf0000000000ffffffffa778eb6a\
+             263dc090464cd125c466b5a99667720b1c110468831d058aa1b82af10100000000ffffffff0200ca9a3b\
+             000000001976a91406afd46bcdfd22ef94ac122aa11f241244a37ecc88ac807840cb0000000020ac9a87\
+             f5594be208f8532db38cff670c450ed2fea8fcdefcc9a663f78bab962b0065cd1d",
+        )
+        .unwrap();
+        let tx = Tx::decode(&mut Cursor::new(&tx[..])).unwrap();
+        let spent = [
+            ("512053a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343", 420000000),
+            ("5120147c9c57132f6e7ecddba9800bb0c4449251c92a1e60371ee77557b6620f3ea3", 462000000),
+            ("76a914751e76e8199196d454941c45d1b3a323f1433bd688ac", 294000000),
+            ("5120e4d810fd50586274face62b8a807eb9719cef49c04177cc6b76a9a4251d5450e", 504000000),
+            ("512091b64d5324723a985170e4dc5a0f84c041804f2cd12660fa5dec09fc21783605", 630000000),
+            ("00147dd65592d0ab2fe0d0257d571abf032cd9db93dc", 378000000),
+            ("512075169f4001aa68f15bbed28b218df1d0a62cbbcf1188c6665110c293c907b831", 672000000),
+            ("5120712447206d7a5238acc7ff53fbe94a3b64539ad291c7cdbc490b7577e4b17df5", 546000000),
+            ("512077e30a5522dd9f894c3f8b8bd4c4b2cf82ca7da8a3ea6a239655c39c050ab220", 588000000),
+        ];
+        let mut builder = PsbtBuilder::new().tx_version(tx.version).lock_time(tx.lock_time);
+        for (input, (script, value)) in tx.inputs.iter().zip(spent) {
+            builder = builder.input(
+                InputBuilder::new(input.prev_output).sequence(input.sequence).witness_utxo(TxOut {
+                    value,
+                    script_pubkey: Vec::<u8>::from_hex(script).unwrap().into(),
+                }),
+            );
+        }
+        for output in &tx.outputs {
+            builder =
+                builder.output(OutputBuilder::new(output.value, output.script_pubkey.clone()));
+        }
+        let psbt = builder.build().unwrap();
+        assert_eq!(psbt.to_unsigned_tx().unwrap(), tx);
+        psbt
+    }
+
+    #[test]
+    fn bip341_key_path_sighash() {
+        let psbt = bip341_psbt();
+        // Inputs with their signature hash types and signature hashes
+        let vectors = [
+            (0, 0x03, "2514a6272f85cfa0f45eb907fcb0d121b808ed37c6ea160a5a9046ed5526d555"),
+            (1, 0x83, "325a644af47e8a5a2591cda0ab0723978537318f10e6a63d4eed783b96a71a4d"),
+            (3, 0x01, "bf013ea93474aa67815b1b6cc441d23b64fa310911d991e713cd34c7f5d46669"),
+            (4, 0x00, "4f900a0bae3f1446fd48490c2958b5a023228f01661cda3496a11da502a7f7ef"),
+            (6, 0x02, "15f25c298eb5cdc7eb1d638dd2d45c97c4c59dcaec6679cfc16ad84f30876b85"),
+            (7, 0x82, "cd292de50313804dabe4685e83f923d2969577191a3e1d2882220dca88cbeb10"),
+            (8, 0x81, "cccb739eca6c13a8a89e6e5cd317ffe55669bbda23f2fd37b0f18755e008edd2"),
+        ];
+        for (index, ty, sighash) in vectors {
+            let ty = match ty {
+                0x00 => None,
+                ty => Some(SighashType::from_standard_u32(ty).unwrap()),
+            };
+            assert_eq!(
+                psbt.taproot_key_sighash(index, ty).unwrap(),
+                <[u8; 32]>::from_hex(sighash).unwrap(),
+                "input #{index}"
+            );
+        }
+    }
+
+    #[test]
+    fn bip341_key_path_signature() {
+        let mut psbt = bip341_psbt();
+        let keypair = KeyPair::from_secret_key(
+            SECP256K1,
+            &SecretKey::from_slice(
+                &Vec::<u8>::from_hex(
+                    "6b973d88838f27366ed61c9ad6367663045cb456e28335c109e30717ae0c6baa",
+                )
+                .unwrap(),
+            )
+            .unwrap(),
+        );
+        let single = SighashType::from_standard_u32(0x03).unwrap();
+        assert_eq!(
+            psbt.sign_taproot_key_spend(0, &keypair, Some(single)),
+            Err(SignError::NoInternalKey(0))
+        );
+        let other = KeyPair::from_secret_key(SECP256K1, &SecretKey::from_slice(&[1; 32]).unwrap());
+        psbt.inputs[0].replace_singleton(InPair::TapInternalKey(other.x_only_public_key().0));
+        assert_eq!(
+            psbt.sign_taproot_key_spend(0, &keypair, Some(single)),
+            Err(SignError::InternalKeyMismatch(0))
+        );
+
+        psbt.inputs[0].replace_singleton(InPair::TapInternalKey(keypair.x_only_public_key().0));
+        psbt.sign_taproot_key_spend(0, &keypair, Some(single)).unwrap();
+        let sig = psbt.inputs[0]
+            .known()
+            .find_map(|pair| match pair {
+                InPair::TapKeySig(sig) => Some(sig.to_vec()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(
+            sig,
+            Vec::<u8>::from_hex(
+                "ed7c1647cb97379e76892be0cacff57ec4a7102aa24296ca39af7541246d8ff14d38958d4cc1e2e478e4\
+                 d4a764bbfd835b16d4e314b72937b29833060b87276c03"
+            )
+            .unwrap()
+        );
+        psbt.verify_taproot_signatures().unwrap();
+    }
+
+    #[test]
+    fn declared_default_sighash() {
+        let mut psbt = bip341_psbt();
+        let keypair = KeyPair::from_secret_key(
+            SECP256K1,
+            &SecretKey::from_slice(
+                &Vec::<u8>::from_hex(
+                    "6b973d88838f27366ed61c9ad6367663045cb456e28335c109e30717ae0c6baa",
+                )
+                .unwrap(),
+            )
+            .unwrap(),
+        );
+        psbt.inputs[0].replace_singleton(InPair::TapInternalKey(keypair.x_only_public_key().0));
+        psbt.inputs[0].replace_singleton(InPair::SighashType(PsbtSighashType::TaprootDefault));
+
+        assert_eq!(
+            psbt.sign_taproot_key_spend(0, &keypair, Some(SighashType::ALL)),
+            Err(SignError::SighashMismatch(0))
+        );
+        psbt.sign_taproot_key_spend(0, &keypair, None).unwrap();
+        let sig = psbt.inputs[0]
+            .known()
+            .find_map(|pair| match pair {
+                InPair::TapKeySig(sig) => Some(*sig),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(sig.sighash_type, None);
+        assert_eq!(sig.to_vec().len(), 64);
+        psbt.verify_taproot_signatures().unwrap();
+
+        // Explicit sighash type doesn't match the declared default one
+        psbt.inputs[0].replace_singleton(InPair::SighashType(SighashType::ALL.into()));
+        assert_eq!(
+            psbt.sign_taproot_key_spend(0, &keypair, None),
+            Err(SignError::SighashMismatch(0))
+        );
+    }
+
+    #[test]
+    fn key_spend_restricts_tx_modifiable() {
+        let keypair =
+            KeyPair::from_secret_key(SECP256K1, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let internal_key = keypair.x_only_public_key().0;
+        let tweak = tap_tweak(internal_key, None).unwrap();
+        let (output_key, _) = internal_key.add_tweak(SECP256K1, &tweak).unwrap();
+        let input = InputBuilder::new(Outpoint {
+            txid: Txid::from([1; 32]),
+            vout: 0,
+        })
+        .witness_utxo(TxOut {
+            value: 10_000,
+            script_pubkey: ScriptBytes::p2tr(output_key),
+        });
+        let mut psbt = PsbtBuilder::new()
+            .version(2)
+            .tx_modifiable(TX_MODIFIABLE_INPUTS | TX_MODIFIABLE_OUTPUTS)
+            .input(input)
+            .output(OutputBuilder::new(9_000, ScriptBytes::p2wpkh([2; 20])))
+            .build()
+            .unwrap();
+        psbt.inputs[0].replace_singleton(InPair::TapInternalKey(internal_key));
+
+        let none_acp = SighashType::from_standard_u32(0x82).unwrap();
+        psbt.sign_taproot_key_spend(0, &keypair, Some(none_acp)).unwrap();
+        assert_eq!(psbt.tx_modifiable(), Some(TX_MODIFIABLE_INPUTS | TX_MODIFIABLE_OUTPUTS));
+
+        psbt.sign_taproot_key_spend(0, &keypair, Some(SighashType::ALL)).unwrap();
+        assert_eq!(psbt.tx_modifiable(), Some(0));
+        psbt.verify_taproot_signatures().unwrap();
+    }
+
+    #[test]
+    fn script_path_signature() {
+        let internal_key =
+            KeyPair::from_secret_key(SECP256K1, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let keypair =
+            KeyPair::from_secret_key(SECP256K1, &SecretKey::from_slice(&[2; 32]).unwrap());
+        let pubkey = keypair.x_only_public_key().0;
+        let mut script = vec![0x20];
+        script.extend(pubkey.serialize());
+        script.push(0xAC);
+        let leaf_hash = tap_leaf_hash(0xC0, &script);
+
+        let internal_key = internal_key.x_only_public_key().0;
+        let tweak = tap_tweak(internal_key, Some(leaf_hash)).unwrap();
+        let (output_key, parity) = internal_key.add_tweak(SECP256K1, &tweak).unwrap();
+        let input = InputBuilder::new(Outpoint {
+            txid: Txid::from([1; 32]),
+            vout: 0,
+        })
+        .witness_utxo(TxOut {
+            value: 10_000,
+            script_pubkey: ScriptBytes::p2tr(output_key),
+        });
+        let mut psbt = PsbtBuilder::new()
+            .input(input)
+            .output(OutputBuilder::new(9_000, ScriptBytes::p2wpkh([2; 20])))
+            .build()
+            .unwrap();
+        psbt.inputs[0].0.push(crate::KeyPair::Known(InPair::TapLeafScript {
+            control_block: ControlBlock {
+                leaf_ver: 0xC0,
+                output_key_parity: parity == Parity::Odd,
+                internal_key,
+                merkle_path: vec![],
+            },
+            script: script.into(),
+            leaf_ver: 0xC0,
+        }));
+
+        assert_eq!(
+            psbt.sign_taproot_script_path(0, &keypair, [0; 32], None),
+            Err(SignError::NoLeafScript(0))
+        );
+        let other = KeyPair::from_secret_key(SECP256K1, &SecretKey::from_slice(&[3; 32]).unwrap());
+        assert_eq!(
+            psbt.sign_taproot_script_path(0, &other, leaf_hash, None),
+            Err(SignError::KeyNotInLeaf(0))
+        );
+
+        psbt.sign_taproot_script_path(0, &keypair, leaf_hash, None).unwrap();
+        let sig = psbt.inputs[0]
+            .known()
+            .find_map(|pair| match pair {
+                InPair::TapScriptSig {
+                    pubkey: key,
+                    leaf_hash: hash,
+                    sig,
+                } if *key == pubkey && *hash == leaf_hash => Some(sig.sig),
+                _ => None,
+            })
+            .unwrap();
+        let sighash = psbt.taproot_script_sighash(0, leaf_hash, None).unwrap();
+        let msg = Message::from_slice(&sighash).unwrap();
+        SECP256K1.verify_schnorr(&sig, &msg, &pubkey).unwrap();
+    }
+}
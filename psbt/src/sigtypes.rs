@@ -0,0 +1,213 @@
+// Partially signed bitcoin transaction library (BIP174, BIP370, BIP371)
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Signature-related data types.
+
+use secp256k1::{ecdsa, schnorr};
+
+/// Base signature hash flag, defining which outputs are committed to by a signature.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display)]
+#[repr(u8)]
+pub enum SighashFlag {
+    /// Signature commits to all outputs.
+    #[display("ALL")]
+    All = 0x01,
+
+    /// Signature doesn't commit to any outputs.
+    #[display("NONE")]
+    None = 0x02,
+
+    /// Signature commits only to the output with the same index as the signed input.
+    #[display("SINGLE")]
+    Single = 0x03,
+}
+
+/// Signature hash type, consisting of the base flag and `ANYONECANPAY` modifier.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct SighashType {
+    /// Base flag.
+    pub flag: SighashFlag,
+    /// Whether the signature commits only to the signed input (`ANYONECANPAY` modifier).
+    pub anyone_can_pay: bool,
+}
+
+/// non-standard signature hash type {0:#x}.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub struct NonStandardSighash(pub u32);
+
+impl SighashType {
+    /// `SIGHASH_ALL` signature hash type.
+    pub const ALL: Self = SighashType {
+        flag: SighashFlag::All,
+        anyone_can_pay: false,
+    };
+
+    /// Constructs signature hash type from its standard 32-bit representation used in PSBT,
+    /// failing on non-standard values.
+    pub fn from_standard_u32(value: u32) -> Result<Self, NonStandardSighash> {
+        let flag = match value & !0x80 {
+            0x01 => SighashFlag::All,
+            0x02 => SighashFlag::None,
+            0x03 => SighashFlag::Single,
+            _ => return Err(NonStandardSighash(value)),
+        };
+        Ok(SighashType {
+            flag,
+            anyone_can_pay: value & 0x80 != 0,
+        })
+    }
+
+    /// Returns single-byte consensus representation of the signature hash type.
+    pub fn to_consensus_u8(self) -> u8 {
+        let mut val = self.flag as u8;
+        if self.anyone_can_pay {
+            val |= 0x80;
+        }
+        val
+    }
+
+    /// Returns 32-bit representation of the signature hash type used in PSBT.
+    pub fn to_u32(self) -> u32 { self.to_consensus_u8() as u32 }
+}
+
+/// Signature hash type which PSBT requires from the signers of an input
+/// (`PSBT_IN_SIGHASH_TYPE`).
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, From)]
+pub enum PsbtSighashType {
+    /// Taproot `SIGHASH_DEFAULT` (0x00), which commits to the same data as `SIGHASH_ALL` and
+    /// is used by signatures without the trailing sighash type byte. Valid only for taproot
+    /// inputs.
+    TaprootDefault,
+
+    /// Standard signature hash type, valid for all inputs.
+    #[from]
+    Standard(SighashType),
+}
+
+impl PsbtSighashType {
+    /// Constructs signature hash type from its 32-bit representation used in PSBT, failing on
+    /// non-standard values. Zero value is read as [`PsbtSighashType::TaprootDefault`].
+    pub fn from_u32(value: u32) -> Result<Self, NonStandardSighash> {
+        match value {
+            0x00 => Ok(PsbtSighashType::TaprootDefault),
+            value => SighashType::from_standard_u32(value).map(PsbtSighashType::Standard),
+        }
+    }
+
+    /// Returns 32-bit representation of the signature hash type used in PSBT.
+    pub fn to_u32(self) -> u32 {
+        match self {
+            PsbtSighashType::TaprootDefault => 0x00,
+            PsbtSighashType::Standard(ty) => ty.to_u32(),
+        }
+    }
+
+    /// Returns signature hash type for taproot signatures, where `None` stands for
+    /// `SIGHASH_DEFAULT`, matching [`SchnorrSig::sighash_type`].
+    pub fn to_taproot(self) -> Option<SighashType> {
+        match self {
+            PsbtSighashType::TaprootDefault => None,
+            PsbtSighashType::Standard(ty) => Some(ty),
+        }
+    }
+
+    /// Returns signature hash type for ECDSA signatures, or `None` for the taproot-only
+    /// `SIGHASH_DEFAULT`.
+    pub fn to_ecdsa(self) -> Option<SighashType> { self.to_taproot() }
+}
+
+/// Errors parsing signatures.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum SigError {
+    /// empty signature data.
+    Empty,
+
+    /// invalid DER encoding of the signature.
+    DerEncoding,
+
+    /// invalid BIP-340 signature encoding.
+    Bip340Encoding,
+
+    #[from]
+    #[display(inner)]
+    Sighash(NonStandardSighash),
+}
+
+/// ECDSA signature with the signature hash type.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct EcdsaSig {
+    /// Signature.
+    pub sig: ecdsa::Signature,
+    /// Signature hash type to which the signature commits.
+    pub sighash_type: SighashType,
+}
+
+impl EcdsaSig {
+    /// Parses DER-encoded signature followed by a sighash type byte.
+    pub fn from_slice(data: &[u8]) -> Result<Self, SigError> {
+        let (sighash_type, der) = data.split_last().ok_or(SigError::Empty)?;
+        let sig = ecdsa::Signature::from_der(der).map_err(|_| SigError::DerEncoding)?;
+        let sighash_type = SighashType::from_standard_u32(*sighash_type as u32)?;
+        Ok(EcdsaSig { sig, sighash_type })
+    }
+
+    /// Serializes signature as DER with a trailing sighash type byte.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut data = self.sig.serialize_der().to_vec();
+        data.push(self.sighash_type.to_consensus_u8());
+        data
+    }
+}
+
+/// BIP-340 signature with the taproot signature hash type.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct SchnorrSig {
+    /// Signature.
+    pub sig: schnorr::Signature,
+    /// Signature hash type to which the signature commits; `None` for `SIGHASH_DEFAULT`.
+    pub sighash_type: Option<SighashType>,
+}
+
+impl SchnorrSig {
+    /// Parses 64-byte signature using `SIGHASH_DEFAULT` or 65-byte signature with an explicit
+    /// sighash type byte.
+    pub fn from_slice(data: &[u8]) -> Result<Self, SigError> {
+        let (sig, sighash_type) = match data.len() {
+            64 => (data, None),
+            65 => (&data[..64], Some(SighashType::from_standard_u32(data[64] as u32)?)),
+            _ => return Err(SigError::Bip340Encoding),
+        };
+        let sig = schnorr::Signature::from_slice(sig).map_err(|_| SigError::Bip340Encoding)?;
+        Ok(SchnorrSig { sig, sighash_type })
+    }
+
+    /// Serializes signature, appending sighash type byte unless it is `SIGHASH_DEFAULT`.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut data = self.sig.as_ref().to_vec();
+        if let Some(sighash_type) = self.sighash_type {
+            data.push(sighash_type.to_consensus_u8());
+        }
+        data
+    }
+}
@@ -0,0 +1,395 @@
+// Partially signed bitcoin transaction library (BIP174, BIP370, BIP371)
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Estimation of the size of the transaction which will be extracted from PSBT.
+
+use crate::encoding::compact_size_len;
+use crate::scripts::{hash160, ScriptTemplate};
+use crate::{InPair, LegacyPk, Psbt, PsbtSighashType, ScriptBytes, Tx, UnsignedTxError};
+
+/// Errors estimating size of the finalized transaction.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum SizeError {
+    /// output spent by input #{0} is unknown.
+    NoSpentOutput(usize),
+
+    /// input #{0} spends output with non-standard script, or the input lacks the redeem or
+    /// witness script.
+    UnsupportedScript(usize),
+
+    /// input #{0} may be spent using taproot script path, while its leaf scripts are unknown or
+    /// are not single-key or `OP_CHECKSIGADD` multisig scripts.
+    UnknownTapScripts(usize),
+
+    #[from]
+    #[display(inner)]
+    Incomplete(UnsignedTxError),
+}
+
+/// Minimal size of ECDSA signature with the sighash type byte, as produced by low-R signers.
+const ECDSA_SIG_MIN_LEN: usize = 72;
+/// Maximal size of ECDSA signature with the sighash type byte.
+const ECDSA_SIG_MAX_LEN: usize = 73;
+/// Size of BIP-340 signature using `SIGHASH_DEFAULT`.
+const SCHNORR_SIG_MIN_LEN: usize = 64;
+/// Size of BIP-340 signature with an explicit sighash type byte.
+const SCHNORR_SIG_MAX_LEN: usize = 65;
+
+/// Sizes of the stack items of the input satisfaction: minimal and maximal.
+type Stacks = (Vec<usize>, Vec<usize>);
+
+/// Size of the data satisfying input.
+#[derive(Clone, Eq, PartialEq, Debug)]
+struct Satisfaction {
+    /// Length of the `scriptSig`.
+    sig_script: usize,
+    /// Lengths of witness stack items; `None` for non-segwit inputs.
+    witness: Option<Vec<usize>>,
+}
+
+impl Satisfaction {
+    fn legacy(stack: Vec<usize>) -> Self {
+        Satisfaction {
+            sig_script: stack.into_iter().map(push_len).sum(),
+            witness: None,
+        }
+    }
+
+    fn segwit(stack: Vec<usize>) -> Self {
+        Satisfaction {
+            sig_script: 0,
+            witness: Some(stack),
+        }
+    }
+
+    fn nested(redeem_script: &ScriptBytes, stack: Vec<usize>) -> Self {
+        Satisfaction {
+            sig_script: push_len(redeem_script.len()),
+            witness: Some(stack),
+        }
+    }
+
+    /// Size of the serialized `scriptSig` including its length prefix.
+    fn sig_script_len(&self) -> usize { compact_size_len(self.sig_script) + self.sig_script }
+
+    /// Size of the serialized witness, which is a single byte for the inputs without witness.
+    fn witness_len(&self) -> usize {
+        match &self.witness {
+            None => 1,
+            Some(items) => items.iter().fold(compact_size_len(items.len()), |len, item| {
+                len + compact_size_len(*item) + item
+            }),
+        }
+    }
+}
+
+impl Psbt {
+    /// Estimates minimal and maximal serialized size of the transaction (in bytes) which will be
+    /// extracted from PSBT once all its inputs are finalized.
+    ///
+    /// The range accounts for the variable length of signatures and for the keys which length
+    /// is not known from PSBT. Finalized inputs contribute their exact size. Taproot inputs are
+    /// estimated for the key path spending and for the script path spending of each of the leaf
+    /// scripts provided in `PSBT_IN_TAP_LEAF_SCRIPT`, such that the maximum accounts for the
+    /// largest script path. Inputs which may be spent with a taproot script path (i.e. which
+    /// have `PSBT_IN_TAP_MERKLE_ROOT`) can't be estimated if they have no leaf scripts or some
+    /// of the leaf scripts are not single-key or `OP_CHECKSIGADD` multisig scripts.
+    pub fn estimate_size_range(&self) -> Result<(usize, usize), SizeError> {
+        let tx = self.to_unsigned_tx()?;
+        let mut min = Vec::with_capacity(self.inputs.len());
+        let mut max = Vec::with_capacity(self.inputs.len());
+        for index in 0..self.inputs.len() {
+            let (input_min, input_max) = self.input_satisfactions(index)?;
+            min.push(input_min);
+            max.push(input_max);
+        }
+        Ok((tx_size(&tx, &min), tx_size(&tx, &max)))
+    }
+
+    /// Returns minimal and maximal satisfactions of the input.
+    fn input_satisfactions(&self, index: usize) -> Result<(Satisfaction, Satisfaction), SizeError> {
+        let map = &self.inputs[index];
+        let final_sig_script = map.known().find_map(|pair| match pair {
+            InPair::FinalScriptSig(script) => Some(script),
+            _ => None,
+        });
+        let final_witness = map.known().find_map(|pair| match pair {
+            InPair::FinalScriptWitness(witness) => Some(witness),
+            _ => None,
+        });
+        if final_sig_script.is_some() || final_witness.is_some() {
+            let satisfaction = Satisfaction {
+                sig_script: final_sig_script.map(|script| script.len()).unwrap_or_default(),
+                witness: final_witness
+                    .filter(|witness| !witness.is_empty())
+                    .map(|witness| witness.iter().map(Vec::len).collect()),
+            };
+            return Ok((satisfaction.clone(), satisfaction));
+        }
+
+        let script_pubkey =
+            self.spent_script_pubkey(index).ok_or(SizeError::NoSpentOutput(index))?;
+        let redeem_script = map.known().find_map(|pair| match pair {
+            InPair::RedeemScript(script) => Some(script),
+            _ => None,
+        });
+        let witness_script = map.known().find_map(|pair| match pair {
+            InPair::WitnessScript(script) => Some(script),
+            _ => None,
+        });
+        let keys = map
+            .known()
+            .filter_map(|pair| match pair {
+                InPair::PartialSig { pubkey, .. } | InPair::Bip32Derivation { pubkey, .. } => {
+                    Some(*pubkey)
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let unsupported = SizeError::UnsupportedScript(index);
+
+        Ok(match script_pubkey.template() {
+            ScriptTemplate::Tr(_) => {
+                // Signatures must commit to the sighash type byte if it is given by PSBT
+                let sig_min = if map
+                    .known()
+                    .any(|pair| matches!(pair, InPair::SighashType(PsbtSighashType::Standard(_))))
+                {
+                    SCHNORR_SIG_MAX_LEN
+                } else {
+                    SCHNORR_SIG_MIN_LEN
+                };
+                let mut min = Satisfaction::segwit(vec![sig_min]);
+                let mut max = Satisfaction::segwit(vec![SCHNORR_SIG_MAX_LEN]);
+                if map.known().any(|pair| matches!(pair, InPair::TapMerkleRoot(_))) {
+                    let unknown = SizeError::UnknownTapScripts(index);
+                    let mut leaves = map
+                        .known()
+                        .filter_map(|pair| match pair {
+                            InPair::TapLeafScript {
+                                control_block,
+                                script,
+                                ..
+                            } => Some((control_block, script)),
+                            _ => None,
+                        })
+                        .peekable();
+                    if leaves.peek().is_none() {
+                        return Err(unknown);
+                    }
+                    for (control_block, script) in leaves {
+                        let (threshold, keys) = script.tap_multisig().ok_or(unknown)?;
+                        // Keys which do not sign are satisfied with empty pushes
+                        let stack = |sig_len| {
+                            let mut stack = [sig_len].repeat(threshold);
+                            stack.extend([0].repeat(keys.len() - threshold));
+                            stack.extend([script.len(), 33 + 32 * control_block.merkle_path.len()]);
+                            Satisfaction::segwit(stack)
+                        };
+                        let (leaf_min, leaf_max) = (stack(sig_min), stack(SCHNORR_SIG_MAX_LEN));
+                        if leaf_max.witness_len() > max.witness_len() {
+                            max = leaf_max;
+                        }
+                        if leaf_min.witness_len() < min.witness_len() {
+                            min = leaf_min;
+                        }
+                    }
+                }
+                (min, max)
+            }
+            ScriptTemplate::Sh(_) => {
+                let redeem_script = redeem_script.ok_or(unsupported)?;
+                match redeem_script.template() {
+                    ScriptTemplate::Wpkh(_) | ScriptTemplate::Wsh(_) => {
+                        let (min, max) = witness_stacks(redeem_script, witness_script, &keys)
+                            .ok_or(unsupported)?;
+                        (
+                            Satisfaction::nested(redeem_script, min),
+                            Satisfaction::nested(redeem_script, max),
+                        )
+                    }
+                    _ => {
+                        let (mut min, mut max) =
+                            script_stacks(redeem_script, &keys).ok_or(unsupported)?;
+                        min.push(redeem_script.len());
+                        max.push(redeem_script.len());
+                        (Satisfaction::legacy(min), Satisfaction::legacy(max))
+                    }
+                }
+            }
+            ScriptTemplate::Wpkh(_) | ScriptTemplate::Wsh(_) => {
+                let (min, max) =
+                    witness_stacks(&script_pubkey, witness_script, &keys).ok_or(unsupported)?;
+                (Satisfaction::segwit(min), Satisfaction::segwit(max))
+            }
+            _ => {
+                let (min, max) = script_stacks(&script_pubkey, &keys).ok_or(unsupported)?;
+                (Satisfaction::legacy(min), Satisfaction::legacy(max))
+            }
+        })
+    }
+}
+
+/// Computes stacks satisfying segwit v0 witness program.
+fn witness_stacks(
+    program: &ScriptBytes,
+    witness_script: Option<&ScriptBytes>,
+    keys: &[LegacyPk],
+) -> Option<Stacks> {
+    match program.template() {
+        ScriptTemplate::Wpkh(_) => Some((vec![ECDSA_SIG_MIN_LEN, 33], vec![ECDSA_SIG_MAX_LEN, 33])),
+        ScriptTemplate::Wsh(_) => {
+            let witness_script = witness_script?;
+            let (mut min, mut max) = script_stacks(witness_script, keys)?;
+            min.push(witness_script.len());
+            max.push(witness_script.len());
+            Some((min, max))
+        }
+        _ => None,
+    }
+}
+
+/// Computes stacks satisfying a (non-wrapping) script.
+fn script_stacks(script: &ScriptBytes, keys: &[LegacyPk]) -> Option<Stacks> {
+    match script.template() {
+        ScriptTemplate::Pk(_) => Some((vec![ECDSA_SIG_MIN_LEN], vec![ECDSA_SIG_MAX_LEN])),
+        ScriptTemplate::Pkh(hash) => {
+            let key_len = keys
+                .iter()
+                .map(LegacyPk::to_vec)
+                .find(|key| hash160(key) == hash)
+                .map(|key| key.len());
+            Some((vec![ECDSA_SIG_MIN_LEN, key_len.unwrap_or(33)], vec![
+                ECDSA_SIG_MAX_LEN,
+                key_len.unwrap_or(65),
+            ]))
+        }
+        // Extra stack item consumed by `OP_CHECKMULTISIG` is an empty push
+        ScriptTemplate::Multisig { threshold, .. } => {
+            let mut min = vec![0];
+            let mut max = vec![0];
+            min.extend([ECDSA_SIG_MIN_LEN].repeat(threshold));
+            max.extend([ECDSA_SIG_MAX_LEN].repeat(threshold));
+            Some((min, max))
+        }
+        _ => None,
+    }
+}
+
+/// Size of the script operation pushing data of the given length on the stack.
+fn push_len(len: usize) -> usize {
+    match len {
+        0..=0x4B => 1 + len,
+        0x4C..=0xFF => 2 + len,
+        0x100..=0xFFFF => 3 + len,
+        _ => 5 + len,
+    }
+}
+
+/// Computes size of the transaction with the inputs satisfied by the `satisfactions`.
+fn tx_size(tx: &Tx, satisfactions: &[Satisfaction]) -> usize {
+    // Base size of the unsigned transaction includes a single byte for each empty scriptSig
+    let mut size = tx.base_size() - satisfactions.len()
+        + satisfactions.iter().map(Satisfaction::sig_script_len).sum::<usize>();
+    if satisfactions.iter().any(|satisfaction| satisfaction.witness.is_some()) {
+        // Segwit marker and flag bytes
+        size += 2 + satisfactions.iter().map(Satisfaction::witness_len).sum::<usize>();
+    }
+    size
+}
+
+#[cfg(test)]
+mod test {
+    use secp256k1::{KeyPair, SecretKey, SECP256K1};
+
+    use super::*;
+    use crate::{
+        ControlBlock, InputBuilder, KeyPair as PsbtPair, Outpoint, OutputBuilder, PsbtBuilder,
+        TxOut, Txid,
+    };
+
+    fn xonly(byte: u8) -> secp256k1::XOnlyPublicKey {
+        KeyPair::from_secret_key(SECP256K1, &SecretKey::from_slice(&[byte; 32]).unwrap())
+            .x_only_public_key()
+            .0
+    }
+
+    fn taproot_psbt() -> Psbt {
+        let input = InputBuilder::new(Outpoint {
+            txid: Txid::from([1; 32]),
+            vout: 0,
+        })
+        .witness_utxo(TxOut {
+            value: 10_000,
+            script_pubkey: ScriptBytes::p2tr(xonly(1)),
+        });
+        PsbtBuilder::new()
+            .version(2)
+            .input(input)
+            .output(OutputBuilder::new(9_000, ScriptBytes::p2wpkh([2; 20])))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn taproot_script_path() {
+        let mut psbt = taproot_psbt();
+        let (key_min, key_max) = psbt.estimate_size_range().unwrap();
+        assert_eq!(key_max - key_min, 1);
+
+        psbt.inputs[0].replace_singleton(InPair::TapMerkleRoot([3; 32]));
+        assert_eq!(psbt.estimate_size_range(), Err(SizeError::UnknownTapScripts(0)));
+
+        // 2-of-3 `OP_CHECKSIGADD` multisig leaf
+        let mut script = vec![];
+        for (no, key) in [2, 3, 4].map(xonly).iter().enumerate() {
+            script.push(0x20);
+            script.extend(key.serialize());
+            script.push(if no == 0 { 0xAC } else { 0xBA });
+        }
+        script.extend([0x52, 0x9C]);
+        let control_block = ControlBlock {
+            leaf_ver: 0xC0,
+            output_key_parity: false,
+            internal_key: xonly(1),
+            merkle_path: vec![[5; 32]],
+        };
+        psbt.inputs[0].0.push(PsbtPair::Known(InPair::TapLeafScript {
+            control_block: control_block.clone(),
+            script: script.into(),
+            leaf_ver: 0xC0,
+        }));
+        let (min, max) = psbt.estimate_size_range().unwrap();
+        assert_eq!(min, key_min);
+        // Two signatures, one empty push, 104-byte script and 65-byte control block replace the
+        // single key path signature
+        assert_eq!(max - key_max, 2 * 66 + 1 + 105 + 66 - 66);
+
+        psbt.inputs[0].0.push(PsbtPair::Known(InPair::TapLeafScript {
+            control_block,
+            script: vec![0x51].into(),
+            leaf_ver: 0xC0,
+        }));
+        assert_eq!(psbt.estimate_size_range(), Err(SizeError::UnknownTapScripts(0)));
+    }
+}
@@ -0,0 +1,217 @@
+// Partially signed bitcoin transaction library (BIP174, BIP370, BIP371)
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Minimal set of bitcoin consensus data types required by PSBT.
+
+use amplify::Bytes32;
+use bitcoin_hashes::{sha256d, Hash};
+
+use crate::encoding::compact_size_len;
+
+/// Transaction id.
+#[derive(Wrapper, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Default, From)]
+#[wrapper(Index, RangeOps, BorrowSlice)]
+pub struct Txid(
+    #[from]
+    #[from([u8; 32])]
+    Bytes32,
+);
+
+/// Reference to a transaction output.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub struct Outpoint {
+    /// Id of the transaction containing the output.
+    pub txid: Txid,
+    /// Index of the output within the transaction.
+    pub vout: u32,
+}
+
+/// Raw script data, which may be a `scriptPubkey`, `scriptSig`, redeem or witness script.
+#[derive(Wrapper, WrapperMut, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Default, From)]
+#[wrapper(Deref, BorrowSlice)]
+#[wrapper_mut(DerefMut, BorrowSliceMut)]
+pub struct ScriptBytes(Vec<u8>);
+
+impl ScriptBytes {
+    /// Detects whether the script is a P2SH `scriptPubkey`.
+    pub fn is_p2sh(&self) -> bool {
+        self.0.len() == 23 && self.0[0] == 0xA9 && self.0[1] == 0x14 && self.0[22] == 0x87
+    }
+
+    /// Detects whether the script is a BIP-141 witness program: a version opcode (`OP_0` or
+    /// `OP_1`-`OP_16`) followed by a single push of 2 to 40 bytes.
+    pub fn is_witness_program(&self) -> bool {
+        let len = self.0.len();
+        (4..=42).contains(&len)
+            && (self.0[0] == 0x00 || (0x51..=0x60).contains(&self.0[0]))
+            && self.0[1] as usize + 2 == len
+    }
+
+    /// Minimal amount of an output with this `scriptPubkey` which Bitcoin Core relays: the
+    /// output must be worth at least the fee of creating and later spending it at the default
+    /// dust relay fee rate of 3 sat/vB.
+    ///
+    /// Provably unspendable (`OP_RETURN`) outputs have no dust limit.
+    pub fn dust_limit(&self) -> u64 {
+        if self.0.first() == Some(&0x6A) {
+            return 0;
+        }
+        let output_size = 8 + compact_size_len(self.0.len()) + self.0.len();
+        // Size of the spending input: outpoint, sequence and either a witness discounted by
+        // the segwit factor or a typical P2PKH `scriptSig`
+        let input_size = if self.is_witness_program() { 32 + 4 + 1 + 107 / 4 + 4 } else { 148 };
+        (output_size + input_size) as u64 * 3
+    }
+}
+
+/// Minimal amount of an output with the given `scriptPubkey` which Bitcoin Core relays; see
+/// [`ScriptBytes::dust_limit`].
+pub fn dust_limit(script_pubkey: &ScriptBytes) -> u64 { script_pubkey.dust_limit() }
+
+/// Witness stack of a transaction input.
+///
+/// Serialized as the number of elements followed by each of the length-prefixed elements.
+#[derive(Wrapper, WrapperMut, Clone, Eq, PartialEq, Hash, Debug, Default, From)]
+#[wrapper(Deref)]
+#[wrapper_mut(DerefMut)]
+pub struct Witness(Vec<Vec<u8>>);
+
+impl Witness {
+    /// Constructs empty witness.
+    pub fn new() -> Self { Witness::default() }
+
+    /// Adds element to the top of the witness stack.
+    pub fn push(&mut self, item: impl Into<Vec<u8>>) { self.0.push(item.into()) }
+
+    /// Returns control block of a taproot script path spend, which is the last witness element
+    /// after the annex (if any) is removed, as defined by BIP-341.
+    ///
+    /// Returns `None` if the witness has less than two elements (excluding annex), which means
+    /// it can be only a key path spend.
+    pub fn tapscript_control_block(&self) -> Option<&[u8]> {
+        let mut items = self.0.as_slice();
+        if items.len() >= 2 && items.last().and_then(|item| item.first()) == Some(&0x50) {
+            items = &items[..items.len() - 1];
+        }
+        match items {
+            [.., _script, control_block] => Some(control_block),
+            _ => None,
+        }
+    }
+
+    /// Size of the witness data in its consensus serialization.
+    pub fn serialized_len(&self) -> usize {
+        self.0.iter().fold(compact_size_len(self.0.len()), |len, item| {
+            len + compact_size_len(item.len()) + item.len()
+        })
+    }
+}
+
+/// Transaction input.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct TxIn {
+    /// Output spent by the input.
+    pub prev_output: Outpoint,
+    /// Input `scriptSig`.
+    pub sig_script: ScriptBytes,
+    /// Input sequence number.
+    pub sequence: u32,
+    /// Input witness; empty for non-segwit inputs.
+    pub witness: Witness,
+}
+
+/// Transaction output.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct TxOut {
+    /// Output amount in satoshis.
+    pub value: u64,
+    /// Output `scriptPubkey`.
+    pub script_pubkey: ScriptBytes,
+}
+
+/// Bitcoin transaction.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Tx {
+    /// Transaction version.
+    pub version: u32,
+    /// Transaction inputs.
+    pub inputs: Vec<TxIn>,
+    /// Transaction outputs.
+    pub outputs: Vec<TxOut>,
+    /// Transaction lock time.
+    pub lock_time: u32,
+}
+
+impl Tx {
+    /// Detects whether some of the transaction inputs have non-empty witness.
+    pub fn has_witness(&self) -> bool { self.inputs.iter().any(|input| !input.witness.is_empty()) }
+
+    /// Size of the transaction serialized without witness data.
+    pub fn base_size(&self) -> usize {
+        let inputs = self.inputs.iter().fold(compact_size_len(self.inputs.len()), |len, input| {
+            len + 32 + 4 + compact_size_len(input.sig_script.len()) + input.sig_script.len() + 4
+        });
+        let outputs =
+            self.outputs.iter().fold(compact_size_len(self.outputs.len()), |len, output| {
+                len + 8 + compact_size_len(output.script_pubkey.len()) + output.script_pubkey.len()
+            });
+        4 + inputs + outputs + 4
+    }
+
+    /// Size of the transaction serialized with witness data (if any).
+    pub fn total_size(&self) -> usize {
+        if !self.has_witness() {
+            return self.base_size();
+        }
+        // Segwit marker and flag bytes
+        let witness = self.inputs.iter().map(|input| input.witness.serialized_len()).sum::<usize>();
+        self.base_size() + 2 + witness
+    }
+
+    /// Computes transaction id, which is a double SHA-256 hash of the transaction serialized
+    /// without witness data.
+    ///
+    /// The serialization is streamed into the hash engine, so no intermediate copy of the
+    /// whole transaction is allocated.
+    pub fn txid(&self) -> Txid {
+        let mut engine = sha256d::Hash::engine();
+        self.hash_consensus(&mut engine);
+        Txid::from(sha256d::Hash::from_engine(engine).to_byte_array())
+    }
+
+    /// Checks that the transaction id matches the `expected` one, as required for the
+    /// transactions provided in `PSBT_IN_NON_WITNESS_UTXO`.
+    pub fn check_txid(&self, expected: Txid) -> bool { self.txid() == expected }
+
+    /// Transaction weight as defined by BIP-141.
+    pub fn weight(&self) -> Weight { Weight((self.base_size() * 3 + self.total_size()) as u64) }
+}
+
+/// Transaction weight in weight units.
+#[derive(Wrapper, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Default, Display, From)]
+#[display("{0} WU")]
+pub struct Weight(u64);
+
+impl Weight {
+    /// Virtual size of the transaction (weight divided by four and rounded up).
+    pub fn vsize(self) -> u64 { (self.0 + 3) / 4 }
+}
@@ -0,0 +1,883 @@
+// Partially signed bitcoin transaction library (BIP174, BIP370, BIP371)
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Operations of the PSBT updater role.
+
+use derive::Xpub;
+use secp256k1::{PublicKey, XOnlyPublicKey};
+
+use crate::encoding::decode_control_block;
+use crate::scripts::{hash160, sha256, ScriptTemplate};
+use crate::{
+    ControlBlock, GlobalPair, InPair, KeyMap, KeyOrigin, KeyPair, KnownPair, LegacyPk, OutPair,
+    Outpoint, ProprietaryPair, Psbt, PsbtSighashType, ScriptBytes, Witness, XpubDerivation,
+};
+
+/// Errors updating PSBT.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum UpdateError {
+    /// PSBT doesn't have input #{0}.
+    NoInput(usize),
+
+    /// PSBT doesn't have output #{0}.
+    NoOutput(usize),
+
+    /// sequence number of input #{0} is a part of the unsigned transaction of PSBT v0 and
+    /// can't be changed.
+    ImmutableSequence(usize),
+
+    /// extended public key is already present in PSBT with a different origin.
+    XpubOriginConflict,
+
+    /// output spent by input #{0} is unknown, since the input has no UTXO information.
+    NoSpentOutput(usize),
+
+    /// P2SH-wrapped witness program doesn't match `scriptPubkey` of the output spent by input
+    /// #{0}.
+    ScriptPubkeyMismatch(usize),
+
+    /// input #{0} doesn't have non-witness UTXO.
+    NoNonWitnessUtxo(usize),
+
+    /// non-witness UTXO of input #{0} doesn't contain the output spent by the input.
+    NonWitnessUtxoMismatch(usize),
+
+    /// input #{0} is not known to spend a segwit output.
+    NotSegwit(usize),
+
+    /// input #{0} is finalized and its signatures can't be updated.
+    Finalized(usize),
+}
+
+/// Data committed to by the witness program wrapped into a P2SH output (nested segwit).
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum SegwitInner {
+    /// Public key of a P2SH-P2WPKH output.
+    Wpkh(PublicKey),
+    /// Witness script of a P2SH-P2WSH output.
+    Wsh(ScriptBytes),
+}
+
+/// Information required to spend an output, which is known to the wallet controlling the output
+/// (usually derived from its descriptor).
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct SpendInfo {
+    /// Redeem script of a P2SH output.
+    pub redeem_script: Option<ScriptBytes>,
+    /// Witness script of a P2WSH output.
+    pub witness_script: Option<ScriptBytes>,
+    /// Public keys required to spend the output together with their origins.
+    pub bip32_derivations: Vec<(LegacyPk, KeyOrigin)>,
+    /// Taproot internal key of a P2TR output.
+    pub tap_internal_key: Option<XOnlyPublicKey>,
+    /// Merkle root of the taproot script tree of a P2TR output.
+    pub tap_merkle_root: Option<[u8; 32]>,
+    /// Leaf scripts of the taproot script tree of a P2TR output together with their control
+    /// blocks, which also define the leaf versions.
+    pub tap_leaf_scripts: Vec<(ControlBlock, ScriptBytes)>,
+}
+
+impl Psbt {
+    /// Adds extended public key with its origin to the global map (`PSBT_GLOBAL_XPUB`).
+    ///
+    /// Adding the same extended key with the same origin again is a no-op, while adding it with
+    /// a different origin fails with [`UpdateError::XpubOriginConflict`].
+    pub fn add_global_xpub(&mut self, derivation: XpubDerivation) -> Result<(), UpdateError> {
+        let existing = self.global.known().find_map(|pair| match pair {
+            GlobalPair::Xpub(existing) if existing.xpub == derivation.xpub => Some(existing),
+            _ => None,
+        });
+        match existing {
+            Some(existing) if *existing == derivation => Ok(()),
+            Some(_) => Err(UpdateError::XpubOriginConflict),
+            None => {
+                self.global.0.push(KeyPair::Known(GlobalPair::Xpub(derivation)));
+                Ok(())
+            }
+        }
+    }
+
+    /// Removes extended public key from the global map, returning it together with its origin
+    /// if it was present.
+    ///
+    /// This allows to strip the accounts of some cosigners before forwarding PSBT to the
+    /// parties which must not learn them.
+    pub fn remove_global_xpub(&mut self, xpub: &Xpub) -> Option<XpubDerivation> {
+        let pos = self.global.0.iter().position(|pair| {
+            matches!(pair, KeyPair::Known(GlobalPair::Xpub(derivation)) if derivation.xpub == *xpub)
+        })?;
+        match self.global.0.remove(pos) {
+            KeyPair::Known(GlobalPair::Xpub(derivation)) => Some(derivation),
+            _ => unreachable!("the position is of a global xpub pair"),
+        }
+    }
+
+    /// Adds spending information to the input, replacing the scripts, key origins and taproot
+    /// data which are already present.
+    pub fn update_input(&mut self, index: usize, info: &SpendInfo) -> Result<(), UpdateError> {
+        let map = self.inputs.get_mut(index).ok_or(UpdateError::NoInput(index))?;
+        if let Some(script) = &info.redeem_script {
+            map.replace_singleton(InPair::RedeemScript(script.clone()));
+        }
+        if let Some(script) = &info.witness_script {
+            map.replace_singleton(InPair::WitnessScript(script.clone()));
+        }
+        for (pubkey, origin) in &info.bip32_derivations {
+            map.replace_keyed(InPair::Bip32Derivation {
+                pubkey: *pubkey,
+                origin: origin.clone(),
+            });
+        }
+        if let Some(key) = info.tap_internal_key {
+            map.replace_singleton(InPair::TapInternalKey(key));
+        }
+        if let Some(root) = info.tap_merkle_root {
+            map.replace_singleton(InPair::TapMerkleRoot(root));
+        }
+        for (control_block, script) in &info.tap_leaf_scripts {
+            map.replace_keyed(InPair::TapLeafScript {
+                control_block: control_block.clone(),
+                script: script.clone(),
+                leaf_ver: control_block.leaf_ver,
+            });
+        }
+        Ok(())
+    }
+
+    /// Sets redeem script of the input spending a P2SH-wrapped segwit output to the witness
+    /// program (`OP_0 <program>`) of the inner P2WPKH or P2WSH script; for P2WSH the witness
+    /// script is set as well.
+    ///
+    /// The operation fails without modifying the input if the hash of the redeem script doesn't
+    /// match the P2SH `scriptPubkey` of the spent output.
+    pub fn set_p2sh_segwit_redeem(
+        &mut self,
+        index: usize,
+        inner: SegwitInner,
+    ) -> Result<(), UpdateError> {
+        if index >= self.inputs.len() {
+            return Err(UpdateError::NoInput(index));
+        }
+        let redeem_script = match &inner {
+            SegwitInner::Wpkh(pubkey) => ScriptBytes::p2wpkh(hash160(&pubkey.serialize())),
+            SegwitInner::Wsh(witness_script) => ScriptBytes::p2wsh(sha256(witness_script)),
+        };
+        match self.spent_script_pubkey(index).map(|script| script.template()) {
+            Some(ScriptTemplate::Sh(hash)) if hash == hash160(&redeem_script) => {}
+            Some(_) => return Err(UpdateError::ScriptPubkeyMismatch(index)),
+            None => return Err(UpdateError::NoSpentOutput(index)),
+        }
+
+        let map = &mut self.inputs[index];
+        map.replace_singleton(InPair::RedeemScript(redeem_script));
+        match inner {
+            SegwitInner::Wpkh(_) => {
+                map.0.retain(|pair| !matches!(pair, KeyPair::Known(InPair::WitnessScript(_))))
+            }
+            SegwitInner::Wsh(witness_script) => {
+                map.replace_singleton(InPair::WitnessScript(witness_script))
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets signature hash type which must be used by signers of the input.
+    ///
+    /// Partial signatures and taproot key and script path signatures of the input made with a
+    /// different signature hash type are removed, since they no longer match the requirements of
+    /// the PSBT and have to be re-created. Requiring [`PsbtSighashType::TaprootDefault`] removes
+    /// all partial signatures, since the type is valid only for taproot inputs.
+    pub fn set_sighash_type(
+        &mut self,
+        index: usize,
+        ty: impl Into<PsbtSighashType>,
+    ) -> Result<(), UpdateError> {
+        let ty = ty.into();
+        let map = self.inputs.get_mut(index).ok_or(UpdateError::NoInput(index))?;
+        map.replace_singleton(InPair::SighashType(ty));
+        map.0.retain(|pair| match pair {
+            KeyPair::Known(InPair::PartialSig { sig, .. }) => {
+                Some(sig.sighash_type) == ty.to_ecdsa()
+            }
+            KeyPair::Known(InPair::TapKeySig(sig) | InPair::TapScriptSig { sig, .. }) => {
+                sig.sighash_type == ty.to_taproot()
+            }
+            _ => true,
+        });
+        Ok(())
+    }
+
+    /// Sets proof of reserves commitment message of the input (BIP-127).
+    pub fn set_por_commitment(
+        &mut self,
+        index: usize,
+        msg: impl Into<String>,
+    ) -> Result<(), UpdateError> {
+        let map = self.inputs.get_mut(index).ok_or(UpdateError::NoInput(index))?;
+        map.replace_singleton(InPair::PorCommitment(msg.into()));
+        Ok(())
+    }
+
+    /// Sets witness UTXO of the segwit input to the spent output taken from its non-witness
+    /// UTXO, which is kept in the input (see [`Psbt::minimize`] to remove it).
+    ///
+    /// The id of the non-witness UTXO transaction must match the id of the transaction spent by
+    /// the input, and the transaction must have the spent output. For P2SH-wrapped segwit inputs
+    /// the redeem script must be already present, since otherwise the input can't be detected
+    /// as segwit.
+    pub fn derive_witness_utxo(&mut self, index: usize) -> Result<(), UpdateError> {
+        let map = self.inputs.get(index).ok_or(UpdateError::NoInput(index))?;
+        let prev_tx = map
+            .known()
+            .find_map(|pair| match pair {
+                InPair::NonWitnessUtxo(tx) => Some(tx),
+                _ => None,
+            })
+            .ok_or(UpdateError::NoNonWitnessUtxo(index))?;
+        let txout = self
+            .input_prev_output(index)
+            .filter(|prev_output| prev_tx.check_txid(prev_output.txid))
+            .and_then(|prev_output| prev_tx.outputs.get(prev_output.vout as usize))
+            .cloned()
+            .ok_or(UpdateError::NonWitnessUtxoMismatch(index))?;
+        if self.input_is_segwit(index) != Ok(true) {
+            return Err(UpdateError::NotSegwit(index));
+        }
+        self.inputs[index].replace_singleton(InPair::WitnessUtxo(txout));
+        Ok(())
+    }
+
+    /// Returns output spent by the input, taking it from the unsigned transaction (v0) or from
+    /// the input map (v2).
+    fn input_prev_output(&self, index: usize) -> Option<Outpoint> {
+        if self.version() < 2 {
+            return self.global.known().find_map(|pair| match pair {
+                GlobalPair::UnsignedTx(tx) => tx.inputs.get(index).map(|input| input.prev_output),
+                _ => None,
+            });
+        }
+        let map = self.inputs.get(index)?;
+        Some(Outpoint {
+            txid: map.known().find_map(|pair| match pair {
+                InPair::PreviousTxid(txid) => Some(*txid),
+                _ => None,
+            })?,
+            vout: map.known().find_map(|pair| match pair {
+                InPair::OutputIndex(vout) => Some(*vout),
+                _ => None,
+            })?,
+        })
+    }
+
+    /// Returns sequence number of the input, taking it from the unsigned transaction (v0) or
+    /// from the input map (v2). For v2 inputs without the sequence field the final sequence
+    /// number `0xFFFFFFFF` is returned.
+    pub fn input_sequence(&self, index: usize) -> Result<u32, UpdateError> {
+        if self.version() < 2 {
+            return self
+                .global
+                .known()
+                .find_map(|pair| match pair {
+                    GlobalPair::UnsignedTx(tx) => tx.inputs.get(index).map(|input| input.sequence),
+                    _ => None,
+                })
+                .ok_or(UpdateError::NoInput(index));
+        }
+        let map = self.inputs.get(index).ok_or(UpdateError::NoInput(index))?;
+        Ok(map
+            .known()
+            .find_map(|pair| match pair {
+                InPair::Sequence(seq) => Some(*seq),
+                _ => None,
+            })
+            .unwrap_or(u32::MAX))
+    }
+
+    /// Sets sequence number of the v2 input, returning whether the sequence number signals
+    /// replaceability according to BIP-125.
+    ///
+    /// In PSBT v0 the sequence numbers are a part of the unsigned transaction, which can't be
+    /// modified, so the method fails with [`UpdateError::ImmutableSequence`].
+    ///
+    /// Since signatures commit to the input sequence number, all signatures of the input are
+    /// removed, as well as the signatures of other inputs which commit to the sequence numbers
+    /// of all inputs (the ones without `ANYONECANPAY`). Signatures of the finalized inputs can't
+    /// be removed, thus the method fails with [`UpdateError::Finalized`] if some of the inputs
+    /// are finalized (see [`Psbt::unfinalize`]).
+    pub fn set_input_sequence(&mut self, index: usize, seq: u32) -> Result<bool, UpdateError> {
+        if index >= self.inputs.len() {
+            return Err(UpdateError::NoInput(index));
+        }
+        if self.version() < 2 {
+            return Err(UpdateError::ImmutableSequence(index));
+        }
+        if let Some(finalized) = self.inputs.iter().position(|map| {
+            map.known().any(|pair| {
+                matches!(pair, InPair::FinalScriptSig(_) | InPair::FinalScriptWitness(_))
+            })
+        }) {
+            return Err(UpdateError::Finalized(finalized));
+        }
+        self.inputs[index].replace_singleton(InPair::Sequence(seq));
+        self.remove_sigs(|input, ty| input == index || !ty.anyone_can_pay);
+        Ok(seq < 0xFFFF_FFFE)
+    }
+}
+
+impl Psbt {
+    /// Adds proprietary pair to the global map. If the map already contains a proprietary pair
+    /// with the same key, its value is replaced and the previous value is returned.
+    pub fn add_global_proprietary(&mut self, pair: ProprietaryPair) -> Option<Vec<u8>> {
+        self.global.insert_proprietary(pair)
+    }
+
+    /// Adds proprietary pair to the input map. If the map already contains a proprietary pair
+    /// with the same key, its value is replaced and the previous value is returned.
+    pub fn add_input_proprietary(
+        &mut self,
+        index: usize,
+        pair: ProprietaryPair,
+    ) -> Result<Option<Vec<u8>>, UpdateError> {
+        let map = self.inputs.get_mut(index).ok_or(UpdateError::NoInput(index))?;
+        Ok(map.insert_proprietary(pair))
+    }
+
+    /// Adds proprietary pair to the output map. If the map already contains a proprietary pair
+    /// with the same key, its value is replaced and the previous value is returned.
+    pub fn add_output_proprietary(
+        &mut self,
+        index: usize,
+        pair: ProprietaryPair,
+    ) -> Result<Option<Vec<u8>>, UpdateError> {
+        let map = self.outputs.get_mut(index).ok_or(UpdateError::NoOutput(index))?;
+        Ok(map.insert_proprietary(pair))
+    }
+
+    /// Removes proprietary pair from the global map, returning its value.
+    pub fn remove_proprietary(
+        &mut self,
+        identifier: impl AsRef<[u8]>,
+        subkey_type: u64,
+        subkey_data: &[u8],
+    ) -> Option<Vec<u8>> {
+        self.global.remove_proprietary(identifier, subkey_type, subkey_data)
+    }
+
+    /// Removes proprietary pair from the input map, returning its value.
+    pub fn remove_input_proprietary(
+        &mut self,
+        index: usize,
+        identifier: impl AsRef<[u8]>,
+        subkey_type: u64,
+        subkey_data: &[u8],
+    ) -> Result<Option<Vec<u8>>, UpdateError> {
+        let map = self.inputs.get_mut(index).ok_or(UpdateError::NoInput(index))?;
+        Ok(map.remove_proprietary(identifier, subkey_type, subkey_data))
+    }
+
+    /// Removes proprietary pair from the output map, returning its value.
+    pub fn remove_output_proprietary(
+        &mut self,
+        index: usize,
+        identifier: impl AsRef<[u8]>,
+        subkey_type: u64,
+        subkey_data: &[u8],
+    ) -> Result<Option<Vec<u8>>, UpdateError> {
+        let map = self.outputs.get_mut(index).ok_or(UpdateError::NoOutput(index))?;
+        Ok(map.remove_proprietary(identifier, subkey_type, subkey_data))
+    }
+}
+
+impl<T: KnownPair> KeyMap<T> {
+    /// Inserts known pair, replacing an existing pair with the same key type and key data.
+    pub(crate) fn replace_keyed(&mut self, pair: T) {
+        let (key_type, key_data) = (pair.key_type(), pair.key_data());
+        let pos = self.0.iter().position(|p| {
+            matches!(p, KeyPair::Known(p) if p.key_type() == key_type && p.key_data() == key_data)
+        });
+        match pos {
+            Some(pos) => self.0[pos] = KeyPair::Known(pair),
+            None => self.0.push(KeyPair::Known(pair)),
+        }
+    }
+
+    /// Position of the proprietary pair with the given key.
+    fn proprietary_pos(
+        &self,
+        identifier: impl AsRef<[u8]>,
+        subkey_type: u64,
+        subkey_data: &[u8],
+    ) -> Option<usize> {
+        self.0.iter().position(|pair| {
+            matches!(pair, KeyPair::Proprietary(p) if p.identifier == identifier.as_ref() &&
+                p.subkey_type == subkey_type && p.subkey_data == subkey_data)
+        })
+    }
+
+    /// Inserts proprietary pair, replacing the value of an existing pair with the same key.
+    fn insert_proprietary(&mut self, pair: ProprietaryPair) -> Option<Vec<u8>> {
+        match self.proprietary_pos(&pair.identifier, pair.subkey_type, &pair.subkey_data) {
+            Some(pos) => match &mut self.0[pos] {
+                KeyPair::Proprietary(existing) => {
+                    Some(core::mem::replace(&mut existing.value, pair.value))
+                }
+                _ => unreachable!("position of a proprietary pair"),
+            },
+            None => {
+                self.0.push(KeyPair::Proprietary(pair));
+                None
+            }
+        }
+    }
+
+    /// Removes proprietary pair with the given key, returning its value.
+    fn remove_proprietary(
+        &mut self,
+        identifier: impl AsRef<[u8]>,
+        subkey_type: u64,
+        subkey_data: &[u8],
+    ) -> Option<Vec<u8>> {
+        let pos = self.proprietary_pos(identifier, subkey_type, subkey_data)?;
+        match self.0.remove(pos) {
+            KeyPair::Proprietary(pair) => Some(pair.value),
+            _ => unreachable!("position of a proprietary pair"),
+        }
+    }
+}
+
+impl Psbt {
+    /// Removes final `scriptSig` and witness from all inputs, keeping the partial signatures,
+    /// such that the inputs may be signed and finalized again (for instance, to add one more
+    /// signature to a multisig).
+    ///
+    /// Since finalizers remove the scripts from the finalized inputs, the redeem script, the
+    /// witness script and the taproot leaf script are recovered from the final data, if they are
+    /// committed to by the spent output and are not present in the input yet. Key origins and
+    /// the signatures removed by the finalizer can't be recovered.
+    pub fn unfinalize(&mut self) {
+        for index in 0..self.inputs.len() {
+            let spent_script = self.spent_output(index).map(|txout| txout.script_pubkey);
+            let map = &mut self.inputs[index];
+            let mut script_sig = None;
+            let mut witness = None;
+            map.0.retain(|pair| match pair {
+                KeyPair::Known(InPair::FinalScriptSig(script)) => {
+                    script_sig = Some(script.clone());
+                    false
+                }
+                KeyPair::Known(InPair::FinalScriptWitness(stack)) => {
+                    witness = Some(stack.clone());
+                    false
+                }
+                _ => true,
+            });
+            let mut spent_script = match spent_script {
+                Some(script) => script,
+                None => continue,
+            };
+            let has = |map: &KeyMap<InPair>, f: fn(&InPair) -> bool| map.known().any(f);
+
+            if let (ScriptTemplate::Sh(hash), Some(script_sig)) =
+                (spent_script.template(), &script_sig)
+            {
+                match script_sig.last_push() {
+                    Some(redeem_script) if hash160(redeem_script) == hash => {
+                        spent_script = ScriptBytes::from(redeem_script.to_vec());
+                        if !has(map, |pair| matches!(pair, InPair::RedeemScript(_))) {
+                            map.0.push(KeyPair::Known(InPair::RedeemScript(spent_script.clone())));
+                        }
+                    }
+                    _ => continue,
+                }
+            }
+
+            let witness = match witness {
+                Some(witness) => witness,
+                None => continue,
+            };
+            match spent_script.template() {
+                ScriptTemplate::Wsh(hash)
+                    if !has(map, |pair| matches!(pair, InPair::WitnessScript(_))) =>
+                {
+                    if let Some(script) = witness.last().filter(|script| sha256(script) == hash) {
+                        let script = ScriptBytes::from(script.clone());
+                        map.0.push(KeyPair::Known(InPair::WitnessScript(script)));
+                    }
+                }
+                ScriptTemplate::Tr(_) => {
+                    let control_block = match witness
+                        .tapscript_control_block()
+                        .and_then(|data| decode_control_block(0x15, data).ok())
+                    {
+                        Some(control_block) => control_block,
+                        None => continue,
+                    };
+                    let script = &witness[witness.len() - 2 - witness_annex_len(&witness)];
+                    let exists = map.known().any(|pair| {
+                        matches!(pair, InPair::TapLeafScript { control_block: cb, .. } if *cb == control_block)
+                    });
+                    if !exists {
+                        map.0.push(KeyPair::Known(InPair::TapLeafScript {
+                            leaf_ver: control_block.leaf_ver,
+                            control_block,
+                            script: ScriptBytes::from(script.clone()),
+                        }));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Returns 1 if the last element of the taproot witness is an annex, and 0 otherwise.
+fn witness_annex_len(witness: &Witness) -> usize {
+    let annex = witness.len() >= 2 && witness.last().and_then(|item| item.first()) == Some(&0x50);
+    annex as usize
+}
+
+/// Report on the data removed by [`Psbt::minimize`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct MinimizeReport {
+    /// Number of removed global extended public keys.
+    pub removed_xpubs: usize,
+    /// Number of inputs which non-witness UTXOs were replaced with witness UTXOs.
+    pub replaced_utxos: usize,
+    /// Number of removed output key origins which can't be used by signers for change
+    /// verification.
+    pub removed_origins: usize,
+    /// Reduction of the serialized PSBT size, in bytes.
+    pub bytes_saved: usize,
+}
+
+impl Psbt {
+    /// Removes data which are not required by signers, producing minimal signing request for
+    /// size-constrained (for instance, QR-code based) hardware signers.
+    ///
+    /// Global extended public keys are always removed. Unless `keep_non_witness_utxo` is set,
+    /// non-witness UTXOs of segwit inputs are replaced with witness UTXOs. Note that some
+    /// hardware wallets require the non-witness UTXO for segwit v0 inputs to protect against
+    /// fee attacks, in which case `keep_non_witness_utxo` must be set.
+    ///
+    /// Output key origins (BIP-32 derivations) are kept only if they originate from a master key
+    /// which has keys in some of the inputs: signers detect change by matching output origins
+    /// against their own master key, so origins of other master keys can't be used for change
+    /// verification by any of the signers.
+    ///
+    /// Inputs which type can't be determined, and the data required to produce signatures, are
+    /// left intact.
+    pub fn minimize(&mut self, keep_non_witness_utxo: bool) -> MinimizeReport {
+        let len = self.to_raw().len();
+        let mut report = MinimizeReport::default();
+
+        let xpubs = self.global.0.len();
+        self.global.0.retain(|pair| !matches!(pair, KeyPair::Known(GlobalPair::Xpub(_))));
+        report.removed_xpubs = xpubs - self.global.0.len();
+
+        if !keep_non_witness_utxo {
+            for index in 0..self.inputs.len() {
+                let has_non_witness_utxo = self.inputs[index]
+                    .known()
+                    .any(|pair| matches!(pair, InPair::NonWitnessUtxo(_)));
+                if !has_non_witness_utxo || self.input_is_segwit(index) != Ok(true) {
+                    continue;
+                }
+                let txout = match self.spent_output(index) {
+                    Some(txout) => txout,
+                    None => continue,
+                };
+                let map = &mut self.inputs[index];
+                map.replace_singleton(InPair::WitnessUtxo(txout));
+                map.0.retain(|pair| !matches!(pair, KeyPair::Known(InPair::NonWitnessUtxo(_))));
+                report.replaced_utxos += 1;
+            }
+        }
+
+        let signer_fps = (0..self.inputs.len())
+            .flat_map(|index| self.input_key_origins(index).map(|(_, fp, _)| fp))
+            .collect::<Vec<_>>();
+        for map in &mut self.outputs {
+            let origins = map.0.len();
+            map.0.retain(|pair| match pair {
+                KeyPair::Known(
+                    OutPair::Bip32Derivation { origin, .. }
+                    | OutPair::TapBip32Derivation { origin, .. },
+                ) => signer_fps.contains(&origin.master_fp),
+                _ => true,
+            });
+            report.removed_origins += origins - map.0.len();
+        }
+
+        report.bytes_saved = len - self.to_raw().len();
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use derive::Fingerprint;
+    use secp256k1::{schnorr, SecretKey, SECP256K1};
+
+    use super::*;
+    use crate::{
+        InputBuilder, OutputBuilder, PsbtBuilder, SchnorrSig, SighashType, Tx, TxOut, Txid,
+    };
+
+    fn psbt() -> Psbt {
+        let input = InputBuilder::new(Outpoint {
+            txid: Txid::from([1; 32]),
+            vout: 0,
+        });
+        PsbtBuilder::new()
+            .version(2)
+            .input(input)
+            .output(OutputBuilder::new(900, ScriptBytes::p2wpkh([3; 20])))
+            .build()
+            .unwrap()
+    }
+
+    fn schnorr_sig(sighash_type: Option<u32>) -> SchnorrSig {
+        SchnorrSig {
+            sig: schnorr::Signature::from_slice(&[1; 64]).unwrap(),
+            sighash_type: sighash_type.map(|ty| SighashType::from_standard_u32(ty).unwrap()),
+        }
+    }
+
+    fn tap_sigs(psbt: &Psbt) -> Vec<Option<u32>> {
+        psbt.inputs[0]
+            .known()
+            .filter_map(|pair| match pair {
+                InPair::TapKeySig(sig) | InPair::TapScriptSig { sig, .. } => {
+                    Some(sig.sighash_type.map(SighashType::to_u32))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sighash_type_removes_taproot_sigs() {
+        let pubkey = SecretKey::from_slice(&[1; 32]).unwrap().x_only_public_key(SECP256K1).0;
+        let tap_script_sig = |leaf, ty| {
+            KeyPair::Known(InPair::TapScriptSig {
+                pubkey,
+                leaf_hash: [leaf; 32],
+                sig: schnorr_sig(ty),
+            })
+        };
+        let mut psbt = psbt();
+        psbt.inputs[0].0.extend([
+            KeyPair::Known(InPair::TapKeySig(schnorr_sig(None))),
+            tap_script_sig(1, Some(0x83)),
+            tap_script_sig(2, Some(0x01)),
+        ]);
+
+        psbt.set_sighash_type(0, SighashType::from_standard_u32(0x83).unwrap()).unwrap();
+        assert_eq!(tap_sigs(&psbt), vec![Some(0x83)]);
+        psbt.set_sighash_type(0, SighashType::ALL).unwrap();
+        assert_eq!(tap_sigs(&psbt), vec![]);
+
+        psbt.inputs[0].0.extend([
+            KeyPair::Known(InPair::TapKeySig(schnorr_sig(None))),
+            tap_script_sig(1, Some(0x01)),
+        ]);
+        psbt.set_sighash_type(0, PsbtSighashType::TaprootDefault).unwrap();
+        assert_eq!(tap_sigs(&psbt), vec![None]);
+        assert_eq!(psbt.set_sighash_type(1, SighashType::ALL), Err(UpdateError::NoInput(1)));
+    }
+
+    #[test]
+    fn minimize_report() {
+        let pubkey = SecretKey::from_slice(&[1; 32]).unwrap().public_key(SECP256K1);
+        let origin = |fp| KeyOrigin {
+            master_fp: Fingerprint::from([fp; 4]),
+            path: vec![],
+        };
+        let prev_tx = Tx {
+            version: 2,
+            inputs: vec![],
+            outputs: vec![TxOut {
+                value: 2_000,
+                script_pubkey: ScriptBytes::p2wpkh(hash160(&pubkey.serialize())),
+            }],
+            lock_time: 0,
+        };
+        let input = InputBuilder::new(Outpoint {
+            txid: prev_tx.txid(),
+            vout: 0,
+        })
+        .non_witness_utxo(prev_tx)
+        .spend_info(SpendInfo {
+            bip32_derivations: vec![(LegacyPk::compressed(pubkey), origin(1))],
+            ..default!()
+        });
+        let mut psbt = PsbtBuilder::new()
+            .input(input)
+            .output(
+                OutputBuilder::new(900, ScriptBytes::p2wpkh([3; 20]))
+                    .bip32_derivation(pubkey, origin(1)),
+            )
+            .output(
+                OutputBuilder::new(900, ScriptBytes::p2wpkh([4; 20]))
+                    .bip32_derivation(pubkey, origin(2)),
+            )
+            .build()
+            .unwrap();
+        let len = psbt.to_raw().len();
+
+        let mut kept = psbt.clone();
+        let report = kept.minimize(true);
+        assert_eq!(report.replaced_utxos, 0);
+        assert_eq!(report.removed_origins, 1);
+        assert_eq!(report.bytes_saved, len - kept.to_raw().len());
+
+        let report = psbt.minimize(false);
+        assert_eq!(report, MinimizeReport {
+            removed_xpubs: 0,
+            replaced_utxos: 1,
+            removed_origins: 1,
+            bytes_saved: len - psbt.to_raw().len(),
+        });
+        assert!(report.bytes_saved > kept.to_raw().len() - psbt.to_raw().len());
+        assert_eq!(psbt.outputs[0].known().count(), 1);
+        assert_eq!(psbt.outputs[1].known().count(), 0);
+    }
+
+    #[test]
+    fn sequence_removes_sigs() {
+        let pubkey = SecretKey::from_slice(&[1; 32]).unwrap().x_only_public_key(SECP256K1).0;
+        let mut psbt = psbt();
+        psbt.inputs.push(psbt.inputs[0].clone());
+        psbt.global.replace_singleton(GlobalPair::InputCount(2));
+        for map in &mut psbt.inputs {
+            map.0.extend([
+                KeyPair::Known(InPair::TapKeySig(schnorr_sig(Some(0x81)))),
+                KeyPair::Known(InPair::TapScriptSig {
+                    pubkey,
+                    leaf_hash: [1; 32],
+                    sig: schnorr_sig(Some(0x03)),
+                }),
+            ]);
+        }
+
+        assert_eq!(psbt.set_input_sequence(0, 0xFFFF_FFFD), Ok(true));
+        assert_eq!(psbt.input_sequence(0), Ok(0xFFFF_FFFD));
+        assert_eq!(tap_sigs(&psbt), vec![]);
+        assert_eq!(
+            psbt.inputs[1]
+                .known()
+                .filter(|pair| matches!(pair, InPair::TapKeySig(_) | InPair::TapScriptSig { .. }))
+                .count(),
+            1
+        );
+
+        psbt.inputs[1].0.push(KeyPair::Known(InPair::FinalScriptWitness(default!())));
+        assert_eq!(psbt.set_input_sequence(0, 0), Err(UpdateError::Finalized(1)));
+        assert_eq!(psbt.set_input_sequence(2, 0), Err(UpdateError::NoInput(2)));
+    }
+
+    #[test]
+    fn witness_utxo_from_non_witness() {
+        let prev_tx = Tx {
+            version: 2,
+            inputs: vec![],
+            outputs: vec![
+                TxOut {
+                    value: 1_000,
+                    script_pubkey: ScriptBytes::p2pkh([1; 20]),
+                },
+                TxOut {
+                    value: 2_000,
+                    script_pubkey: ScriptBytes::p2wpkh([2; 20]),
+                },
+            ],
+            lock_time: 0,
+        };
+        let txid = prev_tx.txid();
+        let psbt_spending = |vout| {
+            let input =
+                InputBuilder::new(Outpoint { txid, vout }).non_witness_utxo(prev_tx.clone());
+            PsbtBuilder::new()
+                .input(input)
+                .output(OutputBuilder::new(900, ScriptBytes::p2wpkh([3; 20])))
+                .build()
+                .unwrap()
+        };
+
+        let mut psbt = psbt_spending(1);
+        psbt.derive_witness_utxo(0).unwrap();
+        assert!(psbt.inputs[0]
+            .known()
+            .any(|pair| *pair == InPair::WitnessUtxo(prev_tx.outputs[1].clone())));
+
+        assert_eq!(psbt_spending(0).derive_witness_utxo(0), Err(UpdateError::NotSegwit(0)));
+        assert_eq!(
+            psbt_spending(2).derive_witness_utxo(0),
+            Err(UpdateError::NonWitnessUtxoMismatch(0))
+        );
+    }
+
+    #[test]
+    fn update_input_tap_tree() {
+        let internal_key = SecretKey::from_slice(&[1; 32]).unwrap().x_only_public_key(SECP256K1).0;
+        let control_block = |leaf_ver| ControlBlock {
+            leaf_ver,
+            output_key_parity: false,
+            internal_key,
+            merkle_path: vec![[leaf_ver; 32]],
+        };
+        let leaf_scripts = vec![
+            (control_block(0xc0), ScriptBytes::from(vec![0x51])),
+            (control_block(0xc2), ScriptBytes::from(vec![0x52])),
+        ];
+        let info = SpendInfo {
+            tap_internal_key: Some(internal_key),
+            tap_merkle_root: Some([7; 32]),
+            tap_leaf_scripts: leaf_scripts.clone(),
+            ..default!()
+        };
+        let mut psbt = psbt();
+        let pairs = psbt.inputs[0].len();
+        psbt.update_input(0, &info).unwrap();
+        // Repeated update replaces the pairs instead of adding them again
+        psbt.update_input(0, &info).unwrap();
+
+        let leaves = psbt.inputs[0]
+            .known()
+            .filter_map(|pair| match pair {
+                InPair::TapLeafScript {
+                    control_block,
+                    script,
+                    leaf_ver,
+                } => {
+                    assert_eq!(*leaf_ver, control_block.leaf_ver);
+                    Some((control_block.clone(), script.clone()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(leaves, leaf_scripts);
+        assert_eq!(psbt.inputs[0].len(), pairs + 4);
+        assert_eq!(psbt.update_input(1, &info), Err(UpdateError::NoInput(1)));
+    }
+}
@@ -0,0 +1,154 @@
+// Partially signed bitcoin transaction library (BIP174, BIP370, BIP371)
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Validation of PSBT structure against the requirements of BIP-174 and BIP-370.
+
+use crate::{GlobalPair, InPair, OutPair, Psbt};
+
+/// Errors in PSBT structure.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum ValidationError {
+    /// PSBT v0 doesn't contain unsigned transaction.
+    MissingUnsignedTx,
+
+    /// PSBT v2 doesn't specify transaction version or specifies zero version.
+    MissingTxVersion,
+
+    /// PSBT v2 doesn't specify number of inputs.
+    MissingInputCount,
+
+    /// PSBT v2 doesn't specify number of outputs.
+    MissingOutputCount,
+
+    /// PSBT declares {declared} inputs, but contains {present} input maps.
+    InputCountMismatch { declared: u64, present: usize },
+
+    /// PSBT declares {declared} outputs, but contains {present} output maps.
+    OutputCountMismatch { declared: u64, present: usize },
+
+    /// PSBT v2 input #{0} doesn't specify previous transaction id or output index.
+    MissingPrevout(usize),
+
+    /// PSBT v2 output #{0} doesn't specify amount or script.
+    MissingTxOut(usize),
+
+    /// `OP_RETURN` output #{0} has non-zero amount, which is burned.
+    ValuedOpReturn(usize),
+}
+
+impl Psbt {
+    /// Checks that PSBT contains all global fields which are mandatory for its version, that
+    /// the number of input and output maps matches the declared number of inputs and outputs,
+    /// and that inputs and outputs of PSBT v2 specify the spent outputs and the new outputs.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let (input_count, output_count) = self.declared_counts()?;
+        if input_count != self.inputs.len() as u64 {
+            return Err(ValidationError::InputCountMismatch {
+                declared: input_count,
+                present: self.inputs.len(),
+            });
+        }
+        if output_count != self.outputs.len() as u64 {
+            return Err(ValidationError::OutputCountMismatch {
+                declared: output_count,
+                present: self.outputs.len(),
+            });
+        }
+        if self.version() < 2 {
+            return Ok(());
+        }
+        for (index, map) in self.inputs.iter().enumerate() {
+            let has = |f: fn(&InPair) -> bool| map.known().any(f);
+            if !has(|pair| matches!(pair, InPair::PreviousTxid(_)))
+                || !has(|pair| matches!(pair, InPair::OutputIndex(_)))
+            {
+                return Err(ValidationError::MissingPrevout(index));
+            }
+        }
+        for (index, map) in self.outputs.iter().enumerate() {
+            let has = |f: fn(&OutPair) -> bool| map.known().any(f);
+            if !has(|pair| matches!(pair, OutPair::Amount(_)))
+                || !has(|pair| matches!(pair, OutPair::Script(_)))
+            {
+                return Err(ValidationError::MissingTxOut(index));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that `OP_RETURN` outputs do not carry any value, which is provably unspendable and
+    /// likely is a mistake.
+    ///
+    /// Unlike [`Psbt::validate`], this is a policy check, since such outputs are allowed by
+    /// consensus, and thus it is not performed when PSBT is decoded or constructed.
+    pub fn check_op_return_amounts(&self) -> Result<(), ValidationError> {
+        for (index, txout) in self.output_txouts().enumerate() {
+            match txout {
+                Some(txout) if txout.value > 0 && txout.script_pubkey.first() == Some(&0x6A) => {
+                    return Err(ValidationError::ValuedOpReturn(index));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns number of inputs and outputs declared by the mandatory global fields.
+    fn declared_counts(&self) -> Result<(u64, u64), ValidationError> {
+        let has = |f: fn(&GlobalPair) -> bool| self.global.known().any(f);
+
+        if self.version() < 2 {
+            return self
+                .global
+                .known()
+                .find_map(|pair| match pair {
+                    GlobalPair::UnsignedTx(tx) => {
+                        Some((tx.inputs.len() as u64, tx.outputs.len() as u64))
+                    }
+                    _ => None,
+                })
+                .ok_or(ValidationError::MissingUnsignedTx);
+        }
+
+        if !has(|pair| matches!(pair, GlobalPair::TxVersion(version) if *version > 0)) {
+            return Err(ValidationError::MissingTxVersion);
+        }
+        let input_count = self
+            .global
+            .known()
+            .find_map(|pair| match pair {
+                GlobalPair::InputCount(count) => Some(*count),
+                _ => None,
+            })
+            .ok_or(ValidationError::MissingInputCount)?;
+        let output_count = self
+            .global
+            .known()
+            .find_map(|pair| match pair {
+                GlobalPair::OutputCount(count) => Some(*count),
+                _ => None,
+            })
+            .ok_or(ValidationError::MissingOutputCount)?;
+        Ok((input_count, output_count))
+    }
+}
@@ -0,0 +1,147 @@
+// Partially signed bitcoin transaction library (BIP174, BIP370, BIP371)
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2023 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2023 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Verification of the signatures contained in PSBT.
+
+use secp256k1::{Message, SECP256K1};
+
+use crate::scripts::ScriptTemplate;
+use crate::sighash::tap_tweak;
+use crate::{InPair, Psbt, SighashError};
+
+/// Errors verifying PSBT signatures.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum VerificationError {
+    /// input #{input} has taproot key path signature, but doesn't specify taproot internal key.
+    NoInternalKey { input: usize },
+
+    /// input #{input} doesn't spend a taproot output with the key tweaked from its internal key.
+    OutputKeyMismatch { input: usize },
+
+    /// taproot key path signature of input #{input} is invalid.
+    InvalidTaprootSig { input: usize },
+
+    #[from]
+    #[display(inner)]
+    Sighash(SighashError),
+}
+
+impl Psbt {
+    /// Verifies `PSBT_IN_TAP_KEY_SIG` signatures of all inputs.
+    ///
+    /// The output key is recomputed by tweaking `PSBT_IN_TAP_INTERNAL_KEY` with
+    /// `PSBT_IN_TAP_MERKLE_ROOT` (if present) according to BIP-341 and must match the key of the
+    /// spent output. Signatures using `SIGHASH_DEFAULT` (64 bytes) and an explicit signature hash
+    /// type (65 bytes) are verified against the BIP-341 signature hash of the corresponding type.
+    /// Inputs without taproot key path signatures are skipped.
+    pub fn verify_taproot_signatures(&self) -> Result<(), VerificationError> {
+        for (input, map) in self.inputs.iter().enumerate() {
+            let mut sig = None;
+            let mut internal_key = None;
+            let mut merkle_root = None;
+            for pair in map.known() {
+                match pair {
+                    InPair::TapKeySig(s) => sig = Some(s),
+                    InPair::TapInternalKey(key) => internal_key = Some(*key),
+                    InPair::TapMerkleRoot(root) => merkle_root = Some(*root),
+                    _ => {}
+                }
+            }
+            let sig = match sig {
+                Some(sig) => sig,
+                None => continue,
+            };
+            let internal_key = internal_key.ok_or(VerificationError::NoInternalKey { input })?;
+
+            let (output_key, _) = tap_tweak(internal_key, merkle_root)
+                .and_then(|tweak| internal_key.add_tweak(SECP256K1, &tweak).ok())
+                .ok_or(VerificationError::OutputKeyMismatch { input })?;
+            match self.spent_script_pubkey(input).map(|script| script.template()) {
+                Some(ScriptTemplate::Tr(key)) if key == output_key.serialize() => {}
+                Some(_) => return Err(VerificationError::OutputKeyMismatch { input }),
+                None => return Err(SighashError::NoSpentOutput(input).into()),
+            }
+
+            let sighash = self.taproot_key_sighash(input, sig.sighash_type)?;
+            let msg = Message::from_slice(&sighash).expect("hash has message length");
+            SECP256K1
+                .verify_schnorr(&sig.sig, &msg, &output_key)
+                .map_err(|_| VerificationError::InvalidTaprootSig { input })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::hex::FromHex;
+    use secp256k1::{schnorr, KeyPair, SecretKey};
+
+    use super::*;
+    use crate::vectors::BIP371_VALID;
+    use crate::SchnorrSig;
+
+    #[test]
+    fn bip371_key_path_signature() {
+        let psbt = Psbt::from_raw(&Vec::<u8>::from_hex(BIP371_VALID[1]).unwrap()).unwrap();
+        psbt.verify_taproot_signatures().unwrap();
+
+        let mut invalid = psbt.clone();
+        let sig = invalid.inputs[0]
+            .known()
+            .find_map(|pair| match pair {
+                InPair::TapKeySig(sig) => Some(*sig),
+                _ => None,
+            })
+            .unwrap();
+        let mut data = *sig.sig.as_ref();
+        data[10] ^= 0x01;
+        invalid.inputs[0].replace_singleton(InPair::TapKeySig(SchnorrSig {
+            sig: schnorr::Signature::from_slice(&data).unwrap(),
+            sighash_type: sig.sighash_type,
+        }));
+        assert_eq!(
+            invalid.verify_taproot_signatures(),
+            Err(VerificationError::InvalidTaprootSig { input: 0 })
+        );
+
+        let mut other_key = psbt.clone();
+        let keypair =
+            KeyPair::from_secret_key(SECP256K1, &SecretKey::from_slice(&[1; 32]).unwrap());
+        other_key.inputs[0]
+            .replace_singleton(InPair::TapInternalKey(keypair.x_only_public_key().0));
+        assert_eq!(
+            other_key.verify_taproot_signatures(),
+            Err(VerificationError::OutputKeyMismatch { input: 0 })
+        );
+
+        let mut no_key = psbt;
+        no_key.inputs[0]
+            .0
+            .retain(|pair| !matches!(pair, crate::KeyPair::Known(InPair::TapInternalKey(_))));
+        assert_eq!(
+            no_key.verify_taproot_signatures(),
+            Err(VerificationError::NoInternalKey { input: 0 })
+        );
+    }
+}